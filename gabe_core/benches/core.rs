@@ -0,0 +1,60 @@
+//! Benchmarks for the core, run with `cargo bench --features bench`. Criterion keeps the
+//! results of the last run, so a change's effect shows as the difference from before it.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use gabe_core::bench::{ApuBench, CpuBench, PpuBench};
+use gabe_core::gb::{Gameboy, RamInit};
+use gabe_core::CYCLES_PER_FRAME;
+
+/// Blargg's CPU instruction tests, which run every opcode and draw their progress to the
+/// screen, so they make for a busy CPU with some video
+const ROM: &[u8] = include_bytes!("../tests/roms/cpu_instrs/cpu_instrs.gb");
+
+/// Seed for the RAM contents at power on and the PPU's video memory, so every run does the
+/// same work
+const SEED: u64 = 0x6AB3;
+
+/// Instructions run in each iteration of the CPU benchmark
+const INSTRUCTIONS: u32 = 10_000;
+
+fn cpu(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cpu");
+    group.throughput(Throughput::Elements(INSTRUCTIONS as u64));
+    let mut cpu = CpuBench::new(ROM);
+    group.bench_function("dispatch", |b| b.iter(|| black_box(cpu.run(INSTRUCTIONS))));
+    group.finish();
+}
+
+fn frame(c: &mut Criterion) {
+    let mut gb = Gameboy::builder()
+        .rom_bytes(ROM.into())
+        .ram_init(RamInit::Random(SEED))
+        .build()
+        .unwrap();
+    // Past the start up, into the tests themselves
+    for _ in 0..60 {
+        gb.step_frame();
+    }
+    c.bench_function("frame", |b| b.iter(|| black_box(gb.step_frame())));
+}
+
+fn ppu(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ppu");
+    // Counted in scanlines drawn
+    group.throughput(Throughput::Elements(144));
+    let mut ppu = PpuBench::new(SEED);
+    group.bench_function("frame", |b| b.iter(|| black_box(ppu.frame())));
+    group.finish();
+}
+
+fn apu(c: &mut Criterion) {
+    let mut group = c.benchmark_group("apu");
+    // Counted in samples mixed
+    group.throughput(Throughput::Elements((CYCLES_PER_FRAME / 16) as u64));
+    let mut apu = ApuBench::new();
+    group.bench_function("frame", |b| b.iter(|| black_box(apu.run(CYCLES_PER_FRAME))));
+    group.finish();
+}
+
+criterion_group!(benches, cpu, frame, ppu, apu);
+criterion_main!(benches);
@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 use super::sink::*;
 use super::{mmu::Memory, util::bit::*};
 
@@ -974,6 +976,20 @@ pub struct Apu {
     /// to each of the two analog signals.
     _hpf_capacitor_l: f32,
     _hpf_capacitor_r: f32,
+
+    /// CPU cycles between samples, which sets the sample rate
+    sample_period: u32,
+
+    /// CPU cycles run since the last sample
+    sample_timer: u32,
+
+    /// Channels left out of the output, with bit 0 for channel 1 up to bit 3 for channel 4.
+    /// Muted channels still run, so unmuting them picks up where the game is.
+    muted_channels: u8,
+
+    /// Samples generated but not yet passed to the audio sink, which they're sent to in
+    /// batches by `flush`
+    samples: Vec<AudioFrame>,
 }
 
 impl Apu {
@@ -1054,10 +1070,82 @@ impl Apu {
             frame_cycle: 0,
             _hpf_capacitor_l: 0.0,
             _hpf_capacitor_r: 0.0,
+            sample_period: SAMPLE_RATE_PERIOD,
+            sample_timer: 0,
+            muted_channels: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Returns the APU to its power-on state, keeping the channels the frontend muted and the
+    /// sample rate
+    pub fn reset(&mut self) {
+        *self = Apu {
+            muted_channels: self.muted_channels,
+            sample_period: self.sample_period,
+            ..Apu::power_on()
+        };
+    }
+
+    /// Generates samples at `rate` Hz instead of `SAMPLE_RATE`, rounded to a whole number of
+    /// CPU cycles between samples. Samples are taken without filtering, so low rates alias.
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.sample_period = (super::CLOCK_RATE / rate.max(1)).max(1);
+        self.sample_timer = 0;
+    }
+
+    /// Returns the rate samples are generated at, in Hz
+    pub fn sample_rate(&self) -> u32 {
+        super::CLOCK_RATE / self.sample_period
+    }
+
+    /// Mutes or unmutes one of the four channels, numbered 1 to 4 as in the sound registers
+    pub fn set_channel_muted(&mut self, channel: u8, muted: bool) {
+        assert!((1..=4).contains(&channel));
+        let bit = 1 << (channel - 1);
+        if muted {
+            self.muted_channels |= bit;
+        } else {
+            self.muted_channels &= !bit;
+        }
+    }
+
+    /// Returns the number of samples generated but not yet passed on with `flush`
+    pub fn buffered(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Passes the samples generated since the last flush on to the sink, in one batch
+    pub fn flush(&mut self, audio_sink: &mut dyn Sink<AudioFrame>) {
+        if !self.samples.is_empty() {
+            audio_sink.append_slice(&self.samples);
+            self.samples.clear();
+        }
+    }
+
+    /// Returns true if the channel, numbered 1 to 4, has been muted
+    pub fn channel_muted(&self, channel: u8) -> bool {
+        assert!((1..=4).contains(&channel));
+        test_bit(self.muted_channels, channel - 1)
+    }
+
+    /// Returns the current amplitude of each channel, with muted channels silent
+    fn channel_amps(&self) -> [f32; 4] {
+        let mut amps = [
+            self.square1.get_amp(),
+            self.square2.get_amp(),
+            self.wave.get_amp(),
+            self.noise.get_amp(),
+        ];
+        for (i, amp) in amps.iter_mut().enumerate() {
+            if test_bit(self.muted_channels, i as u8) {
+                *amp = 0.0;
+            }
         }
+        amps
     }
 
-    pub fn update(&mut self, cycles: u32, audio_sink: &mut dyn Sink<AudioFrame>) {
+    pub fn update(&mut self, cycles: u32) {
         if self.all_sound_on {
             for _ in 0..cycles {
                 self.cycle_count += 1;
@@ -1096,37 +1184,40 @@ impl Apu {
                     }
                 }
 
-                if self.cycle_count % SAMPLE_RATE_PERIOD == 0 {
+                self.sample_timer += 1;
+                if self.sample_timer >= self.sample_period {
                     // Reached period needed to generate a sample
+                    self.sample_timer = 0;
+                    let amps = self.channel_amps();
                     let left_amp = {
                         let mut amp_acc: f32 = 0.0;
                         if test_bit(self.nr51_channel_pan, 4) {
-                            amp_acc += self.square1.get_amp();
+                            amp_acc += amps[0];
                         }
                         if test_bit(self.nr51_channel_pan, 5) {
-                            amp_acc += self.square2.get_amp();
+                            amp_acc += amps[1];
                         }
                         if test_bit(self.nr51_channel_pan, 6) {
-                            amp_acc += self.wave.get_amp();
+                            amp_acc += amps[2];
                         }
                         if test_bit(self.nr51_channel_pan, 7) {
-                            amp_acc += self.noise.get_amp();
+                            amp_acc += amps[3];
                         }
                         amp_acc / 4.0
                     };
                     let right_amp = {
                         let mut amp_acc: f32 = 0.0;
                         if test_bit(self.nr51_channel_pan, 0) {
-                            amp_acc += self.square1.get_amp();
+                            amp_acc += amps[0];
                         }
                         if test_bit(self.nr51_channel_pan, 1) {
-                            amp_acc += self.square2.get_amp();
+                            amp_acc += amps[1];
                         }
                         if test_bit(self.nr51_channel_pan, 2) {
-                            amp_acc += self.wave.get_amp();
+                            amp_acc += amps[2];
                         }
                         if test_bit(self.nr51_channel_pan, 4) {
-                            amp_acc += self.noise.get_amp();
+                            amp_acc += amps[3];
                         }
                         amp_acc / 4.0
                     };
@@ -1136,7 +1227,7 @@ impl Apu {
                         (extract_bits(self.nr50_output_control, 2, 0) as f32 + 1.0) / 8.0;
                     let left_output = left_amp * left_vol;
                     let right_output = right_amp * right_vol;
-                    audio_sink.append(((left_output), (right_output)));
+                    self.samples.push((left_output, right_output));
                 }
             }
         }
@@ -0,0 +1,172 @@
+//! Drivers for the benchmarks in `benches/`, running the CPU, PPU and APU on their own so each
+//! can be timed without the rest of the system. Needs the `bench` feature, and isn't a stable
+//! API. Everything is set up from fixed data or seeds, so runs are comparable.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use super::apu::Apu;
+use super::cpu::Cpu;
+use super::mmu::Memory;
+use super::sink::AudioFrame;
+use super::util::rng::SplitMix64;
+use super::vram::{FrameData, Vram};
+
+/// 64KB of RAM and nothing else, so the CPU benchmark only times the CPU
+struct FlatMemory(Box<[u8]>);
+
+impl Memory for FlatMemory {
+    fn read_byte(&self, addr: u16) -> u8 {
+        self.0[addr as usize]
+    }
+
+    fn write_byte(&mut self, addr: u16, val: u8) {
+        // Keep the program intact
+        if addr >= 0x8000 {
+            self.0[addr as usize] = val;
+        }
+    }
+}
+
+/// Runs instructions from a ROM on the CPU alone, for timing instruction dispatch
+pub struct CpuBench {
+    cpu: Cpu,
+    memory: FlatMemory,
+}
+
+impl CpuBench {
+    /// Maps the first 32KB of `rom` at 0x0000, without an MBC, and starts at 0x0100. With no
+    /// hardware to wake it, the CPU is sent back to the start whenever it halts, stops or
+    /// locks up, so the ROM is run in a loop.
+    pub fn new(rom: &[u8]) -> Self {
+        let mut memory = vec![0; 0x10000].into_boxed_slice();
+        let len = rom.len().min(0x8000);
+        memory[..len].copy_from_slice(&rom[..len]);
+        CpuBench {
+            cpu: Cpu::power_on(),
+            memory: FlatMemory(memory),
+        }
+    }
+
+    /// Runs `instructions` instructions, returning the cycles they took
+    pub fn run(&mut self, instructions: u32) -> u64 {
+        let mut cycles = 0;
+        for _ in 0..instructions {
+            cycles += self.cpu.tick(&mut self.memory) as u64;
+            if self.cpu.halted || self.cpu.stopped || self.cpu.fault.is_some() {
+                self.cpu = Cpu::power_on();
+            }
+        }
+        cycles
+    }
+}
+
+/// Draws frames on the PPU alone, from tiles, maps and sprites filled with seeded data
+pub struct PpuBench {
+    vram: Vram,
+}
+
+impl PpuBench {
+    pub fn new(seed: u64) -> Self {
+        let mut vram = Vram::power_on();
+        let mut rng = SplitMix64::new(seed);
+        for addr in (0x8000..=0x9FFF).chain(0xFE00..=0xFE9F) {
+            vram.write_byte(addr, rng.next_u64() as u8);
+        }
+        // Everything on: background, a window over the lower right and 8x16 sprites, with
+        // differing palettes
+        vram.write_byte(0xFF40, 0xF7);
+        vram.write_byte(0xFF47, 0xE4);
+        vram.write_byte(0xFF48, 0xD2);
+        vram.write_byte(0xFF49, 0x1B);
+        vram.write_byte(0xFF4A, 0x48);
+        vram.write_byte(0xFF4B, 0x57);
+        PpuBench { vram }
+    }
+
+    /// Runs the PPU until it finishes a frame, returning the frame's pixels. The PPU is only
+    /// updated when it changes mode, as the MMU does.
+    pub fn frame(&mut self) -> FrameData {
+        let mut frames: Vec<FrameData> = Vec::with_capacity(1);
+        while frames.is_empty() {
+            let cycles = self.vram.cycles_to_next_event().max(4);
+            self.vram.update(cycles, &mut frames);
+        }
+        frames.pop().unwrap()
+    }
+}
+
+/// Generates audio on the APU alone, with all four channels playing
+pub struct ApuBench {
+    apu: Apu,
+    samples: Vec<AudioFrame>,
+}
+
+impl ApuBench {
+    pub fn new() -> Self {
+        let mut apu = Apu::power_on();
+        // Both squares at full volume with a 50% duty cycle
+        for base in [0xFF10, 0xFF15] {
+            apu.write_byte(base + 1, 0x80);
+            apu.write_byte(base + 2, 0xF0);
+            apu.write_byte(base + 3, 0x40);
+            apu.write_byte(base + 4, 0x87);
+        }
+        // The wave channel playing a sawtooth
+        for (i, addr) in (0xFF30..=0xFF3F).enumerate() {
+            apu.write_byte(addr, (i as u8) * 0x11);
+        }
+        apu.write_byte(0xFF1A, 0x80);
+        apu.write_byte(0xFF1C, 0x20);
+        apu.write_byte(0xFF1E, 0x87);
+        // Noise, with the 7-bit LFSR
+        apu.write_byte(0xFF21, 0xF0);
+        apu.write_byte(0xFF22, 0x5A);
+        apu.write_byte(0xFF23, 0x80);
+        // Every channel to both sides
+        apu.write_byte(0xFF25, 0xFF);
+        ApuBench {
+            apu,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Runs the APU for `cycles` cycles, in steps of 4 as the CPU runs it, and mixes the
+    /// samples generated. Returns how many there were.
+    pub fn run(&mut self, cycles: u32) -> usize {
+        for _ in 0..cycles / 4 {
+            self.apu.update(4);
+        }
+        self.samples.clear();
+        self.apu.flush(&mut self.samples);
+        self.samples.len()
+    }
+}
+
+impl Default for ApuBench {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod bench_tests {
+    use super::*;
+
+    #[test]
+    fn drivers_run() {
+        let mut rom = vec![0; 0x8000];
+        // INC A; JR -3
+        rom[0x100..0x103].copy_from_slice(&[0x3C, 0x18, 0xFD]);
+        assert_eq!(CpuBench::new(&rom).run(4), 4 + 12 + 4 + 12);
+
+        let mut ppu = PpuBench::new(1);
+        let frame = ppu.frame();
+        assert_eq!(frame.len(), 160 * 144 * 3);
+        // The same seed draws the same frame
+        assert_eq!(PpuBench::new(1).frame(), frame);
+
+        // One sample every 16 cycles
+        assert_eq!(ApuBench::new().run(crate::CYCLES_PER_FRAME), 70224 / 16);
+    }
+}
@@ -0,0 +1,101 @@
+//! Observing every access made over the memory bus, for watchpoints, access heatmaps and
+//! trainers. Needs the `bus-observer` feature, so builds without it pay nothing.
+
+use core::cell::RefCell;
+
+use super::mmu::{Memory, Mmu};
+
+/// What made a bus access
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BusSource {
+    /// The CPU, running an instruction or dispatching an interrupt
+    Cpu,
+    /// An OAM DMA transfer, copying a byte into OAM
+    Dma,
+}
+
+/// Told about each byte read or written over the bus, once attached with
+/// `Gameboy::attach_bus_observer`. Accesses made by the emulator itself, such as the frontend
+/// reading memory for a debugger, aren't reported. Observers are Send, so a Gameboy can be
+/// moved to another thread with one attached.
+pub trait BusObserver: Send {
+    /// Called after a byte is read, with the value read
+    fn on_read(&mut self, _addr: u16, _value: u8, _source: BusSource) {}
+
+    /// Called before a byte is written, with the value being written. Writes to ROM, which
+    /// go to the cartridge's MBC, and to read-only registers are reported too.
+    fn on_write(&mut self, _addr: u16, _value: u8, _source: BusSource) {}
+}
+
+/// Passes the CPU's accesses through to the MMU, reporting each one to the observer
+pub(crate) struct ObservedBus<'a> {
+    pub mmu: &'a mut Mmu,
+    // Reads only borrow the bus, but the observer needs to be mutable to record them
+    pub observer: RefCell<&'a mut dyn BusObserver>,
+}
+
+impl Memory for ObservedBus<'_> {
+    fn read_byte(&self, addr: u16) -> u8 {
+        let value = self.mmu.read_byte(addr);
+        self.observer
+            .borrow_mut()
+            .on_read(addr, value, BusSource::Cpu);
+        value
+    }
+
+    fn write_byte(&mut self, addr: u16, val: u8) {
+        self.observer.get_mut().on_write(addr, val, BusSource::Cpu);
+        self.mmu.write_byte(addr, val);
+    }
+}
+
+#[cfg(test)]
+mod bus_tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::gb::Gameboy;
+
+    /// An access, as (address, value, source, whether it's a write)
+    type Access = (u16, u8, BusSource, bool);
+
+    /// Records every access into a list shared with the test
+    struct Recorder(Arc<Mutex<Vec<Access>>>);
+
+    impl BusObserver for Recorder {
+        fn on_read(&mut self, addr: u16, value: u8, source: BusSource) {
+            self.0.lock().unwrap().push((addr, value, source, false));
+        }
+
+        fn on_write(&mut self, addr: u16, value: u8, source: BusSource) {
+            self.0.lock().unwrap().push((addr, value, source, true));
+        }
+    }
+
+    #[test]
+    fn observes_cpu_and_dma() {
+        let mut rom = vec![0; 0x8000];
+        // LD A,C0; LDH (46),A to start a DMA from 0xC000
+        rom[0x100..0x104].copy_from_slice(&[0x3E, 0xC0, 0xE0, 0x46]);
+        let mut gb = Gameboy::power_on(rom.into_boxed_slice(), None).unwrap();
+        let accesses = Arc::new(Mutex::new(Vec::new()));
+        gb.attach_bus_observer(Box::new(Recorder(accesses.clone())));
+        let mut frames = Vec::new();
+        let mut audio = Vec::new();
+        gb.step(&mut frames, &mut audio);
+        assert_eq!(
+            accesses.lock().unwrap()[..],
+            [
+                (0x100, 0x3E, BusSource::Cpu, false),
+                (0x101, 0xC0, BusSource::Cpu, false)
+            ]
+        );
+        gb.step(&mut frames, &mut audio);
+        gb.step(&mut frames, &mut audio);
+        let accesses = accesses.lock().unwrap();
+        assert!(accesses.contains(&(0xFF46, 0xC0, BusSource::Cpu, true)));
+        assert!(accesses.contains(&(0xC000, 0x00, BusSource::Dma, false)));
+        assert!(accesses.contains(&(0xFE00, 0x00, BusSource::Dma, true)));
+        assert!(gb.detach_bus_observer().is_some());
+    }
+}
@@ -0,0 +1,55 @@
+//! The image sensor of the Game Boy Camera, which the frontend supplies pictures to
+
+use alloc::boxed::Box;
+
+/// Width of the pictures the sensor takes, in pixels
+pub const SENSOR_WIDTH: usize = 128;
+/// Height of the pictures the sensor takes, in pixels
+pub const SENSOR_HEIGHT: usize = 112;
+
+/// Supplies the pictures a Game Boy Camera takes, such as frames from a webcam or an image
+/// file. Sensors are Send, so a Gameboy can be moved to another thread with its sensor
+/// connected.
+pub trait CameraSensor: Send {
+    /// Fills `image` with what's in front of the camera, called each time the game takes a
+    /// picture. `image` holds `SENSOR_WIDTH` x `SENSOR_HEIGHT` grayscale pixels, row by row
+    /// from the top left, from 0 for black to 255 for white.
+    fn capture(&mut self, image: &mut [u8]);
+}
+
+/// A sensor that always sees the same picture, such as one loaded from a file
+pub struct StillImage {
+    pixels: Box<[u8]>,
+}
+
+impl StillImage {
+    /// Creates a sensor seeing a `width` x `height` grayscale picture, laid out as for
+    /// `CameraSensor::capture`. The picture is scaled to cover the sensor, keeping its aspect
+    /// ratio, and cropped to the middle.
+    pub fn new(pixels: &[u8], width: usize, height: usize) -> Self {
+        if width == 0 || height == 0 || pixels.len() < width * height {
+            // Nothing to see, so a blank gray
+            return StillImage {
+                pixels: vec![0x80; SENSOR_WIDTH * SENSOR_HEIGHT].into_boxed_slice(),
+            };
+        }
+        // Pixels of the picture per 1/1000 of a pixel of the sensor, along the side that fits
+        let scale = (width * 1000 / SENSOR_WIDTH).min(height * 1000 / SENSOR_HEIGHT);
+        let left = (width * 1000 - SENSOR_WIDTH * scale) / 2000;
+        let top = (height * 1000 - SENSOR_HEIGHT * scale) / 2000;
+        let pixels = (0..SENSOR_WIDTH * SENSOR_HEIGHT)
+            .map(|i| {
+                let x = left + (i % SENSOR_WIDTH) * scale / 1000;
+                let y = top + (i / SENSOR_WIDTH) * scale / 1000;
+                pixels[y.min(height - 1) * width + x.min(width - 1)]
+            })
+            .collect();
+        StillImage { pixels }
+    }
+}
+
+impl CameraSensor for StillImage {
+    fn capture(&mut self, image: &mut [u8]) {
+        image.copy_from_slice(&self.pixels);
+    }
+}
@@ -0,0 +1,247 @@
+use alloc::boxed::Box;
+
+use super::super::mmu::Memory;
+use super::{fit_rom, Cartridge, CartridgeError};
+use crate::camera::{CameraSensor, SENSOR_HEIGHT, SENSOR_WIDTH};
+use crate::error::GabeError;
+
+// 1 MB of ROM, 64 banks of 16 KB
+const MAX_ROM_SIZE: usize = 0x10_0000;
+// 128 KB of RAM, 16 banks of 8 KB
+const RAM_SIZE: usize = 0x2_0000;
+
+/// Registers mapped into 0xA000-0xA035 when RAM bank 0x10 is selected
+const REGISTER_COUNT: usize = 0x36;
+/// 0xA000: Bit 0 starts a capture, and reads as set until it's finished
+const REG_CONTROL: usize = 0x00;
+/// 0xA001: Bit 7 is N, bits 5-6 select the edge enhancement directions, and bits 0-4 the
+/// gain, which isn't emulated
+const REG_FILTER: usize = 0x01;
+/// 0xA002-0xA003: Exposure time, high byte first, in units of 16 M-cycles
+const REG_EXPOSURE: usize = 0x02;
+/// 0xA004: Bits 4-6 select the edge enhancement ratio, and bit 3 inverts the picture
+const REG_EDGE: usize = 0x04;
+/// 0xA006-0xA035: A 4x4 matrix of three thresholds each, comparing every pixel against its
+/// spot in the matrix to pick its color. The game sets them for contrast and dithering.
+const REG_MATRIX: usize = 0x06;
+
+/// Edge enhancement ratios selected by bits 4-6 of 0xA004, in quarters
+const EDGE_RATIOS: [i32; 8] = [2, 3, 4, 5, 8, 12, 16, 20];
+
+/// Where a picture is written in RAM bank 0, as 16x14 tiles ready for the game to copy
+const IMAGE_OFFSET: usize = 0x100;
+
+/// The Pocket Camera, or Game Boy Camera, cartridge. Has 1 MB of ROM, 128 KB of battery-backed
+/// RAM to hold the photos, and an image sensor whose registers replace RAM when bank 0x10 is
+/// selected. Pictures come from the `CameraSensor` connected, or are blank without one.
+pub struct PocketCamera {
+    rom: Box<[u8]>,
+    ram: Box<[u8]>,
+    rom_bank: u8,
+    rom_bank_count: u8,
+    ram_bank: u8,
+    /// RAM can always be read, but only written once enabled
+    ram_write_enabled: bool,
+    /// Set while 0xA000-0xBFFF maps the sensor's registers instead of RAM
+    registers_mapped: bool,
+    registers: [u8; REGISTER_COUNT],
+    /// Cycles left in the capture in progress, 0 if there isn't one
+    capture_cycles: u32,
+    sensor: Option<Box<dyn CameraSensor>>,
+}
+
+impl PocketCamera {
+    pub fn power_on(rom: Box<[u8]>, rom_size: u8) -> Result<Self, GabeError> {
+        if rom_size > 0x5 {
+            return Err(GabeError::UnsupportedRomSize(rom_size));
+        }
+        let rom_bank_count = 2u8 << rom_size;
+        let rom = fit_rom(rom, 0x4000 * rom_bank_count as usize, MAX_ROM_SIZE)?;
+        Ok(PocketCamera {
+            rom,
+            ram: vec![0; RAM_SIZE].into_boxed_slice(),
+            rom_bank: 1,
+            rom_bank_count,
+            ram_bank: 0,
+            ram_write_enabled: false,
+            registers_mapped: false,
+            registers: [0; REGISTER_COUNT],
+            capture_cycles: 0,
+            sensor: None,
+        })
+    }
+
+    /// Starts a capture, which takes longer the longer the exposure
+    fn start_capture(&mut self) {
+        let exposure = u16::from_be_bytes([
+            self.registers[REG_EXPOSURE],
+            self.registers[REG_EXPOSURE + 1],
+        ]) as u32;
+        let n = self.registers[REG_FILTER] & 0x80 != 0;
+        let m_cycles = 32446 + if n { 0 } else { 512 } + 16 * exposure;
+        self.capture_cycles = m_cycles * 4;
+    }
+
+    /// Takes the picture from the sensor and processes it as the sensor's registers say,
+    /// writing it to RAM as tiles
+    fn finish_capture(&mut self) {
+        let mut image = [0x80; SENSOR_WIDTH * SENSOR_HEIGHT];
+        if let Some(sensor) = &mut self.sensor {
+            sensor.capture(&mut image);
+        }
+        let exposure = u16::from_be_bytes([
+            self.registers[REG_EXPOSURE],
+            self.registers[REG_EXPOSURE + 1],
+        ]) as i32;
+        // Brightness scales with the exposure, which is 0x1000 for the picture as given
+        let exposed = |x: isize, y: isize| {
+            let x = x.clamp(0, SENSOR_WIDTH as isize - 1) as usize;
+            let y = y.clamp(0, SENSOR_HEIGHT as isize - 1) as usize;
+            image[y * SENSOR_WIDTH + x] as i32 * exposure / 0x1000
+        };
+        // Only enhancing in both directions at once is emulated
+        let enhance_edges = self.registers[REG_FILTER] & 0x60 == 0x60;
+        let ratio = EDGE_RATIOS[(self.registers[REG_EDGE] >> 4) as usize & 0x7];
+        let invert = self.registers[REG_EDGE] & 0x08 != 0;
+
+        let tiles = &mut self.ram[IMAGE_OFFSET..IMAGE_OFFSET + SENSOR_WIDTH * SENSOR_HEIGHT / 4];
+        tiles.fill(0);
+        for y in 0..SENSOR_HEIGHT {
+            for x in 0..SENSOR_WIDTH {
+                let (xi, yi) = (x as isize, y as isize);
+                let mut value = exposed(xi, yi);
+                if enhance_edges {
+                    let around = exposed(xi - 1, yi)
+                        + exposed(xi + 1, yi)
+                        + exposed(xi, yi - 1)
+                        + exposed(xi, yi + 1);
+                    value += ratio * (4 * value - around) / 4;
+                }
+                let mut value = value.clamp(0, 255);
+                if invert {
+                    value = 255 - value;
+                }
+                let matrix = REG_MATRIX + ((y % 4) * 4 + x % 4) * 3;
+                let thresholds = &self.registers[matrix..matrix + 3];
+                // Darker than the first threshold is black, color 3
+                let color = thresholds
+                    .iter()
+                    .position(|t| value < *t as i32)
+                    .map_or(0, |i| 3 - i as u8);
+                let tile = (y / 8) * (SENSOR_WIDTH / 8) + x / 8;
+                let row = tile * 16 + (y % 8) * 2;
+                let bit = 7 - (x % 8);
+                tiles[row] |= (color & 1) << bit;
+                tiles[row + 1] |= (color >> 1) << bit;
+            }
+        }
+    }
+}
+
+impl Memory for PocketCamera {
+    fn read_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => self.rom[addr as usize],
+            0x4000..=0x7FFF => self.rom[(addr - 0x4000) as usize + 0x4000 * self.rom_bank as usize],
+            0xA000..=0xBFFF => {
+                if self.registers_mapped {
+                    // Only the control register can be read, repeated through the range
+                    match (addr & 0x7F) as usize {
+                        REG_CONTROL => {
+                            self.registers[REG_CONTROL] | (self.capture_cycles > 0) as u8
+                        }
+                        _ => 0x00,
+                    }
+                } else {
+                    self.ram[(addr - 0xA000) as usize + 0x2000 * self.ram_bank as usize]
+                }
+            }
+            _ => {
+                error!("Invalid cartridge read address {}", addr);
+                0
+            }
+        }
+    }
+
+    fn write_byte(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_write_enabled = (val & 0xF) == 0x0A,
+            // Bank 0 can be selected here too
+            0x2000..=0x3FFF => self.rom_bank = val & 0x3F & (self.rom_bank_count - 1),
+            0x4000..=0x5FFF => {
+                self.registers_mapped = val & 0x10 != 0;
+                self.ram_bank = val & 0x0F;
+            }
+            0xA000..=0xBFFF => {
+                if self.registers_mapped {
+                    match (addr & 0x7F) as usize {
+                        REG_CONTROL => {
+                            // The busy bit is kept by `capture_cycles`
+                            self.registers[REG_CONTROL] = val & 0x06;
+                            if val & 0x01 != 0 && self.capture_cycles == 0 {
+                                self.start_capture();
+                            }
+                        }
+                        reg if reg < REGISTER_COUNT => self.registers[reg] = val,
+                        _ => {}
+                    }
+                } else if self.ram_write_enabled {
+                    self.ram[(addr - 0xA000) as usize + 0x2000 * self.ram_bank as usize] = val;
+                }
+            }
+            _ => error!("Invalid cartridge write address {}", addr),
+        }
+    }
+}
+
+impl Cartridge for PocketCamera {
+    fn read_save_data(&mut self, data: Box<[u8]>) -> Result<(), CartridgeError> {
+        let len = data.len().min(self.ram.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+        Ok(())
+    }
+
+    fn write_save_data(&self) -> Result<Box<[u8]>, CartridgeError> {
+        // The photos are always kept by the battery
+        Ok(self.ram.clone())
+    }
+
+    fn reset(&mut self) {
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.ram_write_enabled = false;
+        self.registers_mapped = false;
+        self.registers = [0; REGISTER_COUNT];
+        self.capture_cycles = 0;
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.rom_bank as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.ram_bank
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn update(&mut self, cycles: u32) {
+        if self.capture_cycles > 0 {
+            self.capture_cycles = self.capture_cycles.saturating_sub(cycles);
+            if self.capture_cycles == 0 {
+                self.finish_capture();
+            }
+        }
+    }
+
+    fn connect_camera(&mut self, sensor: Box<dyn CameraSensor>) -> Result<(), CartridgeError> {
+        self.sensor = Some(sensor);
+        Ok(())
+    }
+}
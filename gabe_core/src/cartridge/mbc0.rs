@@ -2,7 +2,8 @@ use alloc::boxed::Box;
 use alloc::string::*;
 
 use super::super::mmu::Memory;
-use super::{Cartridge, CartridgeError};
+use super::{fit_rom, Cartridge, CartridgeError};
+use crate::error::GabeError;
 
 const CART_ROM_START: usize = 0x0000;
 const CART_ROM_END: usize = 0x7FFF;
@@ -16,9 +17,10 @@ pub struct Mbc0 {
 }
 
 impl Mbc0 {
-    pub fn power_on(rom: Box<[u8]>) -> Self {
-        assert!(rom.len() <= CART_ROM_SIZE);
-        Mbc0 { rom }
+    pub fn power_on(rom: Box<[u8]>) -> Result<Self, GabeError> {
+        Ok(Mbc0 {
+            rom: fit_rom(rom, CART_ROM_SIZE, CART_ROM_SIZE)?,
+        })
     }
 }
 
@@ -54,4 +56,19 @@ impl Cartridge for Mbc0 {
             "MBC0 does not support save file writing.".to_string(),
         ))
     }
+
+    fn reset(&mut self) {}
+
+    fn rom_bank(&self) -> u16 {
+        // No bank switching, upper half of ROM is always bank 1
+        1
+    }
+
+    fn ram_bank(&self) -> u8 {
+        0
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
 }
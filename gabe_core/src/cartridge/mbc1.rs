@@ -5,7 +5,8 @@ use alloc::string::*;
 use alloc::vec::*;
 
 use super::super::mmu::Memory;
-use super::{Cartridge, CartridgeError};
+use super::{fit_rom, Cartridge, CartridgeError};
+use crate::error::GabeError;
 
 // Maximum can support 2 MB worth of ROM banks, which is 0x7F = 128 16-Kb banks
 const MAX_ROM_SIZE: u32 = 0x20_0000;
@@ -26,8 +27,12 @@ pub struct Mbc1 {
 }
 
 impl Mbc1 {
-    pub fn power_on(rom: Box<[u8]>, rom_size: u8, ram_size: u8, has_battery: bool) -> Self {
-        assert!(rom.len() <= MAX_ROM_SIZE as usize);
+    pub fn power_on(
+        rom: Box<[u8]>,
+        rom_size: u8,
+        ram_size: u8,
+        has_battery: bool,
+    ) -> Result<Self, GabeError> {
         let rom_bank_count: u8 = match rom_size {
             0x0 => 0x02, // 32 KB
             0x1 => 0x04, // 64 KB
@@ -36,16 +41,17 @@ impl Mbc1 {
             0x4 => 0x20, // 512 KB
             0x5 => 0x40, // 1 MB
             0x6 => 0x80, // 2 MB
-            _ => panic!("Provided ROM Size unsupported for MBC1."),
+            _ => return Err(GabeError::UnsupportedRomSize(rom_size)),
         };
         let ram_bank_count: u8 = match ram_size {
             0x0 | 0x1 => 0x0, // 0 KB
             0x2 => 0x01,      // 8 KB
             0x3 => 0x04,      // 32 KB
-            _ => panic!("Provided RAM Size unsupported for MBC1."),
+            _ => return Err(GabeError::UnsupportedRamSize(ram_size)),
         };
+        let rom = fit_rom(rom, 0x4000 * rom_bank_count as usize, MAX_ROM_SIZE as usize)?;
         let ram: Vec<u8> = vec![0; (0x2000u32 * ram_bank_count as u32) as usize];
-        Mbc1 {
+        Ok(Mbc1 {
             rom,
             ram: ram.into_boxed_slice(),
             rom_bank: 1,
@@ -55,7 +61,7 @@ impl Mbc1 {
             ram_enabled: false,
             has_battery,
             mode1_enabled: false,
-        }
+        })
     }
 }
 
@@ -106,21 +112,17 @@ impl Memory for Mbc1 {
                 if (val & 0x1F) == 0x0 {
                     self.rom_bank = 1;
                 } else {
-                    // Mask into ROM bank after check, so that you can technically select rom_bank 0
-                    match self.rom_bank_count {
-                        0x02 => self.rom_bank = val & 0x01,
-                        0x04 => self.rom_bank = val & 0x03,
-                        0x08 => self.rom_bank = val & 0x07,
-                        0x10 => self.rom_bank = val & 0x0F,
-                        0x20 => self.rom_bank = val & 0x1F,
-                        _ => panic!("MBC1 ROM Bank selection logic failure."),
-                    }
+                    // Mask into ROM bank after check, so that you can technically select rom_bank 0.
+                    // Bank counts are powers of two, and larger ROMs take the upper bits from
+                    // 0x4000-0x5FFF.
+                    self.rom_bank = val & 0x1F & (self.rom_bank_count - 1);
                 }
             }
             0x4000..=0x5FFF => {
                 if self.rom_bank_count >= 0x40 {
                     // Using a >1 MB ROM, need additional bits to select ROM bank
-                    self.rom_bank += (val & 0x3) << 5;
+                    self.rom_bank =
+                        (self.rom_bank + ((val & 0x3) << 5)) & (self.rom_bank_count - 1);
                 } else if self.ram_bank_count == 0x4 {
                     // Using 32 KB of ram, select the RAM bank
                     self.ram_bank = val & 0x3;
@@ -191,4 +193,32 @@ impl Cartridge for Mbc1 {
             ))
         }
     }
+
+    fn reset(&mut self) {
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.ram_enabled = false;
+        self.mode1_enabled = false;
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.rom_bank as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        if self.mode1_enabled {
+            self.ram_bank
+        } else {
+            // Without Mode 1, RAM always uses bank 0.
+            0
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
 }
@@ -5,7 +5,8 @@ use alloc::string::ToString;
 use alloc::vec::*;
 
 use super::super::mmu::Memory;
-use super::{Cartridge, CartridgeError};
+use super::{fit_rom, Cartridge, CartridgeError};
+use crate::error::GabeError;
 
 // Maximum can support 256 KB worth of ROM banks, which is 0x10 = 16 16-KB banks
 const MAX_ROM_SIZE: u32 = 0x4_0000;
@@ -23,24 +24,24 @@ pub struct Mbc2 {
 }
 
 impl Mbc2 {
-    pub fn power_on(rom: Box<[u8]>, rom_size: u8, has_battery: bool) -> Self {
-        assert!(rom.len() <= MAX_ROM_SIZE as usize);
+    pub fn power_on(rom: Box<[u8]>, rom_size: u8, has_battery: bool) -> Result<Self, GabeError> {
         let rom_bank_count: u8 = match rom_size {
             0x0 => 0x02, // 32 KB
             0x1 => 0x04, // 64 KB
             0x2 => 0x08, // 128 KB
             0x3 => 0x10, // 256 KB
-            _ => panic!("Provided ROM Size unsupported for MBC2."),
+            _ => return Err(GabeError::UnsupportedRomSize(rom_size)),
         };
+        let rom = fit_rom(rom, 0x4000 * rom_bank_count as usize, MAX_ROM_SIZE as usize)?;
         let ram: Vec<u8> = vec![0; 512];
-        Mbc2 {
+        Ok(Mbc2 {
             rom,
             ram: ram.into_boxed_slice(),
             rom_bank: 1,
             rom_bank_count,
             ram_enabled: false,
             has_battery,
-        }
+        })
     }
 }
 
@@ -146,4 +147,26 @@ impl Cartridge for Mbc2 {
             ))
         }
     }
+
+    fn reset(&mut self) {
+        self.rom_bank = 1;
+        self.ram_enabled = false;
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.rom_bank as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        // RAM is internal to the MBC, and not banked
+        0
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
 }
@@ -5,26 +5,42 @@ use alloc::string::ToString;
 use alloc::vec::*;
 
 use super::super::mmu::Memory;
-use super::{Cartridge, CartridgeError};
+use super::{fit_rom, Cartridge, CartridgeError};
+use crate::error::GabeError;
+use crate::rtc::{self, RtcClock};
 
 // Maximum can support 2 MB worth of ROM banks, which is 0x7F = 128 16-Kb banks
 const MAX_ROM_SIZE: u32 = 0x20_0000;
+// The MBC30 doubles that to 4 MB, which is 0xFF = 256 16-Kb banks
+const MAX_MBC30_ROM_SIZE: u32 = 0x40_0000;
+// The RTC's state, saved after the RAM: the five registers and the five latched registers as
+// 32-bit values, then the clock's time as a 64-bit value, all little-endian. This is the
+// layout other emulators use too.
+const RTC_SAVE_SIZE: usize = 48;
+// Older saves have a 32-bit time
+const RTC_SAVE_SIZE_32: usize = 44;
 
 /// MBC3 cartridges can support up to 2 MB of ROM banks and/or 32 KB of RAM banks
 /// Requires to be provided the ROM and RAM size to calculate the number of
 /// ROM/RAM banks to support
-/// Also supports a Real-Time Clock
+/// Also supports a Real-Time Clock, which keeps time by an `RtcClock`
+/// The MBC30, used by the Japanese Pokemon Crystal, is an MBC3 with an 8th ROM bank bit and
+/// a 3rd RAM bank bit, for up to 4 MB of ROM and 64 KB of RAM. It shares the MBC3's
+/// cartridge types, so it's told apart by a header asking for more than an MBC3 has.
 pub struct Mbc3 {
     rom: Box<[u8]>,
     ram: Box<[u8]>,
     rom_bank: u8,
-    _rom_bank_count: u8,
+    rom_bank_count: u16,
     ram_bank: u8,
     ram_bank_count: u8,
     ram_enabled: bool,
+    /// Bits of the ROM bank register that are connected, 7 for the MBC3 and 8 for the MBC30
+    rom_bank_mask: u8,
     has_battery: bool,
-    _has_rtc: bool,
-    rtc_enabled: bool,
+    rtc: Option<Rtc>,
+    /// The RTC register mapped into 0xA000-0xBFFF instead of RAM, from 0x08 to 0x0C
+    rtc_register: Option<u8>,
 }
 
 impl Mbc3 {
@@ -34,40 +50,47 @@ impl Mbc3 {
         ram_size: u8,
         has_battery: bool,
         has_rtc: bool,
-    ) -> Self {
-        assert!(rom.len() <= MAX_ROM_SIZE as usize);
-        let rom_bank_count: u8 = match rom_size {
-            0x0 => 0x02, // 32 KB
-            0x1 => 0x04, // 64 KB
-            0x2 => 0x08, // 128 KB
-            0x3 => 0x10, // 256 KB
-            0x4 => 0x20, // 512 KB
-            0x5 => 0x40, // 1 MB
-            0x6 => 0x80, // 2 MB
-            _ => panic!("Provided ROM Size unsupported for MBC3."),
+    ) -> Result<Self, GabeError> {
+        let rom_bank_count: u16 = match rom_size {
+            0x0 => 0x02,  // 32 KB
+            0x1 => 0x04,  // 64 KB
+            0x2 => 0x08,  // 128 KB
+            0x3 => 0x10,  // 256 KB
+            0x4 => 0x20,  // 512 KB
+            0x5 => 0x40,  // 1 MB
+            0x6 => 0x80,  // 2 MB
+            0x7 => 0x100, // 4 MB, MBC30 only
+            _ => return Err(GabeError::UnsupportedRomSize(rom_size)),
         };
         let ram_bank_count: u8 = match ram_size {
             0x0 | 0x1 => 0x0, // 0 KB
             0x2 => 0x01,      // 8 KB
             0x3 => 0x04,      // 32 KB
-            _ => panic!("Provided RAM Size unsupported for MBC3."),
+            0x5 => 0x08,      // 64 KB, MBC30 only
+            _ => return Err(GabeError::UnsupportedRamSize(ram_size)),
         };
+        let mbc30 = rom_bank_count > 0x80 || ram_bank_count > 0x04;
+        let max_rom_size = if mbc30 {
+            info!("\tMBC30, with the larger ROM and RAM");
+            MAX_MBC30_ROM_SIZE
+        } else {
+            MAX_ROM_SIZE
+        };
+        let rom = fit_rom(rom, 0x4000 * rom_bank_count as usize, max_rom_size as usize)?;
         let ram: Vec<u8> = vec![0; (0x2000u32 * ram_bank_count as u32) as usize];
-        if has_rtc {
-            error!("MBC3 RTC not implemented, clock info will not be provided.");
-        }
-        Mbc3 {
+        Ok(Mbc3 {
             rom,
             ram: ram.into_boxed_slice(),
             rom_bank: 1,
             ram_bank: 0,
-            _rom_bank_count: rom_bank_count,
+            rom_bank_count,
             ram_bank_count,
             ram_enabled: false,
+            rom_bank_mask: if mbc30 { 0xFF } else { 0x7F },
             has_battery,
-            _has_rtc: has_rtc,
-            rtc_enabled: false,
-        }
+            rtc: has_rtc.then(|| Rtc::new(rtc::default_clock())),
+            rtc_register: None,
+        })
     }
 }
 
@@ -82,10 +105,11 @@ impl Memory for Mbc3 {
                 self.rom[((addr - 0x4000) as u32 + (0x4000u32 * self.rom_bank as u32)) as usize]
             }
             0xA000..=0xBFFF => {
-                if self.rtc_enabled {
-                    // TODO: Read RTC regs
-                    0x00
-                } else if self.ram_enabled {
+                if !self.ram_enabled {
+                    0xFF
+                } else if let (Some(rtc), Some(reg)) = (&self.rtc, self.rtc_register) {
+                    rtc.latched[(reg - 0x08) as usize]
+                } else if self.ram_bank_count != 0 {
                     self.ram[((addr - 0xA000) as u32 + (0x2000u32 * self.ram_bank as u32)) as usize]
                 } else {
                     0xFF
@@ -99,31 +123,50 @@ impl Memory for Mbc3 {
     }
     fn write_byte(&mut self, addr: u16, val: u8) {
         match addr {
+            // Enables the RTC as well as RAM
             0x0000..=0x1FFF => {
-                self.ram_enabled = ((val & 0xF) == 0x0A) && self.ram_bank_count != 0;
+                self.ram_enabled =
+                    ((val & 0xF) == 0x0A) && (self.ram_bank_count != 0 || self.rtc.is_some());
             }
             0x2000..=0x3FFF => {
-                if (val & 0x7F) == 0x0 {
+                if (val & self.rom_bank_mask) == 0x0 {
                     self.rom_bank = 1;
                 } else {
-                    self.rom_bank = val & 0x7F;
+                    // Banks past the end of the ROM mirror the ones before, as the upper bits
+                    // aren't connected
+                    self.rom_bank = (val & self.rom_bank_mask) & (self.rom_bank_count - 1) as u8;
                 }
             }
             0x4000..=0x5FFF => {
-                if self.ram_bank_count == 0x4 {
-                    // Using 32 KB of ram, select the RAM bank
-                    self.ram_bank = val & 0x3;
+                if val < 0x08 {
+                    self.rtc_register = None;
+                    if self.ram_bank_count > 1 {
+                        // Using 32 KB of ram, or 64 KB on the MBC30, select the RAM bank
+                        self.ram_bank = val & (self.ram_bank_count - 1);
+                    }
+                } else if (0x08..=0x0C).contains(&val) && self.rtc.is_some() {
+                    self.rtc_register = Some(val);
                 }
             }
-            0xA000..=0xBFFF => {
-                if self.rtc_enabled {
-                    // TODO: RTC registers
-                } else if self.ram_enabled {
+            // Writing 0x00 then 0x01 latches the RTC's registers
+            0x6000..=0x7FFF => {
+                if let Some(rtc) = &mut self.rtc {
+                    if rtc.latch_write == 0x00 && val == 0x01 {
+                        rtc.latched = rtc.registers();
+                    }
+                    rtc.latch_write = val;
+                }
+            }
+            0xA000..=0xBFFF if self.ram_enabled => {
+                if let (Some(rtc), Some(reg)) = (&mut self.rtc, self.rtc_register) {
+                    rtc.write_register(reg, val);
+                } else if self.ram_bank_count != 0 {
                     self.ram
                         [((addr - 0xA000) as u32 + (0x2000u32 * self.ram_bank as u32)) as usize] =
                         val;
                 }
             }
+            0xA000..=0xBFFF => {}
             _ => error!("Invalid cartridge write address {}", addr),
         }
     }
@@ -132,6 +175,13 @@ impl Memory for Mbc3 {
 impl Cartridge for Mbc3 {
     fn read_save_data(&mut self, data: Box<[u8]>) -> Result<(), CartridgeError> {
         if self.has_battery {
+            // The RTC's state follows the RAM, if it was saved
+            if let Some(rtc) = &mut self.rtc {
+                let state = &data[self.ram.len().min(data.len())..];
+                if state.len() == RTC_SAVE_SIZE || state.len() == RTC_SAVE_SIZE_32 {
+                    rtc.load(state);
+                }
+            }
             // We have battery-backed RAM available to read from a file
             // If we hit a read error, just propagate up, otherwise we succeed.
             match data.len().cmp(&self.ram.len()) {
@@ -164,12 +214,159 @@ impl Cartridge for Mbc3 {
     fn write_save_data(&self) -> Result<Box<[u8]>, CartridgeError> {
         if self.has_battery {
             // We have battery-backed RAM available to maintain save data
-            // Provide cloned RAM data as a pointer
-            Ok(self.ram.clone())
+            // Provide cloned RAM data as a pointer, followed by the RTC's state
+            match &self.rtc {
+                Some(rtc) => Ok([&self.ram[..], &rtc.save()].concat().into_boxed_slice()),
+                None => Ok(self.ram.clone()),
+            }
         } else {
             Err(CartridgeError::Unsupported(
                 "Game doesn't support save files via battery-backed RAM.".to_string(),
             ))
         }
     }
+
+    fn reset(&mut self) {
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.ram_enabled = false;
+        self.rtc_register = None;
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.rom_bank as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.ram_bank
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn connect_rtc_clock(&mut self, clock: Box<dyn RtcClock>) -> Result<(), CartridgeError> {
+        match &mut self.rtc {
+            Some(rtc) => {
+                rtc.set_clock(clock);
+                Ok(())
+            }
+            None => Err(CartridgeError::Unsupported(
+                "Game doesn't have a real-time clock.".to_string(),
+            )),
+        }
+    }
+}
+
+/// The MBC3's real-time clock, counting seconds, minutes, hours and up to 511 days. Rather
+/// than ticking, it works out the time from how long its `RtcClock` says has passed.
+struct Rtc {
+    clock: Option<Box<dyn RtcClock>>,
+    /// Seconds counted as of `synced_at`
+    seconds: u64,
+    /// The clock's time when `seconds` was last brought up to date
+    synced_at: u64,
+    /// Set while the game has stopped the clock
+    halted: bool,
+    /// Set once the day counter overflows, until the game clears it
+    carry: bool,
+    /// The registers as of the last latch, which are the ones the game reads
+    latched: [u8; 5],
+    /// Last value written to 0x6000-0x7FFF
+    latch_write: u8,
+}
+
+impl Rtc {
+    fn new(clock: Option<Box<dyn RtcClock>>) -> Self {
+        let synced_at = clock.as_ref().map_or(0, |c| c.now());
+        Rtc {
+            clock,
+            seconds: 0,
+            synced_at,
+            halted: false,
+            carry: false,
+            latched: [0; 5],
+            latch_write: 0xFF,
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.clock.as_ref().map_or(0, |c| c.now())
+    }
+
+    /// Replaces the clock, carrying on from the time shown now
+    fn set_clock(&mut self, clock: Box<dyn RtcClock>) {
+        self.seconds = self.counted();
+        self.synced_at = clock.now();
+        self.clock = Some(clock);
+    }
+
+    /// Returns the seconds counted up to now
+    fn counted(&self) -> u64 {
+        if self.halted {
+            self.seconds
+        } else {
+            self.seconds + self.now().saturating_sub(self.synced_at)
+        }
+    }
+
+    /// Returns the seconds, minutes, hours, low 8 bits of the day, and the high day register:
+    /// bit 0 the day's 9th bit, bit 6 halted and bit 7 the day counter's carry
+    fn registers(&self) -> [u8; 5] {
+        let seconds = self.counted();
+        let days = seconds / 86400;
+        let carry = self.carry || days > 0x1FF;
+        [
+            (seconds % 60) as u8,
+            (seconds / 60 % 60) as u8,
+            (seconds / 3600 % 24) as u8,
+            days as u8,
+            ((days >> 8) & 0x1) as u8 | (self.halted as u8) << 6 | (carry as u8) << 7,
+        ]
+    }
+
+    fn set_registers(&mut self, registers: [u8; 5]) {
+        let [seconds, minutes, hours, days_low, days_high] = registers.map(u64::from);
+        let days = days_low | (days_high & 0x1) << 8;
+        self.seconds = days * 86400 + hours * 3600 + minutes * 60 + seconds;
+        self.synced_at = self.now();
+        self.halted = days_high & 0x40 != 0;
+        self.carry = days_high & 0x80 != 0;
+    }
+
+    /// Sets one register, from 0x08 for the seconds to 0x0C for the high day register
+    fn write_register(&mut self, reg: u8, val: u8) {
+        const MASKS: [u8; 5] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+        let index = (reg - 0x08) as usize;
+        let mut registers = self.registers();
+        registers[index] = val & MASKS[index];
+        self.set_registers(registers);
+        // Reading the register back straight away gives the value written
+        self.latched[index] = registers[index];
+    }
+
+    fn save(&self) -> [u8; RTC_SAVE_SIZE] {
+        let mut state = [0; RTC_SAVE_SIZE];
+        let registers = self.registers().into_iter().chain(self.latched);
+        for (bytes, register) in state.chunks_exact_mut(4).zip(registers) {
+            bytes.copy_from_slice(&u32::from(register).to_le_bytes());
+        }
+        state[40..].copy_from_slice(&self.now().to_le_bytes());
+        state
+    }
+
+    /// Restores the state written by `save`, counting the time passed since then. A clock
+    /// showing a time before then, such as a `ManualClock` counting from 0, counts from now.
+    fn load(&mut self, state: &[u8]) {
+        let register = |i: usize| state[i * 4];
+        self.set_registers(core::array::from_fn(register));
+        self.latched = core::array::from_fn(|i| register(i + 5));
+        let mut saved_at = [0; 8];
+        saved_at[..state.len() - 40].copy_from_slice(&state[40..]);
+        self.synced_at = u64::from_le_bytes(saved_at).min(self.now());
+    }
 }
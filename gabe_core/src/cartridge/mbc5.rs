@@ -0,0 +1,179 @@
+use core::cmp::Ordering;
+
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::vec::*;
+
+use super::super::mmu::Memory;
+use super::{fit_rom, Cartridge, CartridgeError};
+use crate::error::GabeError;
+
+// Maximum can support 8 MB worth of ROM banks, which is 0x200 = 512 16-Kb banks
+const MAX_ROM_SIZE: u32 = 0x80_0000;
+
+/// MBC5 cartridges can support up to 8 MB of ROM banks and/or 128 KB of RAM banks.
+/// Unlike the earlier MBCs, bank 0 can be mapped into 0x4000-0x7FFF too.
+/// Cartridges with a rumble motor drive it from bit 3 of the RAM bank register, leaving
+/// them 3 bits for the RAM bank.
+pub struct Mbc5 {
+    rom: Box<[u8]>,
+    ram: Box<[u8]>,
+    rom_bank: u16,
+    rom_bank_count: u16,
+    ram_bank: u8,
+    ram_bank_count: u8,
+    ram_enabled: bool,
+    has_battery: bool,
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl Mbc5 {
+    pub fn power_on(
+        rom: Box<[u8]>,
+        rom_size: u8,
+        ram_size: u8,
+        has_battery: bool,
+        has_rumble: bool,
+    ) -> Result<Self, GabeError> {
+        if rom_size > 0x8 {
+            return Err(GabeError::UnsupportedRomSize(rom_size));
+        }
+        // From 32 KB up to 8 MB
+        let rom_bank_count = 2u16 << rom_size;
+        let ram_bank_count: u8 = match ram_size {
+            0x0 | 0x1 => 0x0, // 0 KB
+            0x2 => 0x01,      // 8 KB
+            0x3 => 0x04,      // 32 KB
+            0x4 => 0x10,      // 128 KB
+            0x5 => 0x08,      // 64 KB
+            _ => return Err(GabeError::UnsupportedRamSize(ram_size)),
+        };
+        let rom = fit_rom(rom, 0x4000 * rom_bank_count as usize, MAX_ROM_SIZE as usize)?;
+        let ram: Vec<u8> = vec![0; 0x2000 * ram_bank_count as usize];
+        Ok(Mbc5 {
+            rom,
+            ram: ram.into_boxed_slice(),
+            rom_bank: 1,
+            rom_bank_count,
+            ram_bank: 0,
+            ram_bank_count,
+            ram_enabled: false,
+            has_battery,
+            has_rumble,
+            rumble: false,
+        })
+    }
+}
+
+impl Memory for Mbc5 {
+    fn read_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => self.rom[addr as usize],
+            0x4000..=0x7FFF => self.rom[(addr - 0x4000) as usize + 0x4000 * self.rom_bank as usize],
+            0xA000..=0xBFFF => {
+                if self.ram_enabled {
+                    self.ram[(addr - 0xA000) as usize + 0x2000 * self.ram_bank as usize]
+                } else {
+                    0xFF
+                }
+            }
+            _ => {
+                error!("Invalid cartridge read address {}", addr);
+                0
+            }
+        }
+    }
+    fn write_byte(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = ((val & 0xF) == 0x0A) && self.ram_bank_count != 0;
+            }
+            // The low 8 bits of the ROM bank
+            0x2000..=0x2FFF => {
+                self.rom_bank = ((self.rom_bank & 0x100) | val as u16) & (self.rom_bank_count - 1);
+            }
+            // The 9th bit of the ROM bank
+            0x3000..=0x3FFF => {
+                self.rom_bank = ((self.rom_bank & 0xFF) | ((val as u16 & 0x1) << 8))
+                    & (self.rom_bank_count - 1);
+            }
+            0x4000..=0x5FFF => {
+                let bank = if self.has_rumble {
+                    self.rumble = val & 0x08 != 0;
+                    val & 0x07
+                } else {
+                    val & 0x0F
+                };
+                if self.ram_bank_count != 0 {
+                    // Banks past the end of the RAM mirror the ones before
+                    self.ram_bank = bank & (self.ram_bank_count - 1);
+                }
+            }
+            0xA000..=0xBFFF => {
+                if self.ram_enabled {
+                    self.ram[(addr - 0xA000) as usize + 0x2000 * self.ram_bank as usize] = val;
+                }
+            }
+            _ => error!("Invalid cartridge write address {}", addr),
+        }
+    }
+}
+
+impl Cartridge for Mbc5 {
+    fn read_save_data(&mut self, data: Box<[u8]>) -> Result<(), CartridgeError> {
+        if self.has_battery {
+            // Fill RAM with as much of the data as fits
+            match data.len().cmp(&self.ram.len()) {
+                Ordering::Equal => self.ram.copy_from_slice(data.as_ref()),
+                Ordering::Greater => {
+                    let len = self.ram.len();
+                    self.ram.copy_from_slice(&data[..len]);
+                }
+                Ordering::Less => self.ram[..data.len()].copy_from_slice(data.as_ref()),
+            }
+            Ok(())
+        } else {
+            Err(CartridgeError::Unsupported(
+                "Game doesn't support save files via battery-backed RAM.".to_string(),
+            ))
+        }
+    }
+
+    fn write_save_data(&self) -> Result<Box<[u8]>, CartridgeError> {
+        if self.has_battery {
+            Ok(self.ram.clone())
+        } else {
+            Err(CartridgeError::Unsupported(
+                "Game doesn't support save files via battery-backed RAM.".to_string(),
+            ))
+        }
+    }
+
+    fn reset(&mut self) {
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.ram_enabled = false;
+        self.rumble = false;
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.rom_bank
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.ram_bank
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+}
@@ -0,0 +1,322 @@
+use alloc::boxed::Box;
+
+use super::super::mmu::Memory;
+use super::{fit_rom, Cartridge, CartridgeError};
+use crate::error::GabeError;
+
+// Maximum can support 2 MB worth of ROM banks, which is 0x7F = 128 16-Kb banks
+const MAX_ROM_SIZE: usize = 0x20_0000;
+// The 93LC56 EEPROM holds 128 16-bit words
+const EEPROM_WORDS: usize = 128;
+
+/// Accelerometer reading when level
+const ACCEL_CENTER: f32 = 0x81D0 as f32;
+/// Change in the accelerometer reading for 1 g of tilt
+const ACCEL_PER_G: f32 = 0x70 as f32;
+/// Latched accelerometer value once erased, until the next reading is latched
+const ACCEL_ERASED: u16 = 0x8000;
+
+/// MBC7 cartridges, as used by Kirby Tilt 'n' Tumble, have up to 2 MB of ROM banks, a two-axis
+/// accelerometer and a 256-byte serial EEPROM for saves instead of RAM. Both are reached
+/// through registers in 0xA000-0xAFFF, once enabled by writing to 0x0000 and 0x4000.
+pub struct Mbc7 {
+    rom: Box<[u8]>,
+    rom_bank: u8,
+    rom_bank_count: u8,
+    /// Enabled by 0x0A in 0x0000-0x1FFF
+    ram_enabled: bool,
+    /// Enabled by 0x40 in 0x4000-0x5FFF. The registers need both.
+    registers_enabled: bool,
+    /// Tilt as last set by the frontend, in g
+    tilt: (f32, f32),
+    /// Accelerometer reading latched by the game
+    latched: (u16, u16),
+    eeprom: Eeprom,
+}
+
+impl Mbc7 {
+    pub fn power_on(rom: Box<[u8]>, rom_size: u8) -> Result<Self, GabeError> {
+        if rom_size > 0x6 {
+            return Err(GabeError::UnsupportedRomSize(rom_size));
+        }
+        let rom_bank_count = 2u8 << rom_size;
+        let rom = fit_rom(rom, 0x4000 * rom_bank_count as usize, MAX_ROM_SIZE)?;
+        Ok(Mbc7 {
+            rom,
+            rom_bank: 1,
+            rom_bank_count,
+            ram_enabled: false,
+            registers_enabled: false,
+            tilt: (0.0, 0.0),
+            latched: (ACCEL_ERASED, ACCEL_ERASED),
+            eeprom: Eeprom::new(),
+        })
+    }
+
+    /// Latches the accelerometer's reading, which only works after it's been erased. Values
+    /// fall as the cartridge is tilted right or forward.
+    fn latch(&mut self) {
+        if self.latched != (ACCEL_ERASED, ACCEL_ERASED) {
+            return;
+        }
+        let reading = |g: f32| (ACCEL_CENTER - g * ACCEL_PER_G) as u16;
+        self.latched = (reading(self.tilt.0), reading(self.tilt.1));
+    }
+}
+
+impl Memory for Mbc7 {
+    fn read_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => self.rom[addr as usize],
+            0x4000..=0x7FFF => self.rom[(addr - 0x4000) as usize + 0x4000 * self.rom_bank as usize],
+            0xA000..=0xAFFF if self.ram_enabled && self.registers_enabled => {
+                // Each register is repeated through its 16 bytes
+                match addr & 0xF0 {
+                    0x20 => self.latched.0 as u8,
+                    0x30 => (self.latched.0 >> 8) as u8,
+                    0x40 => self.latched.1 as u8,
+                    0x50 => (self.latched.1 >> 8) as u8,
+                    0x60 => 0x00,
+                    0x80 => self.eeprom.read(),
+                    _ => 0xFF,
+                }
+            }
+            0xA000..=0xBFFF => 0xFF,
+            _ => {
+                error!("Invalid cartridge read address {}", addr);
+                0
+            }
+        }
+    }
+
+    fn write_byte(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = val == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = val & 0x7F & (self.rom_bank_count - 1),
+            0x4000..=0x5FFF => self.registers_enabled = val == 0x40,
+            0x6000..=0x7FFF => {}
+            0xA000..=0xAFFF if self.ram_enabled && self.registers_enabled => match addr & 0xF0 {
+                0x00 if val == 0x55 => self.latched = (ACCEL_ERASED, ACCEL_ERASED),
+                0x10 if val == 0xAA => self.latch(),
+                0x80 => self.eeprom.write(val),
+                _ => {}
+            },
+            0xA000..=0xBFFF => {}
+            _ => error!("Invalid cartridge write address {}", addr),
+        }
+    }
+}
+
+impl Cartridge for Mbc7 {
+    fn read_save_data(&mut self, data: Box<[u8]>) -> Result<(), CartridgeError> {
+        for (word, bytes) in self.eeprom.words.iter_mut().zip(data.chunks_exact(2)) {
+            *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        Ok(())
+    }
+
+    fn write_save_data(&self) -> Result<Box<[u8]>, CartridgeError> {
+        // The EEPROM keeps its contents without a battery
+        Ok(self
+            .eeprom
+            .words
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect())
+    }
+
+    fn reset(&mut self) {
+        self.rom_bank = 1;
+        self.ram_enabled = false;
+        self.registers_enabled = false;
+        self.latched = (ACCEL_ERASED, ACCEL_ERASED);
+        let words = self.eeprom.words;
+        self.eeprom = Eeprom::new();
+        self.eeprom.words = words;
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.rom_bank as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        0
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = (x, y);
+    }
+}
+
+/// What the EEPROM is doing between clock pulses
+#[derive(Clone, Copy)]
+enum EepromState {
+    /// Waiting for a start bit
+    Idle,
+    /// Taking the 2 opcode and 8 address bits of a command, `count` of them so far
+    Command { bits: u16, count: u8 },
+    /// Shifting out a word, most significant bit first
+    Reading { word: u16, bits_left: u8 },
+    /// Taking a word to write to `address`, or to every word if None
+    Writing {
+        address: Option<u8>,
+        word: u16,
+        count: u8,
+    },
+}
+
+/// A 93LC56 serial EEPROM, driven through bit 7 (chip select), bit 6 (clock) and bit 1 (data
+/// in) of its register, with bit 0 reading its data out. Bits are taken as the clock rises.
+struct Eeprom {
+    words: [u16; EEPROM_WORDS],
+    select: bool,
+    clock: bool,
+    data_in: bool,
+    data_out: bool,
+    /// Writes are refused until enabled by an EWEN command
+    write_enabled: bool,
+    state: EepromState,
+}
+
+impl Eeprom {
+    fn new() -> Self {
+        Eeprom {
+            words: [0xFFFF; EEPROM_WORDS],
+            select: false,
+            clock: false,
+            data_in: false,
+            data_out: true,
+            write_enabled: false,
+            state: EepromState::Idle,
+        }
+    }
+
+    fn read(&self) -> u8 {
+        (self.select as u8) << 7
+            | (self.clock as u8) << 6
+            | (self.data_in as u8) << 1
+            | self.data_out as u8
+    }
+
+    fn write(&mut self, val: u8) {
+        let rising = !self.clock && val & 0x40 != 0;
+        self.select = val & 0x80 != 0;
+        self.clock = val & 0x40 != 0;
+        self.data_in = val & 0x02 != 0;
+        if !self.select {
+            self.state = EepromState::Idle;
+        } else if rising {
+            self.clock_bit();
+        }
+    }
+
+    fn clock_bit(&mut self) {
+        let bit = self.data_in as u16;
+        self.state = match self.state {
+            EepromState::Idle if bit == 1 => EepromState::Command { bits: 0, count: 0 },
+            EepromState::Idle => EepromState::Idle,
+            EepromState::Command { bits, count } if count < 9 => EepromState::Command {
+                bits: bits << 1 | bit,
+                count: count + 1,
+            },
+            EepromState::Command { bits, .. } => self.run_command(bits << 1 | bit),
+            EepromState::Reading { word, bits_left } => {
+                self.data_out = word & 0x8000 != 0;
+                if bits_left > 1 {
+                    EepromState::Reading {
+                        word: word << 1,
+                        bits_left: bits_left - 1,
+                    }
+                } else {
+                    EepromState::Idle
+                }
+            }
+            EepromState::Writing {
+                address,
+                word,
+                count,
+            } => {
+                let word = word << 1 | bit;
+                if count < 15 {
+                    EepromState::Writing {
+                        address,
+                        word,
+                        count: count + 1,
+                    }
+                } else {
+                    self.finish_write(address, word);
+                    EepromState::Idle
+                }
+            }
+        };
+    }
+
+    fn finish_write(&mut self, address: Option<u8>, word: u16) {
+        if self.write_enabled {
+            match address {
+                Some(address) => self.words[address as usize] = word,
+                None => self.words = [word; EEPROM_WORDS],
+            }
+        }
+        // Reports the write finished straight away
+        self.data_out = true;
+    }
+
+    /// Runs a command from its 2 opcode bits and 8 address bits, returning the state after
+    fn run_command(&mut self, bits: u16) -> EepromState {
+        // The top address bit isn't used with 128 words
+        let address = (bits & 0x7F) as u8;
+        match (bits >> 8, (bits >> 6) & 0x3) {
+            // READ, which starts with a dummy 0 bit
+            (0b10, _) => {
+                self.data_out = false;
+                EepromState::Reading {
+                    word: self.words[address as usize],
+                    bits_left: 16,
+                }
+            }
+            // WRITE
+            (0b01, _) => EepromState::Writing {
+                address: Some(address),
+                word: 0,
+                count: 0,
+            },
+            // ERASE
+            (0b11, _) => {
+                if self.write_enabled {
+                    self.words[address as usize] = 0xFFFF;
+                }
+                self.data_out = true;
+                EepromState::Idle
+            }
+            // EWDS
+            (0b00, 0b00) => {
+                self.write_enabled = false;
+                EepromState::Idle
+            }
+            // WRAL
+            (0b00, 0b01) => EepromState::Writing {
+                address: None,
+                word: 0,
+                count: 0,
+            },
+            // ERAL
+            (0b00, 0b10) => {
+                if self.write_enabled {
+                    self.words = [0xFFFF; EEPROM_WORDS];
+                }
+                self.data_out = true;
+                EepromState::Idle
+            }
+            // EWEN
+            _ => {
+                self.write_enabled = true;
+                EepromState::Idle
+            }
+        }
+    }
+}
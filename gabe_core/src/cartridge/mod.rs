@@ -1,11 +1,18 @@
+pub mod camera;
 pub mod mbc0;
 pub mod mbc1;
 pub mod mbc2;
 pub mod mbc3;
+pub mod mbc5;
+pub mod mbc7;
 
 use alloc::boxed::Box;
 use alloc::fmt;
-use alloc::string::String;
+use alloc::string::{String, ToString};
+
+use crate::camera::CameraSensor;
+use crate::error::GabeError;
+use crate::rtc::RtcClock;
 
 /// Error type representing possible errors when using cartridge functions.
 #[derive(Debug)]
@@ -24,10 +31,25 @@ impl fmt::Display for CartridgeError {
     }
 }
 
+/// Checks a ROM fits in a cartridge holding up to `max_size` bytes, and pads it with 0xFF up
+/// to the `size` given by its header, so a ROM shorter than its header says, such as a
+/// truncated dump, can still be read anywhere
+fn fit_rom(rom: Box<[u8]>, size: usize, max_size: usize) -> Result<Box<[u8]>, GabeError> {
+    if rom.len() > max_size {
+        return Err(GabeError::InvalidRomLength(rom.len()));
+    }
+    if rom.len() >= size {
+        return Ok(rom);
+    }
+    let mut rom = rom.into_vec();
+    rom.resize(size, 0xFF);
+    Ok(rom.into_boxed_slice())
+}
+
 /// Trait representing the functionality that a Gameboy cartridge can perform for the rest of the system.
 /// Contains all possible functions for a cartridge, but different Memory Bank Controllers (MBCs) may not
 /// support any given function, in which case an error will be returned.
-pub trait Cartridge: super::mmu::Memory {
+pub trait Cartridge: super::mmu::Memory + Send {
     /// Writes the current content of the Cartridge's battery-backed RAM into the provided
     /// file location. If not supported by the cartridge or fails to write to the location,
     /// returns CartridgeError.
@@ -37,4 +59,55 @@ pub trait Cartridge: super::mmu::Memory {
     /// file location. If not supported by the cartridge or fails to write to the location,
     /// returns CartridgeError.
     fn write_save_data(&self) -> Result<Box<[u8]>, CartridgeError>;
+
+    /// Returns the MBC's registers to their power-on values, as when the Gameboy is reset.
+    /// The ROM and RAM contents are kept.
+    fn reset(&mut self);
+
+    /// Returns the ROM bank currently mapped into 0x4000-0x7FFF
+    fn rom_bank(&self) -> u16;
+
+    /// Returns the RAM bank currently mapped into 0xA000-0xBFFF. Cartridges without
+    /// banked RAM always report bank 0.
+    fn ram_bank(&self) -> u8;
+
+    /// Returns the whole ROM, every bank, as padded when the cartridge was powered on
+    fn rom(&self) -> &[u8];
+
+    /// Returns the cartridge's RAM, every bank, whether or not it's enabled or mapped.
+    /// Cartridges without RAM mapped into 0xA000-0xBFFF return an empty slice.
+    fn ram(&self) -> &[u8] {
+        &[]
+    }
+
+    /// Runs any hardware on the cartridge that takes time, for `cycles` cycles. Most
+    /// cartridges have none.
+    fn update(&mut self, _cycles: u32) {}
+
+    /// Returns true while the cartridge's rumble motor is running. Cartridges without a motor
+    /// never rumble.
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    /// Tilts the cartridge's accelerometer, in g: 1.0 is as if stood on its side. Positive
+    /// x tilts the right side down, and positive y the top away from the player. Cartridges
+    /// without an accelerometer ignore it.
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+
+    /// Replaces the clock that the cartridge's real-time clock keeps time by. If the cartridge
+    /// has no RTC, returns CartridgeError.
+    fn connect_rtc_clock(&mut self, _clock: Box<dyn RtcClock>) -> Result<(), CartridgeError> {
+        Err(CartridgeError::Unsupported(
+            "Game doesn't have a real-time clock.".to_string(),
+        ))
+    }
+
+    /// Connects the image sensor that a Game Boy Camera takes its pictures with. If the
+    /// cartridge has no camera, returns CartridgeError.
+    fn connect_camera(&mut self, _sensor: Box<dyn CameraSensor>) -> Result<(), CartridgeError> {
+        Err(CartridgeError::Unsupported(
+            "Game isn't a Game Boy Camera.".to_string(),
+        ))
+    }
 }
@@ -0,0 +1,231 @@
+//! Game Genie and GameShark cheat codes
+
+use alloc::collections::BTreeMap;
+use alloc::fmt;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// Error type for cheat codes that can't be parsed
+#[derive(Debug)]
+pub enum CheatError {
+    /// The code isn't in a Game Genie or GameShark format
+    InvalidFormat(String),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CheatError::InvalidFormat(ref s) => write!(f, "Invalid cheat code: {}", s),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CheatError {}
+
+/// A decoded cheat code
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CheatCode {
+    /// Replaces the byte read from a ROM address, as a Game Genie does. With a compare value,
+    /// only reads of that original value are replaced, so other banks mapped at the same
+    /// address aren't affected.
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Writes a value to a RAM address every frame, as a GameShark does
+    GameShark { address: u16, value: u8 },
+}
+
+impl CheatCode {
+    /// Parses a Game Genie code, `ABC-DEF` or `ABC-DEF-GHI`, or an 8 digit GameShark code,
+    /// `01VVAAAA`. Dashes and spaces are ignored.
+    pub fn parse(code: &str) -> Result<CheatCode, CheatError> {
+        let invalid = || CheatError::InvalidFormat(String::from(code));
+        let mut digits = [0u8; 9];
+        let mut len = 0;
+        for c in code.chars().filter(|c| *c != '-' && *c != ' ') {
+            let digit = c.to_digit(16).ok_or_else(invalid)?;
+            *digits.get_mut(len).ok_or_else(invalid)? = digit as u8;
+            len += 1;
+        }
+        let d = |i: usize| digits[i] as u16;
+        match len {
+            6 | 9 => {
+                let value = (d(0) << 4 | d(1)) as u8;
+                let address = (d(5) ^ 0xF) << 12 | d(2) << 8 | d(3) << 4 | d(4);
+                // The third digit of the last group isn't used, it only obscures the code
+                let compare = (len == 9).then(|| ((d(6) << 4 | d(8)) as u8).rotate_right(2) ^ 0xBA);
+                Ok(CheatCode::GameGenie {
+                    address,
+                    value,
+                    compare,
+                })
+            }
+            8 => {
+                // The first byte selects the RAM bank on a CGB, which isn't emulated
+                let value = (d(2) << 4 | d(3)) as u8;
+                let address = d(6) << 12 | d(7) << 8 | d(4) << 4 | d(5);
+                Ok(CheatCode::GameShark { address, value })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// A cheat loaded into the emulator, which can be turned on and off
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cheat {
+    pub code: CheatCode,
+    /// Description shown to the user
+    pub label: String,
+    pub enabled: bool,
+}
+
+/// Parses a RetroArch cheat file (`.cht`), as shared by libretro cores, such as
+///
+/// ```text
+/// cheats = 2
+///
+/// cheat0_desc = "Infinite lives"
+/// cheat0_code = "00A-17B-C49"
+/// cheat0_enable = true
+///
+/// cheat1_desc = "Max money"
+/// cheat1_code = "019947C1+019948C1"
+/// cheat1_enable = false
+/// ```
+///
+/// Entries are numbered from 0, and one with several codes joined by `+` gives a cheat for
+/// each, all with its description. Entries are disabled unless `enable` is true, and those
+/// without a code, such as ones only using RetroArch's own memory search, are skipped.
+pub fn parse_cht(text: &str) -> Result<Vec<Cheat>, CheatError> {
+    #[derive(Default)]
+    struct Entry<'a> {
+        desc: &'a str,
+        code: &'a str,
+        enabled: bool,
+    }
+    let mut entries: BTreeMap<usize, Entry> = BTreeMap::new();
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"');
+        let Some((index, field)) = key
+            .trim()
+            .strip_prefix("cheat")
+            .and_then(|key| key.split_once('_'))
+        else {
+            continue;
+        };
+        let Ok(index) = index.parse() else {
+            continue;
+        };
+        let entry = entries.entry(index).or_default();
+        match field {
+            "desc" => entry.desc = value,
+            "code" => entry.code = value,
+            "enable" => entry.enabled = value == "true",
+            _ => {}
+        }
+    }
+    let mut cheats = Vec::new();
+    for entry in entries.into_values() {
+        for code in entry.code.split('+').filter(|code| !code.trim().is_empty()) {
+            let label = if entry.desc.is_empty() {
+                code.trim()
+            } else {
+                entry.desc
+            };
+            cheats.push(Cheat {
+                code: CheatCode::parse(code)?,
+                label: label.to_string(),
+                enabled: entry.enabled,
+            });
+        }
+    }
+    Ok(cheats)
+}
+
+#[cfg(test)]
+mod cheats_tests {
+    use super::{parse_cht, CheatCode};
+
+    #[test]
+    fn game_genie() {
+        assert_eq!(
+            CheatCode::parse("00A-17B-C49").unwrap(),
+            CheatCode::GameGenie {
+                address: 0x4A17,
+                value: 0x00,
+                compare: Some(0xC8),
+            }
+        );
+        assert_eq!(
+            CheatCode::parse("3EB-B2F").unwrap(),
+            CheatCode::GameGenie {
+                address: 0x0BB2,
+                value: 0x3E,
+                compare: None,
+            }
+        );
+    }
+
+    #[test]
+    fn game_shark() {
+        assert_eq!(
+            CheatCode::parse("010238CD").unwrap(),
+            CheatCode::GameShark {
+                address: 0xCD38,
+                value: 0x02,
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_codes() {
+        assert!(CheatCode::parse("").is_err());
+        assert!(CheatCode::parse("00A-17B-C").is_err());
+        assert!(CheatCode::parse("0102 38CG").is_err());
+        assert!(CheatCode::parse("00A-17B-C49-0").is_err());
+    }
+
+    #[test]
+    fn cht_file() {
+        let text = r#"
+cheats = 3
+
+cheat0_desc = "Infinite lives"
+cheat0_code = "00A-17B-C49"
+cheat0_enable = true
+
+cheat1_desc = "Max money"
+cheat1_code = "019947C1+019948C1"
+cheat1_enable = false
+
+cheat2_desc = "Found by memory search"
+cheat2_code = ""
+cheat2_address = "49863"
+"#;
+        let cheats = parse_cht(text).unwrap();
+        assert_eq!(cheats.len(), 3);
+        assert_eq!(cheats[0].code, CheatCode::parse("00A-17B-C49").unwrap());
+        assert_eq!(cheats[0].label, "Infinite lives");
+        assert!(cheats[0].enabled);
+        assert_eq!(
+            cheats[2].code,
+            CheatCode::GameShark {
+                address: 0xC148,
+                value: 0x99,
+            }
+        );
+        assert_eq!(cheats[2].label, "Max money");
+        assert!(!cheats[2].enabled);
+
+        assert!(parse_cht("cheat0_code = \"XYZ\"").is_err());
+    }
+}
@@ -1,5 +1,7 @@
+use super::error::GabeError;
 use super::mmu::InterruptKind;
 use super::mmu::Memory;
+use super::opcodes::{BRANCH_CYCLES, CB_CYCLES, CYCLES};
 use alloc::fmt::*;
 
 /// The register F holds flag information that are set by ALU
@@ -28,6 +30,7 @@ enum Flag {
 /// - PC (Program Counter)
 /// - SP (Stack Pointer)
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Registers {
     pub a: u8,
     pub f: u8,
@@ -126,62 +129,69 @@ impl Registers {
     }
 }
 
-/// Tables of opcode cycle counts.
+/// Runs one opcode, already fetched, returning the number of cycles taken
+type Handler = fn(&mut Cpu, &mut dyn Memory) -> u32;
+
+/// Handlers for each opcode, indexed by the opcode. Each is `Cpu::execute` specialized to its
+/// opcode, so the decode is done once at compile time instead of matching on every tick.
 /// Skipped when running rustfmt
 #[rustfmt::skip]
-const OPCODE_TABLE: [u32; 256] = [
-//  0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
-    4,12, 8, 8, 4, 4, 8, 4,20, 8, 8, 8, 4, 4, 8, 4, // 0
-    4,12, 8, 8, 4, 4, 8, 4,12, 8, 8, 8, 4, 4, 8, 4, // 1
-    8,12, 8, 8, 4, 4, 8, 4, 8, 8, 8, 8, 4, 4, 8, 4, // 2
-    8,12, 8, 8,12,12,12, 4, 8, 8, 8, 8, 4, 4, 8, 4, // 3
-    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 4
-    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 5
-    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 6
-    8, 8, 8, 8, 8, 8, 4, 8, 4, 4, 4, 4, 4, 4, 8, 4, // 7
-    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 8
-    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 9
-    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // A
-    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // B
-    8,12,12,16,12,16, 8,16, 8,16,12, 4,12,24, 8,16, // C
-    8,12,12, 0,12,16, 8,16, 8,16,12, 0,12, 0, 8,16, // D
-   12,12, 8, 0, 0,16, 8,16,16, 4,16, 0, 0, 0, 8,16, // E
-   12,12, 8, 4, 0,16, 8,16,12, 8,16, 4, 0, 0, 8,16, // F
+const HANDLERS: [Handler; 256] = [
+    Cpu::handler::<0x00>, Cpu::handler::<0x01>, Cpu::handler::<0x02>, Cpu::handler::<0x03>, Cpu::handler::<0x04>, Cpu::handler::<0x05>, Cpu::handler::<0x06>, Cpu::handler::<0x07>, Cpu::handler::<0x08>, Cpu::handler::<0x09>, Cpu::handler::<0x0A>, Cpu::handler::<0x0B>, Cpu::handler::<0x0C>, Cpu::handler::<0x0D>, Cpu::handler::<0x0E>, Cpu::handler::<0x0F>,
+    Cpu::handler::<0x10>, Cpu::handler::<0x11>, Cpu::handler::<0x12>, Cpu::handler::<0x13>, Cpu::handler::<0x14>, Cpu::handler::<0x15>, Cpu::handler::<0x16>, Cpu::handler::<0x17>, Cpu::handler::<0x18>, Cpu::handler::<0x19>, Cpu::handler::<0x1A>, Cpu::handler::<0x1B>, Cpu::handler::<0x1C>, Cpu::handler::<0x1D>, Cpu::handler::<0x1E>, Cpu::handler::<0x1F>,
+    Cpu::handler::<0x20>, Cpu::handler::<0x21>, Cpu::handler::<0x22>, Cpu::handler::<0x23>, Cpu::handler::<0x24>, Cpu::handler::<0x25>, Cpu::handler::<0x26>, Cpu::handler::<0x27>, Cpu::handler::<0x28>, Cpu::handler::<0x29>, Cpu::handler::<0x2A>, Cpu::handler::<0x2B>, Cpu::handler::<0x2C>, Cpu::handler::<0x2D>, Cpu::handler::<0x2E>, Cpu::handler::<0x2F>,
+    Cpu::handler::<0x30>, Cpu::handler::<0x31>, Cpu::handler::<0x32>, Cpu::handler::<0x33>, Cpu::handler::<0x34>, Cpu::handler::<0x35>, Cpu::handler::<0x36>, Cpu::handler::<0x37>, Cpu::handler::<0x38>, Cpu::handler::<0x39>, Cpu::handler::<0x3A>, Cpu::handler::<0x3B>, Cpu::handler::<0x3C>, Cpu::handler::<0x3D>, Cpu::handler::<0x3E>, Cpu::handler::<0x3F>,
+    Cpu::handler::<0x40>, Cpu::handler::<0x41>, Cpu::handler::<0x42>, Cpu::handler::<0x43>, Cpu::handler::<0x44>, Cpu::handler::<0x45>, Cpu::handler::<0x46>, Cpu::handler::<0x47>, Cpu::handler::<0x48>, Cpu::handler::<0x49>, Cpu::handler::<0x4A>, Cpu::handler::<0x4B>, Cpu::handler::<0x4C>, Cpu::handler::<0x4D>, Cpu::handler::<0x4E>, Cpu::handler::<0x4F>,
+    Cpu::handler::<0x50>, Cpu::handler::<0x51>, Cpu::handler::<0x52>, Cpu::handler::<0x53>, Cpu::handler::<0x54>, Cpu::handler::<0x55>, Cpu::handler::<0x56>, Cpu::handler::<0x57>, Cpu::handler::<0x58>, Cpu::handler::<0x59>, Cpu::handler::<0x5A>, Cpu::handler::<0x5B>, Cpu::handler::<0x5C>, Cpu::handler::<0x5D>, Cpu::handler::<0x5E>, Cpu::handler::<0x5F>,
+    Cpu::handler::<0x60>, Cpu::handler::<0x61>, Cpu::handler::<0x62>, Cpu::handler::<0x63>, Cpu::handler::<0x64>, Cpu::handler::<0x65>, Cpu::handler::<0x66>, Cpu::handler::<0x67>, Cpu::handler::<0x68>, Cpu::handler::<0x69>, Cpu::handler::<0x6A>, Cpu::handler::<0x6B>, Cpu::handler::<0x6C>, Cpu::handler::<0x6D>, Cpu::handler::<0x6E>, Cpu::handler::<0x6F>,
+    Cpu::handler::<0x70>, Cpu::handler::<0x71>, Cpu::handler::<0x72>, Cpu::handler::<0x73>, Cpu::handler::<0x74>, Cpu::handler::<0x75>, Cpu::handler::<0x76>, Cpu::handler::<0x77>, Cpu::handler::<0x78>, Cpu::handler::<0x79>, Cpu::handler::<0x7A>, Cpu::handler::<0x7B>, Cpu::handler::<0x7C>, Cpu::handler::<0x7D>, Cpu::handler::<0x7E>, Cpu::handler::<0x7F>,
+    Cpu::handler::<0x80>, Cpu::handler::<0x81>, Cpu::handler::<0x82>, Cpu::handler::<0x83>, Cpu::handler::<0x84>, Cpu::handler::<0x85>, Cpu::handler::<0x86>, Cpu::handler::<0x87>, Cpu::handler::<0x88>, Cpu::handler::<0x89>, Cpu::handler::<0x8A>, Cpu::handler::<0x8B>, Cpu::handler::<0x8C>, Cpu::handler::<0x8D>, Cpu::handler::<0x8E>, Cpu::handler::<0x8F>,
+    Cpu::handler::<0x90>, Cpu::handler::<0x91>, Cpu::handler::<0x92>, Cpu::handler::<0x93>, Cpu::handler::<0x94>, Cpu::handler::<0x95>, Cpu::handler::<0x96>, Cpu::handler::<0x97>, Cpu::handler::<0x98>, Cpu::handler::<0x99>, Cpu::handler::<0x9A>, Cpu::handler::<0x9B>, Cpu::handler::<0x9C>, Cpu::handler::<0x9D>, Cpu::handler::<0x9E>, Cpu::handler::<0x9F>,
+    Cpu::handler::<0xA0>, Cpu::handler::<0xA1>, Cpu::handler::<0xA2>, Cpu::handler::<0xA3>, Cpu::handler::<0xA4>, Cpu::handler::<0xA5>, Cpu::handler::<0xA6>, Cpu::handler::<0xA7>, Cpu::handler::<0xA8>, Cpu::handler::<0xA9>, Cpu::handler::<0xAA>, Cpu::handler::<0xAB>, Cpu::handler::<0xAC>, Cpu::handler::<0xAD>, Cpu::handler::<0xAE>, Cpu::handler::<0xAF>,
+    Cpu::handler::<0xB0>, Cpu::handler::<0xB1>, Cpu::handler::<0xB2>, Cpu::handler::<0xB3>, Cpu::handler::<0xB4>, Cpu::handler::<0xB5>, Cpu::handler::<0xB6>, Cpu::handler::<0xB7>, Cpu::handler::<0xB8>, Cpu::handler::<0xB9>, Cpu::handler::<0xBA>, Cpu::handler::<0xBB>, Cpu::handler::<0xBC>, Cpu::handler::<0xBD>, Cpu::handler::<0xBE>, Cpu::handler::<0xBF>,
+    Cpu::handler::<0xC0>, Cpu::handler::<0xC1>, Cpu::handler::<0xC2>, Cpu::handler::<0xC3>, Cpu::handler::<0xC4>, Cpu::handler::<0xC5>, Cpu::handler::<0xC6>, Cpu::handler::<0xC7>, Cpu::handler::<0xC8>, Cpu::handler::<0xC9>, Cpu::handler::<0xCA>, Cpu::handler::<0xCB>, Cpu::handler::<0xCC>, Cpu::handler::<0xCD>, Cpu::handler::<0xCE>, Cpu::handler::<0xCF>,
+    Cpu::handler::<0xD0>, Cpu::handler::<0xD1>, Cpu::handler::<0xD2>, Cpu::handler::<0xD3>, Cpu::handler::<0xD4>, Cpu::handler::<0xD5>, Cpu::handler::<0xD6>, Cpu::handler::<0xD7>, Cpu::handler::<0xD8>, Cpu::handler::<0xD9>, Cpu::handler::<0xDA>, Cpu::handler::<0xDB>, Cpu::handler::<0xDC>, Cpu::handler::<0xDD>, Cpu::handler::<0xDE>, Cpu::handler::<0xDF>,
+    Cpu::handler::<0xE0>, Cpu::handler::<0xE1>, Cpu::handler::<0xE2>, Cpu::handler::<0xE3>, Cpu::handler::<0xE4>, Cpu::handler::<0xE5>, Cpu::handler::<0xE6>, Cpu::handler::<0xE7>, Cpu::handler::<0xE8>, Cpu::handler::<0xE9>, Cpu::handler::<0xEA>, Cpu::handler::<0xEB>, Cpu::handler::<0xEC>, Cpu::handler::<0xED>, Cpu::handler::<0xEE>, Cpu::handler::<0xEF>,
+    Cpu::handler::<0xF0>, Cpu::handler::<0xF1>, Cpu::handler::<0xF2>, Cpu::handler::<0xF3>, Cpu::handler::<0xF4>, Cpu::handler::<0xF5>, Cpu::handler::<0xF6>, Cpu::handler::<0xF7>, Cpu::handler::<0xF8>, Cpu::handler::<0xF9>, Cpu::handler::<0xFA>, Cpu::handler::<0xFB>, Cpu::handler::<0xFC>, Cpu::handler::<0xFD>, Cpu::handler::<0xFE>, Cpu::handler::<0xFF>,
 ];
 
-/// Tables of opcode cycle counts for extended opcodes.
+/// Handlers for each extended opcode, following a CB prefix
 /// Skipped when running rustfmt
 #[rustfmt::skip]
-const OPCODE_CB_TABLE: [u32; 256] = [
-//  0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 0
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 1
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 2
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 3
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 4
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 5
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 6
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 7
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 8
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 9
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // A
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // B
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // C
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // D
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // E
-    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // F
+const CB_HANDLERS: [Handler; 256] = [
+    Cpu::cb_handler::<0x00>, Cpu::cb_handler::<0x01>, Cpu::cb_handler::<0x02>, Cpu::cb_handler::<0x03>, Cpu::cb_handler::<0x04>, Cpu::cb_handler::<0x05>, Cpu::cb_handler::<0x06>, Cpu::cb_handler::<0x07>, Cpu::cb_handler::<0x08>, Cpu::cb_handler::<0x09>, Cpu::cb_handler::<0x0A>, Cpu::cb_handler::<0x0B>, Cpu::cb_handler::<0x0C>, Cpu::cb_handler::<0x0D>, Cpu::cb_handler::<0x0E>, Cpu::cb_handler::<0x0F>,
+    Cpu::cb_handler::<0x10>, Cpu::cb_handler::<0x11>, Cpu::cb_handler::<0x12>, Cpu::cb_handler::<0x13>, Cpu::cb_handler::<0x14>, Cpu::cb_handler::<0x15>, Cpu::cb_handler::<0x16>, Cpu::cb_handler::<0x17>, Cpu::cb_handler::<0x18>, Cpu::cb_handler::<0x19>, Cpu::cb_handler::<0x1A>, Cpu::cb_handler::<0x1B>, Cpu::cb_handler::<0x1C>, Cpu::cb_handler::<0x1D>, Cpu::cb_handler::<0x1E>, Cpu::cb_handler::<0x1F>,
+    Cpu::cb_handler::<0x20>, Cpu::cb_handler::<0x21>, Cpu::cb_handler::<0x22>, Cpu::cb_handler::<0x23>, Cpu::cb_handler::<0x24>, Cpu::cb_handler::<0x25>, Cpu::cb_handler::<0x26>, Cpu::cb_handler::<0x27>, Cpu::cb_handler::<0x28>, Cpu::cb_handler::<0x29>, Cpu::cb_handler::<0x2A>, Cpu::cb_handler::<0x2B>, Cpu::cb_handler::<0x2C>, Cpu::cb_handler::<0x2D>, Cpu::cb_handler::<0x2E>, Cpu::cb_handler::<0x2F>,
+    Cpu::cb_handler::<0x30>, Cpu::cb_handler::<0x31>, Cpu::cb_handler::<0x32>, Cpu::cb_handler::<0x33>, Cpu::cb_handler::<0x34>, Cpu::cb_handler::<0x35>, Cpu::cb_handler::<0x36>, Cpu::cb_handler::<0x37>, Cpu::cb_handler::<0x38>, Cpu::cb_handler::<0x39>, Cpu::cb_handler::<0x3A>, Cpu::cb_handler::<0x3B>, Cpu::cb_handler::<0x3C>, Cpu::cb_handler::<0x3D>, Cpu::cb_handler::<0x3E>, Cpu::cb_handler::<0x3F>,
+    Cpu::cb_handler::<0x40>, Cpu::cb_handler::<0x41>, Cpu::cb_handler::<0x42>, Cpu::cb_handler::<0x43>, Cpu::cb_handler::<0x44>, Cpu::cb_handler::<0x45>, Cpu::cb_handler::<0x46>, Cpu::cb_handler::<0x47>, Cpu::cb_handler::<0x48>, Cpu::cb_handler::<0x49>, Cpu::cb_handler::<0x4A>, Cpu::cb_handler::<0x4B>, Cpu::cb_handler::<0x4C>, Cpu::cb_handler::<0x4D>, Cpu::cb_handler::<0x4E>, Cpu::cb_handler::<0x4F>,
+    Cpu::cb_handler::<0x50>, Cpu::cb_handler::<0x51>, Cpu::cb_handler::<0x52>, Cpu::cb_handler::<0x53>, Cpu::cb_handler::<0x54>, Cpu::cb_handler::<0x55>, Cpu::cb_handler::<0x56>, Cpu::cb_handler::<0x57>, Cpu::cb_handler::<0x58>, Cpu::cb_handler::<0x59>, Cpu::cb_handler::<0x5A>, Cpu::cb_handler::<0x5B>, Cpu::cb_handler::<0x5C>, Cpu::cb_handler::<0x5D>, Cpu::cb_handler::<0x5E>, Cpu::cb_handler::<0x5F>,
+    Cpu::cb_handler::<0x60>, Cpu::cb_handler::<0x61>, Cpu::cb_handler::<0x62>, Cpu::cb_handler::<0x63>, Cpu::cb_handler::<0x64>, Cpu::cb_handler::<0x65>, Cpu::cb_handler::<0x66>, Cpu::cb_handler::<0x67>, Cpu::cb_handler::<0x68>, Cpu::cb_handler::<0x69>, Cpu::cb_handler::<0x6A>, Cpu::cb_handler::<0x6B>, Cpu::cb_handler::<0x6C>, Cpu::cb_handler::<0x6D>, Cpu::cb_handler::<0x6E>, Cpu::cb_handler::<0x6F>,
+    Cpu::cb_handler::<0x70>, Cpu::cb_handler::<0x71>, Cpu::cb_handler::<0x72>, Cpu::cb_handler::<0x73>, Cpu::cb_handler::<0x74>, Cpu::cb_handler::<0x75>, Cpu::cb_handler::<0x76>, Cpu::cb_handler::<0x77>, Cpu::cb_handler::<0x78>, Cpu::cb_handler::<0x79>, Cpu::cb_handler::<0x7A>, Cpu::cb_handler::<0x7B>, Cpu::cb_handler::<0x7C>, Cpu::cb_handler::<0x7D>, Cpu::cb_handler::<0x7E>, Cpu::cb_handler::<0x7F>,
+    Cpu::cb_handler::<0x80>, Cpu::cb_handler::<0x81>, Cpu::cb_handler::<0x82>, Cpu::cb_handler::<0x83>, Cpu::cb_handler::<0x84>, Cpu::cb_handler::<0x85>, Cpu::cb_handler::<0x86>, Cpu::cb_handler::<0x87>, Cpu::cb_handler::<0x88>, Cpu::cb_handler::<0x89>, Cpu::cb_handler::<0x8A>, Cpu::cb_handler::<0x8B>, Cpu::cb_handler::<0x8C>, Cpu::cb_handler::<0x8D>, Cpu::cb_handler::<0x8E>, Cpu::cb_handler::<0x8F>,
+    Cpu::cb_handler::<0x90>, Cpu::cb_handler::<0x91>, Cpu::cb_handler::<0x92>, Cpu::cb_handler::<0x93>, Cpu::cb_handler::<0x94>, Cpu::cb_handler::<0x95>, Cpu::cb_handler::<0x96>, Cpu::cb_handler::<0x97>, Cpu::cb_handler::<0x98>, Cpu::cb_handler::<0x99>, Cpu::cb_handler::<0x9A>, Cpu::cb_handler::<0x9B>, Cpu::cb_handler::<0x9C>, Cpu::cb_handler::<0x9D>, Cpu::cb_handler::<0x9E>, Cpu::cb_handler::<0x9F>,
+    Cpu::cb_handler::<0xA0>, Cpu::cb_handler::<0xA1>, Cpu::cb_handler::<0xA2>, Cpu::cb_handler::<0xA3>, Cpu::cb_handler::<0xA4>, Cpu::cb_handler::<0xA5>, Cpu::cb_handler::<0xA6>, Cpu::cb_handler::<0xA7>, Cpu::cb_handler::<0xA8>, Cpu::cb_handler::<0xA9>, Cpu::cb_handler::<0xAA>, Cpu::cb_handler::<0xAB>, Cpu::cb_handler::<0xAC>, Cpu::cb_handler::<0xAD>, Cpu::cb_handler::<0xAE>, Cpu::cb_handler::<0xAF>,
+    Cpu::cb_handler::<0xB0>, Cpu::cb_handler::<0xB1>, Cpu::cb_handler::<0xB2>, Cpu::cb_handler::<0xB3>, Cpu::cb_handler::<0xB4>, Cpu::cb_handler::<0xB5>, Cpu::cb_handler::<0xB6>, Cpu::cb_handler::<0xB7>, Cpu::cb_handler::<0xB8>, Cpu::cb_handler::<0xB9>, Cpu::cb_handler::<0xBA>, Cpu::cb_handler::<0xBB>, Cpu::cb_handler::<0xBC>, Cpu::cb_handler::<0xBD>, Cpu::cb_handler::<0xBE>, Cpu::cb_handler::<0xBF>,
+    Cpu::cb_handler::<0xC0>, Cpu::cb_handler::<0xC1>, Cpu::cb_handler::<0xC2>, Cpu::cb_handler::<0xC3>, Cpu::cb_handler::<0xC4>, Cpu::cb_handler::<0xC5>, Cpu::cb_handler::<0xC6>, Cpu::cb_handler::<0xC7>, Cpu::cb_handler::<0xC8>, Cpu::cb_handler::<0xC9>, Cpu::cb_handler::<0xCA>, Cpu::cb_handler::<0xCB>, Cpu::cb_handler::<0xCC>, Cpu::cb_handler::<0xCD>, Cpu::cb_handler::<0xCE>, Cpu::cb_handler::<0xCF>,
+    Cpu::cb_handler::<0xD0>, Cpu::cb_handler::<0xD1>, Cpu::cb_handler::<0xD2>, Cpu::cb_handler::<0xD3>, Cpu::cb_handler::<0xD4>, Cpu::cb_handler::<0xD5>, Cpu::cb_handler::<0xD6>, Cpu::cb_handler::<0xD7>, Cpu::cb_handler::<0xD8>, Cpu::cb_handler::<0xD9>, Cpu::cb_handler::<0xDA>, Cpu::cb_handler::<0xDB>, Cpu::cb_handler::<0xDC>, Cpu::cb_handler::<0xDD>, Cpu::cb_handler::<0xDE>, Cpu::cb_handler::<0xDF>,
+    Cpu::cb_handler::<0xE0>, Cpu::cb_handler::<0xE1>, Cpu::cb_handler::<0xE2>, Cpu::cb_handler::<0xE3>, Cpu::cb_handler::<0xE4>, Cpu::cb_handler::<0xE5>, Cpu::cb_handler::<0xE6>, Cpu::cb_handler::<0xE7>, Cpu::cb_handler::<0xE8>, Cpu::cb_handler::<0xE9>, Cpu::cb_handler::<0xEA>, Cpu::cb_handler::<0xEB>, Cpu::cb_handler::<0xEC>, Cpu::cb_handler::<0xED>, Cpu::cb_handler::<0xEE>, Cpu::cb_handler::<0xEF>,
+    Cpu::cb_handler::<0xF0>, Cpu::cb_handler::<0xF1>, Cpu::cb_handler::<0xF2>, Cpu::cb_handler::<0xF3>, Cpu::cb_handler::<0xF4>, Cpu::cb_handler::<0xF5>, Cpu::cb_handler::<0xF6>, Cpu::cb_handler::<0xF7>, Cpu::cb_handler::<0xF8>, Cpu::cb_handler::<0xF9>, Cpu::cb_handler::<0xFA>, Cpu::cb_handler::<0xFB>, Cpu::cb_handler::<0xFC>, Cpu::cb_handler::<0xFD>, Cpu::cb_handler::<0xFE>, Cpu::cb_handler::<0xFF>,
 ];
 
 /// The CPU contains Register state and is responsible for
 /// decoding each opcode at the current PC and updating
 /// the Registers and MMU when appropriate.
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cpu {
     pub reg: Registers,
     pub ime: bool,
     pub next_ime: bool,
     pub halted: bool,
     pub stopped: bool,
+    /// The interrupt serviced during the last `tick`, if any
+    pub serviced_interrupt: Option<InterruptKind>,
+    /// Set when the CPU has locked up, after running an illegal opcode
+    pub fault: Option<GabeError>,
 }
 
 impl Display for Cpu {
@@ -235,6 +245,8 @@ impl Cpu {
             next_ime: false,
             halted: false,
             stopped: false,
+            serviced_interrupt: None,
+            fault: None,
         }
     }
 
@@ -303,6 +315,10 @@ impl Cpu {
                     self.stack_push(mmu, self.reg.pc);
                     self.reg.pc = 0x60;
                 }
+                // Track which interrupt was serviced, matching the priority order above
+                self.serviced_interrupt = InterruptKind::ALL
+                    .into_iter()
+                    .find(|k| interrupt_result & *k as u8 != 0x0);
                 // We're executing a interrupt procedure, disable all interrupts and
                 // return cycles matching an interrupt service
                 self.ime = false;
@@ -316,13 +332,18 @@ impl Cpu {
     /// appropriate function, and executes the functionality.
     /// Returns the number of cycles executed.
     pub fn tick(&mut self, mmu: &mut dyn Memory) -> u32 {
+        self.serviced_interrupt = None;
+        if self.fault.is_some() {
+            // Locked up until the Gameboy is turned off, with time passing as normal
+            return CYCLES[0];
+        }
         if self.stopped {
             // Reset DIV
             mmu.write_byte(0xFF04, 0x0);
             if !(mmu.read_byte(0xFF00) | 0xF0) != 0x0 {
                 self.stopped = false;
             }
-            return OPCODE_TABLE[0];
+            return CYCLES[0];
         }
         if self.ime || self.halted {
             // If CPU is halted or IME is enabled, check if there's any interrupts to execute
@@ -334,16 +355,31 @@ impl Cpu {
 
         if self.halted {
             // Check if still halted after running interrupt checks
-            return OPCODE_TABLE[0];
+            return CYCLES[0];
         }
 
-        let mut opcode = self.imm(mmu);
+        let opcode = self.imm(mmu);
         // Set the IME on the cycle after it changes, delaying any interrupt handling.
         self.ime = self.next_ime;
-        let mut using_cb: bool = false;
+        HANDLERS[opcode as usize](self, mmu)
+    }
+
+    /// `execute` for a single opcode, with its match folded away by the compiler
+    fn handler<const OPCODE: u8>(&mut self, mmu: &mut dyn Memory) -> u32 {
+        self.execute(OPCODE, mmu)
+    }
+
+    /// `execute_cb` for a single extended opcode
+    fn cb_handler<const OPCODE: u8>(&mut self, mmu: &mut dyn Memory) -> u32 {
+        self.execute_cb(OPCODE, mmu)
+    }
+
+    /// Executes an opcode already fetched from PC, returning the number of cycles taken
+    #[inline(always)]
+    fn execute(&mut self, opcode: u8, mmu: &mut dyn Memory) -> u32 {
         // Use more cycles when following conditional branches,
         // set when conditionals are met.
-        let mut cond_cycles = 0;
+        let mut branched = false;
         match opcode {
             // NOP
             0x00 => (),
@@ -795,28 +831,28 @@ impl Cpu {
                 let a = self.imm_word(mmu);
                 if !self.reg.get_flag(Flag::Z) {
                     self.reg.pc = a;
-                    cond_cycles = 4;
+                    branched = true;
                 }
             }
             0xD2 => {
                 let a = self.imm_word(mmu);
                 if !self.reg.get_flag(Flag::C) {
                     self.reg.pc = a;
-                    cond_cycles = 4;
+                    branched = true;
                 }
             }
             0xCA => {
                 let a = self.imm_word(mmu);
                 if self.reg.get_flag(Flag::Z) {
                     self.reg.pc = a;
-                    cond_cycles = 4;
+                    branched = true;
                 }
             }
             0xDA => {
                 let a = self.imm_word(mmu);
                 if self.reg.get_flag(Flag::C) {
                     self.reg.pc = a;
-                    cond_cycles = 4;
+                    branched = true;
                 }
             }
 
@@ -829,28 +865,28 @@ impl Cpu {
                 let a = self.imm(mmu) as i8;
                 if !self.reg.get_flag(Flag::Z) {
                     self.reg.pc = self.reg.pc.wrapping_add(a as u16);
-                    cond_cycles = 4;
+                    branched = true;
                 }
             }
             0x30 => {
                 let a = self.imm(mmu) as i8;
                 if !self.reg.get_flag(Flag::C) {
                     self.reg.pc = self.reg.pc.wrapping_add(a as u16);
-                    cond_cycles = 4;
+                    branched = true;
                 }
             }
             0x28 => {
                 let a = self.imm(mmu) as i8;
                 if self.reg.get_flag(Flag::Z) {
                     self.reg.pc = self.reg.pc.wrapping_add(a as u16);
-                    cond_cycles = 4;
+                    branched = true;
                 }
             }
             0x38 => {
                 let a = self.imm(mmu) as i8;
                 if self.reg.get_flag(Flag::C) {
                     self.reg.pc = self.reg.pc.wrapping_add(a as u16);
-                    cond_cycles = 4;
+                    branched = true;
                 }
             }
 
@@ -865,7 +901,7 @@ impl Cpu {
                 if !self.reg.get_flag(Flag::Z) {
                     self.stack_push(mmu, self.reg.pc);
                     self.reg.pc = a;
-                    cond_cycles = 12;
+                    branched = true;
                 }
             }
             0xCC => {
@@ -873,7 +909,7 @@ impl Cpu {
                 if self.reg.get_flag(Flag::Z) {
                     self.stack_push(mmu, self.reg.pc);
                     self.reg.pc = a;
-                    cond_cycles = 12;
+                    branched = true;
                 }
             }
             0xD4 => {
@@ -881,7 +917,7 @@ impl Cpu {
                 if !self.reg.get_flag(Flag::C) {
                     self.stack_push(mmu, self.reg.pc);
                     self.reg.pc = a;
-                    cond_cycles = 12;
+                    branched = true;
                 }
             }
             0xDC => {
@@ -889,7 +925,7 @@ impl Cpu {
                 if self.reg.get_flag(Flag::C) {
                     self.stack_push(mmu, self.reg.pc);
                     self.reg.pc = a;
-                    cond_cycles = 12;
+                    branched = true;
                 }
             }
 
@@ -902,28 +938,28 @@ impl Cpu {
                 if !self.reg.get_flag(Flag::Z) {
                     let a = self.stack_pop(mmu);
                     self.reg.pc = a;
-                    cond_cycles = 12;
+                    branched = true;
                 }
             }
             0xC8 => {
                 if self.reg.get_flag(Flag::Z) {
                     let a = self.stack_pop(mmu);
                     self.reg.pc = a;
-                    cond_cycles = 12;
+                    branched = true;
                 }
             }
             0xD0 => {
                 if !self.reg.get_flag(Flag::C) {
                     let a = self.stack_pop(mmu);
                     self.reg.pc = a;
-                    cond_cycles = 12;
+                    branched = true;
                 }
             }
             0xD8 => {
                 if self.reg.get_flag(Flag::C) {
                     let a = self.stack_pop(mmu);
                     self.reg.pc = a;
-                    cond_cycles = 12;
+                    branched = true;
                 }
             }
 
@@ -998,522 +1034,534 @@ impl Cpu {
 
             // CB Prefix
             0xCB => {
-                opcode = self.imm(mmu);
-                using_cb = true;
-                match opcode {
-                    0x00 => {
-                        let v = self.rlc(self.reg.b);
-                        self.reg.b = v;
-                    }
-                    0x01 => {
-                        let v = self.rlc(self.reg.c);
-                        self.reg.c = v;
-                    }
-                    0x02 => {
-                        let v = self.rlc(self.reg.d);
-                        self.reg.d = v;
-                    }
-                    0x03 => {
-                        let v = self.rlc(self.reg.e);
-                        self.reg.e = v;
-                    }
-                    0x04 => {
-                        let v = self.rlc(self.reg.h);
-                        self.reg.h = v;
-                    }
-                    0x05 => {
-                        let v = self.rlc(self.reg.l);
-                        self.reg.l = v;
-                    }
-                    0x06 => {
-                        let hl = mmu.read_byte(self.reg.get_hl());
-                        let v = self.rlc(hl);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0x07 => {
-                        let v = self.rlc(self.reg.a);
-                        self.reg.a = v;
-                    }
-                    0x08 => {
-                        let v = self.rrc(self.reg.b);
-                        self.reg.b = v;
-                    }
-                    0x09 => {
-                        let v = self.rrc(self.reg.c);
-                        self.reg.c = v;
-                    }
-                    0x0A => {
-                        let v = self.rrc(self.reg.d);
-                        self.reg.d = v;
-                    }
-                    0x0B => {
-                        let v = self.rrc(self.reg.e);
-                        self.reg.e = v;
-                    }
-                    0x0C => {
-                        let v = self.rrc(self.reg.h);
-                        self.reg.h = v;
-                    }
-                    0x0D => {
-                        let v = self.rrc(self.reg.l);
-                        self.reg.l = v;
-                    }
-                    0x0E => {
-                        let hl = mmu.read_byte(self.reg.get_hl());
-                        let v = self.rrc(hl);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0x0F => {
-                        let v = self.rrc(self.reg.a);
-                        self.reg.a = v;
-                    }
-                    0x10 => {
-                        let v = self.rl(self.reg.b);
-                        self.reg.b = v;
-                    }
-                    0x11 => {
-                        let v = self.rl(self.reg.c);
-                        self.reg.c = v;
-                    }
-                    0x12 => {
-                        let v = self.rl(self.reg.d);
-                        self.reg.d = v;
-                    }
-                    0x13 => {
-                        let v = self.rl(self.reg.e);
-                        self.reg.e = v;
-                    }
-                    0x14 => {
-                        let v = self.rl(self.reg.h);
-                        self.reg.h = v;
-                    }
-                    0x15 => {
-                        let v = self.rl(self.reg.l);
-                        self.reg.l = v;
-                    }
-                    0x16 => {
-                        let hl = mmu.read_byte(self.reg.get_hl());
-                        let v = self.rl(hl);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0x17 => {
-                        let v = self.rl(self.reg.a);
-                        self.reg.a = v;
-                    }
-                    0x18 => {
-                        let v = self.rr(self.reg.b);
-                        self.reg.b = v;
-                    }
-                    0x19 => {
-                        let v = self.rr(self.reg.c);
-                        self.reg.c = v;
-                    }
-                    0x1A => {
-                        let v = self.rr(self.reg.d);
-                        self.reg.d = v;
-                    }
-                    0x1B => {
-                        let v = self.rr(self.reg.e);
-                        self.reg.e = v;
-                    }
-                    0x1C => {
-                        let v = self.rr(self.reg.h);
-                        self.reg.h = v;
-                    }
-                    0x1D => {
-                        let v = self.rr(self.reg.l);
-                        self.reg.l = v;
-                    }
-                    0x1E => {
-                        let hl = mmu.read_byte(self.reg.get_hl());
-                        let v = self.rr(hl);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0x1F => {
-                        let v = self.rr(self.reg.a);
-                        self.reg.a = v;
-                    }
-                    0x20 => {
-                        let v = self.sla(self.reg.b);
-                        self.reg.b = v;
-                    }
-                    0x21 => {
-                        let v = self.sla(self.reg.c);
-                        self.reg.c = v;
-                    }
-                    0x22 => {
-                        let v = self.sla(self.reg.d);
-                        self.reg.d = v;
-                    }
-                    0x23 => {
-                        let v = self.sla(self.reg.e);
-                        self.reg.e = v;
-                    }
-                    0x24 => {
-                        let v = self.sla(self.reg.h);
-                        self.reg.h = v;
-                    }
-                    0x25 => {
-                        let v = self.sla(self.reg.l);
-                        self.reg.l = v;
-                    }
-                    0x26 => {
-                        let hl = mmu.read_byte(self.reg.get_hl());
-                        let v = self.sla(hl);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0x27 => {
-                        let v = self.sla(self.reg.a);
-                        self.reg.a = v;
-                    }
-                    0x28 => {
-                        let v = self.sra(self.reg.b);
-                        self.reg.b = v;
-                    }
-                    0x29 => {
-                        let v = self.sra(self.reg.c);
-                        self.reg.c = v;
-                    }
-                    0x2A => {
-                        let v = self.sra(self.reg.d);
-                        self.reg.d = v;
-                    }
-                    0x2B => {
-                        let v = self.sra(self.reg.e);
-                        self.reg.e = v;
-                    }
-                    0x2C => {
-                        let v = self.sra(self.reg.h);
-                        self.reg.h = v;
-                    }
-                    0x2D => {
-                        let v = self.sra(self.reg.l);
-                        self.reg.l = v;
-                    }
-                    0x2E => {
-                        let hl = mmu.read_byte(self.reg.get_hl());
-                        let v = self.sra(hl);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0x2F => {
-                        let v = self.sra(self.reg.a);
-                        self.reg.a = v;
-                    }
-                    0x30 => {
-                        let v = self.swap(self.reg.b);
-                        self.reg.b = v;
-                    }
-                    0x31 => {
-                        let v = self.swap(self.reg.c);
-                        self.reg.c = v;
-                    }
-                    0x32 => {
-                        let v = self.swap(self.reg.d);
-                        self.reg.d = v;
-                    }
-                    0x33 => {
-                        let v = self.swap(self.reg.e);
-                        self.reg.e = v;
-                    }
-                    0x34 => {
-                        let v = self.swap(self.reg.h);
-                        self.reg.h = v;
-                    }
-                    0x35 => {
-                        let v = self.swap(self.reg.l);
-                        self.reg.l = v;
-                    }
-                    0x36 => {
-                        let hl = mmu.read_byte(self.reg.get_hl());
-                        let v = self.swap(hl);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0x37 => {
-                        let v = self.swap(self.reg.a);
-                        self.reg.a = v;
-                    }
-                    0x38 => {
-                        let v = self.srl(self.reg.b);
-                        self.reg.b = v;
-                    }
-                    0x39 => {
-                        let v = self.srl(self.reg.c);
-                        self.reg.c = v;
-                    }
-                    0x3A => {
-                        let v = self.srl(self.reg.d);
-                        self.reg.d = v;
-                    }
-                    0x3B => {
-                        let v = self.srl(self.reg.e);
-                        self.reg.e = v;
-                    }
-                    0x3C => {
-                        let v = self.srl(self.reg.h);
-                        self.reg.h = v;
-                    }
-                    0x3D => {
-                        let v = self.srl(self.reg.l);
-                        self.reg.l = v;
-                    }
-                    0x3E => {
-                        let hl = mmu.read_byte(self.reg.get_hl());
-                        let v = self.srl(hl);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0x3F => {
-                        let v = self.srl(self.reg.a);
-                        self.reg.a = v;
-                    }
-                    0x40 => self.bit(self.reg.b, 0),
-                    0x41 => self.bit(self.reg.c, 0),
-                    0x42 => self.bit(self.reg.d, 0),
-                    0x43 => self.bit(self.reg.e, 0),
-                    0x44 => self.bit(self.reg.h, 0),
-                    0x45 => self.bit(self.reg.l, 0),
-                    0x46 => self.bit(mmu.read_byte(self.reg.get_hl()), 0),
-                    0x47 => self.bit(self.reg.a, 0),
-                    0x48 => self.bit(self.reg.b, 1),
-                    0x49 => self.bit(self.reg.c, 1),
-                    0x4A => self.bit(self.reg.d, 1),
-                    0x4B => self.bit(self.reg.e, 1),
-                    0x4C => self.bit(self.reg.h, 1),
-                    0x4D => self.bit(self.reg.l, 1),
-                    0x4E => self.bit(mmu.read_byte(self.reg.get_hl()), 1),
-                    0x4F => self.bit(self.reg.a, 1),
-                    0x50 => self.bit(self.reg.b, 2),
-                    0x51 => self.bit(self.reg.c, 2),
-                    0x52 => self.bit(self.reg.d, 2),
-                    0x53 => self.bit(self.reg.e, 2),
-                    0x54 => self.bit(self.reg.h, 2),
-                    0x55 => self.bit(self.reg.l, 2),
-                    0x56 => self.bit(mmu.read_byte(self.reg.get_hl()), 2),
-                    0x57 => self.bit(self.reg.a, 2),
-                    0x58 => self.bit(self.reg.b, 3),
-                    0x59 => self.bit(self.reg.c, 3),
-                    0x5A => self.bit(self.reg.d, 3),
-                    0x5B => self.bit(self.reg.e, 3),
-                    0x5C => self.bit(self.reg.h, 3),
-                    0x5D => self.bit(self.reg.l, 3),
-                    0x5E => self.bit(mmu.read_byte(self.reg.get_hl()), 3),
-                    0x5F => self.bit(self.reg.a, 3),
-                    0x60 => self.bit(self.reg.b, 4),
-                    0x61 => self.bit(self.reg.c, 4),
-                    0x62 => self.bit(self.reg.d, 4),
-                    0x63 => self.bit(self.reg.e, 4),
-                    0x64 => self.bit(self.reg.h, 4),
-                    0x65 => self.bit(self.reg.l, 4),
-                    0x66 => self.bit(mmu.read_byte(self.reg.get_hl()), 4),
-                    0x67 => self.bit(self.reg.a, 4),
-                    0x68 => self.bit(self.reg.b, 5),
-                    0x69 => self.bit(self.reg.c, 5),
-                    0x6A => self.bit(self.reg.d, 5),
-                    0x6B => self.bit(self.reg.e, 5),
-                    0x6C => self.bit(self.reg.h, 5),
-                    0x6D => self.bit(self.reg.l, 5),
-                    0x6E => self.bit(mmu.read_byte(self.reg.get_hl()), 5),
-                    0x6F => self.bit(self.reg.a, 5),
-                    0x70 => self.bit(self.reg.b, 6),
-                    0x71 => self.bit(self.reg.c, 6),
-                    0x72 => self.bit(self.reg.d, 6),
-                    0x73 => self.bit(self.reg.e, 6),
-                    0x74 => self.bit(self.reg.h, 6),
-                    0x75 => self.bit(self.reg.l, 6),
-                    0x76 => self.bit(mmu.read_byte(self.reg.get_hl()), 6),
-                    0x77 => self.bit(self.reg.a, 6),
-                    0x78 => self.bit(self.reg.b, 7),
-                    0x79 => self.bit(self.reg.c, 7),
-                    0x7A => self.bit(self.reg.d, 7),
-                    0x7B => self.bit(self.reg.e, 7),
-                    0x7C => self.bit(self.reg.h, 7),
-                    0x7D => self.bit(self.reg.l, 7),
-                    0x7E => self.bit(mmu.read_byte(self.reg.get_hl()), 7),
-                    0x7F => self.bit(self.reg.a, 7),
-                    0x80 => self.reg.b = self.res(self.reg.b, 0),
-                    0x81 => self.reg.c = self.res(self.reg.c, 0),
-                    0x82 => self.reg.d = self.res(self.reg.d, 0),
-                    0x83 => self.reg.e = self.res(self.reg.e, 0),
-                    0x84 => self.reg.h = self.res(self.reg.h, 0),
-                    0x85 => self.reg.l = self.res(self.reg.l, 0),
-                    0x86 => {
-                        let v = self.res(mmu.read_byte(self.reg.get_hl()), 0);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0x87 => self.reg.a = self.res(self.reg.a, 0),
-                    0x88 => self.reg.b = self.res(self.reg.b, 1),
-                    0x89 => self.reg.c = self.res(self.reg.c, 1),
-                    0x8A => self.reg.d = self.res(self.reg.d, 1),
-                    0x8B => self.reg.e = self.res(self.reg.e, 1),
-                    0x8C => self.reg.h = self.res(self.reg.h, 1),
-                    0x8D => self.reg.l = self.res(self.reg.l, 1),
-                    0x8E => {
-                        let v = self.res(mmu.read_byte(self.reg.get_hl()), 1);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0x8F => self.reg.a = self.res(self.reg.a, 1),
-                    0x90 => self.reg.b = self.res(self.reg.b, 2),
-                    0x91 => self.reg.c = self.res(self.reg.c, 2),
-                    0x92 => self.reg.d = self.res(self.reg.d, 2),
-                    0x93 => self.reg.e = self.res(self.reg.e, 2),
-                    0x94 => self.reg.h = self.res(self.reg.h, 2),
-                    0x95 => self.reg.l = self.res(self.reg.l, 2),
-                    0x96 => {
-                        let v = self.res(mmu.read_byte(self.reg.get_hl()), 2);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0x97 => self.reg.a = self.res(self.reg.a, 2),
-                    0x98 => self.reg.b = self.res(self.reg.b, 3),
-                    0x99 => self.reg.c = self.res(self.reg.c, 3),
-                    0x9A => self.reg.d = self.res(self.reg.d, 3),
-                    0x9B => self.reg.e = self.res(self.reg.e, 3),
-                    0x9C => self.reg.h = self.res(self.reg.h, 3),
-                    0x9D => self.reg.l = self.res(self.reg.l, 3),
-                    0x9E => {
-                        let v = self.res(mmu.read_byte(self.reg.get_hl()), 3);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0x9F => self.reg.a = self.res(self.reg.a, 3),
-                    0xA0 => self.reg.b = self.res(self.reg.b, 4),
-                    0xA1 => self.reg.c = self.res(self.reg.c, 4),
-                    0xA2 => self.reg.d = self.res(self.reg.d, 4),
-                    0xA3 => self.reg.e = self.res(self.reg.e, 4),
-                    0xA4 => self.reg.h = self.res(self.reg.h, 4),
-                    0xA5 => self.reg.l = self.res(self.reg.l, 4),
-                    0xA6 => {
-                        let v = self.res(mmu.read_byte(self.reg.get_hl()), 4);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0xA7 => self.reg.a = self.res(self.reg.a, 4),
-                    0xA8 => self.reg.b = self.res(self.reg.b, 5),
-                    0xA9 => self.reg.c = self.res(self.reg.c, 5),
-                    0xAA => self.reg.d = self.res(self.reg.d, 5),
-                    0xAB => self.reg.e = self.res(self.reg.e, 5),
-                    0xAC => self.reg.h = self.res(self.reg.h, 5),
-                    0xAD => self.reg.l = self.res(self.reg.l, 5),
-                    0xAE => {
-                        let v = self.res(mmu.read_byte(self.reg.get_hl()), 5);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0xAF => self.reg.a = self.res(self.reg.a, 5),
-                    0xB0 => self.reg.b = self.res(self.reg.b, 6),
-                    0xB1 => self.reg.c = self.res(self.reg.c, 6),
-                    0xB2 => self.reg.d = self.res(self.reg.d, 6),
-                    0xB3 => self.reg.e = self.res(self.reg.e, 6),
-                    0xB4 => self.reg.h = self.res(self.reg.h, 6),
-                    0xB5 => self.reg.l = self.res(self.reg.l, 6),
-                    0xB6 => {
-                        let v = self.res(mmu.read_byte(self.reg.get_hl()), 6);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0xB7 => self.reg.a = self.res(self.reg.a, 6),
-                    0xB8 => self.reg.b = self.res(self.reg.b, 7),
-                    0xB9 => self.reg.c = self.res(self.reg.c, 7),
-                    0xBA => self.reg.d = self.res(self.reg.d, 7),
-                    0xBB => self.reg.e = self.res(self.reg.e, 7),
-                    0xBC => self.reg.h = self.res(self.reg.h, 7),
-                    0xBD => self.reg.l = self.res(self.reg.l, 7),
-                    0xBE => {
-                        let v = self.res(mmu.read_byte(self.reg.get_hl()), 7);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0xBF => self.reg.a = self.res(self.reg.a, 7),
-                    0xC0 => self.reg.b = self.set(self.reg.b, 0),
-                    0xC1 => self.reg.c = self.set(self.reg.c, 0),
-                    0xC2 => self.reg.d = self.set(self.reg.d, 0),
-                    0xC3 => self.reg.e = self.set(self.reg.e, 0),
-                    0xC4 => self.reg.h = self.set(self.reg.h, 0),
-                    0xC5 => self.reg.l = self.set(self.reg.l, 0),
-                    0xC6 => {
-                        let v = self.set(mmu.read_byte(self.reg.get_hl()), 0);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0xC7 => self.reg.a = self.set(self.reg.a, 0),
-                    0xC8 => self.reg.b = self.set(self.reg.b, 1),
-                    0xC9 => self.reg.c = self.set(self.reg.c, 1),
-                    0xCA => self.reg.d = self.set(self.reg.d, 1),
-                    0xCB => self.reg.e = self.set(self.reg.e, 1),
-                    0xCC => self.reg.h = self.set(self.reg.h, 1),
-                    0xCD => self.reg.l = self.set(self.reg.l, 1),
-                    0xCE => {
-                        let v = self.set(mmu.read_byte(self.reg.get_hl()), 1);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0xCF => self.reg.a = self.set(self.reg.a, 1),
-                    0xD0 => self.reg.b = self.set(self.reg.b, 2),
-                    0xD1 => self.reg.c = self.set(self.reg.c, 2),
-                    0xD2 => self.reg.d = self.set(self.reg.d, 2),
-                    0xD3 => self.reg.e = self.set(self.reg.e, 2),
-                    0xD4 => self.reg.h = self.set(self.reg.h, 2),
-                    0xD5 => self.reg.l = self.set(self.reg.l, 2),
-                    0xD6 => {
-                        let v = self.set(mmu.read_byte(self.reg.get_hl()), 2);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0xD7 => self.reg.a = self.set(self.reg.a, 2),
-                    0xD8 => self.reg.b = self.set(self.reg.b, 3),
-                    0xD9 => self.reg.c = self.set(self.reg.c, 3),
-                    0xDA => self.reg.d = self.set(self.reg.d, 3),
-                    0xDB => self.reg.e = self.set(self.reg.e, 3),
-                    0xDC => self.reg.h = self.set(self.reg.h, 3),
-                    0xDD => self.reg.l = self.set(self.reg.l, 3),
-                    0xDE => {
-                        let v = self.set(mmu.read_byte(self.reg.get_hl()), 3);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0xDF => self.reg.a = self.set(self.reg.a, 3),
-                    0xE0 => self.reg.b = self.set(self.reg.b, 4),
-                    0xE1 => self.reg.c = self.set(self.reg.c, 4),
-                    0xE2 => self.reg.d = self.set(self.reg.d, 4),
-                    0xE3 => self.reg.e = self.set(self.reg.e, 4),
-                    0xE4 => self.reg.h = self.set(self.reg.h, 4),
-                    0xE5 => self.reg.l = self.set(self.reg.l, 4),
-                    0xE6 => {
-                        let v = self.set(mmu.read_byte(self.reg.get_hl()), 4);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0xE7 => self.reg.a = self.set(self.reg.a, 4),
-                    0xE8 => self.reg.b = self.set(self.reg.b, 5),
-                    0xE9 => self.reg.c = self.set(self.reg.c, 5),
-                    0xEA => self.reg.d = self.set(self.reg.d, 5),
-                    0xEB => self.reg.e = self.set(self.reg.e, 5),
-                    0xEC => self.reg.h = self.set(self.reg.h, 5),
-                    0xED => self.reg.l = self.set(self.reg.l, 5),
-                    0xEE => {
-                        let v = self.set(mmu.read_byte(self.reg.get_hl()), 5);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0xEF => self.reg.a = self.set(self.reg.a, 5),
-                    0xF0 => self.reg.b = self.set(self.reg.b, 6),
-                    0xF1 => self.reg.c = self.set(self.reg.c, 6),
-                    0xF2 => self.reg.d = self.set(self.reg.d, 6),
-                    0xF3 => self.reg.e = self.set(self.reg.e, 6),
-                    0xF4 => self.reg.h = self.set(self.reg.h, 6),
-                    0xF5 => self.reg.l = self.set(self.reg.l, 6),
-                    0xF6 => {
-                        let v = self.set(mmu.read_byte(self.reg.get_hl()), 6);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0xF7 => self.reg.a = self.set(self.reg.a, 6),
-                    0xF8 => self.reg.b = self.set(self.reg.b, 7),
-                    0xF9 => self.reg.c = self.set(self.reg.c, 7),
-                    0xFA => self.reg.d = self.set(self.reg.d, 7),
-                    0xFB => self.reg.e = self.set(self.reg.e, 7),
-                    0xFC => self.reg.h = self.set(self.reg.h, 7),
-                    0xFD => self.reg.l = self.set(self.reg.l, 7),
-                    0xFE => {
-                        let v = self.set(mmu.read_byte(self.reg.get_hl()), 7);
-                        mmu.write_byte(self.reg.get_hl(), v);
-                    }
-                    0xFF => self.reg.a = self.set(self.reg.a, 7),
-                }
+                let opcode = self.imm(mmu);
+                return CB_HANDLERS[opcode as usize](self, mmu);
+            }
+            _ => {
+                self.fault = Some(GabeError::IllegalOpcode {
+                    opcode,
+                    address: self.reg.pc.wrapping_sub(1),
+                });
+                return CYCLES[0];
             }
-            _ => panic!("Unsupported or unimplemented opcode 0x{:X}", opcode),
         };
-        if using_cb {
-            OPCODE_CB_TABLE[opcode as usize]
+        if branched {
+            CYCLES[opcode as usize] + BRANCH_CYCLES[opcode as usize]
         } else {
-            OPCODE_TABLE[opcode as usize] + cond_cycles
+            CYCLES[opcode as usize]
+        }
+    }
+
+    /// Executes an extended opcode, following a CB prefix, returning the number of cycles taken
+    #[inline(always)]
+    fn execute_cb(&mut self, opcode: u8, mmu: &mut dyn Memory) -> u32 {
+        match opcode {
+            0x00 => {
+                let v = self.rlc(self.reg.b);
+                self.reg.b = v;
+            }
+            0x01 => {
+                let v = self.rlc(self.reg.c);
+                self.reg.c = v;
+            }
+            0x02 => {
+                let v = self.rlc(self.reg.d);
+                self.reg.d = v;
+            }
+            0x03 => {
+                let v = self.rlc(self.reg.e);
+                self.reg.e = v;
+            }
+            0x04 => {
+                let v = self.rlc(self.reg.h);
+                self.reg.h = v;
+            }
+            0x05 => {
+                let v = self.rlc(self.reg.l);
+                self.reg.l = v;
+            }
+            0x06 => {
+                let hl = mmu.read_byte(self.reg.get_hl());
+                let v = self.rlc(hl);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0x07 => {
+                let v = self.rlc(self.reg.a);
+                self.reg.a = v;
+            }
+            0x08 => {
+                let v = self.rrc(self.reg.b);
+                self.reg.b = v;
+            }
+            0x09 => {
+                let v = self.rrc(self.reg.c);
+                self.reg.c = v;
+            }
+            0x0A => {
+                let v = self.rrc(self.reg.d);
+                self.reg.d = v;
+            }
+            0x0B => {
+                let v = self.rrc(self.reg.e);
+                self.reg.e = v;
+            }
+            0x0C => {
+                let v = self.rrc(self.reg.h);
+                self.reg.h = v;
+            }
+            0x0D => {
+                let v = self.rrc(self.reg.l);
+                self.reg.l = v;
+            }
+            0x0E => {
+                let hl = mmu.read_byte(self.reg.get_hl());
+                let v = self.rrc(hl);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0x0F => {
+                let v = self.rrc(self.reg.a);
+                self.reg.a = v;
+            }
+            0x10 => {
+                let v = self.rl(self.reg.b);
+                self.reg.b = v;
+            }
+            0x11 => {
+                let v = self.rl(self.reg.c);
+                self.reg.c = v;
+            }
+            0x12 => {
+                let v = self.rl(self.reg.d);
+                self.reg.d = v;
+            }
+            0x13 => {
+                let v = self.rl(self.reg.e);
+                self.reg.e = v;
+            }
+            0x14 => {
+                let v = self.rl(self.reg.h);
+                self.reg.h = v;
+            }
+            0x15 => {
+                let v = self.rl(self.reg.l);
+                self.reg.l = v;
+            }
+            0x16 => {
+                let hl = mmu.read_byte(self.reg.get_hl());
+                let v = self.rl(hl);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0x17 => {
+                let v = self.rl(self.reg.a);
+                self.reg.a = v;
+            }
+            0x18 => {
+                let v = self.rr(self.reg.b);
+                self.reg.b = v;
+            }
+            0x19 => {
+                let v = self.rr(self.reg.c);
+                self.reg.c = v;
+            }
+            0x1A => {
+                let v = self.rr(self.reg.d);
+                self.reg.d = v;
+            }
+            0x1B => {
+                let v = self.rr(self.reg.e);
+                self.reg.e = v;
+            }
+            0x1C => {
+                let v = self.rr(self.reg.h);
+                self.reg.h = v;
+            }
+            0x1D => {
+                let v = self.rr(self.reg.l);
+                self.reg.l = v;
+            }
+            0x1E => {
+                let hl = mmu.read_byte(self.reg.get_hl());
+                let v = self.rr(hl);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0x1F => {
+                let v = self.rr(self.reg.a);
+                self.reg.a = v;
+            }
+            0x20 => {
+                let v = self.sla(self.reg.b);
+                self.reg.b = v;
+            }
+            0x21 => {
+                let v = self.sla(self.reg.c);
+                self.reg.c = v;
+            }
+            0x22 => {
+                let v = self.sla(self.reg.d);
+                self.reg.d = v;
+            }
+            0x23 => {
+                let v = self.sla(self.reg.e);
+                self.reg.e = v;
+            }
+            0x24 => {
+                let v = self.sla(self.reg.h);
+                self.reg.h = v;
+            }
+            0x25 => {
+                let v = self.sla(self.reg.l);
+                self.reg.l = v;
+            }
+            0x26 => {
+                let hl = mmu.read_byte(self.reg.get_hl());
+                let v = self.sla(hl);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0x27 => {
+                let v = self.sla(self.reg.a);
+                self.reg.a = v;
+            }
+            0x28 => {
+                let v = self.sra(self.reg.b);
+                self.reg.b = v;
+            }
+            0x29 => {
+                let v = self.sra(self.reg.c);
+                self.reg.c = v;
+            }
+            0x2A => {
+                let v = self.sra(self.reg.d);
+                self.reg.d = v;
+            }
+            0x2B => {
+                let v = self.sra(self.reg.e);
+                self.reg.e = v;
+            }
+            0x2C => {
+                let v = self.sra(self.reg.h);
+                self.reg.h = v;
+            }
+            0x2D => {
+                let v = self.sra(self.reg.l);
+                self.reg.l = v;
+            }
+            0x2E => {
+                let hl = mmu.read_byte(self.reg.get_hl());
+                let v = self.sra(hl);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0x2F => {
+                let v = self.sra(self.reg.a);
+                self.reg.a = v;
+            }
+            0x30 => {
+                let v = self.swap(self.reg.b);
+                self.reg.b = v;
+            }
+            0x31 => {
+                let v = self.swap(self.reg.c);
+                self.reg.c = v;
+            }
+            0x32 => {
+                let v = self.swap(self.reg.d);
+                self.reg.d = v;
+            }
+            0x33 => {
+                let v = self.swap(self.reg.e);
+                self.reg.e = v;
+            }
+            0x34 => {
+                let v = self.swap(self.reg.h);
+                self.reg.h = v;
+            }
+            0x35 => {
+                let v = self.swap(self.reg.l);
+                self.reg.l = v;
+            }
+            0x36 => {
+                let hl = mmu.read_byte(self.reg.get_hl());
+                let v = self.swap(hl);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0x37 => {
+                let v = self.swap(self.reg.a);
+                self.reg.a = v;
+            }
+            0x38 => {
+                let v = self.srl(self.reg.b);
+                self.reg.b = v;
+            }
+            0x39 => {
+                let v = self.srl(self.reg.c);
+                self.reg.c = v;
+            }
+            0x3A => {
+                let v = self.srl(self.reg.d);
+                self.reg.d = v;
+            }
+            0x3B => {
+                let v = self.srl(self.reg.e);
+                self.reg.e = v;
+            }
+            0x3C => {
+                let v = self.srl(self.reg.h);
+                self.reg.h = v;
+            }
+            0x3D => {
+                let v = self.srl(self.reg.l);
+                self.reg.l = v;
+            }
+            0x3E => {
+                let hl = mmu.read_byte(self.reg.get_hl());
+                let v = self.srl(hl);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0x3F => {
+                let v = self.srl(self.reg.a);
+                self.reg.a = v;
+            }
+            0x40 => self.bit(self.reg.b, 0),
+            0x41 => self.bit(self.reg.c, 0),
+            0x42 => self.bit(self.reg.d, 0),
+            0x43 => self.bit(self.reg.e, 0),
+            0x44 => self.bit(self.reg.h, 0),
+            0x45 => self.bit(self.reg.l, 0),
+            0x46 => self.bit(mmu.read_byte(self.reg.get_hl()), 0),
+            0x47 => self.bit(self.reg.a, 0),
+            0x48 => self.bit(self.reg.b, 1),
+            0x49 => self.bit(self.reg.c, 1),
+            0x4A => self.bit(self.reg.d, 1),
+            0x4B => self.bit(self.reg.e, 1),
+            0x4C => self.bit(self.reg.h, 1),
+            0x4D => self.bit(self.reg.l, 1),
+            0x4E => self.bit(mmu.read_byte(self.reg.get_hl()), 1),
+            0x4F => self.bit(self.reg.a, 1),
+            0x50 => self.bit(self.reg.b, 2),
+            0x51 => self.bit(self.reg.c, 2),
+            0x52 => self.bit(self.reg.d, 2),
+            0x53 => self.bit(self.reg.e, 2),
+            0x54 => self.bit(self.reg.h, 2),
+            0x55 => self.bit(self.reg.l, 2),
+            0x56 => self.bit(mmu.read_byte(self.reg.get_hl()), 2),
+            0x57 => self.bit(self.reg.a, 2),
+            0x58 => self.bit(self.reg.b, 3),
+            0x59 => self.bit(self.reg.c, 3),
+            0x5A => self.bit(self.reg.d, 3),
+            0x5B => self.bit(self.reg.e, 3),
+            0x5C => self.bit(self.reg.h, 3),
+            0x5D => self.bit(self.reg.l, 3),
+            0x5E => self.bit(mmu.read_byte(self.reg.get_hl()), 3),
+            0x5F => self.bit(self.reg.a, 3),
+            0x60 => self.bit(self.reg.b, 4),
+            0x61 => self.bit(self.reg.c, 4),
+            0x62 => self.bit(self.reg.d, 4),
+            0x63 => self.bit(self.reg.e, 4),
+            0x64 => self.bit(self.reg.h, 4),
+            0x65 => self.bit(self.reg.l, 4),
+            0x66 => self.bit(mmu.read_byte(self.reg.get_hl()), 4),
+            0x67 => self.bit(self.reg.a, 4),
+            0x68 => self.bit(self.reg.b, 5),
+            0x69 => self.bit(self.reg.c, 5),
+            0x6A => self.bit(self.reg.d, 5),
+            0x6B => self.bit(self.reg.e, 5),
+            0x6C => self.bit(self.reg.h, 5),
+            0x6D => self.bit(self.reg.l, 5),
+            0x6E => self.bit(mmu.read_byte(self.reg.get_hl()), 5),
+            0x6F => self.bit(self.reg.a, 5),
+            0x70 => self.bit(self.reg.b, 6),
+            0x71 => self.bit(self.reg.c, 6),
+            0x72 => self.bit(self.reg.d, 6),
+            0x73 => self.bit(self.reg.e, 6),
+            0x74 => self.bit(self.reg.h, 6),
+            0x75 => self.bit(self.reg.l, 6),
+            0x76 => self.bit(mmu.read_byte(self.reg.get_hl()), 6),
+            0x77 => self.bit(self.reg.a, 6),
+            0x78 => self.bit(self.reg.b, 7),
+            0x79 => self.bit(self.reg.c, 7),
+            0x7A => self.bit(self.reg.d, 7),
+            0x7B => self.bit(self.reg.e, 7),
+            0x7C => self.bit(self.reg.h, 7),
+            0x7D => self.bit(self.reg.l, 7),
+            0x7E => self.bit(mmu.read_byte(self.reg.get_hl()), 7),
+            0x7F => self.bit(self.reg.a, 7),
+            0x80 => self.reg.b = self.res(self.reg.b, 0),
+            0x81 => self.reg.c = self.res(self.reg.c, 0),
+            0x82 => self.reg.d = self.res(self.reg.d, 0),
+            0x83 => self.reg.e = self.res(self.reg.e, 0),
+            0x84 => self.reg.h = self.res(self.reg.h, 0),
+            0x85 => self.reg.l = self.res(self.reg.l, 0),
+            0x86 => {
+                let v = self.res(mmu.read_byte(self.reg.get_hl()), 0);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0x87 => self.reg.a = self.res(self.reg.a, 0),
+            0x88 => self.reg.b = self.res(self.reg.b, 1),
+            0x89 => self.reg.c = self.res(self.reg.c, 1),
+            0x8A => self.reg.d = self.res(self.reg.d, 1),
+            0x8B => self.reg.e = self.res(self.reg.e, 1),
+            0x8C => self.reg.h = self.res(self.reg.h, 1),
+            0x8D => self.reg.l = self.res(self.reg.l, 1),
+            0x8E => {
+                let v = self.res(mmu.read_byte(self.reg.get_hl()), 1);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0x8F => self.reg.a = self.res(self.reg.a, 1),
+            0x90 => self.reg.b = self.res(self.reg.b, 2),
+            0x91 => self.reg.c = self.res(self.reg.c, 2),
+            0x92 => self.reg.d = self.res(self.reg.d, 2),
+            0x93 => self.reg.e = self.res(self.reg.e, 2),
+            0x94 => self.reg.h = self.res(self.reg.h, 2),
+            0x95 => self.reg.l = self.res(self.reg.l, 2),
+            0x96 => {
+                let v = self.res(mmu.read_byte(self.reg.get_hl()), 2);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0x97 => self.reg.a = self.res(self.reg.a, 2),
+            0x98 => self.reg.b = self.res(self.reg.b, 3),
+            0x99 => self.reg.c = self.res(self.reg.c, 3),
+            0x9A => self.reg.d = self.res(self.reg.d, 3),
+            0x9B => self.reg.e = self.res(self.reg.e, 3),
+            0x9C => self.reg.h = self.res(self.reg.h, 3),
+            0x9D => self.reg.l = self.res(self.reg.l, 3),
+            0x9E => {
+                let v = self.res(mmu.read_byte(self.reg.get_hl()), 3);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0x9F => self.reg.a = self.res(self.reg.a, 3),
+            0xA0 => self.reg.b = self.res(self.reg.b, 4),
+            0xA1 => self.reg.c = self.res(self.reg.c, 4),
+            0xA2 => self.reg.d = self.res(self.reg.d, 4),
+            0xA3 => self.reg.e = self.res(self.reg.e, 4),
+            0xA4 => self.reg.h = self.res(self.reg.h, 4),
+            0xA5 => self.reg.l = self.res(self.reg.l, 4),
+            0xA6 => {
+                let v = self.res(mmu.read_byte(self.reg.get_hl()), 4);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0xA7 => self.reg.a = self.res(self.reg.a, 4),
+            0xA8 => self.reg.b = self.res(self.reg.b, 5),
+            0xA9 => self.reg.c = self.res(self.reg.c, 5),
+            0xAA => self.reg.d = self.res(self.reg.d, 5),
+            0xAB => self.reg.e = self.res(self.reg.e, 5),
+            0xAC => self.reg.h = self.res(self.reg.h, 5),
+            0xAD => self.reg.l = self.res(self.reg.l, 5),
+            0xAE => {
+                let v = self.res(mmu.read_byte(self.reg.get_hl()), 5);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0xAF => self.reg.a = self.res(self.reg.a, 5),
+            0xB0 => self.reg.b = self.res(self.reg.b, 6),
+            0xB1 => self.reg.c = self.res(self.reg.c, 6),
+            0xB2 => self.reg.d = self.res(self.reg.d, 6),
+            0xB3 => self.reg.e = self.res(self.reg.e, 6),
+            0xB4 => self.reg.h = self.res(self.reg.h, 6),
+            0xB5 => self.reg.l = self.res(self.reg.l, 6),
+            0xB6 => {
+                let v = self.res(mmu.read_byte(self.reg.get_hl()), 6);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0xB7 => self.reg.a = self.res(self.reg.a, 6),
+            0xB8 => self.reg.b = self.res(self.reg.b, 7),
+            0xB9 => self.reg.c = self.res(self.reg.c, 7),
+            0xBA => self.reg.d = self.res(self.reg.d, 7),
+            0xBB => self.reg.e = self.res(self.reg.e, 7),
+            0xBC => self.reg.h = self.res(self.reg.h, 7),
+            0xBD => self.reg.l = self.res(self.reg.l, 7),
+            0xBE => {
+                let v = self.res(mmu.read_byte(self.reg.get_hl()), 7);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0xBF => self.reg.a = self.res(self.reg.a, 7),
+            0xC0 => self.reg.b = self.set(self.reg.b, 0),
+            0xC1 => self.reg.c = self.set(self.reg.c, 0),
+            0xC2 => self.reg.d = self.set(self.reg.d, 0),
+            0xC3 => self.reg.e = self.set(self.reg.e, 0),
+            0xC4 => self.reg.h = self.set(self.reg.h, 0),
+            0xC5 => self.reg.l = self.set(self.reg.l, 0),
+            0xC6 => {
+                let v = self.set(mmu.read_byte(self.reg.get_hl()), 0);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0xC7 => self.reg.a = self.set(self.reg.a, 0),
+            0xC8 => self.reg.b = self.set(self.reg.b, 1),
+            0xC9 => self.reg.c = self.set(self.reg.c, 1),
+            0xCA => self.reg.d = self.set(self.reg.d, 1),
+            0xCB => self.reg.e = self.set(self.reg.e, 1),
+            0xCC => self.reg.h = self.set(self.reg.h, 1),
+            0xCD => self.reg.l = self.set(self.reg.l, 1),
+            0xCE => {
+                let v = self.set(mmu.read_byte(self.reg.get_hl()), 1);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0xCF => self.reg.a = self.set(self.reg.a, 1),
+            0xD0 => self.reg.b = self.set(self.reg.b, 2),
+            0xD1 => self.reg.c = self.set(self.reg.c, 2),
+            0xD2 => self.reg.d = self.set(self.reg.d, 2),
+            0xD3 => self.reg.e = self.set(self.reg.e, 2),
+            0xD4 => self.reg.h = self.set(self.reg.h, 2),
+            0xD5 => self.reg.l = self.set(self.reg.l, 2),
+            0xD6 => {
+                let v = self.set(mmu.read_byte(self.reg.get_hl()), 2);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0xD7 => self.reg.a = self.set(self.reg.a, 2),
+            0xD8 => self.reg.b = self.set(self.reg.b, 3),
+            0xD9 => self.reg.c = self.set(self.reg.c, 3),
+            0xDA => self.reg.d = self.set(self.reg.d, 3),
+            0xDB => self.reg.e = self.set(self.reg.e, 3),
+            0xDC => self.reg.h = self.set(self.reg.h, 3),
+            0xDD => self.reg.l = self.set(self.reg.l, 3),
+            0xDE => {
+                let v = self.set(mmu.read_byte(self.reg.get_hl()), 3);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0xDF => self.reg.a = self.set(self.reg.a, 3),
+            0xE0 => self.reg.b = self.set(self.reg.b, 4),
+            0xE1 => self.reg.c = self.set(self.reg.c, 4),
+            0xE2 => self.reg.d = self.set(self.reg.d, 4),
+            0xE3 => self.reg.e = self.set(self.reg.e, 4),
+            0xE4 => self.reg.h = self.set(self.reg.h, 4),
+            0xE5 => self.reg.l = self.set(self.reg.l, 4),
+            0xE6 => {
+                let v = self.set(mmu.read_byte(self.reg.get_hl()), 4);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0xE7 => self.reg.a = self.set(self.reg.a, 4),
+            0xE8 => self.reg.b = self.set(self.reg.b, 5),
+            0xE9 => self.reg.c = self.set(self.reg.c, 5),
+            0xEA => self.reg.d = self.set(self.reg.d, 5),
+            0xEB => self.reg.e = self.set(self.reg.e, 5),
+            0xEC => self.reg.h = self.set(self.reg.h, 5),
+            0xED => self.reg.l = self.set(self.reg.l, 5),
+            0xEE => {
+                let v = self.set(mmu.read_byte(self.reg.get_hl()), 5);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0xEF => self.reg.a = self.set(self.reg.a, 5),
+            0xF0 => self.reg.b = self.set(self.reg.b, 6),
+            0xF1 => self.reg.c = self.set(self.reg.c, 6),
+            0xF2 => self.reg.d = self.set(self.reg.d, 6),
+            0xF3 => self.reg.e = self.set(self.reg.e, 6),
+            0xF4 => self.reg.h = self.set(self.reg.h, 6),
+            0xF5 => self.reg.l = self.set(self.reg.l, 6),
+            0xF6 => {
+                let v = self.set(mmu.read_byte(self.reg.get_hl()), 6);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0xF7 => self.reg.a = self.set(self.reg.a, 6),
+            0xF8 => self.reg.b = self.set(self.reg.b, 7),
+            0xF9 => self.reg.c = self.set(self.reg.c, 7),
+            0xFA => self.reg.d = self.set(self.reg.d, 7),
+            0xFB => self.reg.e = self.set(self.reg.e, 7),
+            0xFC => self.reg.h = self.set(self.reg.h, 7),
+            0xFD => self.reg.l = self.set(self.reg.l, 7),
+            0xFE => {
+                let v = self.set(mmu.read_byte(self.reg.get_hl()), 7);
+                mmu.write_byte(self.reg.get_hl(), v);
+            }
+            0xFF => self.reg.a = self.set(self.reg.a, 7),
         }
+        CB_CYCLES[opcode as usize]
     }
 
     /// Reads and returns the value at the current PC location
@@ -1867,7 +1915,7 @@ impl Cpu {
         self.reg.set_flag(Flag::N, false);
         self.reg.set_flag(Flag::H, false);
         self.reg.set_flag(Flag::C, false);
-        (r >> 4) | (r << 4)
+        r.rotate_left(4)
     }
 
     /// Shift register `r` right into the Carry flag. Bit 7 set to 0.
@@ -1957,7 +2005,7 @@ impl Cpu {
 #[cfg(test)]
 mod cpu_tests {
 
-    use std::io::{BufReader, Read};
+    use std::io::BufReader;
 
     use super::*;
     #[test]
@@ -2093,6 +2141,23 @@ mod cpu_tests {
         }
     }
 
+    #[test]
+    fn illegal_opcode_locks_up() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100] = 0xD3;
+        let mut mmu = crate::mmu::Mmu::power_on(rom.into_boxed_slice(), None).unwrap();
+        let mut cpu = Cpu::power_on();
+        cpu.tick(&mut mmu);
+        let fault = Some(GabeError::IllegalOpcode {
+            opcode: 0xD3,
+            address: 0x100,
+        });
+        assert_eq!(cpu.fault, fault);
+        cpu.tick(&mut mmu);
+        assert_eq!(cpu.reg.pc, 0x101);
+        assert_eq!(cpu.fault, fault);
+    }
+
     #[test]
     fn rl_test() {
         let mut cpu = Cpu::power_on();
@@ -0,0 +1,237 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+pub use super::cpu::Registers;
+pub use super::mmu::InterruptKind;
+pub use super::mmu::Memory;
+
+/// Maximum number of interrupt events kept in the `InterruptLog`
+const INTERRUPT_LOG_SIZE: usize = 64;
+
+/// A single interrupt event seen while stepping the emulator.
+/// Requests are recorded when a subsystem sets the interrupt's bit in IF, and
+/// dispatches are recorded when the CPU jumps to the interrupt vector.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InterruptEvent {
+    /// The interrupt that was requested or serviced
+    pub kind: InterruptKind,
+    /// The emulated cycle count at the time of the event
+    pub cycle: u64,
+    /// The PC at the time of the event. For dispatches, this is the return
+    /// address pushed to the stack before jumping to the vector.
+    pub pc: u16,
+    /// True if the CPU jumped to the interrupt vector, false if the interrupt was only requested
+    pub taken: bool,
+}
+
+/// Ring buffer of the most recent interrupt events.
+/// Also tracks the total number of events ever recorded, so callers can
+/// find events that occurred since they last checked.
+pub struct InterruptLog {
+    events: VecDeque<InterruptEvent>,
+    total: u64,
+}
+
+impl InterruptLog {
+    pub fn new() -> Self {
+        InterruptLog {
+            events: VecDeque::with_capacity(INTERRUPT_LOG_SIZE),
+            total: 0,
+        }
+    }
+
+    /// Records the event, dropping the oldest event if the log is full
+    pub fn push(&mut self, event: InterruptEvent) {
+        if self.events.len() >= INTERRUPT_LOG_SIZE {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+        self.total += 1;
+    }
+
+    /// Returns an iterator over the retained events, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &InterruptEvent> {
+        self.events.iter()
+    }
+
+    /// Returns the total number of events recorded since power on, including dropped events
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns an iterator over the retained events recorded after the log's total was `total`
+    pub fn since(&self, total: u64) -> impl Iterator<Item = &InterruptEvent> {
+        let new_events = self.total.saturating_sub(total) as usize;
+        self.events
+            .iter()
+            .skip(self.events.len().saturating_sub(new_events))
+    }
+}
+
+impl Default for InterruptLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The bytes of cartridge ROM the CPU has run instructions from, recorded with
+/// `Gameboy::start_coverage`. Bytes are kept by their offset in the ROM file, which gives the
+/// bank that was mapped as well as the address, so a disassembler can tell code from data
+/// and which bank banked code runs in.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Coverage {
+    /// A bit for each byte of ROM, set if an instruction starting there was run
+    bits: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns coverage saved with `as_bytes`
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Coverage { bits: bytes }
+    }
+
+    /// Returns the coverage as bytes to be saved, with a bit for each byte of ROM, starting
+    /// from the lowest bit of the first byte
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+
+    /// Records that an instruction starting at `offset` in the ROM was run
+    pub fn record(&mut self, offset: usize) {
+        let index = offset / 8;
+        if index >= self.bits.len() {
+            self.bits.resize(index + 1, 0);
+        }
+        self.bits[index] |= 1 << (offset % 8);
+    }
+
+    /// Returns true if an instruction starting at `offset` in the ROM was run
+    pub fn executed(&self, offset: usize) -> bool {
+        matches!(self.bits.get(offset / 8), Some(bits) if bits & (1 << (offset % 8)) != 0)
+    }
+
+    /// Returns the offset of every instruction run, lowest first
+    pub fn offsets(&self) -> impl Iterator<Item = usize> + '_ {
+        self.bits.iter().enumerate().flat_map(|(index, bits)| {
+            (0..8)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| index * 8 + bit)
+        })
+    }
+}
+
+/// The parts of the emulator timed by a `Profiler`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Subsystem {
+    Cpu = 0,
+    Dma = 1,
+    Apu = 2,
+    /// Timer and joypad updates
+    Io = 3,
+    Ppu = 4,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 5] = [
+        Subsystem::Cpu,
+        Subsystem::Dma,
+        Subsystem::Apu,
+        Subsystem::Io,
+        Subsystem::Ppu,
+    ];
+}
+
+/// Receives a mark as each subsystem finishes its work during `Gameboy::step_profiled`.
+/// The core has no clock, so implementors time the work themselves: the time since the
+/// previous mark was spent in the marked subsystem.
+pub trait Profiler {
+    fn mark(&mut self, subsystem: Subsystem);
+}
+
+/// A Profiler that ignores all marks, used when stepping without profiling
+pub struct NoProfiler;
+
+impl Profiler for NoProfiler {
+    #[inline(always)]
+    fn mark(&mut self, _subsystem: Subsystem) {}
+}
+
+#[cfg(test)]
+mod debug_tests {
+    use super::*;
+
+    fn event(cycle: u64) -> InterruptEvent {
+        InterruptEvent {
+            kind: InterruptKind::VBlank,
+            cycle,
+            pc: 0x0150,
+            taken: false,
+        }
+    }
+
+    struct MarkRecorder(Vec<Subsystem>);
+
+    struct NullSink;
+
+    impl<T> crate::sink::Sink<T> for NullSink {
+        fn append(&mut self, _value: T) {}
+    }
+
+    impl Profiler for MarkRecorder {
+        fn mark(&mut self, subsystem: Subsystem) {
+            self.0.push(subsystem);
+        }
+    }
+
+    #[test]
+    fn profiler_marks_each_subsystem() {
+        let rom = vec![0; 0x8000].into_boxed_slice();
+        let mut gb = crate::gb::Gameboy::power_on(rom, None).unwrap();
+        let mut profiler = MarkRecorder(vec![]);
+        gb.step_profiled(&mut NullSink, &mut NullSink, &mut profiler);
+        assert_eq!(profiler.0, Subsystem::ALL);
+    }
+
+    #[test]
+    fn coverage() {
+        let mut coverage = Coverage::new();
+        coverage.record(0x100);
+        coverage.record(0x4003);
+        coverage.record(0x101);
+        assert!(coverage.executed(0x4003));
+        assert!(!coverage.executed(0x102));
+        assert!(!coverage.executed(0x10000));
+        let offsets: Vec<usize> = coverage.offsets().collect();
+        assert_eq!(offsets, [0x100, 0x101, 0x4003]);
+        let saved = Coverage::from_bytes(coverage.as_bytes().to_vec());
+        assert_eq!(saved, coverage);
+    }
+
+    #[test]
+    fn interrupt_log_wraps() {
+        let mut log = InterruptLog::new();
+        for i in 0..(INTERRUPT_LOG_SIZE as u64 + 10) {
+            log.push(event(i));
+        }
+        assert_eq!(log.total(), INTERRUPT_LOG_SIZE as u64 + 10);
+        assert_eq!(log.iter().count(), INTERRUPT_LOG_SIZE);
+        assert_eq!(log.iter().next().unwrap().cycle, 10);
+    }
+
+    #[test]
+    fn interrupt_log_since() {
+        let mut log = InterruptLog::new();
+        log.push(event(0));
+        log.push(event(1));
+        let seen = log.total();
+        assert_eq!(log.since(seen).count(), 0);
+        log.push(event(2));
+        let new: alloc::vec::Vec<u64> = log.since(seen).map(|e| e.cycle).collect();
+        assert_eq!(new, vec![2]);
+    }
+}
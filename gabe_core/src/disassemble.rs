@@ -1,2107 +1,1213 @@
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::string::*;
 use alloc::vec::*;
+use core::fmt;
+
+use crate::debug::Coverage;
+use crate::mmu::Memory;
+use crate::opcodes;
+
+/// Size of a cartridge ROM bank. Bank 0 is always mapped at 0x0000-0x3FFF, and the switchable
+/// bank at 0x4000-0x7FFF.
+pub const ROM_BANK_SIZE: usize = 0x4000;
+
+/// Where a byte of cartridge ROM is seen by the CPU: the bank it's in, and its address while
+/// that bank is mapped. Displayed as "01:4000".
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BankedAddress {
+    pub bank: u16,
+    pub address: u16,
+}
+
+impl BankedAddress {
+    /// Returns where the byte at `offset` in the ROM file is mapped
+    pub fn from_rom_offset(offset: usize) -> BankedAddress {
+        let bank = offset / ROM_BANK_SIZE;
+        let address = match bank {
+            0 => offset,
+            _ => ROM_BANK_SIZE + offset % ROM_BANK_SIZE,
+        };
+        BankedAddress {
+            bank: bank as u16,
+            address: address as u16,
+        }
+    }
+
+    /// Returns the offset in the ROM file of the byte at this address
+    pub fn rom_offset(&self) -> usize {
+        self.bank as usize * ROM_BANK_SIZE + self.address as usize % ROM_BANK_SIZE
+    }
+}
+
+impl fmt::Display for BankedAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02X}:{:04X}", self.bank, self.address)
+    }
+}
+
+/// One decoded instruction
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Instruction {
+    /// Address of the opcode
+    pub address: u16,
+    /// Bytes taken by the opcode and its operands, 1 to 3
+    pub len: usize,
+    /// The mnemonic with its operands filled in, e.g. "LD B,$3E". Relative jump targets are
+    /// resolved to absolute addresses, e.g. "JR NZ,$0150".
+    pub text: String,
+    /// The address jumped or called to, for JP, JR, CALL and RST with a fixed target
+    pub target: Option<u16>,
+}
+
+impl Instruction {
+    /// Returns the text with the target address replaced by `label`, e.g. "JP L_0150"
+    pub fn text_with_label(&self, label: &str) -> String {
+        match self.target {
+            Some(target) if self.text.starts_with("RST") => {
+                self.text.replace(&format!("${:02X}", target), label)
+            }
+            Some(target) => self.text.replace(&format!("${:04X}", target), label),
+            None => self.text.clone(),
+        }
+    }
+}
+
+/// Decodes the instruction at the start of `data`, which is found at address `pc`. Returns
+/// None if `data` is empty or ends partway through the instruction.
+pub fn decode(data: &[u8], pc: u16) -> Option<Instruction> {
+    let opcode = *data.first()?;
+    if opcode == 0xCB {
+        // The prefix takes the next byte as a second opcode, not an operand
+        return Some(Instruction {
+            address: pc,
+            len: 2,
+            text: get_cb_opcode(*data.get(1)?),
+            target: None,
+        });
+    }
+    let info = opcodes::info(opcode);
+    let len = info.len;
+    let operands = data.get(1..len)?;
+    let byte = || operands[0];
+    let word = || u16::from_le_bytes([operands[0], operands[1]]);
+    let template = info.mnemonic;
+    let mut target = None;
+    let text = if template == "NULL" {
+        "???".to_string()
+    } else if template.contains("d16") {
+        template.replace("d16", &format!("${:04X}", word()))
+    } else if template.contains("a16") {
+        if template.starts_with("JP") || template.starts_with("CALL") {
+            target = Some(word());
+        }
+        template.replace("a16", &format!("${:04X}", word()))
+    } else if template.contains("d8") {
+        template.replace("d8", &format!("${:02X}", byte()))
+    } else if template.contains("a8") {
+        template.replace("a8", &format!("$FF{:02X}", byte()))
+    } else if template.contains("SP+r8") {
+        template.replace("+r8", &format!("{:+}", byte() as i8))
+    } else if template.starts_with("JR") {
+        // Relative to the address after the instruction
+        let address = pc.wrapping_add(2).wrapping_add(byte() as i8 as u16);
+        target = Some(address);
+        template.replace("r8", &format!("${:04X}", address))
+    } else if template.contains("r8") {
+        template.replace("r8", &format!("{}", byte() as i8))
+    } else {
+        if template.starts_with("RST") {
+            // The vector is given by bits 3-5
+            target = Some((opcode & 0x38) as u16);
+        }
+        template.to_string()
+    };
+    Some(Instruction {
+        address: pc,
+        len,
+        text,
+        target,
+    })
+}
 
 /// Given a block of code separated into u8 values, interpret each byte as a valid Gameboy opcode,
-/// and convert it and its operands into a human-readable mnemonic.
+/// and convert it and its operands into a human-readable mnemonic, after the bytes it was
+/// decoded from.
 /// Note: This converts data naively, and assumes the initial start point is an opcode and not the
 /// operand of a previous opcode or data. Ensure that the input starts on a known-good opcode,
 /// and that the entire range is valid code, not data.
 pub fn disassemble_block(data: &[u8], pc: u16) -> Vec<(u16, String)> {
-    let mut iter = data.iter();
     let mut ret: Vec<(u16, String)> = vec![];
-    let mut current_pc = pc;
-    while let Some(opcode) = iter.next() {
-        match opcode {
-            0x00 => ret.push((current_pc, format!("{:02X}:\tnop", opcode).to_string())),
-            0x76 => ret.push((current_pc, format!("{:02X}:\thalt", opcode).to_string())),
-            0x10 => ret.push((current_pc, format!("{:02X}:\tstop", opcode).to_string())),
-            0x3F => ret.push((current_pc, format!("{:02X}:\tccf", opcode).to_string())),
-            0x37 => ret.push((current_pc, format!("{:02X}:\tscf", opcode).to_string())),
-            0xF3 => ret.push((current_pc, format!("{:02X}:\tdi", opcode).to_string())),
-            0xFB => ret.push((current_pc, format!("{:02X}:\tei", opcode).to_string())),
-            0x06 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t ld b,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x0E => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t ld c,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x16 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t ld d,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x1E => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t ld e,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x26 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t ld h,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x2E => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t ld l,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x36 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t ld (hl),${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x3E => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t ld a,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x02 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld (bc),a", opcode).to_string(),
-            )),
-            0x12 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld (de),a", opcode).to_string(),
-            )),
-            0x0a => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld a,(bc)", opcode).to_string(),
-            )),
-            0x1a => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld a,(de)", opcode).to_string(),
-            )),
-            0x22 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld (hl+),a", opcode).to_string(),
-            )),
-            0x32 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld (hl-),a", opcode).to_string(),
-            )),
-            0x2a => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld a,(hl+)", opcode).to_string(),
-            )),
-            0x3a => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld a,(hl-)", opcode).to_string(),
-            )),
-
-            // LDH (a8),A
-            0xE0 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t ld ($FF{:02X}),a", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            // LDH A,(a8)
-            0xF0 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t ld a,($FF{:02X})", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0xE2 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld ($FF00+c),a", opcode).to_string(),
-            )),
-            0xF2 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld a,($FF00+c)", opcode).to_string(),
-            )),
-            0x40 => ret.push((current_pc, format!("{:02X}:\t ld b,b", opcode).to_string())),
-            0x41 => ret.push((current_pc, format!("{:02X}:\t ld b,c", opcode).to_string())),
-            0x42 => ret.push((current_pc, format!("{:02X}:\t ld b,d", opcode).to_string())),
-            0x43 => ret.push((current_pc, format!("{:02X}:\t ld b,e", opcode).to_string())),
-            0x44 => ret.push((current_pc, format!("{:02X}:\t ld b,h", opcode).to_string())),
-            0x45 => ret.push((current_pc, format!("{:02X}:\t ld b,l", opcode).to_string())),
-            0x46 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld b,(hl)", opcode).to_string(),
-            )),
-            0x47 => ret.push((current_pc, format!("{:02X}:\t ld b,a", opcode).to_string())),
-            0x48 => ret.push((current_pc, format!("{:02X}:\t ld c,b", opcode).to_string())),
-            0x49 => ret.push((current_pc, format!("{:02X}:\t ld c,c", opcode).to_string())),
-            0x4A => ret.push((current_pc, format!("{:02X}:\t ld c,d", opcode).to_string())),
-            0x4B => ret.push((current_pc, format!("{:02X}:\t ld c,e", opcode).to_string())),
-            0x4C => ret.push((current_pc, format!("{:02X}:\t ld c,h", opcode).to_string())),
-            0x4D => ret.push((current_pc, format!("{:02X}:\t ld c,l", opcode).to_string())),
-            0x4E => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld c,(hl)", opcode).to_string(),
-            )),
-            0x4F => ret.push((current_pc, format!("{:02X}:\t ld c,a", opcode).to_string())),
-            0x50 => ret.push((current_pc, format!("{:02X}:\t ld d,b", opcode).to_string())),
-            0x51 => ret.push((current_pc, format!("{:02X}:\t ld d,c", opcode).to_string())),
-            0x52 => ret.push((current_pc, format!("{:02X}:\t ld d,d", opcode).to_string())),
-            0x53 => ret.push((current_pc, format!("{:02X}:\t ld d,e", opcode).to_string())),
-            0x54 => ret.push((current_pc, format!("{:02X}:\t ld d,h", opcode).to_string())),
-            0x55 => ret.push((current_pc, format!("{:02X}:\t ld d,l", opcode).to_string())),
-            0x56 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld d,(hl)", opcode).to_string(),
-            )),
-            0x57 => ret.push((current_pc, format!("{:02X}:\t ld d,a", opcode).to_string())),
-            0x58 => ret.push((current_pc, format!("{:02X}:\t ld e,b", opcode).to_string())),
-            0x59 => ret.push((current_pc, format!("{:02X}:\t ld e,c", opcode).to_string())),
-            0x5A => ret.push((current_pc, format!("{:02X}:\t ld e,d", opcode).to_string())),
-            0x5B => ret.push((current_pc, format!("{:02X}:\t ld e,e", opcode).to_string())),
-            0x5C => ret.push((current_pc, format!("{:02X}:\t ld e,h", opcode).to_string())),
-            0x5D => ret.push((current_pc, format!("{:02X}:\t ld e,l", opcode).to_string())),
-            0x5E => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld e,(hl)", opcode).to_string(),
-            )),
-            0x5F => ret.push((current_pc, format!("{:02X}:\t ld e,a", opcode).to_string())),
-            0x60 => ret.push((current_pc, format!("{:02X}:\t ld h,b", opcode).to_string())),
-            0x61 => ret.push((current_pc, format!("{:02X}:\t ld h,c", opcode).to_string())),
-            0x62 => ret.push((current_pc, format!("{:02X}:\t ld h,d", opcode).to_string())),
-            0x63 => ret.push((current_pc, format!("{:02X}:\t ld h,e", opcode).to_string())),
-            0x64 => ret.push((current_pc, format!("{:02X}:\t ld h,h", opcode).to_string())),
-            0x65 => ret.push((current_pc, format!("{:02X}:\t ld h,l", opcode).to_string())),
-            0x66 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld h,(hl)", opcode).to_string(),
-            )),
-            0x67 => ret.push((current_pc, format!("{:02X}:\t ld h,a", opcode).to_string())),
-            0x68 => ret.push((current_pc, format!("{:02X}:\t ld l,b", opcode).to_string())),
-            0x69 => ret.push((current_pc, format!("{:02X}:\t ld l,c", opcode).to_string())),
-            0x6A => ret.push((current_pc, format!("{:02X}:\t ld l,d", opcode).to_string())),
-            0x6B => ret.push((current_pc, format!("{:02X}:\t ld l,e", opcode).to_string())),
-            0x6C => ret.push((current_pc, format!("{:02X}:\t ld l,h", opcode).to_string())),
-            0x6D => ret.push((current_pc, format!("{:02X}:\t ld l,l", opcode).to_string())),
-            0x6E => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld l,(hl)", opcode).to_string(),
-            )),
-            0x6F => ret.push((current_pc, format!("{:02X}:\t ld l,a", opcode).to_string())),
-            0x70 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld (hl),b", opcode).to_string(),
-            )),
-            0x71 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld (hl),c", opcode).to_string(),
-            )),
-            0x72 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld (hl),d", opcode).to_string(),
-            )),
-            0x73 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld (hl),e", opcode).to_string(),
-            )),
-            0x74 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld (hl),h", opcode).to_string(),
-            )),
-            0x75 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld (hl),l", opcode).to_string(),
-            )),
-            0x77 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld (hl),a", opcode).to_string(),
-            )),
-            0x78 => ret.push((current_pc, format!("{:02X}:\t ld a,b", opcode).to_string())),
-            0x79 => ret.push((current_pc, format!("{:02X}:\t ld a,c", opcode).to_string())),
-            0x7A => ret.push((current_pc, format!("{:02X}:\t ld a,d", opcode).to_string())),
-            0x7B => ret.push((current_pc, format!("{:02X}:\t ld a,e", opcode).to_string())),
-            0x7C => ret.push((current_pc, format!("{:02X}:\t ld a,h", opcode).to_string())),
-            0x7D => ret.push((current_pc, format!("{:02X}:\t ld a,l", opcode).to_string())),
-            0x7E => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld a,(hl)", opcode).to_string(),
-            )),
-            0x7F => ret.push((current_pc, format!("{:02X}:\t ld a,a", opcode).to_string())),
-            0x01 => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t ld bc,(${:02X}{:02X})",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x11 => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t ld de,(${:02X}{:02X})",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x21 => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t ld hl,(${:02X}{:02X})",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x31 => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t ld sp,(${:02X}{:02X})",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0xEA => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t ld (${:02X}{:02X}),a",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0xFA => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t ld a,(${:02X}{:02X})",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x08 => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t ld (${:02X}{:02X}),sp",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0xF9 => ret.push((
-                current_pc,
-                format!("{:02X}:\t ld sp,hl", opcode).to_string(),
-            )),
-            0xF8 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t ld hl,sp+${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x80 => ret.push((current_pc, format!("{:02X}:\t add a,b", opcode).to_string())),
-            0x81 => ret.push((current_pc, format!("{:02X}:\t add a,c", opcode).to_string())),
-            0x82 => ret.push((current_pc, format!("{:02X}:\t add a,d", opcode).to_string())),
-            0x83 => ret.push((current_pc, format!("{:02X}:\t add a,e", opcode).to_string())),
-            0x84 => ret.push((current_pc, format!("{:02X}:\t add a,h", opcode).to_string())),
-            0x85 => ret.push((current_pc, format!("{:02X}:\t add a,l", opcode).to_string())),
-            0x86 => ret.push((
-                current_pc,
-                format!("{:02X}:\t add a,(hl)", opcode).to_string(),
-            )),
-            0x87 => ret.push((current_pc, format!("{:02X}:\t add a,a", opcode).to_string())),
-            0xC6 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t add a,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x88 => ret.push((current_pc, format!("{:02X}:\t adc a,b", opcode).to_string())),
-            0x89 => ret.push((current_pc, format!("{:02X}:\t adc a,c", opcode).to_string())),
-            0x8A => ret.push((current_pc, format!("{:02X}:\t adc a,d", opcode).to_string())),
-            0x8B => ret.push((current_pc, format!("{:02X}:\t adc a,e", opcode).to_string())),
-            0x8C => ret.push((current_pc, format!("{:02X}:\t adc a,h", opcode).to_string())),
-            0x8D => ret.push((current_pc, format!("{:02X}:\t adc a,l", opcode).to_string())),
-            0x8E => ret.push((
-                current_pc,
-                format!("{:02X}:\t adc a,(hl)", opcode).to_string(),
-            )),
-            0x8F => ret.push((current_pc, format!("{:02X}:\t adc a,a", opcode).to_string())),
-            0xCE => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t adc a,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0xE8 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t add sp,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x09 => ret.push((
-                current_pc,
-                format!("{:02X}:\t add hl,bc", opcode).to_string(),
-            )),
-            0x19 => ret.push((
-                current_pc,
-                format!("{:02X}:\t add hl,de", opcode).to_string(),
-            )),
-            0x29 => ret.push((
-                current_pc,
-                format!("{:02X}:\t add hl,hl", opcode).to_string(),
-            )),
-            0x39 => ret.push((
-                current_pc,
-                format!("{:02X}:\t add hl,sp", opcode).to_string(),
-            )),
-            0x90 => ret.push((current_pc, format!("{:02X}:\t sub a,b", opcode).to_string())),
-            0x91 => ret.push((current_pc, format!("{:02X}:\t sub a,c", opcode).to_string())),
-            0x92 => ret.push((current_pc, format!("{:02X}:\t sub a,d", opcode).to_string())),
-            0x93 => ret.push((current_pc, format!("{:02X}:\t sub a,e", opcode).to_string())),
-            0x94 => ret.push((current_pc, format!("{:02X}:\t sub a,h", opcode).to_string())),
-            0x95 => ret.push((current_pc, format!("{:02X}:\t sub a,l", opcode).to_string())),
-            0x96 => ret.push((
-                current_pc,
-                format!("{:02X}:\t sub a,(hl)", opcode).to_string(),
-            )),
-            0x97 => ret.push((current_pc, format!("{:02X}:\t sub a,a", opcode).to_string())),
-            0xD6 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t sub a,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x98 => ret.push((current_pc, format!("{:02X}:\t sbc a,b", opcode).to_string())),
-            0x99 => ret.push((current_pc, format!("{:02X}:\t sbc a,c", opcode).to_string())),
-            0x9A => ret.push((current_pc, format!("{:02X}:\t sbc a,d", opcode).to_string())),
-            0x9B => ret.push((current_pc, format!("{:02X}:\t sbc a,e", opcode).to_string())),
-            0x9C => ret.push((current_pc, format!("{:02X}:\t sbc a,h", opcode).to_string())),
-            0x9D => ret.push((current_pc, format!("{:02X}:\t sbc a,l", opcode).to_string())),
-            0x9E => ret.push((
-                current_pc,
-                format!("{:02X}:\t sbc a,(hl)", opcode).to_string(),
-            )),
-            0x9F => ret.push((current_pc, format!("{:02X}:\t sbc a,a", opcode).to_string())),
-            0xDE => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t sbc a,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0xA0 => ret.push((current_pc, format!("{:02X}:\t and a,b", opcode).to_string())),
-            0xA1 => ret.push((current_pc, format!("{:02X}:\t and a,c", opcode).to_string())),
-            0xA2 => ret.push((current_pc, format!("{:02X}:\t and a,d", opcode).to_string())),
-            0xA3 => ret.push((current_pc, format!("{:02X}:\t and a,e", opcode).to_string())),
-            0xA4 => ret.push((current_pc, format!("{:02X}:\t and a,h", opcode).to_string())),
-            0xA5 => ret.push((current_pc, format!("{:02X}:\t and a,l", opcode).to_string())),
-            0xA6 => ret.push((
-                current_pc,
-                format!("{:02X}:\t and a,(hl)", opcode).to_string(),
-            )),
-            0xA7 => ret.push((current_pc, format!("{:02X}:\t and a,a", opcode).to_string())),
-            0xE6 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t and a,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0xA8 => ret.push((current_pc, format!("{:02X}:\t xor a,b", opcode).to_string())),
-            0xA9 => ret.push((current_pc, format!("{:02X}:\t xor a,c", opcode).to_string())),
-            0xAA => ret.push((current_pc, format!("{:02X}:\t xor a,d", opcode).to_string())),
-            0xAB => ret.push((current_pc, format!("{:02X}:\t xor a,e", opcode).to_string())),
-            0xAC => ret.push((current_pc, format!("{:02X}:\t xor a,h", opcode).to_string())),
-            0xAD => ret.push((current_pc, format!("{:02X}:\t xor a,l", opcode).to_string())),
-            0xAE => ret.push((
-                current_pc,
-                format!("{:02X}:\t xor a,(hl)", opcode).to_string(),
-            )),
-            0xAF => ret.push((current_pc, format!("{:02X}:\t xor a,a", opcode).to_string())),
-            0xEE => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t xor a,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0xB0 => ret.push((current_pc, format!("{:02X}:\t or a,b", opcode).to_string())),
-            0xB1 => ret.push((current_pc, format!("{:02X}:\t or a,c", opcode).to_string())),
-            0xB2 => ret.push((current_pc, format!("{:02X}:\t or a,d", opcode).to_string())),
-            0xB3 => ret.push((current_pc, format!("{:02X}:\t or a,e", opcode).to_string())),
-            0xB4 => ret.push((current_pc, format!("{:02X}:\t or a,h", opcode).to_string())),
-            0xB5 => ret.push((current_pc, format!("{:02X}:\t or a,l", opcode).to_string())),
-            0xB6 => ret.push((
-                current_pc,
-                format!("{:02X}:\t or a,(hl)", opcode).to_string(),
-            )),
-            0xB7 => ret.push((current_pc, format!("{:02X}:\t or a,a", opcode).to_string())),
-            0xF6 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t or a,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0xB8 => ret.push((current_pc, format!("{:02X}:\t cp a,b", opcode).to_string())),
-            0xB9 => ret.push((current_pc, format!("{:02X}:\t cp a,c", opcode).to_string())),
-            0xBA => ret.push((current_pc, format!("{:02X}:\t cp a,d", opcode).to_string())),
-            0xBB => ret.push((current_pc, format!("{:02X}:\t cp a,e", opcode).to_string())),
-            0xBC => ret.push((current_pc, format!("{:02X}:\t cp a,h", opcode).to_string())),
-            0xBD => ret.push((current_pc, format!("{:02X}:\t cp a,l", opcode).to_string())),
-            0xBE => ret.push((
-                current_pc,
-                format!("{:02X}:\t cp a,(hl)", opcode).to_string(),
-            )),
-            0xBF => ret.push((current_pc, format!("{:02X}:\t cp a,a", opcode).to_string())),
-            0xFE => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t cp a,${:02X}", opcode, a1, a1).to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0x2F => ret.push((current_pc, format!("{:02X}:\t cpl a", opcode).to_string())),
-            0x04 => ret.push((current_pc, format!("{:02X}:\t inc b", opcode).to_string())),
-            0x0C => ret.push((current_pc, format!("{:02X}:\t inc c", opcode).to_string())),
-            0x14 => ret.push((current_pc, format!("{:02X}:\t inc d", opcode).to_string())),
-            0x1C => ret.push((current_pc, format!("{:02X}:\t inc e", opcode).to_string())),
-            0x24 => ret.push((current_pc, format!("{:02X}:\t inc h", opcode).to_string())),
-            0x2C => ret.push((current_pc, format!("{:02X}:\t inc l", opcode).to_string())),
-            0x34 => ret.push((
-                current_pc,
-                format!("{:02X}:\t inc (hl)", opcode).to_string(),
-            )),
-            0x3C => ret.push((current_pc, format!("{:02X}:\t inc a", opcode).to_string())),
-            0x05 => ret.push((current_pc, format!("{:02X}:\t dec b", opcode).to_string())),
-            0x0D => ret.push((current_pc, format!("{:02X}:\t dec c", opcode).to_string())),
-            0x15 => ret.push((current_pc, format!("{:02X}:\t dec d", opcode).to_string())),
-            0x1D => ret.push((current_pc, format!("{:02X}:\t dec e", opcode).to_string())),
-            0x25 => ret.push((current_pc, format!("{:02X}:\t dec h", opcode).to_string())),
-            0x2D => ret.push((current_pc, format!("{:02X}:\t dec l", opcode).to_string())),
-            0x35 => ret.push((
-                current_pc,
-                format!("{:02X}:\t dec (hl)", opcode).to_string(),
-            )),
-            0x3D => ret.push((current_pc, format!("{:02X}:\t dec a", opcode).to_string())),
-            0x03 => ret.push((current_pc, format!("{:02X}:\t inc bc", opcode).to_string())),
-            0x13 => ret.push((current_pc, format!("{:02X}:\t inc de", opcode).to_string())),
-            0x23 => ret.push((current_pc, format!("{:02X}:\t inc hl", opcode).to_string())),
-            0x33 => ret.push((current_pc, format!("{:02X}:\t inc sp", opcode).to_string())),
-            0x0B => ret.push((current_pc, format!("{:02X}:\t dec bc", opcode).to_string())),
-            0x1B => ret.push((current_pc, format!("{:02X}:\t dec de", opcode).to_string())),
-            0x2B => ret.push((current_pc, format!("{:02X}:\t dec hl", opcode).to_string())),
-            0x3B => ret.push((current_pc, format!("{:02X}:\t dec sp", opcode).to_string())),
-            0xC1 => ret.push((current_pc, format!("{:02X}:\t pop bc", opcode).to_string())),
-            0xD1 => ret.push((current_pc, format!("{:02X}:\t pop de", opcode).to_string())),
-            0xE1 => ret.push((current_pc, format!("{:02X}:\t pop hl", opcode).to_string())),
-            0xF1 => ret.push((current_pc, format!("{:02X}:\t pop af", opcode).to_string())),
-            0xC5 => ret.push((current_pc, format!("{:02X}:\t push bc", opcode).to_string())),
-            0xD5 => ret.push((current_pc, format!("{:02X}:\t push de", opcode).to_string())),
-            0xE5 => ret.push((current_pc, format!("{:02X}:\t push hl", opcode).to_string())),
-            0xF5 => ret.push((current_pc, format!("{:02X}:\t push af", opcode).to_string())),
-            0xC3 => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t jp ${:02X}{:02X}",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0xE9 => ret.push((current_pc, format!("{:02X}:\t jp hl", opcode).to_string())),
-            0xC2 => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t jp nz,${:02X}{:02X}",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0xD2 => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t jp nc,${:02X}{:02X}",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
-                }
-            }
-            0xCA => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t jp z,${:02X}{:02X}",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
+    let mut offset = 0;
+    while let Some(inst) = decode(&data[offset..], pc.wrapping_add(offset as u16)) {
+        let bytes = hex_bytes(&data[offset..offset + inst.len]);
+        ret.push((inst.address, format!("{}:\t {}", bytes, inst.text)));
+        offset += inst.len;
+    }
+    ret
+}
+
+/// Decodes the instruction at `pc` as the CPU would see it in `memory`, with whichever banks
+/// are mapped in. Returns None if the instruction runs past 0xFFFF.
+pub fn decode_memory(memory: &dyn Memory, pc: u16) -> Option<Instruction> {
+    let mut data = [0; 3];
+    let len = (0x10000 - pc as usize).min(data.len());
+    for (i, byte) in data[..len].iter_mut().enumerate() {
+        *byte = memory.read_byte(pc + i as u16);
+    }
+    decode(&data[..len], pc)
+}
+
+/// Disassembles `memory` from `pc` onward, decoding each instruction only when it's asked
+/// for, so live memory can be followed without copying it out first. Stops at the end of
+/// the address space. As with `disassemble_block`, `pc` must be the start of an instruction.
+pub fn disassemble_memory(memory: &dyn Memory, pc: u16) -> MemoryDisassembly<'_> {
+    MemoryDisassembly {
+        memory,
+        pc: Some(pc),
+    }
+}
+
+/// The instructions in memory, returned by `disassemble_memory`
+pub struct MemoryDisassembly<'a> {
+    memory: &'a dyn Memory,
+    /// Address of the next instruction, None once the end of memory is reached
+    pc: Option<u16>,
+}
+
+impl Iterator for MemoryDisassembly<'_> {
+    type Item = Instruction;
+
+    fn next(&mut self) -> Option<Instruction> {
+        let inst = decode_memory(self.memory, self.pc?)?;
+        self.pc = inst.address.checked_add(inst.len as u16);
+        Some(inst)
+    }
+}
+
+/// Formats an instruction decoded from `memory` as `disassemble_block` does, after the bytes
+/// it was decoded from, e.g. "C35001:\t JP $0150"
+pub fn format_memory(memory: &dyn Memory, inst: &Instruction) -> String {
+    let bytes: String = (0..inst.len as u16)
+        .map(|i| format!("{:02X}", memory.read_byte(inst.address + i)))
+        .collect();
+    format!("{}:\t {}", bytes, inst.text)
+}
+
+/// Formats bytes as they're shown before each instruction, e.g. "C35001"
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Where execution can start without being jumped to: the RST and interrupt vectors, and the
+/// entry point after the boot ROM
+pub const ENTRY_POINTS: [u16; 14] = [
+    0x00, 0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38, 0x40, 0x48, 0x50, 0x58, 0x60, 0x100,
+];
+
+/// Returns the name of the RST or interrupt vector, or the entry point, at `address`
+pub fn vector_name(address: u16) -> Option<&'static str> {
+    Some(match address {
+        0x00 => "RST_00",
+        0x08 => "RST_08",
+        0x10 => "RST_10",
+        0x18 => "RST_18",
+        0x20 => "RST_20",
+        0x28 => "RST_28",
+        0x30 => "RST_30",
+        0x38 => "RST_38",
+        0x40 => "VBlankInterrupt",
+        0x48 => "StatInterrupt",
+        0x50 => "TimerInterrupt",
+        0x58 => "SerialInterrupt",
+        0x60 => "JoypadInterrupt",
+        0x100 => "EntryPoint",
+        _ => return None,
+    })
+}
+
+/// Returns the name of the IO register at `address`, as named in hardware.inc, e.g. "rLCDC"
+pub fn io_register_name(address: u16) -> Option<&'static str> {
+    Some(match address {
+        0xFF00 => "rJOYP",
+        0xFF01 => "rSB",
+        0xFF02 => "rSC",
+        0xFF04 => "rDIV",
+        0xFF05 => "rTIMA",
+        0xFF06 => "rTMA",
+        0xFF07 => "rTAC",
+        0xFF0F => "rIF",
+        0xFF10 => "rNR10",
+        0xFF11 => "rNR11",
+        0xFF12 => "rNR12",
+        0xFF13 => "rNR13",
+        0xFF14 => "rNR14",
+        0xFF16 => "rNR21",
+        0xFF17 => "rNR22",
+        0xFF18 => "rNR23",
+        0xFF19 => "rNR24",
+        0xFF1A => "rNR30",
+        0xFF1B => "rNR31",
+        0xFF1C => "rNR32",
+        0xFF1D => "rNR33",
+        0xFF1E => "rNR34",
+        0xFF20 => "rNR41",
+        0xFF21 => "rNR42",
+        0xFF22 => "rNR43",
+        0xFF23 => "rNR44",
+        0xFF24 => "rNR50",
+        0xFF25 => "rNR51",
+        0xFF26 => "rNR52",
+        0xFF30..=0xFF3F => "_AUD3WAVERAM",
+        0xFF40 => "rLCDC",
+        0xFF41 => "rSTAT",
+        0xFF42 => "rSCY",
+        0xFF43 => "rSCX",
+        0xFF44 => "rLY",
+        0xFF45 => "rLYC",
+        0xFF46 => "rDMA",
+        0xFF47 => "rBGP",
+        0xFF48 => "rOBP0",
+        0xFF49 => "rOBP1",
+        0xFF4A => "rWY",
+        0xFF4B => "rWX",
+        0xFF4D => "rKEY1",
+        0xFF4F => "rVBK",
+        0xFF51 => "rHDMA1",
+        0xFF52 => "rHDMA2",
+        0xFF53 => "rHDMA3",
+        0xFF54 => "rHDMA4",
+        0xFF55 => "rHDMA5",
+        0xFF56 => "rRP",
+        0xFF68 => "rBCPS",
+        0xFF69 => "rBCPD",
+        0xFF6A => "rOCPS",
+        0xFF6B => "rOCPD",
+        0xFF70 => "rSVBK",
+        0xFFFF => "rIE",
+        _ => return None,
+    })
+}
+
+/// Returns a comment for the instruction, naming the IO register an operand refers to, or
+/// the vector called by RST
+pub fn annotation(inst: &Instruction) -> Option<&'static str> {
+    if inst.text.starts_with("RST") {
+        return inst.target.and_then(vector_name);
+    }
+    // IO registers are written as a 16-bit operand, e.g. "LDH ($FF40),A"
+    let (_, operand) = inst.text.split_once("$FF")?;
+    let low = u8::from_str_radix(operand.get(..2)?, 16).ok()?;
+    let is_word = !operand[2..].starts_with(|c: char| c.is_ascii_hexdigit());
+    is_word.then(|| io_register_name(0xFF00 | low as u16))?
+}
+
+/// Most bytes shown on one data line
+const DATA_LINE_BYTES: usize = 8;
+
+/// What a byte of ROM was found to be by `CodeMap::trace`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ByteKind {
+    /// Never reached by following the code, so assumed to be data
+    Data,
+    /// The first byte of a reachable instruction
+    Opcode,
+    /// An operand of a reachable instruction, or the second byte of a CB opcode
+    Operand,
+}
+
+/// Marks which bytes of a ROM are code, found by following every path of execution from the
+/// entry points
+pub struct CodeMap {
+    kinds: Vec<ByteKind>,
+    /// Instructions seen running in the emulator, which guide the trace
+    coverage: Coverage,
+}
+
+impl CodeMap {
+    /// Traces the code in `rom` from each of `ENTRY_POINTS`, following jumps, calls and
+    /// fallthrough until each path ends with an unconditional jump or return. Jumps from bank 0
+    /// into 0x4000-0x7FFF can go to any bank, so they're only followed when the ROM has just
+    /// two banks. Jumps into RAM, and jumps through HL, aren't followed.
+    pub fn trace(rom: &[u8]) -> CodeMap {
+        Self::trace_with_coverage(rom, Coverage::new())
+    }
+
+    /// Traces the code in `rom` as `trace` does, also starting from every instruction in
+    /// `coverage`, as recorded while playing the game. This finds code only reached through
+    /// jump tables and JP (HL), and code in other banks. A jump from bank 0 into 0x4000-0x7FFF
+    /// is followed into the bank it was seen running in, when that's only one bank.
+    pub fn trace_with_coverage(rom: &[u8], coverage: Coverage) -> CodeMap {
+        let mut map = CodeMap {
+            kinds: vec![ByteKind::Data; rom.len()],
+            coverage,
+        };
+        let mut pending: Vec<BankedAddress> = ENTRY_POINTS
+            .iter()
+            .filter_map(|&addr| map.resolve(addr, 0))
+            .collect();
+        pending.extend(
+            map.coverage
+                .offsets()
+                .filter(|offset| *offset < rom.len())
+                .map(BankedAddress::from_rom_offset),
+        );
+        while let Some(addr) = pending.pop() {
+            let mut offset = addr.rom_offset();
+            while map.kinds[offset] == ByteKind::Data {
+                let addr = BankedAddress::from_rom_offset(offset);
+                // Instructions can't run on past the end of their bank
+                let bank_end = (addr.bank as usize + 1) * ROM_BANK_SIZE;
+                let inst = match decode(&rom[offset..bank_end.min(rom.len())], addr.address) {
+                    Some(inst) if inst.text != "???" => inst,
+                    _ => break,
+                };
+                map.kinds[offset] = ByteKind::Opcode;
+                for kind in &mut map.kinds[offset + 1..offset + inst.len] {
+                    *kind = ByteKind::Operand;
                 }
-            }
-            0xDA => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t jp c,${:02X}{:02X}",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
+                if let Some(target) = inst.target.and_then(|t| map.resolve(t, addr.bank)) {
+                    pending.push(target);
                 }
-            }
-            0x18 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t jp pc+({})", opcode, a1, *a1 as i8).to_string(),
-                    ))
-                } else {
+                // JR, JP, RET, RETI and JP (HL) never continue to the next instruction
+                if matches!(rom[offset], 0x18 | 0xC3 | 0xC9 | 0xD9 | 0xE9) {
                     break;
                 }
-            }
-            0x20 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t jp nz,pc+({})", opcode, a1, *a1 as i8).to_string(),
-                    ))
-                } else {
-                    break;
+                // Running off the end of bank 0 only continues into a known bank
+                let next = addr.address.wrapping_add(inst.len as u16);
+                match map.resolve(next, addr.bank) {
+                    Some(next) => offset = next.rom_offset(),
+                    None => break,
                 }
             }
-            0x30 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t jp nc,pc+({})", opcode, a1, *a1 as i8).to_string(),
-                    ))
-                } else {
-                    break;
-                }
+        }
+        map
+    }
+
+    /// Returns what the byte at `offset` in the ROM is
+    pub fn kind(&self, offset: usize) -> ByteKind {
+        self.kinds[offset]
+    }
+
+    /// Returns the traced instructions jumped or called to by other traced instructions
+    pub fn jump_targets(&self, rom: &[u8]) -> BTreeSet<BankedAddress> {
+        let mut targets = BTreeSet::new();
+        for (offset, kind) in self.kinds.iter().enumerate() {
+            if *kind != ByteKind::Opcode {
+                continue;
             }
-            0x28 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t jp z,pc+({})", opcode, a1, *a1 as i8).to_string(),
-                    ))
-                } else {
-                    break;
+            let addr = BankedAddress::from_rom_offset(offset);
+            let target = decode(&rom[offset..], addr.address)
+                .and_then(|inst| inst.target)
+                .and_then(|t| self.resolve(t, addr.bank));
+            if let Some(target) = target {
+                if self.kind(target.rom_offset()) == ByteKind::Opcode {
+                    targets.insert(target);
                 }
             }
-            0x38 => {
-                if let Some(a1) = iter.next() {
-                    ret.push((
-                        current_pc,
-                        format!("{:02X}{:02X}:\t jp c,pc+({})", opcode, a1, *a1 as i8).to_string(),
-                    ))
-                } else {
-                    break;
-                }
+        }
+        targets
+    }
+
+    /// Finds every reference the traced instructions make to another address: the jumps and
+    /// calls, and the loads and stores with the address as an operand, such as
+    /// `LD A,($C000)` and `LDH ($FF40),A`. Accesses through a register pair, such as
+    /// `LD A,(HL)`, aren't found, since the address isn't known until the code runs.
+    pub fn cross_references(&self, rom: &[u8]) -> CrossReferences {
+        let mut refs: BTreeMap<u16, Vec<CrossReference>> = BTreeMap::new();
+        for (offset, kind) in self.kinds.iter().enumerate() {
+            if *kind != ByteKind::Opcode {
+                continue;
             }
-            0xCD => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t call ${:02X}{:02X}",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
+            let from = BankedAddress::from_rom_offset(offset);
+            let inst = match decode(&rom[offset..], from.address) {
+                Some(inst) => inst,
+                None => continue,
+            };
+            let operands = &rom[offset + 1..offset + inst.len];
+            let (to, kind) = match (rom[offset], inst.target) {
+                (0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC, Some(target)) => (target, RefKind::Call),
+                (opcode, Some(target)) if opcode & 0xC7 == 0xC7 => (target, RefKind::Call),
+                (_, Some(target)) => (target, RefKind::Jump),
+                (0xFA, None) => (
+                    u16::from_le_bytes([operands[0], operands[1]]),
+                    RefKind::Read,
+                ),
+                (0x08 | 0xEA, None) => (
+                    u16::from_le_bytes([operands[0], operands[1]]),
+                    RefKind::Write,
+                ),
+                (0xF0, None) => (0xFF00 | operands[0] as u16, RefKind::Read),
+                (0xE0, None) => (0xFF00 | operands[0] as u16, RefKind::Write),
+                _ => continue,
+            };
+            // Writes to ROM go to the MBC, so don't refer to any bank
+            let to_bank = match kind {
+                RefKind::Write => None,
+                _ => self.resolve(to, from.bank).map(|addr| addr.bank),
+            };
+            refs.entry(to).or_default().push(CrossReference {
+                from,
+                to,
+                to_bank,
+                kind,
+            });
+        }
+        CrossReferences { refs }
+    }
+
+    /// Returns where in the ROM a jump to `address` goes, when made from code in `bank`
+    fn resolve(&self, address: u16, bank: u16) -> Option<BankedAddress> {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF if bank != 0 => bank,
+            0x4000..=0x7FFF if self.kinds.len() <= 2 * ROM_BANK_SIZE => 1,
+            0x4000..=0x7FFF => {
+                let mut banks = (1..)
+                    .map(|bank| BankedAddress { bank, address })
+                    .take_while(|addr| addr.rom_offset() < self.kinds.len())
+                    .filter(|addr| self.coverage.executed(addr.rom_offset()));
+                match (banks.next(), banks.next()) {
+                    (Some(addr), None) => addr.bank,
+                    _ => return None,
                 }
             }
-            0xC4 => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t call nz,${:02X}{:02X}",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
+            _ => return None,
+        };
+        let addr = BankedAddress { bank, address };
+        (addr.rom_offset() < self.kinds.len()).then_some(addr)
+    }
+}
+
+/// How an instruction refers to an address
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RefKind {
+    /// CALL or RST
+    Call,
+    /// JP or JR
+    Jump,
+    /// A load from the address
+    Read,
+    /// A store to the address
+    Write,
+}
+
+/// An instruction in a ROM referring to an address, found by `CodeMap::cross_references`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CrossReference {
+    /// The instruction making the reference
+    pub from: BankedAddress,
+    /// The address referred to
+    pub to: u16,
+    /// The bank holding `to`, if it's in ROM and the bank mapped there is known
+    pub to_bank: Option<u16>,
+    pub kind: RefKind,
+}
+
+/// Every reference made by the code in a ROM, for finding what calls or jumps to an address,
+/// and where it's read or written
+pub struct CrossReferences {
+    refs: BTreeMap<u16, Vec<CrossReference>>,
+}
+
+impl CrossReferences {
+    /// Returns the references to `address`, in the order they're found in the ROM. A switchable
+    /// bank address such as 0x4000 is in every bank, so the references to each are returned
+    /// together. Check `to_bank` to tell them apart.
+    pub fn to(&self, address: u16) -> &[CrossReference] {
+        self.refs.get(&address).map_or(&[], |refs| refs.as_slice())
+    }
+
+    /// Returns the references to `addr` only, leaving out those known to go to other banks
+    pub fn to_banked(&self, addr: BankedAddress) -> impl Iterator<Item = &CrossReference> {
+        self.to(addr.address)
+            .iter()
+            .filter(move |r| r.to_bank.is_none() || r.to_bank == Some(addr.bank))
+    }
+}
+
+/// One instruction or line of data in a ROM's disassembly, split into its parts for tools
+/// to read
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RomLine {
+    pub bank: u16,
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// The instruction's mnemonic, e.g. "JP", or "db" for data
+    pub mnemonic: String,
+    /// The operands, e.g. ["NZ", "$0150"], or each byte of data
+    pub operands: Vec<String>,
+    /// The address jumped or called to, for JP, JR, CALL and RST with a fixed target
+    pub target: Option<u16>,
+    /// The bank holding the target, if it's in ROM and the bank mapped there is known
+    pub target_bank: Option<u16>,
+    /// A note on the instruction, from `annotation`
+    pub comment: Option<String>,
+}
+
+impl RomLine {
+    /// Returns the instruction as written by `decode`, e.g. "JP NZ,$0150"
+    pub fn text(&self) -> String {
+        match self.operands.is_empty() {
+            true => self.mnemonic.clone(),
+            false => format!("{} {}", self.mnemonic, self.operands.join(",")),
+        }
+    }
+}
+
+/// Disassembles a whole cartridge ROM, one bank at a time. Each bank is decoded as though it
+/// were mapped in, so bank 0 starts at 0x0000 and every other bank at 0x4000. Only code found
+/// by tracing `map` is decoded. The bytes in between are given as data, up to 8 to a line.
+pub fn disassemble_rom_lines(rom: &[u8], map: &CodeMap) -> Vec<RomLine> {
+    let mut lines = vec![];
+    for (bank, data) in rom.chunks(ROM_BANK_SIZE).enumerate() {
+        let mut offset = 0;
+        while offset < data.len() {
+            let rom_offset = bank * ROM_BANK_SIZE + offset;
+            let addr = BankedAddress::from_rom_offset(rom_offset);
+            let inst = match map.kind(rom_offset) {
+                ByteKind::Opcode => decode(&data[offset..], addr.address),
+                _ => None,
+            };
+            let line = match inst {
+                Some(inst) => {
+                    let (mnemonic, operands) = match inst.text.split_once(' ') {
+                        Some((mnemonic, operands)) => {
+                            (mnemonic, operands.split(',').map(String::from).collect())
+                        }
+                        None => (inst.text.as_str(), vec![]),
+                    };
+                    RomLine {
+                        bank: addr.bank,
+                        address: addr.address,
+                        bytes: data[offset..offset + inst.len].to_vec(),
+                        mnemonic: mnemonic.to_string(),
+                        operands,
+                        target: inst.target,
+                        target_bank: inst
+                            .target
+                            .and_then(|t| map.resolve(t, addr.bank))
+                            .map(|t| t.bank),
+                        comment: annotation(&inst).map(String::from),
+                    }
                 }
-            }
-            0xCC => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t call nc,${:02X}{:02X}",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
+                None => {
+                    let len = (1..DATA_LINE_BYTES)
+                        .take_while(|&i| {
+                            offset + i < data.len() && map.kind(rom_offset + i) != ByteKind::Opcode
+                        })
+                        .count()
+                        + 1;
+                    let bytes = &data[offset..offset + len];
+                    RomLine {
+                        bank: addr.bank,
+                        address: addr.address,
+                        bytes: bytes.to_vec(),
+                        mnemonic: "db".to_string(),
+                        operands: bytes.iter().map(|b| format!("${:02X}", b)).collect(),
+                        target: None,
+                        target_bank: None,
+                        comment: None,
+                    }
                 }
-            }
-            0xD4 => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t call z,${:02X}{:02X}",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
+            };
+            offset += line.bytes.len();
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+/// Disassembles a whole cartridge ROM as `disassemble_rom_lines` does, into a listing. Each
+/// line starts with the instruction's bank and address, e.g. "01:4000  C35001:\t JP $0150",
+/// and data is shown as e.g. "01:4003  0102:\t db $01,$02". Instructions are followed by
+/// their comment, if they have one, e.g. "; rLCDC". A comment line such as "; Bank 01" comes
+/// before each bank.
+pub fn disassemble_rom(rom: &[u8], map: &CodeMap) -> Vec<String> {
+    let mut lines = vec![];
+    for line in disassemble_rom_lines(rom, map) {
+        if line.address & 0x3FFF == 0 {
+            lines.push(format!("; Bank {:02X}", line.bank));
+        }
+        let addr = BankedAddress {
+            bank: line.bank,
+            address: line.address,
+        };
+        let mut text = format!("{}  {}:\t {}", addr, hex_bytes(&line.bytes), line.text());
+        if let Some(comment) = &line.comment {
+            text.push_str(&format!(" ; {}", comment));
+        }
+        lines.push(text);
+    }
+    lines
+}
+
+/// Formats bytes as a data directive, e.g. "db $01,$02"
+fn data_directive(bytes: &[u8]) -> String {
+    let values: Vec<String> = bytes.iter().map(|b| format!("${:02X}", b)).collect();
+    format!("db {}", values.join(","))
+}
+
+/// Returns the label given to a jump or call target, e.g. "L_0150"
+pub fn label_name(address: u16) -> String {
+    format!("L_{:04X}", address)
+}
+
+/// Returns the label given to code in a ROM bank. Labels in bank 0 are named as by
+/// `label_name`, and those in other banks include the bank, e.g. "L_01_4000".
+pub fn rom_label_name(addr: BankedAddress) -> String {
+    match addr.bank {
+        0 => label_name(addr.address),
+        bank => format!("L_{:02X}_{:04X}", bank, addr.address),
+    }
+}
+
+/// Disassembles a block of code as `disassemble_block` does, into lines of source. Each
+/// address jumped or called to from within the block gets a label line, e.g. "L_0150:", and
+/// the instructions jumping there refer to the label instead of the address. Instructions
+/// are indented under the labels.
+pub fn disassemble_with_labels(data: &[u8], pc: u16) -> Vec<String> {
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while let Some(inst) = decode(&data[offset..], pc.wrapping_add(offset as u16)) {
+        offset += inst.len;
+        instructions.push(inst);
+    }
+    // Only targets landing on an instruction in the block can be labeled
+    let starts: BTreeSet<u16> = instructions.iter().map(|i| i.address).collect();
+    let labels: BTreeSet<u16> = instructions
+        .iter()
+        .filter_map(|i| i.target)
+        .filter(|t| starts.contains(t))
+        .collect();
+    let mut lines = vec![];
+    for inst in &instructions {
+        if labels.contains(&inst.address) {
+            lines.push(format!("{}:", label_name(inst.address)));
+        }
+        let text = match inst.target {
+            Some(target) if labels.contains(&target) => inst.text_with_label(&label_name(target)),
+            _ => inst.text.clone(),
+        };
+        lines.push(format!("    {}", text));
+    }
+    lines
+}
+
+/// Fields of the cartridge header, which runs from 0x0104 to 0x014F, as (start, end, name)
+const HEADER_FIELDS: [(usize, usize, &str); 12] = [
+    (0x104, 0x134, "Nintendo logo"),
+    (0x134, 0x144, "Title"),
+    (0x144, 0x146, "New licensee code"),
+    (0x146, 0x147, "SGB flag"),
+    (0x147, 0x148, "Cartridge type"),
+    (0x148, 0x149, "ROM size"),
+    (0x149, 0x14A, "RAM size"),
+    (0x14A, 0x14B, "Destination code"),
+    (0x14B, 0x14C, "Old licensee code"),
+    (0x14C, 0x14D, "Mask ROM version"),
+    (0x14D, 0x14E, "Header checksum"),
+    (0x14E, 0x150, "Global checksum"),
+];
+
+/// Returns the labels for a ROM: every traced instruction that's jumped or called to, named
+/// by `rom_label_name`, and the traced vectors, named by `vector_name`. The `names` given by
+/// the user take the place of either.
+pub fn rom_symbols(
+    rom: &[u8],
+    map: &CodeMap,
+    names: &BTreeMap<BankedAddress, String>,
+) -> BTreeMap<BankedAddress, String> {
+    let mut symbols: BTreeMap<BankedAddress, String> = map
+        .jump_targets(rom)
+        .into_iter()
+        .map(|addr| (addr, rom_label_name(addr)))
+        .collect();
+    for address in ENTRY_POINTS {
+        let addr = BankedAddress { bank: 0, address };
+        if addr.rom_offset() < rom.len() && map.kind(addr.rom_offset()) == ByteKind::Opcode {
+            symbols.insert(addr, vector_name(address).unwrap_or_default().to_string());
+        }
+    }
+    symbols.extend(names.iter().map(|(addr, name)| (*addr, name.clone())));
+    symbols
+}
+
+/// Parses a symbol file, as written by rgblink and `write_symbols`, with a "bank:address name"
+/// line for each symbol, e.g. "01:4000 Main". Comments starting with ';', and lines that
+/// aren't a symbol, are skipped.
+pub fn parse_symbols(text: &str) -> BTreeMap<BankedAddress, String> {
+    let mut symbols = BTreeMap::new();
+    for line in text.lines() {
+        let line = line.split(';').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        let (Some(addr), Some(name)) = (words.next(), words.next()) else {
+            continue;
+        };
+        let Some((bank, address)) = addr.split_once(':') else {
+            continue;
+        };
+        if let (Ok(bank), Ok(address)) = (
+            u16::from_str_radix(bank, 16),
+            u16::from_str_radix(address, 16),
+        ) {
+            symbols.insert(BankedAddress { bank, address }, name.to_string());
+        }
+    }
+    symbols
+}
+
+/// Writes symbols as a symbol file, which debuggers and `parse_symbols` can read
+pub fn write_symbols(symbols: &BTreeMap<BankedAddress, String>) -> String {
+    let mut text = String::from("; File generated by gabe\n");
+    for (addr, name) in symbols {
+        text.push_str(&format!("{} {}\n", addr, name));
+    }
+    text
+}
+
+/// Disassembles a whole cartridge ROM into source for rgbasm, which assembles and links back
+/// into the same ROM. Each bank gets its own SECTION, fixed at its bank and address. Code is
+/// found by tracing `map`, and labels are placed as given by `rom_symbols`, with `names`
+/// naming any of them. Everything else is written as "db" data, including the header, which
+/// is split into its fields with a comment naming each one.
+/// STOP, and LD to or from 0xFF00-0xFFFF with a 16-bit address, are written as data with the
+/// instruction in a comment, since rgbasm would assemble them into different bytes.
+pub fn disassemble_rom_rgbds(
+    rom: &[u8],
+    map: &CodeMap,
+    names: &BTreeMap<BankedAddress, String>,
+) -> Vec<String> {
+    let labels = rom_symbols(rom, map, names);
+    let mut lines = vec![];
+    for (bank, data) in rom.chunks(ROM_BANK_SIZE).enumerate() {
+        if bank > 0 {
+            lines.push(String::new());
+        }
+        lines.push(match bank {
+            0 => "SECTION \"ROM Bank $00\", ROM0[$0000]".to_string(),
+            _ => format!(
+                "SECTION \"ROM Bank ${:02X}\", ROMX[$4000], BANK[${:02X}]",
+                bank, bank
+            ),
+        });
+        let mut offset = 0;
+        while offset < data.len() {
+            let rom_offset = bank * ROM_BANK_SIZE + offset;
+            let addr = BankedAddress::from_rom_offset(rom_offset);
+            if let Some(&(start, end, name)) = HEADER_FIELDS.iter().find(|f| f.0 == rom_offset) {
+                let end = end.min(rom.len());
+                if let Some(label) = labels.get(&addr) {
+                    lines.push(format!("{}:", label));
                 }
+                lines.push(format!(
+                    "    {} ; {}",
+                    data_directive(&rom[start..end]),
+                    name
+                ));
+                offset += end - start;
+                continue;
             }
-            0xDC => {
-                if let (Some(a1), Some(a2)) = (iter.next(), iter.next()) {
-                    ret.push((
-                        current_pc,
-                        format!(
-                            "{:02X}{:02X}{:02X}:\t call c,${:02X}{:02X}",
-                            opcode, a1, a2, a2, a1
-                        )
-                        .to_string(),
-                    ))
-                } else {
-                    break;
-                }
+            let in_header = (0x104..0x150).contains(&rom_offset);
+            let inst = match map.kind(rom_offset) {
+                ByteKind::Opcode if !in_header => decode(&data[offset..], addr.address)
+                    .filter(|inst| rom_offset + inst.len <= 0x104 || rom_offset >= 0x150),
+                _ => None,
+            };
+            if let Some(label) = labels.get(&addr) {
+                lines.push(format!("{}:", label));
             }
-            0xC9 => ret.push((current_pc, format!("{:02X}:\t ret", opcode).to_string())),
-            0xC0 => ret.push((current_pc, format!("{:02X}:\t ret nz", opcode).to_string())),
-            0xC8 => ret.push((current_pc, format!("{:02X}:\t ret z", opcode).to_string())),
-            0xD0 => ret.push((current_pc, format!("{:02X}:\t ret nc", opcode).to_string())),
-            0xD8 => ret.push((current_pc, format!("{:02X}:\t ret c", opcode).to_string())),
-            0xD9 => ret.push((current_pc, format!("{:02X}:\t reti", opcode).to_string())),
-            0xC7 => ret.push((current_pc, format!("{:02X}:\t rst 00", opcode).to_string())),
-            0xCF => ret.push((current_pc, format!("{:02X}:\t rst 08", opcode).to_string())),
-            0xD7 => ret.push((current_pc, format!("{:02X}:\t rst 10", opcode).to_string())),
-            0xDF => ret.push((current_pc, format!("{:02X}:\t rst 18", opcode).to_string())),
-            0xE7 => ret.push((current_pc, format!("{:02X}:\t rst 20", opcode).to_string())),
-            0xEF => ret.push((current_pc, format!("{:02X}:\t rst 28", opcode).to_string())),
-            0xF7 => ret.push((current_pc, format!("{:02X}:\t rst 30", opcode).to_string())),
-            0xFF => ret.push((current_pc, format!("{:02X}:\t rst 38", opcode).to_string())),
-            0x07 => ret.push((current_pc, format!("{:02X}:\t rlca", opcode).to_string())),
-            0x17 => ret.push((current_pc, format!("{:02X}:\t rla", opcode).to_string())),
-            0x0F => ret.push((current_pc, format!("{:02X}:\t rrca", opcode).to_string())),
-            0x1F => ret.push((current_pc, format!("{:02X}:\t rra", opcode).to_string())),
-            0x27 => ret.push((current_pc, format!("{:02X}:\t daa", opcode).to_string())),
-
-            // CB Prefix
-            0xCB => {
-                if let Some(opcode) = iter.next() {
-                    match opcode {
-                        0x00 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rlc b", opcode).to_string()))
-                        }
-                        0x01 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rlc c", opcode).to_string()))
-                        }
-                        0x02 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rlc d", opcode).to_string()))
-                        }
-                        0x03 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rlc e", opcode).to_string()))
-                        }
-                        0x04 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rlc h", opcode).to_string()))
-                        }
-                        0x05 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rlc l", opcode).to_string()))
-                        }
-                        0x06 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t rlc (hl)", opcode).to_string(),
-                        )),
-                        0x07 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rlc a", opcode).to_string()))
-                        }
-                        0x08 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rrc b", opcode).to_string()))
-                        }
-                        0x09 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rrc c", opcode).to_string()))
-                        }
-                        0x0A => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rrc d", opcode).to_string()))
-                        }
-                        0x0B => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rrc e", opcode).to_string()))
-                        }
-                        0x0C => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rrc h", opcode).to_string()))
-                        }
-                        0x0D => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rrc l", opcode).to_string()))
-                        }
-                        0x0E => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t rrc (hl)", opcode).to_string(),
-                        )),
-                        0x0F => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rrc a", opcode).to_string()))
-                        }
-                        0x10 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rl b", opcode).to_string()))
-                        }
-                        0x11 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rl c", opcode).to_string()))
-                        }
-                        0x12 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rl d", opcode).to_string()))
-                        }
-                        0x13 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rl e", opcode).to_string()))
-                        }
-                        0x14 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rl h", opcode).to_string()))
-                        }
-                        0x15 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rl l", opcode).to_string()))
-                        }
-                        0x16 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t rl (hl)", opcode).to_string(),
-                        )),
-                        0x17 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rl a", opcode).to_string()))
-                        }
-                        0x18 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rr b", opcode).to_string()))
-                        }
-                        0x19 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rr c", opcode).to_string()))
-                        }
-                        0x1A => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rr d", opcode).to_string()))
-                        }
-                        0x1B => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rr e", opcode).to_string()))
-                        }
-                        0x1C => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rr h", opcode).to_string()))
-                        }
-                        0x1D => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rr l", opcode).to_string()))
-                        }
-                        0x1E => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t rr (hl)", opcode).to_string(),
-                        )),
-                        0x1F => {
-                            ret.push((current_pc, format!("CB{:02X}:\t rr a", opcode).to_string()))
-                        }
-                        0x20 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sla b", opcode).to_string()))
-                        }
-                        0x21 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sla c", opcode).to_string()))
-                        }
-                        0x22 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sla d", opcode).to_string()))
-                        }
-                        0x23 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sla e", opcode).to_string()))
-                        }
-                        0x24 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sla h", opcode).to_string()))
-                        }
-                        0x25 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sla l", opcode).to_string()))
-                        }
-                        0x26 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t sla (hl)", opcode).to_string(),
-                        )),
-                        0x27 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sla a", opcode).to_string()))
-                        }
-                        0x28 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sra b", opcode).to_string()))
-                        }
-                        0x29 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sra c", opcode).to_string()))
-                        }
-                        0x2A => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sra d", opcode).to_string()))
-                        }
-                        0x2B => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sra e", opcode).to_string()))
-                        }
-                        0x2C => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sra h", opcode).to_string()))
-                        }
-                        0x2D => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sra l", opcode).to_string()))
-                        }
-                        0x2E => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t sra (hl)", opcode).to_string(),
-                        )),
-                        0x2F => {
-                            ret.push((current_pc, format!("CB{:02X}:\t sra a", opcode).to_string()))
-                        }
-                        0x30 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t swap b", opcode).to_string(),
-                        )),
-                        0x31 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t swap c", opcode).to_string(),
-                        )),
-                        0x32 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t swap d", opcode).to_string(),
-                        )),
-                        0x33 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t swap e", opcode).to_string(),
-                        )),
-                        0x34 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t swap h", opcode).to_string(),
-                        )),
-                        0x35 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t swap l", opcode).to_string(),
-                        )),
-                        0x36 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t swap (hl)", opcode).to_string(),
-                        )),
-                        0x37 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t swap a", opcode).to_string(),
-                        )),
-                        0x38 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t srl b", opcode).to_string()))
-                        }
-                        0x39 => {
-                            ret.push((current_pc, format!("CB{:02X}:\t srl c", opcode).to_string()))
-                        }
-                        0x3A => {
-                            ret.push((current_pc, format!("CB{:02X}:\t srl d", opcode).to_string()))
-                        }
-                        0x3B => {
-                            ret.push((current_pc, format!("CB{:02X}:\t srl e", opcode).to_string()))
-                        }
-                        0x3C => {
-                            ret.push((current_pc, format!("CB{:02X}:\t srl h", opcode).to_string()))
-                        }
-                        0x3D => {
-                            ret.push((current_pc, format!("CB{:02X}:\t srl l", opcode).to_string()))
-                        }
-                        0x3E => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t srl (hl)", opcode).to_string(),
-                        )),
-                        0x3F => {
-                            ret.push((current_pc, format!("CB{:02X}:\t srl a", opcode).to_string()))
-                        }
-                        0x40 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 0,b", opcode).to_string(),
-                        )),
-                        0x41 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 0,c", opcode).to_string(),
-                        )),
-                        0x42 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 0,d", opcode).to_string(),
-                        )),
-                        0x43 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 0,e", opcode).to_string(),
-                        )),
-                        0x44 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 0,h", opcode).to_string(),
-                        )),
-                        0x45 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 0,l", opcode).to_string(),
-                        )),
-                        0x46 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 0,(hl)", opcode).to_string(),
-                        )),
-                        0x47 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 0,a", opcode).to_string(),
-                        )),
-                        0x48 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 1,b", opcode).to_string(),
-                        )),
-                        0x49 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 1,c", opcode).to_string(),
-                        )),
-                        0x4A => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 1,d", opcode).to_string(),
-                        )),
-                        0x4B => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 1,e", opcode).to_string(),
-                        )),
-                        0x4C => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 1,h", opcode).to_string(),
-                        )),
-                        0x4D => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 1,l", opcode).to_string(),
-                        )),
-                        0x4E => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 1,(hl)", opcode).to_string(),
-                        )),
-                        0x4F => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 1,a", opcode).to_string(),
-                        )),
-                        0x50 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 2,b", opcode).to_string(),
-                        )),
-                        0x51 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 2,c", opcode).to_string(),
-                        )),
-                        0x52 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 2,d", opcode).to_string(),
-                        )),
-                        0x53 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 2,e", opcode).to_string(),
-                        )),
-                        0x54 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 2,h", opcode).to_string(),
-                        )),
-                        0x55 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 2,l", opcode).to_string(),
-                        )),
-                        0x56 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 2,(hl)", opcode).to_string(),
-                        )),
-                        0x57 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 2,a", opcode).to_string(),
-                        )),
-                        0x58 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 3,b", opcode).to_string(),
-                        )),
-                        0x59 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 3,c", opcode).to_string(),
-                        )),
-                        0x5A => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 3,d", opcode).to_string(),
-                        )),
-                        0x5B => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 3,e", opcode).to_string(),
-                        )),
-                        0x5C => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 3,h", opcode).to_string(),
-                        )),
-                        0x5D => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 3,l", opcode).to_string(),
-                        )),
-                        0x5E => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 3,(hl)", opcode).to_string(),
-                        )),
-                        0x5F => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 3,a", opcode).to_string(),
-                        )),
-                        0x60 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 4,b", opcode).to_string(),
-                        )),
-                        0x61 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 4,c", opcode).to_string(),
-                        )),
-                        0x62 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 4,d", opcode).to_string(),
-                        )),
-                        0x63 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 4,e", opcode).to_string(),
-                        )),
-                        0x64 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 4,h", opcode).to_string(),
-                        )),
-                        0x65 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 4,l", opcode).to_string(),
-                        )),
-                        0x66 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 4,(hl)", opcode).to_string(),
-                        )),
-                        0x67 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 4,a", opcode).to_string(),
-                        )),
-                        0x68 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 5,b", opcode).to_string(),
-                        )),
-                        0x69 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 5,c", opcode).to_string(),
-                        )),
-                        0x6A => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 5,d", opcode).to_string(),
-                        )),
-                        0x6B => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 5,e", opcode).to_string(),
-                        )),
-                        0x6C => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 5,h", opcode).to_string(),
-                        )),
-                        0x6D => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 5,l", opcode).to_string(),
-                        )),
-                        0x6E => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 5,(hl)", opcode).to_string(),
-                        )),
-                        0x6F => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 5,a", opcode).to_string(),
-                        )),
-                        0x70 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 6,b", opcode).to_string(),
-                        )),
-                        0x71 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 6,c", opcode).to_string(),
-                        )),
-                        0x72 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 6,d", opcode).to_string(),
-                        )),
-                        0x73 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 6,e", opcode).to_string(),
-                        )),
-                        0x74 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 6,h", opcode).to_string(),
-                        )),
-                        0x75 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 6,l", opcode).to_string(),
-                        )),
-                        0x76 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 6,(hl)", opcode).to_string(),
-                        )),
-                        0x77 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 6,a", opcode).to_string(),
-                        )),
-                        0x78 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 7,b", opcode).to_string(),
-                        )),
-                        0x79 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 7,c", opcode).to_string(),
-                        )),
-                        0x7A => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 7,d", opcode).to_string(),
-                        )),
-                        0x7B => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 7,e", opcode).to_string(),
-                        )),
-                        0x7C => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 7,h", opcode).to_string(),
-                        )),
-                        0x7D => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 7,l", opcode).to_string(),
-                        )),
-                        0x7E => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 7,(hl)", opcode).to_string(),
-                        )),
-                        0x7F => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t bit 7,a", opcode).to_string(),
-                        )),
-                        0x80 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 0,b", opcode).to_string(),
-                        )),
-                        0x81 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 0,c", opcode).to_string(),
-                        )),
-                        0x82 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 0,d", opcode).to_string(),
-                        )),
-                        0x83 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 0,e", opcode).to_string(),
-                        )),
-                        0x84 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 0,h", opcode).to_string(),
-                        )),
-                        0x85 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 0,l", opcode).to_string(),
-                        )),
-                        0x86 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 0,(hl)", opcode).to_string(),
-                        )),
-                        0x87 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 0,a", opcode).to_string(),
-                        )),
-                        0x88 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 1,b", opcode).to_string(),
-                        )),
-                        0x89 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 1,c", opcode).to_string(),
-                        )),
-                        0x8A => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 1,d", opcode).to_string(),
-                        )),
-                        0x8B => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 1,e", opcode).to_string(),
-                        )),
-                        0x8C => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 1,h", opcode).to_string(),
-                        )),
-                        0x8D => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 1,l", opcode).to_string(),
-                        )),
-                        0x8E => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 1,(hl)", opcode).to_string(),
-                        )),
-                        0x8F => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 1,a", opcode).to_string(),
-                        )),
-                        0x90 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 2,b", opcode).to_string(),
-                        )),
-                        0x91 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 2,c", opcode).to_string(),
-                        )),
-                        0x92 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 2,d", opcode).to_string(),
-                        )),
-                        0x93 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 2,e", opcode).to_string(),
-                        )),
-                        0x94 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 2,h", opcode).to_string(),
-                        )),
-                        0x95 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 2,l", opcode).to_string(),
-                        )),
-                        0x96 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 2,(hl)", opcode).to_string(),
-                        )),
-                        0x97 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 2,a", opcode).to_string(),
-                        )),
-                        0x98 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 3,b", opcode).to_string(),
-                        )),
-                        0x99 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 3,c", opcode).to_string(),
-                        )),
-                        0x9A => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 3,d", opcode).to_string(),
-                        )),
-                        0x9B => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 3,e", opcode).to_string(),
-                        )),
-                        0x9C => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 3,h", opcode).to_string(),
-                        )),
-                        0x9D => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 3,l", opcode).to_string(),
-                        )),
-                        0x9E => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 3,(hl)", opcode).to_string(),
-                        )),
-                        0x9F => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 3,a", opcode).to_string(),
-                        )),
-                        0xA0 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 4,b", opcode).to_string(),
-                        )),
-                        0xA1 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 4,c", opcode).to_string(),
-                        )),
-                        0xA2 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 4,d", opcode).to_string(),
-                        )),
-                        0xA3 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 4,e", opcode).to_string(),
-                        )),
-                        0xA4 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 4,h", opcode).to_string(),
-                        )),
-                        0xA5 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 4,l", opcode).to_string(),
-                        )),
-                        0xA6 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 4,(hl)", opcode).to_string(),
-                        )),
-                        0xA7 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 4,a", opcode).to_string(),
-                        )),
-                        0xA8 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 5,b", opcode).to_string(),
-                        )),
-                        0xA9 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 5,c", opcode).to_string(),
-                        )),
-                        0xAA => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 5,d", opcode).to_string(),
-                        )),
-                        0xAB => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 5,e", opcode).to_string(),
-                        )),
-                        0xAC => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 5,h", opcode).to_string(),
-                        )),
-                        0xAD => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 5,l", opcode).to_string(),
-                        )),
-                        0xAE => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 5,(hl)", opcode).to_string(),
-                        )),
-                        0xAF => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 5,a", opcode).to_string(),
-                        )),
-                        0xB0 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 6,b", opcode).to_string(),
-                        )),
-                        0xB1 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 6,c", opcode).to_string(),
-                        )),
-                        0xB2 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 6,d", opcode).to_string(),
-                        )),
-                        0xB3 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 6,e", opcode).to_string(),
-                        )),
-                        0xB4 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 6,h", opcode).to_string(),
-                        )),
-                        0xB5 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 6,l", opcode).to_string(),
-                        )),
-                        0xB6 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 6,(hl)", opcode).to_string(),
-                        )),
-                        0xB7 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 6,a", opcode).to_string(),
-                        )),
-                        0xB8 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 7,b", opcode).to_string(),
-                        )),
-                        0xB9 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 7,c", opcode).to_string(),
-                        )),
-                        0xBA => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 7,d", opcode).to_string(),
-                        )),
-                        0xBB => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 7,e", opcode).to_string(),
-                        )),
-                        0xBC => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 7,h", opcode).to_string(),
-                        )),
-                        0xBD => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 7,l", opcode).to_string(),
-                        )),
-                        0xBE => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 7,(hl)", opcode).to_string(),
-                        )),
-                        0xBF => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t res 7,a", opcode).to_string(),
-                        )),
-                        0xC0 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 0,b", opcode).to_string(),
-                        )),
-                        0xC1 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 0,c", opcode).to_string(),
-                        )),
-                        0xC2 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 0,d", opcode).to_string(),
-                        )),
-                        0xC3 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 0,e", opcode).to_string(),
-                        )),
-                        0xC4 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 0,h", opcode).to_string(),
-                        )),
-                        0xC5 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 0,l", opcode).to_string(),
-                        )),
-                        0xC6 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 0,(hl)", opcode).to_string(),
-                        )),
-                        0xC7 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 0,a", opcode).to_string(),
-                        )),
-                        0xC8 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 1,b", opcode).to_string(),
-                        )),
-                        0xC9 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 1,c", opcode).to_string(),
-                        )),
-                        0xCA => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 1,d", opcode).to_string(),
-                        )),
-                        0xCB => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 1,e", opcode).to_string(),
-                        )),
-                        0xCC => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 1,h", opcode).to_string(),
-                        )),
-                        0xCD => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 1,l", opcode).to_string(),
-                        )),
-                        0xCE => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 1,(hl)", opcode).to_string(),
-                        )),
-                        0xCF => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 1,a", opcode).to_string(),
-                        )),
-                        0xD0 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 2,b", opcode).to_string(),
-                        )),
-                        0xD1 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 2,c", opcode).to_string(),
-                        )),
-                        0xD2 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 2,d", opcode).to_string(),
-                        )),
-                        0xD3 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 2,e", opcode).to_string(),
-                        )),
-                        0xD4 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 2,h", opcode).to_string(),
-                        )),
-                        0xD5 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 2,l", opcode).to_string(),
-                        )),
-                        0xD6 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 2,(hl)", opcode).to_string(),
-                        )),
-                        0xD7 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 2,a", opcode).to_string(),
-                        )),
-                        0xD8 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 3,b", opcode).to_string(),
-                        )),
-                        0xD9 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 3,c", opcode).to_string(),
-                        )),
-                        0xDA => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 3,d", opcode).to_string(),
-                        )),
-                        0xDB => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 3,e", opcode).to_string(),
-                        )),
-                        0xDC => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 3,h", opcode).to_string(),
-                        )),
-                        0xDD => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 3,l", opcode).to_string(),
-                        )),
-                        0xDE => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 3,(hl)", opcode).to_string(),
-                        )),
-                        0xDF => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 3,a", opcode).to_string(),
-                        )),
-                        0xE0 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 4,b", opcode).to_string(),
-                        )),
-                        0xE1 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 4,c", opcode).to_string(),
-                        )),
-                        0xE2 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 4,d", opcode).to_string(),
-                        )),
-                        0xE3 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 4,e", opcode).to_string(),
-                        )),
-                        0xE4 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 4,h", opcode).to_string(),
-                        )),
-                        0xE5 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 4,l", opcode).to_string(),
-                        )),
-                        0xE6 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 4,(hl)", opcode).to_string(),
-                        )),
-                        0xE7 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 4,a", opcode).to_string(),
-                        )),
-                        0xE8 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 5,b", opcode).to_string(),
-                        )),
-                        0xE9 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 5,c", opcode).to_string(),
-                        )),
-                        0xEA => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 5,d", opcode).to_string(),
-                        )),
-                        0xEB => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 5,e", opcode).to_string(),
-                        )),
-                        0xEC => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 5,h", opcode).to_string(),
-                        )),
-                        0xED => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 5,l", opcode).to_string(),
-                        )),
-                        0xEE => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 5,(hl)", opcode).to_string(),
-                        )),
-                        0xEF => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 5,a", opcode).to_string(),
-                        )),
-                        0xF0 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 6,b", opcode).to_string(),
-                        )),
-                        0xF1 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 6,c", opcode).to_string(),
-                        )),
-                        0xF2 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 6,d", opcode).to_string(),
-                        )),
-                        0xF3 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 6,e", opcode).to_string(),
-                        )),
-                        0xF4 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 6,h", opcode).to_string(),
-                        )),
-                        0xF5 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 6,l", opcode).to_string(),
-                        )),
-                        0xF6 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 6,(hl)", opcode).to_string(),
-                        )),
-                        0xF7 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 6,a", opcode).to_string(),
-                        )),
-                        0xF8 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 7,b", opcode).to_string(),
-                        )),
-                        0xF9 => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 7,c", opcode).to_string(),
-                        )),
-                        0xFA => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 7,d", opcode).to_string(),
-                        )),
-                        0xFB => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 7,e", opcode).to_string(),
-                        )),
-                        0xFC => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 7,h", opcode).to_string(),
-                        )),
-                        0xFD => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 7,l", opcode).to_string(),
-                        )),
-                        0xFE => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 7,(hl)", opcode).to_string(),
-                        )),
-                        0xFF => ret.push((
-                            current_pc,
-                            format!("CB{:02X}:\t set 7,a", opcode).to_string(),
-                        )),
+            match inst {
+                Some(inst) => {
+                    let bytes = &data[offset..offset + inst.len];
+                    // RST opcodes encode their vector, so it's left as a number
+                    let is_rst = bytes[0] & 0xC7 == 0xC7;
+                    let comment = annotation(&inst).map(|c| format!(" ; {}", c));
+                    let text = match inst.target.and_then(|t| map.resolve(t, addr.bank)) {
+                        Some(target) if !is_rst => match labels.get(&target) {
+                            Some(label) => inst.text_with_label(label),
+                            None => inst.text,
+                        },
+                        _ => inst.text,
                     };
-                    current_pc += 1;
+                    let changed_by_rgbasm = match bytes[0] {
+                        0x10 => true,
+                        0xEA | 0xFA => bytes[2] == 0xFF,
+                        _ => false,
+                    };
+                    lines.push(match changed_by_rgbasm {
+                        true => format!("    {} ; {}", data_directive(bytes), text),
+                        false => format!("    {}", rgbds_syntax(&text)),
+                    });
+                    if let Some(comment) = comment {
+                        lines.last_mut().unwrap().push_str(&comment);
+                    }
+                    offset += inst.len;
+                }
+                None => {
+                    // Data runs stop at code, labels and the start of the header
+                    let len = (1..DATA_LINE_BYTES)
+                        .take_while(|&i| {
+                            let next = BankedAddress::from_rom_offset(rom_offset + i);
+                            offset + i < data.len()
+                                && map.kind(rom_offset + i) != ByteKind::Opcode
+                                && !labels.contains_key(&next)
+                                && rom_offset + i != 0x104
+                        })
+                        .count()
+                        + 1;
+                    lines.push(format!(
+                        "    {}",
+                        data_directive(&data[offset..offset + len])
+                    ));
+                    offset += len;
                 }
             }
-            _ => ret.push((current_pc, format!("{:02X}:\t ???", opcode).to_string())),
-        };
-        current_pc += OPCODE_SIZE[*opcode as usize] as u16;
+        }
+    }
+    lines
+}
+
+/// Rewrites an instruction from the syntax used by `decode` into rgbasm's, which puts memory
+/// operands in square brackets
+fn rgbds_syntax(text: &str) -> String {
+    match text {
+        "JP (HL)" => "JP HL".to_string(),
+        "LD (C),A" => "LDH [C],A".to_string(),
+        "LD A,(C)" => "LDH A,[C]".to_string(),
+        _ => text.replace('(', "[").replace(')', "]"),
+    }
+}
+
+/// Decodes the opcode following a 0xCB prefix, e.g. "BIT 3,(HL)". The register operated on is
+/// given by the low 3 bits, and the operation by the rest.
+fn cb_mnemonic(opcode: u8) -> String {
+    const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+    const SHIFTS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+    let reg = REGISTERS[(opcode & 0x07) as usize];
+    let bit = (opcode >> 3) & 0x07;
+    match opcode >> 6 {
+        0 => format!("{} {}", SHIFTS[bit as usize], reg),
+        1 => format!("BIT {},{}", bit, reg),
+        2 => format!("RES {},{}", bit, reg),
+        _ => format!("SET {},{}", bit, reg),
     }
-    ret
 }
 
 /// Returns a String representation of the
 pub fn get_opcode(opcode: u8) -> String {
-    OPCODE_STRINGS[opcode as usize].to_string()
+    opcodes::info(opcode).mnemonic.to_string()
 }
 
-const OPCODE_STRINGS: [&str; 256] = [
-    "NOP",
-    "LD BC,d16",
-    "LD (BC),A",
-    "INC BC",
-    "INC B",
-    "DEC B",
-    "LD B,d8",
-    "RLCA",
-    "LD (a16),SP",
-    "ADD HL,BC",
-    "LD A,(BC)",
-    "DEC BC",
-    "INC C",
-    "DEC C",
-    "LD C,d8",
-    "RRCA",
-    "STOP 0",
-    "LD DE,d16",
-    "LD (DE),A",
-    "INC DE",
-    "INC D",
-    "DEC D",
-    "LD D,d8",
-    "RLA",
-    "JR r8",
-    "ADD HL,DE",
-    "LD A,(DE)",
-    "DEC DE",
-    "INC E",
-    "DEC E",
-    "LD E,d8",
-    "RRA",
-    "JR NZ,r8",
-    "LD HL,d16",
-    "LD (HL+),A",
-    "INC HL",
-    "INC H",
-    "DEC H",
-    "LD H,d8",
-    "DAA",
-    "JR Z,r8",
-    "ADD HL,HL",
-    "LD A,(HL+)",
-    "DEC HL",
-    "INC L",
-    "DEC L",
-    "LD L,d8",
-    "CPL",
-    "JR NC,r8",
-    "LD SP,d16",
-    "LD (HL-),A",
-    "INC SP",
-    "INC (HL)",
-    "DEC (HL)",
-    "LD (HL),d8",
-    "SCF",
-    "JR C,r8",
-    "ADD HL,SP",
-    "LD A,(HL-)",
-    "DEC SP",
-    "INC A",
-    "DEC A",
-    "LD A,d8",
-    "CCF",
-    "LD B,B",
-    "LD B,C",
-    "LD B,D",
-    "LD B,E",
-    "LD B,H",
-    "LD B,L",
-    "LD B,(HL)",
-    "LD B,A",
-    "LD C,B",
-    "LD C,C",
-    "LD C,D",
-    "LD C,E",
-    "LD C,H",
-    "LD C,L",
-    "LD C,(HL)",
-    "LD C,A",
-    "LD D,B",
-    "LD D,C",
-    "LD D,D",
-    "LD D,E",
-    "LD D,H",
-    "LD D,L",
-    "LD D,(HL)",
-    "LD D,A",
-    "LD E,B",
-    "LD E,C",
-    "LD E,D",
-    "LD E,E",
-    "LD E,H",
-    "LD E,L",
-    "LD E,(HL)",
-    "LD E,A",
-    "LD H,B",
-    "LD H,C",
-    "LD H,D",
-    "LD H,E",
-    "LD H,H",
-    "LD H,L",
-    "LD H,(HL)",
-    "LD H,A",
-    "LD L,B",
-    "LD L,C",
-    "LD L,D",
-    "LD L,E",
-    "LD L,H",
-    "LD L,L",
-    "LD L,(HL)",
-    "LD L,A",
-    "LD (HL),B",
-    "LD (HL),C",
-    "LD (HL),D",
-    "LD (HL),E",
-    "LD (HL),H",
-    "LD (HL),L",
-    "HALT",
-    "LD (HL),A",
-    "LD A,B",
-    "LD A,C",
-    "LD A,D",
-    "LD A,E",
-    "LD A,H",
-    "LD A,L",
-    "LD A,(HL)",
-    "LD A,A",
-    "ADD A,B",
-    "ADD A,C",
-    "ADD A,D",
-    "ADD A,E",
-    "ADD A,H",
-    "ADD A,L",
-    "ADD A,(HL)",
-    "ADD A,A",
-    "ADC A,B",
-    "ADC A,C",
-    "ADC A,D",
-    "ADC A,E",
-    "ADC A,H",
-    "ADC A,L",
-    "ADC A,(HL)",
-    "ADC A,A",
-    "SUB A,B",
-    "SUB A,C",
-    "SUB A,D",
-    "SUB A,E",
-    "SUB A,H",
-    "SUB A,L",
-    "SUB A,(HL)",
-    "SUB A,A",
-    "SBC A,B",
-    "SBC A,C",
-    "SBC A,D",
-    "SBC A,E",
-    "SBC A,H",
-    "SBC A,L",
-    "SBC A,(HL)",
-    "SBC A,A",
-    "AND B",
-    "AND C",
-    "AND D",
-    "AND E",
-    "AND H",
-    "AND L",
-    "AND (HL)",
-    "AND A",
-    "XOR B",
-    "XOR C",
-    "XOR D",
-    "XOR E",
-    "XOR H",
-    "XOR L",
-    "XOR (HL)",
-    "XOR A",
-    "OR B",
-    "OR C",
-    "OR D",
-    "OR E",
-    "OR H",
-    "OR L",
-    "OR (HL)",
-    "OR A",
-    "CP B",
-    "CP C",
-    "CP D",
-    "CP E",
-    "CP H",
-    "CP L",
-    "CP (HL)",
-    "CP A",
-    "RET NZ",
-    "POP BC",
-    "JP NZ,a16",
-    "JP a16",
-    "CALL NZ,a16",
-    "PUSH BC",
-    "ADD A,d8",
-    "RST 00H",
-    "RET Z",
-    "RET",
-    "JP Z,a16",
-    "CB ",
-    "CALL Z,a16",
-    "CALL a16",
-    "ADC A,d8",
-    "RST 08H",
-    "RET NC",
-    "POP DE",
-    "JP NC,a16",
-    "NULL",
-    "CALL NC,a16",
-    "PUSH DE",
-    "SUB d8",
-    "RST 10H",
-    "RET C",
-    "RETI",
-    "JP C,a16",
-    "NULL",
-    "CALL C,a16",
-    "NULL",
-    "SBC A,d8",
-    "RST 18H",
-    "LDH (a8),A",
-    "POP HL",
-    "LD (C),A",
-    "NULL",
-    "NULL",
-    "PUSH HL",
-    "AND d8",
-    "RST 20H",
-    "ADD SP,r8",
-    "JP (HL)",
-    "JP (a16),A",
-    "NULL",
-    "NULL",
-    "NULL",
-    "XOR d8",
-    "RST 28H",
-    "LDH A,(a8)",
-    "POP AF",
-    "LD A,(C)",
-    "DI",
-    "NULL",
-    "PUSH AF",
-    "OR d8",
-    "RST 30H",
-    "LD HL,SP+r8",
-    "LD SP,HL",
-    "JP A,(a16)",
-    "EI",
-    "NULL",
-    "NULL",
-    "CP d8",
-    "RST 38H",
-];
-
-/// Tables of opcode sizes in bytes
-/// Skipped when running rustfmt
-#[rustfmt::skip]
-const OPCODE_SIZE: [usize; 256] = [
-//  0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
-    1, 3, 1, 1, 1, 1, 2, 1, 3, 1, 1, 1, 1, 1, 2, 1, // 0
-    1, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1, // 1
-    2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1, // 2
-    2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1, // 3
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 4
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 5
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 6
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 7
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 8
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 9
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // A
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // B
-    1, 1, 3, 3, 3, 1, 2, 1, 1, 1, 3, 1, 3, 3, 2, 1, // C
-    1, 1, 3, 1, 3, 1, 2, 1, 1, 1, 3, 1, 3, 1, 2, 1, // D
-    2, 1, 1, 1, 1, 1, 2, 1, 2, 1, 3, 1, 1, 1, 2, 1, // E
-    2, 1, 1, 1, 1, 1, 2, 1, 2, 1, 3, 1, 1, 1, 2, 1, // F
-];
+/// Returns a String representation of the opcode following a 0xCB prefix, in the same style
+/// as `get_opcode`, e.g. "BIT 3,(HL)"
+pub fn get_cb_opcode(opcode: u8) -> String {
+    cb_mnemonic(opcode)
+}
 
 #[cfg(test)]
 mod disassemble_tests {
+    use super::*;
+
     #[test]
     fn interrupt_requests() {}
+
+    #[test]
+    fn cb_opcodes() {
+        let lines = disassemble_block(
+            &[0xCB, 0x37, 0xCB, 0x7E, 0xCB, 0x87, 0xCB, 0xF9, 0x00],
+            0x100,
+        );
+        assert_eq!(
+            lines,
+            [
+                (0x100, "CB37:\t SWAP A".to_string()),
+                (0x102, "CB7E:\t BIT 7,(HL)".to_string()),
+                (0x104, "CB87:\t RES 0,A".to_string()),
+                (0x106, "CBF9:\t SET 7,C".to_string()),
+                (0x108, "00:\t NOP".to_string()),
+            ]
+        );
+        assert_eq!(get_cb_opcode(0x1E), "RR (HL)");
+    }
+
+    /// 64k of memory, read as the CPU would see it
+    struct TestRam(Vec<u8>);
+
+    impl Memory for TestRam {
+        fn read_byte(&self, addr: u16) -> u8 {
+            self.0[addr as usize]
+        }
+
+        fn write_byte(&mut self, addr: u16, val: u8) {
+            self.0[addr as usize] = val
+        }
+    }
+
+    #[test]
+    fn memory() {
+        let mut ram = TestRam(vec![0; 0x10000]);
+        ram.0[0x150..0x155].copy_from_slice(&[0x3E, 0x12, 0xC3, 0x50, 0x01]);
+        let insts: Vec<Instruction> = disassemble_memory(&ram, 0x150).take(2).collect();
+        assert_eq!(insts[0].text, "LD A,$12");
+        assert_eq!(insts[1].target, Some(0x150));
+        assert_eq!(format_memory(&ram, &insts[1]), "C35001:\t JP $0150");
+        // Stops at the end of memory rather than wrapping, or reading past it
+        ram.0[0xFFFE] = 0x01;
+        let end: Vec<u16> = disassemble_memory(&ram, 0xFFFC)
+            .map(|inst| inst.address)
+            .collect();
+        assert_eq!(end, [0xFFFC, 0xFFFD]);
+        assert_eq!(decode_memory(&ram, 0xFFFE), None);
+        ram.0[0xFFFE] = 0x00;
+        assert_eq!(disassemble_memory(&ram, 0xFFFC).count(), 4);
+    }
+
+    #[test]
+    fn cross_references() {
+        let mut rom = vec![0; ROM_BANK_SIZE * 3];
+        // CALL $0200; JP $0150
+        rom[0x150..0x156].copy_from_slice(&[0xCD, 0x00, 0x02, 0xC3, 0x50, 0x01]);
+        // LD A,($C000); LD ($C000),A; LDH ($FF40),A; JR NZ,$0200; RST $38; RET
+        rom[0x200..0x20B].copy_from_slice(&[
+            0xFA, 0x00, 0xC0, 0xEA, 0x00, 0xC0, 0xE0, 0x40, 0x20, 0xF6, 0xFF,
+        ]);
+        rom[0x20B] = 0xC9;
+        // Bank 2: JP $4000
+        rom[0x8000..0x8003].copy_from_slice(&[0xC3, 0x00, 0x40]);
+        let mut coverage = Coverage::new();
+        coverage.record(0x8000);
+        let refs = CodeMap::trace_with_coverage(&rom, coverage).cross_references(&rom);
+        let from = |bank, address| BankedAddress { bank, address };
+        let summary = |address| -> Vec<(BankedAddress, RefKind)> {
+            refs.to(address).iter().map(|r| (r.from, r.kind)).collect()
+        };
+        assert_eq!(summary(0x150), [(from(0, 0x153), RefKind::Jump)]);
+        assert_eq!(
+            summary(0x200),
+            [
+                (from(0, 0x150), RefKind::Call),
+                (from(0, 0x208), RefKind::Jump)
+            ]
+        );
+        assert_eq!(
+            summary(0xC000),
+            [
+                (from(0, 0x200), RefKind::Read),
+                (from(0, 0x203), RefKind::Write)
+            ]
+        );
+        assert_eq!(summary(0xFF40), [(from(0, 0x206), RefKind::Write)]);
+        assert_eq!(summary(0x38), [(from(0, 0x20A), RefKind::Call)]);
+        assert!(refs.to(0x300).is_empty());
+        // Only the jump from bank 2 goes to bank 2
+        assert_eq!(refs.to(0x4000)[0].to_bank, Some(2));
+        assert_eq!(refs.to_banked(from(2, 0x4000)).count(), 1);
+        assert_eq!(refs.to_banked(from(1, 0x4000)).count(), 0);
+    }
+
+    #[test]
+    fn banked_rom() {
+        let mut rom = vec![0; ROM_BANK_SIZE * 3];
+        rom[0x8000] = 0x3E;
+        rom[0x8001] = 0x01;
+        let lines = disassemble_rom(&rom, &CodeMap::trace(&rom));
+        assert_eq!(lines[0], "; Bank 00");
+        // Bank 0 is all NOPs run from the RST 00 vector, which stop at the end of the bank
+        assert_eq!(lines[1], "00:0000  00:\t NOP");
+        let bank1 = lines.iter().position(|l| l == "; Bank 01").unwrap();
+        assert_eq!(lines[bank1 - 1], "00:3FFF  00:\t NOP");
+        assert_eq!(
+            lines[bank1 + 1],
+            "01:4000  0000000000000000:\t db $00,$00,$00,$00,$00,$00,$00,$00"
+        );
+        let bank2 = lines.iter().position(|l| l == "; Bank 02").unwrap();
+        assert_eq!(
+            lines[bank2 + 1],
+            "02:4000  3E01000000000000:\t db $3E,$01,$00,$00,$00,$00,$00,$00"
+        );
+        assert_eq!(
+            lines.last().unwrap(),
+            "02:7FF8  0000000000000000:\t db $00,$00,$00,$00,$00,$00,$00,$00"
+        );
+        let addr = BankedAddress::from_rom_offset(0x8002);
+        assert_eq!((addr.bank, addr.address), (2, 0x4002));
+        assert_eq!(addr.rom_offset(), 0x8002);
+        assert_eq!(
+            BankedAddress::from_rom_offset(0x3FFF).to_string(),
+            "00:3FFF"
+        );
+    }
+
+    #[test]
+    fn traced_code() {
+        let mut rom = vec![0xFF; ROM_BANK_SIZE * 4];
+        // 0100: JR 0150; 0150: CALL NZ,0160; LD A,$02; LD ($2000),A; JP $4000
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0x4E]);
+        rom[0x150..0x15A]
+            .copy_from_slice(&[0xC4, 0x60, 0x01, 0x3E, 0x02, 0xEA, 0x00, 0x20, 0xC3, 0x00]);
+        rom[0x15A] = 0x40;
+        // 0160: RET
+        rom[0x160] = 0xC9;
+        // 02:4000: JR 4000, which only gets traced from code in bank 2
+        rom[0x8000..0x8002].copy_from_slice(&[0x18, 0xFE]);
+        let map = CodeMap::trace(&rom);
+        assert_eq!(map.kind(0x100), ByteKind::Opcode);
+        assert_eq!(map.kind(0x101), ByteKind::Operand);
+        assert_eq!(map.kind(0x102), ByteKind::Data);
+        assert_eq!(map.kind(0x153), ByteKind::Opcode);
+        assert_eq!(map.kind(0x160), ByteKind::Opcode);
+        assert_eq!(map.kind(0x161), ByteKind::Data);
+        // RST 38 is traced from its vector, and loops on the 0xFF that follows it
+        assert_eq!(map.kind(0x38), ByteKind::Opcode);
+        assert_eq!(map.kind(0x39), ByteKind::Opcode);
+        // The bank mapped at 0x4000 isn't known from bank 0
+        assert_eq!(map.kind(0x8000), ByteKind::Data);
+        let lines = disassemble_rom(&rom, &map);
+        assert!(lines.contains(&"00:0150  C46001:\t CALL NZ,$0160".to_string()));
+        let call = disassemble_rom_lines(&rom, &map)
+            .into_iter()
+            .find(|l| l.address == 0x150)
+            .unwrap();
+        assert_eq!(call.mnemonic, "CALL");
+        assert_eq!(call.operands, ["NZ", "$0160"]);
+        assert_eq!((call.target, call.target_bank), (Some(0x160), Some(0)));
+        assert!(lines.contains(
+            &"00:0102  FFFFFFFFFFFFFFFF:\t db $FF,$FF,$FF,$FF,$FF,$FF,$FF,$FF".to_string()
+        ));
+    }
+
+    #[test]
+    fn coverage_guided() {
+        let mut rom = vec![0xFF; ROM_BANK_SIZE * 4];
+        // 0100: CALL $4000; JP (HL)
+        rom[0x100..0x104].copy_from_slice(&[0xCD, 0x00, 0x40, 0xE9]);
+        // 02:4000: RET, only seen running in bank 2
+        rom[0x8000] = 0xC9;
+        // 0200: NOP; RET, reached through JP (HL)
+        rom[0x200..0x202].copy_from_slice(&[0x00, 0xC9]);
+        let untraced = CodeMap::trace(&rom);
+        assert_eq!(untraced.kind(0x8000), ByteKind::Data);
+        assert_eq!(untraced.kind(0x200), ByteKind::Data);
+        let mut coverage = Coverage::new();
+        coverage.record(0x8000);
+        coverage.record(0x200);
+        let map = CodeMap::trace_with_coverage(&rom, coverage);
+        assert_eq!(map.kind(0x8000), ByteKind::Opcode);
+        assert_eq!(map.kind(0x201), ByteKind::Opcode);
+        let call = disassemble_rom_lines(&rom, &map)
+            .into_iter()
+            .find(|l| l.address == 0x100)
+            .unwrap();
+        assert_eq!(call.target_bank, Some(2));
+    }
+
+    #[test]
+    fn rgbds_output() {
+        let mut rom = vec![0; ROM_BANK_SIZE * 2];
+        // 0000: JP (HL); 0100: NOP; JP 0150; 0150: LD ($FF40),A; LD A,(C); JR 0150
+        rom[0] = 0xE9;
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[0x134..0x138].copy_from_slice(b"GABE");
+        rom[0x150..0x156].copy_from_slice(&[0xEA, 0x40, 0xFF, 0xF2, 0x18, 0xFA]);
+        let lines = disassemble_rom_rgbds(&rom, &CodeMap::trace(&rom), &BTreeMap::new());
+        assert_eq!(lines[0], "SECTION \"ROM Bank $00\", ROM0[$0000]");
+        assert_eq!(lines[1..3], ["RST_00:", "    JP HL"]);
+        let entry = lines.iter().position(|l| l == "    JP L_0150").unwrap();
+        assert_eq!(lines[entry - 2..entry], ["EntryPoint:", "    NOP"]);
+        assert_eq!(
+            lines[entry + 2],
+            "    db $47,$41,$42,$45,$00,$00,$00,$00,$00,$00,$00,$00,$00,$00,$00,$00 ; Title"
+        );
+        assert_eq!(lines[entry + 12], "    db $00,$00 ; Global checksum");
+        assert_eq!(
+            &lines[entry + 13..entry + 17],
+            [
+                "L_0150:",
+                "    db $EA,$40,$FF ; LD ($FF40),A ; rLCDC",
+                "    LDH A,[C]",
+                "    JR L_0150"
+            ]
+        );
+        assert!(lines.contains(&"SECTION \"ROM Bank $01\", ROMX[$4000], BANK[$01]".to_string()));
+        assert_eq!(
+            rom_label_name(BankedAddress {
+                bank: 1,
+                address: 0x4000
+            }),
+            "L_01_4000"
+        );
+    }
+
+    #[test]
+    fn symbols() {
+        let mut rom = vec![0; ROM_BANK_SIZE * 2];
+        // 0150: JP 0160; 0160: JR 0160, with 0155 named as data
+        rom[0x150..0x153].copy_from_slice(&[0xC3, 0x60, 0x01]);
+        rom[0x160..0x162].copy_from_slice(&[0x18, 0xFE]);
+        let names = parse_symbols("; Comment\n00:0155 Table ; inline\n00:0160 Loop\nbad line\n");
+        let table = BankedAddress {
+            bank: 0,
+            address: 0x155,
+        };
+        assert_eq!(names.get(&table).map(String::as_str), Some("Table"));
+        let symbols = rom_symbols(&rom, &CodeMap::trace(&rom), &names);
+        // The vectors are all traced through the NOPs that fill them
+        let text = write_symbols(&symbols);
+        assert!(text.starts_with("; File generated by gabe\n00:0000 RST_00\n"));
+        assert!(text.ends_with("00:0100 EntryPoint\n00:0155 Table\n00:0160 Loop\n"));
+        assert_eq!(parse_symbols(&write_symbols(&symbols)), symbols);
+        let lines = disassemble_rom_rgbds(&rom, &CodeMap::trace(&rom), &names);
+        let table = lines.iter().position(|l| l == "Table:").unwrap();
+        assert_eq!(lines[table + 1], "    db $00,$00,$00,$00,$00,$00,$00,$00");
+        assert_eq!(lines[table + 3], "Loop:");
+        assert_eq!(lines[table + 4], "    JR Loop");
+    }
+
+    #[test]
+    fn labels() {
+        // 0100: JP 0107, RST 08 outside the block, CALL 0109, then at 0107: JR -2, RET
+        let data = [0xC3, 0x07, 0x01, 0xCF, 0xCD, 0x09, 0x01, 0x18, 0xFE, 0xC9];
+        assert_eq!(
+            disassemble_with_labels(&data, 0x100),
+            [
+                "    JP L_0107",
+                "    RST $08",
+                "    CALL L_0109",
+                "L_0107:",
+                "    JR L_0107",
+                "L_0109:",
+                "    RET",
+            ]
+        );
+        assert_eq!(decode(&[0xFF], 0).unwrap().target, Some(0x38));
+    }
+
+    #[test]
+    fn operands() {
+        let text = |data: &[u8], pc| decode(data, pc).unwrap().text;
+        assert_eq!(text(&[0x06, 0x3E], 0), "LD B,$3E");
+        assert_eq!(text(&[0xC3, 0x50, 0x01], 0), "JP $0150");
+        assert_eq!(text(&[0xE0, 0x40], 0), "LDH ($FF40),A");
+        assert_eq!(text(&[0xEA, 0x00, 0xC0], 0), "LD ($C000),A");
+        // Relative jumps are resolved from the address after the instruction
+        assert_eq!(text(&[0x20, 0xFE], 0x150), "JR NZ,$0150");
+        assert_eq!(text(&[0x18, 0x10], 0x150), "JR $0162");
+        assert_eq!(text(&[0xE8, 0xFE], 0), "ADD SP,-2");
+        assert_eq!(text(&[0xF8, 0x02], 0), "LD HL,SP+2");
+        assert_eq!(text(&[0xD3], 0), "???");
+        let comment = |data: &[u8]| annotation(&decode(data, 0).unwrap());
+        assert_eq!(comment(&[0xE0, 0x40]), Some("rLCDC"));
+        assert_eq!(comment(&[0x21, 0x44, 0xFF]), Some("rLY"));
+        assert_eq!(comment(&[0xEA, 0xFF, 0xFF]), Some("rIE"));
+        assert_eq!(comment(&[0xFF]), Some("RST_38"));
+        assert_eq!(comment(&[0x3E, 0xFF]), None);
+        assert_eq!(comment(&[0xEA, 0x80, 0xFF]), None);
+        // Operands cut off by the end of the data
+        assert_eq!(decode(&[0xC3, 0x50], 0), None);
+        assert_eq!(decode(&[0xCB], 0), None);
+    }
 }
@@ -0,0 +1,52 @@
+//! Errors reported by the emulator to the frontend
+
+use alloc::fmt;
+
+/// Error type for problems with a ROM or with running it, which a frontend can report to the
+/// user instead of the emulator panicking.
+///
+/// There's no I/O error, as the core is `no_std` and is handed ROMs and saves as bytes, so
+/// reading them is left to the frontend. Nor is there one for illegal memory accesses: on a
+/// real Gameboy, reading an unmapped address gives 0xFF and writing one does nothing, which
+/// the MMU does too, so games that do it keep running as they would on hardware.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GabeError {
+    /// A `GameboyBuilder` was built without being given a ROM
+    MissingRom,
+    /// The ROM is too small to hold a cartridge header, or bigger than its cartridge type allows
+    InvalidRomLength(usize),
+    /// A boot ROM was given that isn't the 256 bytes of the DMG, MGB or SGB boot ROM
+    InvalidBootRomLength(usize),
+    /// The cartridge type in the header, at 0x147, isn't emulated
+    UnsupportedMapper(u8),
+    /// The ROM size in the header, at 0x148, isn't valid for the cartridge type
+    UnsupportedRomSize(u8),
+    /// The RAM size in the header, at 0x149, isn't valid for the cartridge type
+    UnsupportedRamSize(u8),
+    /// The CPU ran an opcode that doesn't exist, which locks up a real Gameboy
+    IllegalOpcode { opcode: u8, address: u16 },
+}
+
+impl fmt::Display for GabeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GabeError::MissingRom => write!(f, "No ROM was given"),
+            GabeError::InvalidRomLength(len) => write!(f, "Invalid ROM length: {} bytes", len),
+            GabeError::InvalidBootRomLength(len) => {
+                write!(f, "Invalid boot ROM length: {} bytes", len)
+            }
+            GabeError::UnsupportedMapper(kind) => {
+                write!(f, "Unsupported cartridge type: {:02X}", kind)
+            }
+            GabeError::UnsupportedRomSize(size) => write!(f, "Unsupported ROM size: {:02X}", size),
+            GabeError::UnsupportedRamSize(size) => write!(f, "Unsupported RAM size: {:02X}", size),
+            GabeError::IllegalOpcode { opcode, address } => {
+                write!(f, "Illegal opcode {:02X} at {:04X}", opcode, address)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for GabeError {}
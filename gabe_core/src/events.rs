@@ -0,0 +1,59 @@
+//! Events raised while emulating, which frontends and tools can watch for and react to
+//! instead of polling registers after every step
+
+/// Maximum number of events queued. Once full, the oldest events are dropped.
+pub(crate) const EVENT_QUEUE_SIZE: usize = 4096;
+
+/// Something that happened while emulating
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+    /// The PPU entered VBlank, after drawing a frame
+    VBlank,
+    /// A serial transfer finished, having shifted out this byte
+    SerialSent(u8),
+    /// A different cartridge ROM bank was mapped into 0x4000-0x7FFF
+    RomBankSwitched(u16),
+    /// A different cartridge RAM bank was mapped into 0xA000-0xBFFF
+    RamBankSwitched(u8),
+    /// The CPU wrote to the cartridge RAM area, 0xA000-0xBFFF. Writes while the cartridge's
+    /// RAM is disabled are included, though they don't change the RAM.
+    SramWrite { address: u16, value: u8 },
+    /// The cartridge's rumble motor was turned on (true) or off (false). Games vary the
+    /// strength by switching it on and off many times a frame.
+    Rumble(bool),
+}
+
+/// The kinds of events, for choosing which to watch with `Gameboy::watch_event`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EventKind {
+    VBlank = 0b0000_0001,
+    SerialSent = 0b0000_0010,
+    RomBankSwitched = 0b0000_0100,
+    RamBankSwitched = 0b0000_1000,
+    SramWrite = 0b0001_0000,
+    Rumble = 0b0010_0000,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::VBlank => EventKind::VBlank,
+            Event::SerialSent(_) => EventKind::SerialSent,
+            Event::RomBankSwitched(_) => EventKind::RomBankSwitched,
+            Event::RamBankSwitched(_) => EventKind::RamBankSwitched,
+            Event::SramWrite { .. } => EventKind::SramWrite,
+            Event::Rumble(_) => EventKind::Rumble,
+        }
+    }
+}
+
+/// An event, with when it happened
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimedEvent {
+    /// The emulated cycle count at the end of the step that raised the event
+    pub cycle: u64,
+    pub event: Event,
+}
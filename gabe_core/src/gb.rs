@@ -1,17 +1,60 @@
+use super::camera::CameraSensor;
+use super::cheats::{Cheat, CheatCode};
 use super::cpu;
+use super::debug::*;
+use super::error::GabeError;
+use super::events::*;
+pub use super::joypad::MAX_PLAYERS;
 use super::mmu;
 use super::mmu::Memory;
+use super::palette::{Colorization, Palette};
+use super::rtc::RtcClock;
+use super::serial::SerialDevice;
 use super::sink::*;
+use super::util::hash::Fnv64;
+use super::vram::FrameData;
 
 use alloc::boxed::*;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// Audio samples held back by `Gameboy::step` before they're passed on together, about 2ms
+/// of sound
+const AUDIO_BATCH: usize = 512;
+/// Size of the DMG, MGB and SGB boot ROMs
+const BOOT_ROM_SIZE: usize = 0x100;
 
 pub struct Gameboy {
+    model: Model,
+    /// Whether games flagged as supporting the CGB are told they're in CGB mode
+    cgb_mode: bool,
+    ram_init: RamInit,
     cpu: cpu::Cpu,
     mmu: mmu::Mmu,
+    cycles: u64,
+    /// Frames drawn since power on
+    frames: u64,
+    /// Hash of the pixels of the last frame drawn
+    frame_hash: Option<u64>,
+    /// Running hash of every audio sample produced
+    audio_hash: Fnv64,
+    #[cfg(feature = "debug-hooks")]
+    interrupt_log: InterruptLog,
+    /// The ROM instructions run, while recording
+    #[cfg(feature = "debug-hooks")]
+    coverage: Option<Coverage>,
+    cheats: Vec<Cheat>,
+    /// Input changes waiting for their time to come, in the order they were queued
+    input_queue: VecDeque<(InputTime, JoypadState)>,
+    /// The event kinds being watched, as a mask of `EventKind` bits
+    watched_events: u8,
+    events: VecDeque<TimedEvent>,
 }
 
 /// The supported input states for the Joypad.
 /// User provides a combined mask of these values during each step call
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GbKeys {
     Right = 0,
     Left = 1,
@@ -23,6 +66,468 @@ pub enum GbKeys {
     Start = 7,
 }
 
+/// The state of all eight buttons at once, with a bit set for each button held. Bits are
+/// numbered as in `GbKeys`, and states are combined with the usual bitwise operators.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JoypadState(u8);
+
+impl JoypadState {
+    pub const RIGHT: Self = Self(1 << GbKeys::Right as u8);
+    pub const LEFT: Self = Self(1 << GbKeys::Left as u8);
+    pub const UP: Self = Self(1 << GbKeys::Up as u8);
+    pub const DOWN: Self = Self(1 << GbKeys::Down as u8);
+    pub const A: Self = Self(1 << GbKeys::A as u8);
+    pub const B: Self = Self(1 << GbKeys::B as u8);
+    pub const SELECT: Self = Self(1 << GbKeys::Select as u8);
+    pub const START: Self = Self(1 << GbKeys::Start as u8);
+
+    /// No buttons held
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Every button held
+    pub const fn all() -> Self {
+        Self(0xFF)
+    }
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if every button held in `other` is also held in `self`
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+
+    /// Inserts `other` if `held`, otherwise removes it
+    pub fn set(&mut self, other: Self, held: bool) {
+        if held {
+            self.insert(other);
+        } else {
+            self.remove(other);
+        }
+    }
+}
+
+/// When an input change queued with `Gameboy::queue_input` takes effect. Both count from
+/// power on, and the change is applied before the first instruction run once it's due.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InputTime {
+    /// Once this many cycles have run
+    Cycle(u64),
+    /// Once this many frames have been drawn, so the change is seen while the frame with
+    /// this index is drawn
+    Frame(u64),
+}
+
+impl From<GbKeys> for JoypadState {
+    fn from(key: GbKeys) -> Self {
+        Self(1 << key as u8)
+    }
+}
+
+impl core::ops::BitOr for JoypadState {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for JoypadState {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl core::ops::BitAnd for JoypadState {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl core::ops::Not for JoypadState {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(!self.0)
+    }
+}
+
+/// The Gameboy hardware being emulated. Each model's boot ROM leaves the CPU registers with
+/// different values, which games read to tell which model they're running on. Only the DMG's
+/// hardware is emulated, so CGB features such as color palettes and double speed aren't
+/// available, even to games told they're running in CGB mode. Games made only for the DMG are
+/// colorized on the CGB, as it does with its compatibility palettes, and games flagged as
+/// supporting the CGB are run as if made for the DMG unless `GameboyBuilder::cgb_mode` is set.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Model {
+    /// The original Gameboy
+    #[default]
+    Dmg,
+    /// The Gameboy Pocket
+    Mgb,
+    /// The Super Gameboy
+    Sgb,
+    /// The Gameboy Color
+    Cgb,
+}
+
+impl Model {
+    /// Returns the registers as the model's boot ROM leaves them, given the CGB flag from
+    /// the ROM header. On the CGB, games flagged as supporting it are told they're in CGB mode
+    /// if `cgb_mode` is set, and otherwise that they're on a DMG, as the CGB's own features
+    /// aren't emulated.
+    fn boot_registers(self, cgb_flag: u8, cgb_mode: bool) -> Registers {
+        let cgb_game = cgb_flag & 0x80 != 0;
+        let model = match self {
+            Model::Cgb if cgb_game && !cgb_mode => Model::Dmg,
+            model => model,
+        };
+        let [a, f, b, c, d, e, h, l] = match model {
+            Model::Dmg => [0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::Mgb => [0xFF, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::Sgb => [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+            Model::Cgb if cgb_game => [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+            Model::Cgb => [0x11, 0x80, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7C],
+        };
+        Registers {
+            a,
+            f,
+            b,
+            c,
+            d,
+            e,
+            h,
+            l,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        }
+    }
+}
+
+/// What work RAM, high RAM and VRAM hold at power on. On real hardware they come up holding a
+/// semi-random pattern, which some games accidentally depend on. VRAM is only filled without a
+/// boot ROM, as the boot ROM clears it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RamInit {
+    /// Every byte cleared to 0
+    #[default]
+    Zeroed,
+    /// Every byte set to the value
+    Fill(u8),
+    /// Pseudo-random bytes generated from the seed, the same for every run with that seed
+    Random(u64),
+}
+
+/// Sets up a Gameboy with options applied before it powers on, so the first instruction
+/// already runs with them. Created by `Gameboy::builder`, and only a ROM is required.
+#[derive(Default)]
+pub struct GameboyBuilder {
+    rom: Option<Box<[u8]>>,
+    boot_rom: Option<Box<[u8]>>,
+    model: Model,
+    cgb_mode: bool,
+    ram_init: RamInit,
+    save_data: Option<Box<[u8]>>,
+    palette: Option<Palette>,
+    colorization: Option<Colorization>,
+    pixel_format: PixelFormat,
+    cheats: Vec<Cheat>,
+    serial: Option<Box<dyn SerialDevice>>,
+    camera: Option<Box<dyn CameraSensor>>,
+    rtc_clock: Option<Box<dyn RtcClock>>,
+    #[cfg(feature = "apu")]
+    audio_rate: Option<u32>,
+}
+
+impl GameboyBuilder {
+    /// The cartridge ROM to run
+    pub fn rom_bytes(mut self, rom: Box<[u8]>) -> Self {
+        self.rom = Some(rom);
+        self
+    }
+
+    /// A boot ROM to run before the cartridge, such as the DMG's, which scrolls in the logo.
+    /// Only the 256 byte boot ROMs of the DMG, MGB and SGB are supported, as the CGB's sets up
+    /// hardware that isn't emulated. Without one, the Gameboy starts in the state the model's
+    /// boot ROM leaves it.
+    pub fn boot_rom(mut self, boot_rom: &[u8]) -> Self {
+        self.boot_rom = Some(boot_rom.into());
+        self
+    }
+
+    /// The hardware to emulate, the DMG by default
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Tells games flagged as supporting the CGB that they're in CGB mode on the CGB model,
+    /// overriding the header flag, which is otherwise ignored so they run as on the DMG. Only
+    /// useful for games that still work without the CGB's own features, which aren't
+    /// emulated. Off by default.
+    pub fn cgb_mode(mut self, cgb_mode: bool) -> Self {
+        self.cgb_mode = cgb_mode;
+        self
+    }
+
+    /// What RAM holds at power on, cleared by default. Also used when the Gameboy is reset.
+    pub fn ram_init(mut self, ram_init: RamInit) -> Self {
+        self.ram_init = ram_init;
+        self
+    }
+
+    /// Battery-backed RAM to load into the cartridge, as returned by `Gameboy::get_save_data`
+    pub fn save_data(mut self, save_data: Box<[u8]>) -> Self {
+        self.save_data = Some(save_data);
+        self
+    }
+
+    /// The colors used to display each gray shade, see `Gameboy::set_palette`
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+
+    /// The colors a game made only for the DMG is shown in on the CGB model, in place of the
+    /// palette. Unless given, the colors the CGB's boot ROM picks for the game's title, as
+    /// `Colorization::for_rom`.
+    pub fn colorization(mut self, colorization: Colorization) -> Self {
+        self.colorization = Some(colorization);
+        self
+    }
+
+    /// How the pixels of each frame are laid out, 3 bytes of RGB by default
+    pub fn pixel_format(mut self, format: PixelFormat) -> Self {
+        self.pixel_format = format;
+        self
+    }
+
+    /// Cheats to add, see `Gameboy::add_cheat`
+    pub fn cheats(mut self, cheats: impl IntoIterator<Item = Cheat>) -> Self {
+        self.cheats.extend(cheats);
+        self
+    }
+
+    /// The device plugged into the serial port, see `Gameboy::connect_serial`
+    pub fn serial(mut self, device: Box<dyn SerialDevice>) -> Self {
+        self.serial = Some(device);
+        self
+    }
+
+    /// The image sensor of a Game Boy Camera, see `Gameboy::connect_camera`. Ignored for
+    /// other cartridges.
+    pub fn camera(mut self, sensor: Box<dyn CameraSensor>) -> Self {
+        self.camera = Some(sensor);
+        self
+    }
+
+    /// The clock the cartridge's real-time clock keeps time by, see
+    /// `Gameboy::connect_rtc_clock`. Ignored for cartridges without an RTC.
+    pub fn rtc_clock(mut self, clock: Box<dyn RtcClock>) -> Self {
+        self.rtc_clock = Some(clock);
+        self
+    }
+
+    /// The rate audio samples are generated at, in Hz, `SAMPLE_RATE` by default, see
+    /// `Gameboy::audio_rate`
+    #[cfg(feature = "apu")]
+    pub fn audio_rate(mut self, rate: u32) -> Self {
+        self.audio_rate = Some(rate);
+        self
+    }
+
+    /// Powers on the Gameboy. Fails if no ROM was given, if the boot ROM isn't 256 bytes, or
+    /// for the same reasons as `Gameboy::power_on`.
+    pub fn build(self) -> Result<Gameboy, GabeError> {
+        let rom = self.rom.ok_or(GabeError::MissingRom)?;
+        let mut gb = Gameboy::power_on(rom, None)?;
+        if let Some(boot_rom) = self.boot_rom {
+            if boot_rom.len() != BOOT_ROM_SIZE {
+                return Err(GabeError::InvalidBootRomLength(boot_rom.len()));
+            }
+            gb.mmu.set_boot_rom(boot_rom);
+        }
+        // Connected before the save is loaded, so it measures the time passed since saving
+        if let Some(clock) = self.rtc_clock {
+            gb.connect_rtc_clock(clock);
+        }
+        if let Some(save_data) = self.save_data {
+            gb.mmu.load_save_data(save_data);
+        }
+        gb.model = self.model;
+        gb.cgb_mode = self.cgb_mode;
+        gb.boot();
+        gb.mmu.joypad.set_sgb(self.model == Model::Sgb);
+        gb.ram_init = self.ram_init;
+        gb.mmu.init_ram(self.ram_init);
+        if self.model == Model::Cgb && gb.rom()[0x143] & 0x80 == 0 {
+            gb.set_colorization(
+                self.colorization
+                    .unwrap_or_else(|| Colorization::for_rom(gb.rom())),
+            );
+        } else if let Some(palette) = self.palette {
+            gb.set_palette(palette);
+        }
+        gb.set_pixel_format(self.pixel_format);
+        gb.cheats = self.cheats;
+        if let Some(device) = self.serial {
+            gb.connect_serial(device);
+        }
+        if let Some(sensor) = self.camera {
+            gb.connect_camera(sensor);
+        }
+        #[cfg(feature = "apu")]
+        if let Some(rate) = self.audio_rate {
+            gb.mmu.apu.set_sample_rate(rate);
+        }
+        Ok(gb)
+    }
+}
+
+/// Output of a call to `Gameboy::step_frame`
+pub struct Frame {
+    /// The frame drawn, or None if the LCD was off
+    pub video: Option<VideoFrame>,
+    /// The audio samples generated while the frame was drawn
+    pub audio: Vec<AudioFrame>,
+    /// Cycles run, which can be less than a full frame for the first frame drawn after the
+    /// LCD is turned on
+    pub cycles: u32,
+}
+
+/// Conditions that end a `Gameboy::run_cycles` call early
+#[derive(Clone, Copy, Default, Debug)]
+pub struct StopOn<'a> {
+    /// Stop once a frame has been drawn
+    pub frame: bool,
+    /// Stop before running the instruction at any of these addresses. The first instruction
+    /// of a run is always executed, so running again continues past the breakpoint.
+    pub breakpoints: &'a [u16],
+}
+
+/// Why a `Gameboy::run_cycles` call returned before running all of its cycles
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    /// A frame was drawn, with `StopOn::frame` set
+    Frame,
+    /// The CPU reached a breakpoint, at the given address
+    Breakpoint(u16),
+    /// The CPU locked up during the run
+    Fault(GabeError),
+}
+
+/// Output of a call to `Gameboy::run_cycles`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RunResult {
+    /// Cycles run, which can go slightly over the number asked for as the last instruction
+    /// is always finished
+    pub cycles: u32,
+    /// Frames drawn during the run
+    pub frames: u32,
+    /// Set if the run ended early
+    pub stop: Option<StopReason>,
+}
+
+/// Counts the frames passed through to another sink, for `run_cycles`
+struct CountingSink<'a> {
+    inner: &'a mut dyn Sink<VideoFrame>,
+    frames: u32,
+}
+
+impl Sink<VideoFrame> for CountingSink<'_> {
+    fn append(&mut self, value: VideoFrame) {
+        self.frames += 1;
+        self.inner.append(value);
+    }
+}
+
+/// Adds the frame number and time to frames drawn by the PPU, and hashes them
+struct FrameStamper<'a> {
+    inner: &'a mut dyn Sink<VideoFrame>,
+    frames: &'a mut u64,
+    hash: &'a mut Option<u64>,
+    cycles: u64,
+    format: PixelFormat,
+    /// Set if the frame being finished was left undrawn by frame skip
+    skipped: bool,
+}
+
+impl Sink<FrameData> for FrameStamper<'_> {
+    fn append(&mut self, pixels: FrameData) {
+        if !self.skipped {
+            let mut hash = Fnv64::new();
+            hash.write(&pixels);
+            *self.hash = Some(hash.finish());
+        }
+        self.inner.append(VideoFrame {
+            pixels,
+            format: self.format,
+            frame_index: *self.frames,
+            cycles: self.cycles,
+            skipped: self.skipped,
+        });
+        *self.frames += 1;
+    }
+}
+
+/// Hashes the audio samples passed through to another sink
+struct AudioHasher<'a> {
+    inner: &'a mut dyn Sink<AudioFrame>,
+    hash: &'a mut Fnv64,
+}
+
+impl Sink<AudioFrame> for AudioHasher<'_> {
+    fn append(&mut self, value: AudioFrame) {
+        self.hash.write_u32(value.0.to_bits());
+        self.hash.write_u32(value.1.to_bits());
+        self.inner.append(value);
+    }
+
+    fn append_slice(&mut self, values: &[AudioFrame]) {
+        for value in values {
+            self.hash.write_u32(value.0.to_bits());
+            self.hash.write_u32(value.1.to_bits());
+        }
+        self.inner.append_slice(values);
+    }
+}
+
+/// Keeps the last frame appended, for `step_frame`
+struct FrameSink(Option<VideoFrame>);
+
+impl Sink<VideoFrame> for FrameSink {
+    fn append(&mut self, value: VideoFrame) {
+        self.0 = Some(value);
+    }
+}
+
+/// A snapshot of the CPU, PPU and mapper state, for debuggers and tools
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GbDebug {
     pub cpu_data: cpu::Cpu,
     pub ie_data: u8,
@@ -30,46 +535,569 @@ pub struct GbDebug {
     pub vram_lcdc: u8,
     pub vram_stat: u8,
     pub vram_ly: u8,
+    /// The cartridge ROM bank mapped into 0x4000-0x7FFF
+    pub rom_bank: u16,
+    /// The cartridge RAM bank mapped into 0xA000-0xBFFF
+    pub ram_bank: u8,
+}
+
+/// A copy of the state most often shown by a frontend, cheap enough to take every frame and
+/// hand to a UI thread to draw from while emulation carries on
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    pub registers: Registers,
+    pub ime: bool,
+    pub halted: bool,
+    /// Cycles run since power on
+    pub cycles: u64,
+    /// Frames drawn since power on
+    pub frames: u64,
+    /// The cartridge ROM bank mapped into 0x4000-0x7FFF
+    pub rom_bank: u16,
+    /// The cartridge RAM bank mapped into 0xA000-0xBFFF
+    pub ram_bank: u8,
+    /// 0xFF0F: Interrupts requested
+    pub interrupt_flags: u8,
+    /// 0xFFFF: Interrupts enabled
+    pub interrupt_enable: u8,
+    /// 0xFF40
+    pub lcdc: u8,
+    /// 0xFF41
+    pub stat: u8,
+    /// 0xFF42
+    pub scy: u8,
+    /// 0xFF43
+    pub scx: u8,
+    /// 0xFF44: The scanline being drawn
+    pub ly: u8,
+    /// 0xFF45
+    pub lyc: u8,
+    /// 0xFF04
+    pub div: u8,
+    /// 0xFF05
+    pub tima: u8,
+    /// 0xFF06
+    pub tma: u8,
+    /// 0xFF07
+    pub tac: u8,
+    /// The buttons held
+    pub input: JoypadState,
 }
 
 impl Gameboy {
     /// Initializes Gameboy state to begin emulation on provided
-    /// binary file
-    pub fn power_on(rom_data: Box<[u8]>, save_data: Option<Box<[u8]>>) -> Self {
-        let mmu = mmu::Mmu::power_on(rom_data, save_data);
-        Gameboy {
+    /// binary file. Fails if the ROM has no valid header or uses a cartridge type that
+    /// isn't supported.
+    pub fn power_on(rom_data: Box<[u8]>, save_data: Option<Box<[u8]>>) -> Result<Self, GabeError> {
+        let mmu = mmu::Mmu::power_on(rom_data, save_data)?;
+        Ok(Gameboy {
+            model: Model::Dmg,
+            cgb_mode: false,
+            ram_init: RamInit::Zeroed,
             cpu: cpu::Cpu::power_on(),
             mmu,
+            cycles: 0,
+            frames: 0,
+            frame_hash: None,
+            audio_hash: Fnv64::new(),
+            #[cfg(feature = "debug-hooks")]
+            interrupt_log: InterruptLog::new(),
+            #[cfg(feature = "debug-hooks")]
+            coverage: None,
+            cheats: Vec::new(),
+            input_queue: VecDeque::new(),
+            watched_events: 0,
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Returns a builder for setting up a Gameboy with more options than `power_on` takes
+    pub fn builder() -> GameboyBuilder {
+        GameboyBuilder::default()
+    }
+
+    /// Resets the Gameboy, as if it had been turned off and on again with the same cartridge.
+    /// Battery-backed RAM, cheats, the palette, the serial connection, any camera sensor and
+    /// the real-time clock are kept, and the cycle and frame counts carry on from where they
+    /// were.
+    pub fn reset(&mut self) {
+        self.mmu.reset();
+        self.mmu.init_ram(self.ram_init);
+        self.cpu = cpu::Cpu::power_on();
+        self.boot();
+    }
+
+    /// Starts the CPU at the boot ROM if one is mapped, or otherwise with the registers the
+    /// model's boot ROM leaves
+    fn boot(&mut self) {
+        if self.mmu.boot_rom_mapped() {
+            // The boot ROM sets up everything else, starting with the LCD off
+            self.cpu.reg = Registers::default();
+            self.mmu.write_byte(0xFF40, 0x00);
+        } else {
+            let cgb_flag = self.mmu.read_byte(0x143);
+            self.cpu.reg = self.model.boot_registers(cgb_flag, self.cgb_mode);
         }
     }
 
+    /// Returns the hardware being emulated
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Returns what RAM held at power on, including the seed of a random pattern, so a
+    /// replay can power on with the same RAM
+    pub fn ram_init(&self) -> RamInit {
+        self.ram_init
+    }
+
+    /// Returns the error that stopped the CPU, if it has locked up. Emulation carries on
+    /// without running any more instructions, as on a real Gameboy, so the frontend should
+    /// check this after stepping and report it.
+    pub fn fault(&self) -> Option<GabeError> {
+        self.cpu.fault
+    }
+
     /// Executes one CPU instruction and updates the other
     /// subsystems with the appropriate number of cycles
-    /// Returns a frame if completed during the tick.
+    /// Returns a frame if completed during the tick. Audio is passed on in batches, see
+    /// `flush_audio`.
     pub fn step(
         &mut self,
         video_sink: &mut dyn Sink<VideoFrame>,
         audio_sink: &mut dyn Sink<AudioFrame>,
     ) -> u32 {
-        let cycles = self.cpu.tick(&mut self.mmu);
+        self.step_profiled(video_sink, audio_sink, &mut NoProfiler)
+    }
+
+    /// Same as `step`, but marks the profiler as each subsystem finishes updating,
+    /// so the frontend can measure where emulation time is spent
+    pub fn step_profiled<P: Profiler>(
+        &mut self,
+        video_sink: &mut dyn Sink<VideoFrame>,
+        audio_sink: &mut dyn Sink<AudioFrame>,
+        profiler: &mut P,
+    ) -> u32 {
+        self.apply_queued_input();
+        #[cfg(feature = "debug-hooks")]
+        let pc = self.cpu.reg.pc;
+        #[cfg(feature = "debug-hooks")]
+        if self.coverage.is_some() {
+            self.record_coverage(pc);
+        }
+        let watch_banks =
+            self.watching(EventKind::RomBankSwitched) || self.watching(EventKind::RamBankSwitched);
+        let banks = watch_banks.then(|| (self.rom_bank(), self.ram_bank()));
+        let rumble = self
+            .watching(EventKind::Rumble)
+            .then(|| self.mmu.cart.rumble());
+        let cycles = self.tick_cpu();
+        profiler.mark(Subsystem::Cpu);
+        #[cfg(feature = "debug-hooks")]
+        if let Some(kind) = self.cpu.serviced_interrupt {
+            self.interrupt_log.push(InterruptEvent {
+                kind,
+                cycle: self.cycles,
+                pc,
+                taken: true,
+            });
+        }
 
         // Update memory
-        self.mmu.update(cycles, video_sink, audio_sink);
+        let watch_serial = self.watching(EventKind::SerialSent);
+        // Interrupt requests are only looked for by the interrupt log and serial events
+        let prev_if =
+            (cfg!(feature = "debug-hooks") || watch_serial).then(|| self.mmu.interrupt_flags());
+        let prev_frames = self.frames;
+        // The byte being shifted out, in case the transfer finishes during the update
+        let prev_sb = watch_serial.then(|| self.mmu.serial.read_byte(0xFF01));
+        let mut video_sink = FrameStamper {
+            inner: video_sink,
+            frames: &mut self.frames,
+            hash: &mut self.frame_hash,
+            cycles: self.cycles + cycles as u64,
+            format: self.mmu.vram.pixel_format(),
+            // Only changes as a frame is finished, so this is for the frame finished by the update
+            skipped: self.mmu.vram.skipping_frame(),
+        };
+        let mut audio_sink = AudioHasher {
+            inner: audio_sink,
+            hash: &mut self.audio_hash,
+        };
+        self.mmu.update(cycles, &mut video_sink, profiler);
+        self.cycles += cycles as u64;
+        // Audio is passed on in batches, and at the end of each frame to keep up with the video
+        if self.frames != prev_frames || self.mmu.buffered_audio() >= AUDIO_BATCH {
+            self.mmu.flush_audio(&mut audio_sink);
+        }
+
+        // Interrupts newly requested by the update
+        let requested = prev_if.map_or(0, |prev_if| self.mmu.interrupt_flags() & !prev_if);
+        // VBlank is told by the frame finishing rather than the request, since IF can still
+        // have VBlank set from the last frame when nothing services or clears it
+        if self.frames != prev_frames {
+            self.apply_ram_cheats();
+            self.push_event(Event::VBlank);
+        }
+        if let Some(prev_sb) = prev_sb {
+            if requested & InterruptKind::Serial as u8 != 0 {
+                self.push_event(Event::SerialSent(prev_sb));
+            }
+        }
+        if let Some((rom_bank, ram_bank)) = banks {
+            if self.rom_bank() != rom_bank {
+                self.push_event(Event::RomBankSwitched(self.rom_bank()));
+            }
+            if self.ram_bank() != ram_bank {
+                self.push_event(Event::RamBankSwitched(self.ram_bank()));
+            }
+        }
+        if let Some(rumble) = rumble {
+            if self.rumble() != rumble {
+                self.push_event(Event::Rumble(self.rumble()));
+            }
+        }
+        if !self.mmu.sram_writes.is_empty() {
+            for (address, value) in core::mem::take(&mut self.mmu.sram_writes) {
+                self.push_event(Event::SramWrite { address, value });
+            }
+        }
+        #[cfg(feature = "debug-hooks")]
+        if requested != 0 {
+            // Log them for the debugger
+            for kind in InterruptKind::ALL {
+                if requested & kind as u8 != 0 {
+                    self.interrupt_log.push(InterruptEvent {
+                        kind,
+                        cycle: self.cycles,
+                        pc: self.cpu.reg.pc,
+                        taken: false,
+                    });
+                }
+            }
+        }
         cycles
     }
 
+    /// Runs one CPU instruction, through the bus observer if one is attached
+    fn tick_cpu(&mut self) -> u32 {
+        #[cfg(feature = "bus-observer")]
+        if let Some(mut observer) = self.mmu.observer.take() {
+            let mut bus = crate::bus::ObservedBus {
+                mmu: &mut self.mmu,
+                observer: core::cell::RefCell::new(&mut *observer),
+            };
+            let cycles = self.cpu.tick(&mut bus);
+            self.mmu.observer = Some(observer);
+            return cycles;
+        }
+        self.cpu.tick(&mut self.mmu)
+    }
+
+    /// Runs until a frame is drawn, returning it with the audio generated along the way.
+    /// While the LCD is off no frames are drawn, so this runs for a frame's worth of cycles
+    /// instead and returns no video, keeping the audio flowing at the same rate.
+    pub fn step_frame(&mut self) -> Frame {
+        let mut video_sink = FrameSink(None);
+        let mut audio = Vec::new();
+        let mut cycles = 0;
+        while video_sink.0.is_none() && cycles < super::CYCLES_PER_FRAME {
+            cycles += self.step(&mut video_sink, &mut audio);
+        }
+        self.flush_audio(&mut audio);
+        Frame {
+            video: video_sink.0,
+            audio,
+            cycles,
+        }
+    }
+
+    /// Runs for about `cycles` cycles, or until one of the `stop` conditions is met, passing
+    /// frames and audio to the sinks as `step` does. Also stops if the CPU locks up during
+    /// the run.
+    pub fn run_cycles(
+        &mut self,
+        cycles: u32,
+        stop: StopOn,
+        video_sink: &mut dyn Sink<VideoFrame>,
+        audio_sink: &mut dyn Sink<AudioFrame>,
+    ) -> RunResult {
+        let mut video_sink = CountingSink {
+            inner: video_sink,
+            frames: 0,
+        };
+        let faulted = self.cpu.fault.is_some();
+        let mut result = RunResult {
+            cycles: 0,
+            frames: 0,
+            stop: None,
+        };
+        while result.cycles < cycles {
+            let pc = self.cpu.reg.pc;
+            if result.cycles > 0 && stop.breakpoints.contains(&pc) {
+                result.stop = Some(StopReason::Breakpoint(pc));
+                break;
+            }
+            result.cycles += self.step(&mut video_sink, audio_sink);
+            match self.cpu.fault {
+                Some(fault) if !faulted => {
+                    result.stop = Some(StopReason::Fault(fault));
+                    break;
+                }
+                _ => {}
+            }
+            if stop.frame && video_sink.frames > 0 {
+                result.stop = Some(StopReason::Frame);
+                break;
+            }
+        }
+        self.flush_audio(audio_sink);
+        result.frames = video_sink.frames;
+        result
+    }
+
+    /// Passes on the audio samples `step` has held back, which it sends in batches at the
+    /// end of each frame or once enough have built up. `step_frame` and `run_cycles` already
+    /// flush before returning, so this is only needed when stepping by hand, such as to hear
+    /// everything up to a breakpoint.
+    pub fn flush_audio(&mut self, audio_sink: &mut dyn Sink<AudioFrame>) {
+        let mut audio_sink = AudioHasher {
+            inner: audio_sink,
+            hash: &mut self.audio_hash,
+        };
+        self.mmu.flush_audio(&mut audio_sink);
+    }
+
+    /// Attaches an observer told about every memory access made by the CPU and by DMA,
+    /// replacing any attached before
+    #[cfg(feature = "bus-observer")]
+    pub fn attach_bus_observer(&mut self, observer: Box<dyn crate::bus::BusObserver>) {
+        self.mmu.observer = Some(observer);
+    }
+
+    /// Detaches the bus observer, returning it
+    #[cfg(feature = "bus-observer")]
+    pub fn detach_bus_observer(&mut self) -> Option<Box<dyn crate::bus::BusObserver>> {
+        self.mmu.observer.take()
+    }
+
+    /// Adds a cheat, returning its index
+    pub fn add_cheat(&mut self, cheat: Cheat) -> usize {
+        self.cheats.push(cheat);
+        self.update_rom_patches();
+        self.cheats.len() - 1
+    }
+
+    /// Returns the cheats added, in the order they were added
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /// Turns a cheat on or off. Returns false if there's no cheat at `index`.
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) -> bool {
+        let Some(cheat) = self.cheats.get_mut(index) else {
+            return false;
+        };
+        cheat.enabled = enabled;
+        self.update_rom_patches();
+        true
+    }
+
+    /// Removes all cheats
+    pub fn clear_cheats(&mut self) {
+        self.cheats.clear();
+        self.update_rom_patches();
+    }
+
+    /// Passes the enabled Game Genie codes to the MMU, which applies them to ROM reads
+    fn update_rom_patches(&mut self) {
+        let patches = self
+            .cheats
+            .iter()
+            .filter(|c| c.enabled)
+            .filter_map(|c| match c.code {
+                CheatCode::GameGenie {
+                    address,
+                    value,
+                    compare,
+                } => Some((address, value, compare)),
+                CheatCode::GameShark { .. } => None,
+            })
+            .collect();
+        self.mmu.set_rom_patches(patches);
+    }
+
+    /// Writes the enabled GameShark codes' values to RAM, which is done at the start of every
+    /// VBlank
+    fn apply_ram_cheats(&mut self) {
+        for cheat in self.cheats.iter().filter(|c| c.enabled) {
+            if let CheatCode::GameShark { address, value } = cheat.code {
+                self.mmu.write_byte(address, value);
+            }
+        }
+    }
+
     pub fn update_key_state(&mut self, key: GbKeys, pressed: bool) {
         self.mmu.joypad.set_key_pressed(key, pressed);
     }
 
-    pub fn get_save_data(&self) -> Option<Box<[u8]>> {
-        let result = self.mmu.cart.write_save_data();
-        match result {
-            Ok(d) => Some(d),
-            Err(_) => None,
+    /// Sets all eight buttons at once, releasing any not held in `input`
+    pub fn set_input(&mut self, input: JoypadState) {
+        self.mmu.joypad.set_keys_pressed(input.bits());
+    }
+
+    /// Returns the buttons currently held
+    pub fn input(&self) -> JoypadState {
+        JoypadState::from_bits(self.mmu.joypad.keys_pressed())
+    }
+
+    /// Sets all eight buttons of one player at once, counting players from 0, so player 0's
+    /// buttons are the ones `set_input` sets. Only Super Gameboy games read the other
+    /// players' buttons, once they've asked for them; see `Gameboy::players`. Players past
+    /// the fourth are ignored.
+    pub fn set_player_input(&mut self, player: usize, input: JoypadState) {
+        if player < MAX_PLAYERS {
+            self.mmu
+                .joypad
+                .set_player_keys_pressed(player, input.bits());
+        }
+    }
+
+    /// Returns the buttons currently held by a player, counting players from 0
+    pub fn player_input(&self, player: usize) -> JoypadState {
+        if player < MAX_PLAYERS {
+            JoypadState::from_bits(self.mmu.joypad.player_keys_pressed(player))
+        } else {
+            JoypadState::empty()
         }
     }
 
+    /// Returns how many players' buttons the game is reading: 1, or 2 or 4 once a game run
+    /// as `Model::Sgb` has asked for them with the multiplayer adapter
+    pub fn players(&self) -> usize {
+        self.mmu.joypad.players()
+    }
+
+    /// Queues the buttons held to change to `input` at `time`, which lands on the exact
+    /// instruction however many cycles the frontend runs at once. Changes are applied in the
+    /// order they're queued, each waiting for the one before it, so they should be queued in
+    /// time order. Changes already due are applied before the next instruction.
+    pub fn queue_input(&mut self, time: InputTime, input: JoypadState) {
+        self.input_queue.push_back((time, input));
+    }
+
+    /// Drops any queued input changes that haven't been applied yet
+    pub fn clear_input_queue(&mut self) {
+        self.input_queue.clear();
+    }
+
+    /// Applies the queued input changes that are due
+    fn apply_queued_input(&mut self) {
+        while let Some(&(time, input)) = self.input_queue.front() {
+            let due = match time {
+                InputTime::Cycle(cycle) => self.cycles >= cycle,
+                InputTime::Frame(frame) => self.frames >= frame,
+            };
+            if !due {
+                break;
+            }
+            self.set_input(input);
+            self.input_queue.pop_front();
+        }
+    }
+
+    /// Sets the colors used to display each gray shade in the frames produced
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.mmu.vram.set_palette(palette);
+    }
+
+    /// Sets the colors used to display the background's shades and each sprite palette's, as
+    /// the CGB colorizes games made for the DMG
+    pub fn set_colorization(&mut self, colorization: Colorization) {
+        self.mmu.vram.set_colorization(colorization);
+    }
+
+    /// Sets how the pixels of the frames produced are laid out, such as `PixelFormat::Xrgb32`
+    /// to use each frame as a buffer of u32 pixels. The frame being drawn is cleared, so the
+    /// next frame is the first in the new format.
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.mmu.vram.set_pixel_format(format);
+    }
+
+    /// Returns how the pixels of the frames produced are laid out
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.mmu.vram.pixel_format()
+    }
+
+    /// Leaves `frames` frames undrawn after each frame drawn, such as 3 to draw 1 frame in 4,
+    /// to save the time spent drawing when fast-forwarding or on slow hosts. Timing and
+    /// interrupts are unaffected and every frame is still passed to the video sink, but a
+    /// skipped frame is marked `skipped` and holds the pixels of the last frame drawn. 0 draws
+    /// every frame, as at power on.
+    pub fn set_frame_skip(&mut self, frames: u32) {
+        self.mmu.vram.set_frame_skip(frames);
+    }
+
+    /// Returns the frames left undrawn after each frame drawn
+    pub fn frame_skip(&self) -> u32 {
+        self.mmu.vram.frame_skip()
+    }
+
+    /// Mutes or unmutes one of the four sound channels, numbered 1 to 4: the two square
+    /// channels, the wave channel and the noise channel. Only the output is affected, the
+    /// game sees the channel playing as normal.
+    #[cfg(feature = "apu")]
+    pub fn set_channel_muted(&mut self, channel: u8, muted: bool) {
+        self.mmu.apu.set_channel_muted(channel, muted);
+    }
+
+    /// Returns the rate audio samples are generated at, in Hz. It's `SAMPLE_RATE` unless set
+    /// with `GameboyBuilder::audio_rate`, rounded to a whole number of CPU cycles between
+    /// samples. Samples are taken without filtering, so low rates alias.
+    #[cfg(feature = "apu")]
+    pub fn audio_rate(&self) -> u32 {
+        self.mmu.apu.sample_rate()
+    }
+
+    /// Returns true if the sound channel, numbered 1 to 4, has been muted
+    #[cfg(feature = "apu")]
+    pub fn channel_muted(&self, channel: u8) -> bool {
+        self.mmu.apu.channel_muted(channel)
+    }
+
+    pub fn get_save_data(&self) -> Option<Box<[u8]>> {
+        self.mmu.cart.write_save_data().ok()
+    }
+
+    /// Connects a device, such as one end of a `serial::link_cable`, to the link port, replacing
+    /// any connected before
+    pub fn connect_serial(&mut self, device: Box<dyn SerialDevice>) {
+        self.mmu.serial.connect(Some(device));
+    }
+
+    /// Disconnects the device from the link port
+    pub fn disconnect_serial(&mut self) {
+        self.mmu.serial.connect(None);
+    }
+
+    /// Connects the image sensor that a Game Boy Camera cartridge takes its pictures with,
+    /// replacing any connected before. Without one, the camera sees a blank gray. Returns
+    /// false, dropping the sensor, if the cartridge isn't a Game Boy Camera.
+    pub fn connect_camera(&mut self, sensor: Box<dyn CameraSensor>) -> bool {
+        self.mmu.cart.connect_camera(sensor).is_ok()
+    }
+
+    /// Replaces the clock that the cartridge's real-time clock keeps time by, carrying on
+    /// from the time it shows now. By default it follows the wall clock with the `std`
+    /// feature, so it keeps going while the game isn't running, and is stopped without it.
+    /// A `rtc::ManualClock` makes the RTC move only when told, for tests and runs that have
+    /// to play back the same way. Returns false, dropping the clock, if the cartridge has no
+    /// RTC.
+    pub fn connect_rtc_clock(&mut self, clock: Box<dyn RtcClock>) -> bool {
+        self.mmu.cart.connect_rtc_clock(clock).is_ok()
+    }
+
     pub fn poll_serial(&mut self) -> Option<u8> {
         if self.mmu.read_byte(0xFF02) == 0x81 {
             // Output ready
@@ -89,7 +1117,136 @@ impl Gameboy {
             vram_lcdc: self.mmu.read_byte(0xFF40),
             vram_stat: self.mmu.read_byte(0xFF41),
             vram_ly: self.mmu.read_byte(0xFF44),
+            rom_bank: self.rom_bank(),
+            ram_bank: self.ram_bank(),
+        }
+    }
+
+    /// Returns a copy of the CPU registers, the main IO registers and the mapped banks
+    pub fn snapshot(&self) -> Snapshot {
+        let io = |addr| self.mmu.read_byte(addr);
+        Snapshot {
+            registers: self.cpu.reg.clone(),
+            ime: self.cpu.ime,
+            halted: self.cpu.halted,
+            cycles: self.cycles,
+            frames: self.frames,
+            rom_bank: self.rom_bank(),
+            ram_bank: self.ram_bank(),
+            interrupt_flags: io(0xFF0F),
+            interrupt_enable: io(0xFFFF),
+            lcdc: io(0xFF40),
+            stat: io(0xFF41),
+            scy: io(0xFF42),
+            scx: io(0xFF43),
+            ly: io(0xFF44),
+            lyc: io(0xFF45),
+            div: io(0xFF04),
+            tima: io(0xFF05),
+            tma: io(0xFF06),
+            tac: io(0xFF07),
+            input: self.input(),
+        }
+    }
+
+    /// Returns the log of the most recent interrupt requests and dispatches
+    #[cfg(feature = "debug-hooks")]
+    pub fn interrupt_log(&self) -> &InterruptLog {
+        &self.interrupt_log
+    }
+
+    /// Starts recording the ROM instructions run into `coverage`, which can be empty or hold
+    /// coverage recorded before with the same ROM
+    #[cfg(feature = "debug-hooks")]
+    pub fn start_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(coverage);
+    }
+
+    /// Stops recording coverage, returning what was recorded
+    #[cfg(feature = "debug-hooks")]
+    pub fn stop_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Returns the coverage recorded so far, if recording
+    #[cfg(feature = "debug-hooks")]
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Records the instruction at `pc` as run, if it's in ROM. Instructions run from RAM
+    /// aren't recorded.
+    #[cfg(feature = "debug-hooks")]
+    fn record_coverage(&mut self, pc: u16) {
+        let offset = match pc {
+            0x0000..=0x3FFF => pc as usize,
+            0x4000..=0x7FFF => self.rom_bank() as usize * 0x4000 + (pc as usize - 0x4000),
+            _ => return,
+        };
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(offset);
+        }
+    }
+
+    /// Starts or stops watching for a kind of event. Events watched are queued as they happen,
+    /// to be taken with `drain_events`.
+    pub fn watch_event(&mut self, kind: EventKind, watch: bool) {
+        if watch {
+            self.watched_events |= kind as u8;
+        } else {
+            self.watched_events &= !(kind as u8);
+        }
+        self.mmu.watch_sram_writes = self.watching(EventKind::SramWrite);
+    }
+
+    /// Takes the events queued since the last call, oldest first. Only the most recent
+    /// events are kept, so this should be called at least once a frame.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = TimedEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    fn watching(&self, kind: EventKind) -> bool {
+        self.watched_events & kind as u8 != 0
+    }
+
+    /// Queues an event if its kind is being watched
+    fn push_event(&mut self, event: Event) {
+        if !self.watching(event.kind()) {
+            return;
+        }
+        if self.events.len() == EVENT_QUEUE_SIZE {
+            self.events.pop_front();
         }
+        self.events.push_back(TimedEvent {
+            cycle: self.cycles,
+            event,
+        });
+    }
+
+    /// Returns a hash of the last frame drawn, or None if none has been drawn yet. Cheaper to
+    /// compare than the frames themselves, such as against a known good frame in a test, or
+    /// between the two sides of a netplay session to spot a desync. Frames in different pixel
+    /// formats hash differently. Frames skipped by `set_frame_skip` aren't hashed.
+    pub fn frame_hash(&self) -> Option<u64> {
+        self.frame_hash
+    }
+
+    /// Returns a hash of all the audio produced since power on, the audio counterpart of
+    /// `frame_hash`
+    pub fn audio_hash(&self) -> u64 {
+        self.audio_hash.finish()
+    }
+
+    /// Returns the cycles run since power on, which a frontend can divide by `CLOCK_HZ` for
+    /// the emulated time
+    pub fn cycles_elapsed(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Returns the frames drawn since power on, which is also the index of the next frame.
+    /// Frames skipped by `set_frame_skip` are counted too.
+    pub fn frames(&self) -> u64 {
+        self.frames
     }
 
     /// Returns the current program counter of the CPU
@@ -97,9 +1254,547 @@ impl Gameboy {
         self.cpu.reg.pc
     }
 
+    /// Returns the cartridge ROM bank currently mapped into 0x4000-0x7FFF
+    pub fn rom_bank(&self) -> u16 {
+        self.mmu.cart.rom_bank()
+    }
+
+    /// Returns the whole cartridge ROM, every bank, as loaded
+    pub fn rom(&self) -> &[u8] {
+        self.mmu.cart.rom()
+    }
+
+    /// Returns the cartridge RAM, every bank, including those not mapped and while it's
+    /// disabled, when reads of 0xA000-0xBFFF return 0xFF. Empty for cartridges without RAM.
+    pub fn cartridge_ram(&self) -> &[u8] {
+        self.mmu.cart.ram()
+    }
+
+    /// Returns the cartridge RAM bank currently mapped into 0xA000-0xBFFF
+    pub fn ram_bank(&self) -> u8 {
+        self.mmu.cart.ram_bank()
+    }
+
+    /// Returns true while the cartridge's rumble motor is running. Watch `EventKind::Rumble`
+    /// to see each time it's switched, as games switch it many times a frame.
+    pub fn rumble(&self) -> bool {
+        self.mmu.cart.rumble()
+    }
+
+    /// Tilts the Gameboy, for cartridges with an accelerometer such as the MBC7, in g: 1.0 is
+    /// as if stood on its side. Positive x tilts the right side down, and positive y the top
+    /// away from the player. Level is 0.0 for both, and the tilt is kept until it's set again.
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.mmu.cart.set_tilt(x, y);
+    }
+
+    /// Replaces the CPU register state, for use by debuggers and scripting
+    pub fn set_registers(&mut self, reg: Registers) {
+        self.cpu.reg = reg;
+    }
+
+    /// Writes a byte into memory as the CPU would, so writes to ROM are
+    /// handled by the cartridge's MBC and read-only registers are not modified.
+    pub fn write_memory(&mut self, addr: u16, val: u8) {
+        self.mmu.write_byte(addr, val);
+    }
+
+    /// Returns memory as the CPU sees it, with the currently mapped banks, for reading it
+    /// without copying it out, such as with `disassemble::disassemble_memory`
+    pub fn memory(&self) -> &dyn Memory {
+        &self.mmu
+    }
+
     /// Returns a boxed slice of u8 values contained within the given range of usize values.
     /// Only returns values as read via the CPU, so forbidden or fixed reads will not be bypassed
     pub fn get_memory_range(&self, range: core::ops::Range<usize>) -> Box<[u8]> {
         self.mmu.get_memory_range(range).into_boxed_slice()
     }
 }
+
+#[cfg(test)]
+mod gb_tests {
+    use super::*;
+
+    #[test]
+    fn step_frame() {
+        let rom = vec![0; 0x8000].into_boxed_slice();
+        let mut gb = Gameboy::power_on(rom, None).unwrap();
+        let frame = gb.step_frame();
+        let video = frame.video.unwrap();
+        assert_eq!(video.len(), 160 * 144 * 3);
+        assert_eq!(video.frame_index, 0);
+        assert!(frame.cycles <= crate::CYCLES_PER_FRAME);
+
+        let next = gb.step_frame().video.unwrap();
+        assert_eq!(next.frame_index, 1);
+        assert_eq!(next.cycles - video.cycles, crate::CYCLES_PER_FRAME as u64);
+
+        // Turn the LCD off, after which a frame's worth of cycles runs without drawing
+        gb.write_memory(0xFF40, 0x00);
+        let frame = gb.step_frame();
+        assert!(frame.video.is_none());
+        assert!(frame.cycles >= crate::CYCLES_PER_FRAME);
+    }
+
+    #[test]
+    fn frame_skip() {
+        let rom = vec![0; 0x8000].into_boxed_slice();
+        let mut gb = Gameboy::power_on(rom, None).unwrap();
+        gb.set_frame_skip(2);
+        let skipped: Vec<bool> = (0..6)
+            .map(|_| gb.step_frame().video.unwrap().skipped)
+            .collect();
+        assert_eq!(skipped, [false, true, true, false, true, true]);
+        assert_eq!(gb.frames(), 6);
+    }
+
+    #[test]
+    fn gameboy_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Gameboy>();
+    }
+
+    #[test]
+    fn builder() {
+        assert_eq!(
+            Gameboy::builder().build().err(),
+            Some(GabeError::MissingRom)
+        );
+
+        let cheat = Cheat {
+            code: CheatCode::GameShark {
+                address: 0xC000,
+                value: 0x42,
+            },
+            label: "Test".into(),
+            enabled: true,
+        };
+        let gb = Gameboy::builder()
+            .rom_bytes(vec![0; 0x8000].into_boxed_slice())
+            .cheats([cheat])
+            .build()
+            .unwrap();
+        assert_eq!(gb.cheats().len(), 1);
+    }
+
+    #[test]
+    fn boot_rom() {
+        // NOPs, then unmapping itself at the end, leaving the PC at the cartridge's entry
+        let mut boot_rom = vec![0; 0x100];
+        boot_rom[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+        let mut rom = vec![0; 0x8000];
+        rom[0] = 0x42;
+        let mut gb = Gameboy::builder()
+            .rom_bytes(rom.clone().into_boxed_slice())
+            .boot_rom(&boot_rom)
+            .build()
+            .unwrap();
+        assert_eq!(gb.get_pc(), 0x0000);
+        assert_eq!(gb.get_memory_range(0x0000..0x0001)[0], 0x00);
+        assert_eq!(gb.get_memory_range(0xFF40..0xFF41)[0], 0x00);
+        for _ in 0..0x100 {
+            if gb.get_pc() == 0x0100 {
+                break;
+            }
+            gb.step(&mut Vec::new(), &mut Vec::new());
+        }
+        assert_eq!(gb.get_pc(), 0x0100);
+        assert_eq!(gb.get_memory_range(0x0000..0x0001)[0], 0x42);
+
+        gb.reset();
+        assert_eq!(gb.get_pc(), 0x0000);
+        assert_eq!(gb.get_memory_range(0x0000..0x0001)[0], 0x00);
+
+        assert_eq!(
+            Gameboy::builder()
+                .rom_bytes(rom.into_boxed_slice())
+                .boot_rom(&[0; 0x900])
+                .build()
+                .err(),
+            Some(GabeError::InvalidBootRomLength(0x900))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "apu")]
+    fn audio_rate() {
+        let build = |rate| {
+            Gameboy::builder()
+                .rom_bytes(vec![0; 0x8000].into_boxed_slice())
+                .audio_rate(rate)
+                .build()
+                .unwrap()
+        };
+        let mut gb = build(crate::SAMPLE_RATE / 4);
+        assert_eq!(gb.audio_rate(), crate::SAMPLE_RATE / 4);
+        gb.step_frame();
+        let samples = gb.step_frame().audio.len();
+        let expected = crate::CYCLES_PER_FRAME as usize / 64;
+        assert!((expected..=expected + 1).contains(&samples));
+
+        // Rounded to a whole number of cycles between samples
+        assert_eq!(build(44100).audio_rate(), crate::CLOCK_RATE / 95);
+        gb.reset();
+        assert_eq!(gb.audio_rate(), crate::SAMPLE_RATE / 4);
+    }
+
+    #[test]
+    fn reset() {
+        let mut rom = vec![0; 0x8000];
+        // MBC1 with battery-backed RAM
+        rom[0x147] = 0x03;
+        rom[0x148] = 0x01;
+        rom[0x149] = 0x02;
+        let mut gb = Gameboy::builder()
+            .rom_bytes(rom.into_boxed_slice())
+            .model(Model::Mgb)
+            .build()
+            .unwrap();
+        gb.step_frame();
+        gb.write_memory(0x0000, 0x0A);
+        gb.write_memory(0xA000, 0x42);
+        gb.write_memory(0x2000, 0x02);
+        gb.write_memory(0xC000, 0x42);
+        assert_eq!(gb.rom_bank(), 2);
+
+        gb.reset();
+        assert_eq!(gb.get_pc(), 0x100);
+        assert_eq!(gb.get_debug_state().cpu_data.reg.a, 0xFF);
+        assert_eq!(gb.rom_bank(), 1);
+        assert_eq!(gb.get_memory_range(0xC000..0xC001)[0], 0x00);
+        assert_eq!(gb.get_save_data().unwrap()[0], 0x42);
+    }
+
+    #[test]
+    fn set_input() {
+        let rom = vec![0; 0x8000].into_boxed_slice();
+        let mut gb = Gameboy::power_on(rom, None).unwrap();
+        gb.update_key_state(GbKeys::B, true);
+        gb.set_input(JoypadState::A | JoypadState::UP);
+        assert_eq!(gb.input(), JoypadState::from(GbKeys::A) | JoypadState::UP);
+        assert!(!gb.input().contains(JoypadState::B));
+
+        gb.update_key_state(GbKeys::A, false);
+        assert_eq!(gb.input(), JoypadState::UP);
+
+        // Select the action buttons
+        gb.write_memory(0xFF00, 0x10);
+        gb.set_input(JoypadState::START);
+        gb.step_frame();
+        assert_eq!(gb.get_memory_range(0xFF00..0xFF01)[0] & 0x0F, 0b0111);
+    }
+
+    #[test]
+    fn queue_input() {
+        let rom = vec![0; 0x8000].into_boxed_slice();
+        let mut gb = Gameboy::power_on(rom, None).unwrap();
+        let mut frames = Vec::new();
+        let mut audio = Vec::new();
+        gb.queue_input(InputTime::Cycle(1000), JoypadState::A);
+        gb.queue_input(InputTime::Frame(2), JoypadState::B);
+
+        while gb.cycles_elapsed() < 1000 {
+            gb.run_cycles(1, StopOn::default(), &mut frames, &mut audio);
+        }
+        assert_eq!(gb.input(), JoypadState::empty());
+        // Applied before the first instruction at or past cycle 1000
+        gb.run_cycles(1, StopOn::default(), &mut frames, &mut audio);
+        assert_eq!(gb.input(), JoypadState::A);
+
+        while gb.frames() < 2 {
+            gb.step_frame();
+        }
+        assert_eq!(gb.input(), JoypadState::A);
+        gb.run_cycles(1, StopOn::default(), &mut frames, &mut audio);
+        assert_eq!(gb.input(), JoypadState::B);
+
+        gb.queue_input(InputTime::Frame(10), JoypadState::START);
+        gb.clear_input_queue();
+        for _ in 0..10 {
+            gb.step_frame();
+        }
+        assert_eq!(gb.input(), JoypadState::B);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_debug_state() {
+        let rom = vec![0; 0x8000].into_boxed_slice();
+        let mut gb = Gameboy::power_on(rom, None).unwrap();
+        gb.step_frame();
+        let json = serde_json::to_string(&gb.get_debug_state()).unwrap();
+        let state: GbDebug = serde_json::from_str(&json).unwrap();
+        assert_eq!(state.cpu_data.reg.pc, gb.get_pc());
+        assert_eq!(state.rom_bank, 1);
+
+        let input: JoypadState = serde_json::from_str("144").unwrap();
+        assert_eq!(input, JoypadState::A | JoypadState::START);
+    }
+
+    #[test]
+    fn events() {
+        let mut rom = vec![0; 0x8000];
+        // MBC1 with RAM
+        rom[0x147] = 0x02;
+        rom[0x148] = 0x01;
+        rom[0x149] = 0x03;
+        // LD A,2; LD (2000),A; LD (A000),A; then loops on itself with JR -2
+        rom[0x100..0x10A]
+            .copy_from_slice(&[0x3E, 0x02, 0xEA, 0x00, 0x20, 0xEA, 0x00, 0xA0, 0x18, 0xFE]);
+        let mut gb = Gameboy::power_on(rom.into_boxed_slice(), None).unwrap();
+        gb.watch_event(EventKind::RomBankSwitched, true);
+        gb.watch_event(EventKind::SramWrite, true);
+        gb.watch_event(EventKind::VBlank, true);
+        gb.step_frame();
+        gb.step_frame();
+
+        let events: Vec<Event> = gb.drain_events().map(|e| e.event).collect();
+        assert_eq!(
+            events[..2],
+            [
+                Event::RomBankSwitched(2),
+                Event::SramWrite {
+                    address: 0xA000,
+                    value: 0x02
+                }
+            ]
+        );
+        assert!(events[2..].iter().all(|e| *e == Event::VBlank));
+        assert!(!events[2..].is_empty());
+        assert_eq!(gb.drain_events().count(), 0);
+
+        gb.watch_event(EventKind::VBlank, false);
+        gb.step_frame();
+        assert_eq!(gb.drain_events().count(), 0);
+    }
+
+    #[test]
+    fn rumble_events() {
+        let mut rom = vec![0; 0x8000];
+        // MBC5 with rumble
+        rom[0x147] = 0x1C;
+        // LD A,8; LD (4000),A; XOR A; LD (4000),A; then loops on itself with JR -2
+        rom[0x100..0x10A]
+            .copy_from_slice(&[0x3E, 0x08, 0xEA, 0x00, 0x40, 0xAF, 0xEA, 0x00, 0x40, 0x18]);
+        rom[0x10A] = 0xFE;
+        let mut gb = Gameboy::power_on(rom.into_boxed_slice(), None).unwrap();
+        gb.watch_event(EventKind::Rumble, true);
+        for _ in 0..3 {
+            gb.step(&mut Vec::new(), &mut Vec::new());
+        }
+        assert!(gb.rumble());
+        gb.step_frame();
+        assert!(!gb.rumble());
+        let events: Vec<Event> = gb.drain_events().map(|e| e.event).collect();
+        assert_eq!(events, [Event::Rumble(true), Event::Rumble(false)]);
+    }
+
+    #[test]
+    fn ram_init() {
+        let build = |ram_init| {
+            Gameboy::builder()
+                .rom_bytes(vec![0; 0x8000].into_boxed_slice())
+                .ram_init(ram_init)
+                .build()
+                .unwrap()
+        };
+        let gb = build(RamInit::Fill(0xFF));
+        assert!(gb
+            .get_memory_range(0xC000..0xE000)
+            .iter()
+            .all(|b| *b == 0xFF));
+        assert!(gb
+            .get_memory_range(0xFF80..0xFFFF)
+            .iter()
+            .all(|b| *b == 0xFF));
+        assert!(gb
+            .get_memory_range(0x8000..0xA000)
+            .iter()
+            .all(|b| *b == 0xFF));
+        let gb = Gameboy::builder()
+            .rom_bytes(vec![0; 0x8000].into_boxed_slice())
+            .boot_rom(&[0; 0x100])
+            .ram_init(RamInit::Fill(0xFF))
+            .build()
+            .unwrap();
+        assert!(gb
+            .get_memory_range(0x8000..0xA000)
+            .iter()
+            .all(|b| *b == 0x00));
+
+        let mut gb = build(RamInit::Random(42));
+        let ram = gb.get_memory_range(0xC000..0xE000);
+        assert!(ram.iter().any(|b| *b != ram[0]));
+        assert_eq!(
+            build(RamInit::Random(42)).get_memory_range(0xC000..0xE000),
+            ram
+        );
+        assert_ne!(
+            build(RamInit::Random(43)).get_memory_range(0xC000..0xE000),
+            ram
+        );
+        assert_eq!(gb.ram_init(), RamInit::Random(42));
+
+        gb.write_memory(0xC000, !ram[0]);
+        gb.reset();
+        assert_eq!(gb.get_memory_range(0xC000..0xE000), ram);
+    }
+
+    #[test]
+    fn cycles_elapsed() {
+        let rom = vec![0; 0x8000].into_boxed_slice();
+        let mut gb = Gameboy::power_on(rom, None).unwrap();
+        assert_eq!(gb.cycles_elapsed(), 0);
+        let video = gb.step_frame().video.unwrap();
+        assert_eq!(gb.cycles_elapsed(), video.cycles);
+        assert_eq!(
+            crate::FRAME_TIME_NS,
+            crate::CYCLES_PER_FRAME as u64 * 1_000_000_000 / crate::CLOCK_HZ as u64
+        );
+    }
+
+    #[test]
+    fn output_hashes() {
+        let run = || {
+            let rom = vec![0; 0x8000].into_boxed_slice();
+            let mut gb = Gameboy::power_on(rom, None).unwrap();
+            assert_eq!(gb.frame_hash(), None);
+            // Turn on sound, with channel 1 playing to both sides
+            gb.write_memory(0xFF26, 0x80);
+            gb.write_memory(0xFF25, 0x11);
+            gb.write_memory(0xFF24, 0x77);
+            gb.write_memory(0xFF12, 0xF0);
+            gb.write_memory(0xFF14, 0x80);
+            gb.step_frame();
+            gb
+        };
+        let mut gb = run();
+        let hashes = (gb.frame_hash(), gb.audio_hash());
+        assert!(hashes.0.is_some());
+        assert_eq!((run().frame_hash(), run().audio_hash()), hashes);
+
+        gb.step_frame();
+        assert_ne!(gb.audio_hash(), hashes.1);
+    }
+
+    #[test]
+    fn snapshot() {
+        let rom = vec![0; 0x8000].into_boxed_slice();
+        let mut gb = Gameboy::power_on(rom, None).unwrap();
+        gb.set_input(JoypadState::START);
+        gb.write_memory(0xFF06, 0x42);
+        gb.step_frame();
+        let snapshot = gb.snapshot();
+        assert_eq!(snapshot.registers.pc, gb.get_pc());
+        assert_eq!(snapshot.cycles, gb.cycles_elapsed());
+        assert_eq!(snapshot.frames, 1);
+        assert_eq!(snapshot.rom_bank, 1);
+        assert_eq!(snapshot.tma, 0x42);
+        assert_eq!(snapshot.lcdc, 0x91);
+        assert_eq!(snapshot.input, JoypadState::START);
+    }
+
+    #[test]
+    #[cfg(feature = "debug-hooks")]
+    fn coverage() {
+        let mut rom = vec![0; 0x8000];
+        // JP $4000, then JR -2 in bank 1
+        rom[0x100..0x103].copy_from_slice(&[0xC3, 0x00, 0x40]);
+        rom[0x4000..0x4002].copy_from_slice(&[0x18, 0xFE]);
+        let mut gb = Gameboy::power_on(rom.into_boxed_slice(), None).unwrap();
+        gb.start_coverage(Coverage::new());
+        let mut frames = Vec::new();
+        let mut audio = Vec::new();
+        for _ in 0..4 {
+            gb.step(&mut frames, &mut audio);
+        }
+        let coverage = gb.stop_coverage().unwrap();
+        let offsets: Vec<usize> = coverage.offsets().collect();
+        assert_eq!(offsets, [0x100, 0x4000]);
+        assert!(gb.coverage().is_none());
+    }
+
+    #[test]
+    fn cartridge_ram() {
+        let mut rom = vec![0; 0x8000];
+        // MBC1 with four banks of RAM
+        rom[0x147] = 0x02;
+        rom[0x149] = 0x03;
+        let mut gb = Gameboy::power_on(rom.into_boxed_slice(), None).unwrap();
+        assert_eq!(gb.cartridge_ram().len(), 0x8000);
+        gb.write_memory(0x0000, 0x0A);
+        gb.write_memory(0x6000, 0x01);
+        gb.write_memory(0x4000, 0x02);
+        gb.write_memory(0xA010, 0x42);
+        gb.write_memory(0x0000, 0x00);
+        assert_eq!(gb.memory().read_byte(0xA010), 0xFF);
+        assert_eq!(gb.cartridge_ram()[0x4010], 0x42);
+    }
+
+    #[test]
+    fn model_registers() {
+        let mut rom = vec![0; 0x8000];
+        let gb = Gameboy::builder()
+            .rom_bytes(rom.clone().into_boxed_slice())
+            .model(Model::Cgb)
+            .build()
+            .unwrap();
+        assert_eq!(gb.model(), Model::Cgb);
+        assert_eq!(gb.get_debug_state().cpu_data.reg.a, 0x11);
+        assert_eq!(gb.get_debug_state().cpu_data.reg.e, 0x08);
+
+        // Run as on the DMG unless CGB mode is asked for
+        rom[0x143] = 0x80;
+        let gb = Gameboy::builder()
+            .rom_bytes(rom.clone().into_boxed_slice())
+            .model(Model::Cgb)
+            .build()
+            .unwrap();
+        assert_eq!(gb.get_debug_state().cpu_data.reg.a, 0x01);
+        let gb = Gameboy::builder()
+            .rom_bytes(rom.into_boxed_slice())
+            .model(Model::Cgb)
+            .cgb_mode(true)
+            .build()
+            .unwrap();
+        assert_eq!(gb.get_debug_state().cpu_data.reg.a, 0x11);
+        assert_eq!(gb.get_debug_state().cpu_data.reg.e, 0x56);
+    }
+
+    #[test]
+    fn run_cycles() {
+        let mut rom = vec![0; 0x8000];
+        // Jumps to 0x0150, which loops on itself with JR -2
+        rom[0x100] = 0xC3;
+        rom[0x101] = 0x50;
+        rom[0x102] = 0x01;
+        rom[0x150] = 0x18;
+        rom[0x151] = 0xFE;
+        let mut gb = Gameboy::power_on(rom.into_boxed_slice(), None).unwrap();
+        let mut frames = Vec::new();
+        let mut audio = Vec::new();
+
+        let result = gb.run_cycles(1000, StopOn::default(), &mut frames, &mut audio);
+        assert!(result.cycles >= 1000);
+        assert_eq!(result.stop, None);
+
+        let stop = StopOn {
+            breakpoints: &[0x150],
+            ..Default::default()
+        };
+        let result = gb.run_cycles(1000, stop, &mut frames, &mut audio);
+        // JR takes 12 cycles, after which it's back at the breakpoint
+        assert_eq!(result.cycles, 12);
+        assert_eq!(result.stop, Some(StopReason::Breakpoint(0x150)));
+
+        let stop = StopOn {
+            frame: true,
+            ..Default::default()
+        };
+        let result = gb.run_cycles(crate::CYCLES_PER_FRAME * 2, stop, &mut frames, &mut audio);
+        assert_eq!(result.frames, 1);
+        assert_eq!(result.stop, Some(StopReason::Frame));
+        assert_eq!(frames.len(), 1);
+    }
+}
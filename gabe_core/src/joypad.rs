@@ -2,6 +2,12 @@ use super::gb::GbKeys;
 use super::mmu::InterruptKind;
 use super::mmu::Memory;
 
+/// Most players a Super Gameboy can read buttons from, with the multiplayer adapter
+pub const MAX_PLAYERS: usize = 4;
+
+/// Super Gameboy command that sets how many players' buttons are read
+const MLT_REQ: u8 = 0x11;
+
 /// The eight Game Boy action/direction buttons are arranged as a 2x4 matrix.
 /// Select either action or direction buttons by writing to this register, then read out the bits 0-3.
 /// Internally represents all 8 buttons as a single byte, then returns the correct nibble when read.
@@ -10,7 +16,87 @@ use super::mmu::Memory;
 pub struct Joypad {
     state: u8,
     using_directions: bool,
-    keys_pressed: [bool; 8],
+    /// Bits 4 and 5 as last written, low for each half of the matrix selected
+    select: u8,
+    /// A bit set for each button held by each player, numbered as in GbKeys. Only the first
+    /// player is read unless a Super Gameboy game asks for more.
+    keys_pressed: [u8; MAX_PLAYERS],
+    /// Set on the Super Gameboy, which games talk to through this register
+    sgb: Option<SgbPort>,
+}
+
+/// The Super Gameboy's end of the joypad register. Games send it commands as 16-byte packets,
+/// a bit at a time, by pulsing P14 low for a 0 and P15 low for a 1. Only MLT_REQ is acted on,
+/// which selects how many players' buttons are read. While more than one is, deselecting
+/// both halves of the matrix reads the current player's ID, and raising P15 moves on to the
+/// next player.
+#[derive(Default)]
+struct SgbPort {
+    packet: [u8; 16],
+    /// Bits of the packet received so far, or None between packets
+    bits: Option<usize>,
+    /// Packets still to come of a command taking more than one
+    packets_left: u8,
+    /// Players whose buttons are read in turn: 1, 2 or 4
+    players: u8,
+    /// Player whose buttons are read now, from 0
+    player: u8,
+}
+
+impl SgbPort {
+    /// Takes a write of the select bits, `select` being the bits before it. Returns true if
+    /// the game moved on to the next player.
+    fn write(&mut self, old_select: u8, select: u8) -> bool {
+        match select {
+            // Both lines low resets, starting a packet
+            0b00 => {
+                self.packet = [0; 16];
+                self.bits = Some(0);
+            }
+            // Only a pulse from both lines high is a bit, a line held low is only counted once
+            0b01 | 0b10 if old_select == 0b11 => {
+                if let Some(bits) = self.bits {
+                    if select == 0b01 {
+                        self.packet[bits / 8] |= 1 << (bits % 8);
+                    }
+                    if bits + 1 == self.packet.len() * 8 {
+                        // The stop bit that follows is ignored
+                        self.bits = None;
+                        self.run_packet();
+                    } else {
+                        self.bits = Some(bits + 1);
+                    }
+                }
+            }
+            _ => {}
+        }
+        let p15_raised = old_select & 0b10 == 0 && select & 0b10 != 0;
+        if p15_raised && self.players > 1 {
+            self.player = (self.player + 1) % self.players;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn run_packet(&mut self) {
+        if self.packets_left > 0 {
+            self.packets_left -= 1;
+            return;
+        }
+        // The first byte holds the command and how many packets it takes
+        let command = self.packet[0] >> 3;
+        self.packets_left = (self.packet[0] & 0x7).saturating_sub(1);
+        if command == MLT_REQ {
+            self.players = match self.packet[1] & 0x3 {
+                0 => 1,
+                1 => 2,
+                3 => 4,
+                _ => return,
+            };
+            self.player = 0;
+        }
+    }
 }
 
 impl Joypad {
@@ -18,20 +104,45 @@ impl Joypad {
         Joypad {
             state: 0xFF,
             using_directions: false,
-            keys_pressed: [false; 8],
+            select: 0b11,
+            keys_pressed: [0; MAX_PLAYERS],
+            sgb: None,
         }
     }
 
+    /// Returns to the power-on state, staying a Super Gameboy's if it was one
+    pub fn reset(&mut self) {
+        let sgb = self.sgb.is_some();
+        *self = Joypad::power_on();
+        self.set_sgb(sgb);
+    }
+
+    /// Connects the register to a Super Gameboy, or disconnects it
+    pub fn set_sgb(&mut self, sgb: bool) {
+        self.sgb = if sgb {
+            Some(SgbPort {
+                players: 1,
+                ..Default::default()
+            })
+        } else {
+            None
+        };
+    }
+
+    /// Returns how many players' buttons are read in turn
+    pub fn players(&self) -> usize {
+        self.sgb.as_ref().map_or(1, |sgb| sgb.players as usize)
+    }
+
+    /// Returns the player whose buttons are read now
+    fn player(&self) -> usize {
+        self.sgb.as_ref().map_or(0, |sgb| sgb.player as usize)
+    }
+
     pub fn update(&mut self) -> Option<InterruptKind> {
         let old_state = self.state;
-        // Reset values
-        self.state |= 0xFFu8;
-
-        for (i, b) in self.keys_pressed.iter().enumerate() {
-            if *b {
-                self.state &= !(0b1 << i);
-            }
-        }
+        // Held buttons read as low
+        self.state = !self.keys_pressed[self.player()];
         // Get which bits changed states
         let cmp = old_state ^ self.state;
 
@@ -44,14 +155,40 @@ impl Joypad {
     }
 
     pub fn set_key_pressed(&mut self, key: GbKeys, pressed: bool) {
-        self.keys_pressed[key as usize] = pressed;
+        if pressed {
+            self.keys_pressed[0] |= 1 << key as u8;
+        } else {
+            self.keys_pressed[0] &= !(1 << key as u8);
+        }
+    }
+
+    /// Sets all eight buttons at once, from a mask with a bit set for each button held
+    pub fn set_keys_pressed(&mut self, keys: u8) {
+        self.keys_pressed[0] = keys;
+    }
+
+    pub fn keys_pressed(&self) -> u8 {
+        self.keys_pressed[0]
+    }
+
+    /// Sets all eight buttons of a player at once, counting players from 0
+    pub fn set_player_keys_pressed(&mut self, player: usize, keys: u8) {
+        self.keys_pressed[player] = keys;
+    }
+
+    pub fn player_keys_pressed(&self, player: usize) -> u8 {
+        self.keys_pressed[player]
     }
 }
 
 impl Memory for Joypad {
     fn read_byte(&self, addr: u16) -> u8 {
         assert!(addr == 0xFF00);
-        if self.using_directions {
+        if self.select == 0b11 && self.players() > 1 {
+            // With neither half selected, the current player's ID is read instead, counting
+            // down from 0xF for the first player
+            0b1111_0000 | (0xF - self.player() as u8)
+        } else if self.using_directions {
             // Return directional pad values
             (self.state | 0b1111_0000) & 0b1110_1111
         } else {
@@ -62,11 +199,19 @@ impl Memory for Joypad {
     fn write_byte(&mut self, addr: u16, val: u8) {
         assert!(addr == 0xFF00);
         // Only write the bit 4/5 into the register, mask everything else off
-        match (val >> 4) & 0b11 {
+        let old_select = self.select;
+        self.select = (val >> 4) & 0b11;
+        match self.select {
             0b00 | 0b10 => self.using_directions = true,
             0b01 | 0b11 => self.using_directions = false,
             _ => panic!("Logic error."),
         }
+        if let Some(sgb) = &mut self.sgb {
+            if sgb.write(old_select, self.select) {
+                // Switched straight to the next player's buttons, which isn't a button press
+                self.state = !self.keys_pressed[sgb.player as usize];
+            }
+        }
     }
 }
 
@@ -76,6 +221,7 @@ mod joypad_tests {
 
     use super::GbKeys;
     use super::Joypad;
+    use super::MLT_REQ;
 
     #[test]
     fn action_buttons() {
@@ -191,4 +337,74 @@ mod joypad_tests {
         assert!(joy.update().is_none());
         assert_eq!(joy.read_byte(0xFF00), 0b1110_1111);
     }
+
+    /// Sends a Super Gameboy command packet, a bit at a time
+    fn send_packet(joy: &mut Joypad, packet: [u8; 16]) {
+        joy.write_byte(0xFF00, 0x00);
+        joy.write_byte(0xFF00, 0x30);
+        for i in 0..128 {
+            let one = packet[i / 8] & (1 << (i % 8)) != 0;
+            joy.write_byte(0xFF00, if one { 0x10 } else { 0x20 });
+            joy.write_byte(0xFF00, 0x30);
+        }
+        // Stop bit
+        joy.write_byte(0xFF00, 0x20);
+        joy.write_byte(0xFF00, 0x30);
+    }
+
+    #[test]
+    fn sgb_multiplayer() {
+        let mut joy = Joypad::power_on();
+        joy.set_sgb(true);
+        joy.set_player_keys_pressed(0, 0b0001_0000);
+        joy.set_player_keys_pressed(1, 0b0010_0000);
+        joy.set_player_keys_pressed(3, 0b1000_0000);
+
+        // MLT_REQ for 4 players
+        let mut packet = [0; 16];
+        packet[0] = (MLT_REQ << 3) | 1;
+        packet[1] = 0x03;
+        send_packet(&mut joy, packet);
+        assert_eq!(joy.players(), 4);
+        joy.update();
+
+        // Each player's ID and buttons are read in turn
+        let expected = [(0xF, 0b1110), (0xE, 0b1101), (0xD, 0b1111), (0xC, 0b0111)];
+        for (id, buttons) in expected.into_iter().chain(expected) {
+            assert_eq!(joy.read_byte(0xFF00) & 0xF, id);
+            joy.write_byte(0xFF00, 0x10);
+            joy.update();
+            assert_eq!(joy.read_byte(0xFF00) & 0xF, buttons);
+            joy.write_byte(0xFF00, 0x30);
+        }
+
+        // And back to one player
+        packet[1] = 0x00;
+        send_packet(&mut joy, packet);
+        assert_eq!(joy.players(), 1);
+    }
+
+    #[test]
+    fn multiplayer_needs_sgb() {
+        let mut joy = Joypad::power_on();
+        let mut packet = [0; 16];
+        packet[0] = (MLT_REQ << 3) | 1;
+        packet[1] = 0x01;
+        send_packet(&mut joy, packet);
+        assert_eq!(joy.players(), 1);
+    }
+
+    #[test]
+    fn keys_pressed_mask() {
+        let mut joy = Joypad::power_on();
+        joy.write_byte(0xFF00, 0xDF);
+
+        // A and Start held
+        joy.set_keys_pressed(0b1001_0000);
+        assert!(joy.update().is_some());
+        assert_eq!(joy.read_byte(0xFF00), 0b1101_0110);
+
+        joy.set_key_pressed(GbKeys::Start, false);
+        assert_eq!(joy.keys_pressed(), 0b0001_0000);
+    }
 }
@@ -1,4 +1,4 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
 #[macro_use]
 extern crate alloc;
@@ -6,20 +6,57 @@ extern crate alloc;
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "apu")]
 mod apu;
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench;
+#[cfg(feature = "bus-observer")]
+pub mod bus;
+pub mod camera;
 mod cartridge;
+pub mod cheats;
 mod cpu;
+pub mod debug;
+#[cfg(feature = "disassembler")]
 pub mod disassemble;
+pub mod error;
+pub mod events;
 pub mod gb;
 mod joypad;
 mod mmu;
-mod serial;
+pub mod opcodes;
+pub mod palette;
+pub mod rtc;
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+pub mod runner;
+pub mod serial;
 pub mod sink;
+// Browsers can't spawn threads from wasm32-unknown-unknown, so only the core is built there
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+pub mod thread;
 mod timer;
 mod util;
 mod vram;
 mod wram;
 
-pub const CLOCK_RATE: u32 = 4_194_304;
+/// Cycles run per second
+pub const CLOCK_HZ: u32 = 4_194_304;
+/// The same as `CLOCK_HZ`
+pub const CLOCK_RATE: u32 = CLOCK_HZ;
 pub const CGB_CLOCK_RATE: u32 = CLOCK_RATE * 2;
 pub const SAMPLE_RATE: u32 = CLOCK_RATE / 16; // 262.144 KHz sample rate
+/// Cycles taken to draw one frame, 154 lines of 456 cycles each
+pub const CYCLES_PER_FRAME: u32 = 70_224;
+/// Nanoseconds taken to run one cycle, about 238.4
+pub const CYCLE_TIME_NS: f64 = 1e9 / CLOCK_HZ as f64;
+/// Nanoseconds taken to draw one frame, rounded down
+pub const FRAME_TIME_NS: u64 = CYCLES_PER_FRAME as u64 * 1_000_000_000 / CLOCK_HZ as u64;
+/// Frames drawn per second, about 59.73
+pub const FRAME_RATE: f64 = CLOCK_HZ as f64 / CYCLES_PER_FRAME as f64;
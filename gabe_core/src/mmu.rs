@@ -1,13 +1,20 @@
 use alloc::boxed::*;
 use alloc::vec::*;
 
+#[cfg(feature = "apu")]
 use super::apu::Apu;
+#[cfg(feature = "bus-observer")]
+use super::bus::{BusObserver, BusSource};
 use super::cartridge::Cartridge;
+use super::debug::{Profiler, Subsystem};
+use super::error::GabeError;
+use super::gb::RamInit;
 use super::joypad::Joypad;
 use super::serial::Serial;
 use super::sink::*;
 use super::timer::Timer;
-use super::vram::Vram;
+use super::util::rng::SplitMix64;
+use super::vram::{FrameData, Vram};
 use super::wram::Wram;
 
 /// The possible states of a DMA transfer running within the MMU. Until a write is performed
@@ -30,7 +37,8 @@ enum DmaState {
 ///
 /// Order represents the priority of interrupt execution when multiple
 /// interrupts are enabled and requested at once.
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InterruptKind {
     /// Vertical Blank interrupt whenever the LCD enters the V-Blank period.
     /// (INT 0x40)
@@ -50,6 +58,48 @@ pub enum InterruptKind {
     Joypad = 0b0001_0000,
 }
 
+impl InterruptKind {
+    /// All interrupt kinds, in order of priority
+    pub const ALL: [InterruptKind; 5] = [
+        InterruptKind::VBlank,
+        InterruptKind::LcdStat,
+        InterruptKind::Timer,
+        InterruptKind::Serial,
+        InterruptKind::Joypad,
+    ];
+
+    /// Returns the address of the interrupt handler the CPU jumps to when servicing the interrupt
+    pub fn vector(&self) -> u16 {
+        match self {
+            InterruptKind::VBlank => 0x40,
+            InterruptKind::LcdStat => 0x48,
+            InterruptKind::Timer => 0x50,
+            InterruptKind::Serial => 0x58,
+            InterruptKind::Joypad => 0x60,
+        }
+    }
+}
+
+/// A set of interrupt kinds, held as their bits in the IF register, so an update can request
+/// several at once without allocating
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Interrupts(u8);
+
+impl Interrupts {
+    pub fn insert(&mut self, int: InterruptKind) {
+        self.0 |= int as u8;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the interrupts as they're set in the IF register
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+}
+
 /// Trait representing a piece of memory in the system that can have bytes read and written to.
 /// write/read words are just composed from write/read byte, so implementors only need to implement
 /// `read_byte` and `write_byte`.
@@ -72,31 +122,55 @@ pub trait Memory {
 /// reading and writing into each block, no logic is performed otherwise.
 pub struct Mmu {
     pub cart: Box<dyn Cartridge>,
-    apu: Apu,
-    vram: Vram,
+    #[cfg(feature = "apu")]
+    pub apu: Apu,
+    pub vram: Vram,
     wram: Wram,
     timer: Timer,
     pub joypad: Joypad,
-    serial: Serial,
+    pub serial: Serial,
     hram: [u8; 0x7F],
     intf: u8,
     ie: u8,
     dma_state: DmaState,
     previous_dma: u8,
+    /// Cycles run since the PPU was last updated. It's only brought up to date when it's due
+    /// to change mode or line, or before its registers are written, so most steps skip it.
+    ppu_cycles: u32,
+    /// Cycles the PPU can fall behind by before it has to be updated
+    ppu_deadline: u32,
+    /// Enabled Game Genie codes, as (address, value, compare) patches applied to ROM reads
+    rom_patches: Vec<(u16, u8, Option<u8>)>,
+    /// Set to record writes to the cartridge RAM area in `sram_writes`
+    pub watch_sram_writes: bool,
+    /// Writes to the cartridge RAM area, as (address, value), waiting to be taken as events
+    pub sram_writes: Vec<(u16, u8)>,
+    #[cfg(feature = "bus-observer")]
+    pub observer: Option<Box<dyn BusObserver>>,
+    /// The boot ROM, if one was given, read in place of the cartridge ROM's first 256 bytes
+    /// while it's mapped
+    boot_rom: Option<Box<[u8]>>,
+    /// Set while the boot ROM is mapped, until the CPU writes to 0xFF50
+    boot_rom_mapped: bool,
 }
 
 impl Mmu {
-    /// Initializes the MMU with the given ROM path.
-    /// Opens the given file and reads cartridge header information to find
-    /// the MBC type.
-    pub fn power_on(rom_data: Box<[u8]>, save_data: Option<Box<[u8]>>) -> Self {
+    /// Initializes the MMU with the given ROM data.
+    /// Reads cartridge header information to find the MBC type, failing if the ROM
+    /// has no header or its cartridge type isn't supported.
+    pub fn power_on(rom_data: Box<[u8]>, save_data: Option<Box<[u8]>>) -> Result<Self, GabeError> {
+        use super::cartridge::camera::PocketCamera;
         use super::cartridge::mbc0::Mbc0;
         use super::cartridge::mbc1::Mbc1;
         use super::cartridge::mbc2::Mbc2;
         use super::cartridge::mbc3::Mbc3;
+        use super::cartridge::mbc5::Mbc5;
+        use super::cartridge::mbc7::Mbc7;
 
-        let title =
-            core::str::from_utf8(&rom_data[0x134..0x13F]).map_or_else(|_| "Invalid Title", |v| v);
+        if rom_data.len() < 0x150 {
+            return Err(GabeError::InvalidRomLength(rom_data.len()));
+        }
+        let title = core::str::from_utf8(&rom_data[0x134..0x13F]).unwrap_or("Invalid Title");
         let rom_size = rom_data[0x148];
         let ram_size = rom_data[0x149];
         info!("Cartridge Info:");
@@ -110,60 +184,88 @@ impl Mmu {
             0x5 => info!("\tRAM Size: 64 KiB"),
             _ => info!("\tRAM Size: Unknown"),
         };
-        let mut cart: Box<dyn Cartridge> = match rom_data[0x147] {
+        let cart: Box<dyn Cartridge> = match rom_data[0x147] {
             0x00 => {
                 info!("\tMBC Type: MBC0/No MBC.");
-                Box::new(Mbc0::power_on(rom_data))
+                Box::new(Mbc0::power_on(rom_data)?)
             }
             0x01 => {
                 info!("\tMBC Type: MBC1 w/o RAM");
-                Box::new(Mbc1::power_on(rom_data, rom_size, 0, false))
+                Box::new(Mbc1::power_on(rom_data, rom_size, 0, false)?)
             }
             0x02 => {
                 info!("\tMBC Type: MBC1 w/ RAM");
-                Box::new(Mbc1::power_on(rom_data, rom_size, ram_size, false))
+                Box::new(Mbc1::power_on(rom_data, rom_size, ram_size, false)?)
             }
             0x03 => {
                 info!("\tMBC Type: MBC1 w/ RAM and Battery");
-                Box::new(Mbc1::power_on(rom_data, rom_size, ram_size, true))
+                Box::new(Mbc1::power_on(rom_data, rom_size, ram_size, true)?)
             }
             0x05 => {
                 info!("\tMBC Type: MBC2");
-                Box::new(Mbc2::power_on(rom_data, rom_size, false))
+                Box::new(Mbc2::power_on(rom_data, rom_size, false)?)
             }
             0x06 => {
                 info!("\tMBC Type: MBC2 w/ Battery");
-                Box::new(Mbc2::power_on(rom_data, rom_size, true))
+                Box::new(Mbc2::power_on(rom_data, rom_size, true)?)
             }
             0x0F => {
                 info!("\tMBC Type: MBC3 w/ RTC + Battery");
-                Box::new(Mbc3::power_on(rom_data, rom_size, 0, true, true))
+                Box::new(Mbc3::power_on(rom_data, rom_size, 0, true, true)?)
             }
             0x10 => {
                 info!("\tMBC Type: MBC3 w/ RTC + RAM + Battery");
-                Box::new(Mbc3::power_on(rom_data, rom_size, ram_size, true, true))
+                Box::new(Mbc3::power_on(rom_data, rom_size, ram_size, true, true)?)
             }
             0x11 => {
                 info!("\tMBC Type: MBC3");
-                Box::new(Mbc3::power_on(rom_data, rom_size, 0, false, false))
+                Box::new(Mbc3::power_on(rom_data, rom_size, 0, false, false)?)
             }
             0x12 => {
                 info!("\tMBC Type: MBC3 w/ RAM");
-                Box::new(Mbc3::power_on(rom_data, rom_size, ram_size, false, false))
+                Box::new(Mbc3::power_on(rom_data, rom_size, ram_size, false, false)?)
             }
             0x13 => {
                 info!("\tMBC Type: MBC3 w/ RAM + Battery");
-                Box::new(Mbc3::power_on(rom_data, rom_size, ram_size, true, false))
+                Box::new(Mbc3::power_on(rom_data, rom_size, ram_size, true, false)?)
             }
-            _ => unimplemented!("MBC value {:02X} not supported!", rom_data[0x147]),
-        };
-        if let Some(data) = save_data {
-            if let Err(e) = cart.read_save_data(data) {
-                info!("Save file will not be written: {}", e);
+            0x19 => {
+                info!("\tMBC Type: MBC5");
+                Box::new(Mbc5::power_on(rom_data, rom_size, 0, false, false)?)
             }
-        }
-        Mmu {
+            0x1A => {
+                info!("\tMBC Type: MBC5 w/ RAM");
+                Box::new(Mbc5::power_on(rom_data, rom_size, ram_size, false, false)?)
+            }
+            0x1B => {
+                info!("\tMBC Type: MBC5 w/ RAM + Battery");
+                Box::new(Mbc5::power_on(rom_data, rom_size, ram_size, true, false)?)
+            }
+            0x1C => {
+                info!("\tMBC Type: MBC5 w/ Rumble");
+                Box::new(Mbc5::power_on(rom_data, rom_size, 0, false, true)?)
+            }
+            0x1D => {
+                info!("\tMBC Type: MBC5 w/ Rumble + RAM");
+                Box::new(Mbc5::power_on(rom_data, rom_size, ram_size, false, true)?)
+            }
+            0x1E => {
+                info!("\tMBC Type: MBC5 w/ Rumble + RAM + Battery");
+                Box::new(Mbc5::power_on(rom_data, rom_size, ram_size, true, true)?)
+            }
+            0x22 => {
+                info!("\tMBC Type: MBC7 w/ Accelerometer + EEPROM");
+                Box::new(Mbc7::power_on(rom_data, rom_size)?)
+            }
+            0xFC => {
+                info!("\tMBC Type: Pocket Camera");
+                Box::new(PocketCamera::power_on(rom_data, rom_size)?)
+            }
+            kind => return Err(GabeError::UnsupportedMapper(kind)),
+        };
+        let mut mmu = Mmu {
             cart,
+            #[cfg(feature = "apu")]
             apu: Apu::power_on(),
             vram: Vram::power_on(),
             wram: Wram::power_on(),
@@ -175,6 +277,80 @@ impl Mmu {
             ie: 0x00,
             dma_state: DmaState::Stopped,
             previous_dma: 0xFF,
+            ppu_cycles: 0,
+            ppu_deadline: 0,
+            rom_patches: vec![],
+            watch_sram_writes: false,
+            sram_writes: vec![],
+            #[cfg(feature = "bus-observer")]
+            observer: None,
+            boot_rom: None,
+            boot_rom_mapped: false,
+        };
+        if let Some(data) = save_data {
+            mmu.load_save_data(data);
+        }
+        Ok(mmu)
+    }
+
+    /// Loads the cartridge's battery-backed RAM, and the state of its real-time clock if it
+    /// has one, from a save file
+    pub fn load_save_data(&mut self, data: Box<[u8]>) {
+        if let Err(e) = self.cart.read_save_data(data) {
+            info!("Save file will not be written: {}", e);
+        }
+    }
+
+    /// Returns every component to its power-on state, as the reset button does, keeping the
+    /// cartridge's ROM and RAM, the connected serial device and the frontend's settings
+    pub fn reset(&mut self) {
+        self.cart.reset();
+        #[cfg(feature = "apu")]
+        self.apu.reset();
+        self.vram.reset();
+        self.wram = Wram::power_on();
+        self.timer = Timer::power_on();
+        self.joypad.reset();
+        self.serial.reset();
+        self.hram = [0; 0x7F];
+        self.intf = 0xE1;
+        self.ie = 0x00;
+        self.dma_state = DmaState::Stopped;
+        self.previous_dma = 0xFF;
+        self.ppu_cycles = 0;
+        self.ppu_deadline = 0;
+        self.boot_rom_mapped = self.boot_rom.is_some();
+    }
+
+    /// Maps a boot ROM over the start of the cartridge ROM, where it stays until the boot ROM
+    /// unmaps itself by writing to 0xFF50. It's mapped again on every reset.
+    pub fn set_boot_rom(&mut self, boot_rom: Box<[u8]>) {
+        self.boot_rom = Some(boot_rom);
+        self.boot_rom_mapped = true;
+    }
+
+    /// Returns true while the boot ROM is mapped
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom_mapped
+    }
+
+    /// Fills work RAM and high RAM as they're found at power on, and VRAM too unless a boot
+    /// ROM is mapped, as the boot ROM clears it
+    pub fn init_ram(&mut self, init: RamInit) {
+        let mut bytes: Box<dyn FnMut() -> u8> = match init {
+            RamInit::Zeroed => Box::new(|| 0),
+            RamInit::Fill(value) => Box::new(move || value),
+            RamInit::Random(seed) => {
+                let mut rng = SplitMix64::new(seed);
+                Box::new(move || rng.next_u64() as u8)
+            }
+        };
+        self.wram.fill(&mut bytes);
+        self.hram.fill_with(&mut bytes);
+        if !self.boot_rom_mapped {
+            for addr in 0x8000..=0x9FFF {
+                self.vram.write_byte(addr, bytes());
+            }
         }
     }
 
@@ -184,17 +360,20 @@ impl Mmu {
     /// block, for the CPU to handle on the next fetch.
     /// If a frame was completed during execution, return `FrameData` to caller,
     /// otherwise return `None`
-    pub fn update(
+    pub fn update<P: Profiler>(
         &mut self,
         cycles: u32,
-        video_sink: &mut dyn Sink<VideoFrame>,
-        audio_sink: &mut dyn Sink<AudioFrame>,
+        video_sink: &mut dyn Sink<FrameData>,
+        profiler: &mut P,
     ) {
         if self.dma_state != DmaState::Stopped {
             self.dma_state = self.run_dma(cycles);
         }
+        profiler.mark(Subsystem::Dma);
         // Update APU
-        self.apu.update(cycles, audio_sink);
+        #[cfg(feature = "apu")]
+        self.apu.update(cycles);
+        profiler.mark(Subsystem::Apu);
 
         // Update Joypad
         if let Some(i) = self.joypad.update() {
@@ -205,12 +384,65 @@ impl Mmu {
         if let Some(i) = self.timer.update(cycles) {
             self.request_interrupt(i);
         }
-        // Update VRAM
-        if let Some(i) = self.vram.update(cycles, video_sink) {
-            for interrupt in i {
-                self.request_interrupt(interrupt);
+
+        // Update Serial
+        if let Some(i) = self.serial.update(cycles) {
+            self.request_interrupt(i);
+        }
+
+        // Update the cartridge, for mappers with hardware of their own
+        self.cart.update(cycles);
+        profiler.mark(Subsystem::Io);
+        // Update VRAM, once it's due to change mode or line
+        self.ppu_cycles = self.ppu_cycles.saturating_add(cycles);
+        if self.ppu_cycles >= self.ppu_deadline {
+            let interrupts = self.vram.update(self.ppu_cycles, video_sink);
+            if !interrupts.is_empty() {
+                self.request_interrupts(interrupts);
+            }
+            self.ppu_cycles = 0;
+            self.ppu_deadline = self.vram.cycles_to_next_event();
+        }
+        profiler.mark(Subsystem::Ppu);
+    }
+
+    /// Returns the number of audio samples generated but not yet passed on with `flush_audio`
+    pub fn buffered_audio(&self) -> usize {
+        #[cfg(feature = "apu")]
+        let buffered = self.apu.buffered();
+        #[cfg(not(feature = "apu"))]
+        let buffered = 0;
+        buffered
+    }
+
+    /// Passes the audio samples generated since the last flush on to the sink
+    pub fn flush_audio(&mut self, audio_sink: &mut dyn Sink<AudioFrame>) {
+        #[cfg(feature = "apu")]
+        self.apu.flush(audio_sink);
+        #[cfg(not(feature = "apu"))]
+        let _ = audio_sink;
+    }
+
+    /// Replaces the Game Genie patches applied to ROM reads
+    pub fn set_rom_patches(&mut self, patches: Vec<(u16, u8, Option<u8>)>) {
+        self.rom_patches = patches;
+    }
+
+    /// Reads a byte of ROM, with any Game Genie patches for the address applied
+    fn read_rom(&self, addr: u16) -> u8 {
+        let val = self.cart.read_byte(addr);
+        for &(patch_addr, patch_val, compare) in &self.rom_patches {
+            if patch_addr == addr && compare.unwrap_or(val) == val {
+                return patch_val;
             }
         }
+        val
+    }
+
+    /// Returns the interrupts requested, as the IF register holds them. Unlike reading 0xFF0F,
+    /// this works during DMA.
+    pub fn interrupt_flags(&self) -> u8 {
+        self.intf
     }
 
     /// Takes the given Interrupt enum value, and sets the corresponding bit
@@ -222,6 +454,25 @@ impl Mmu {
         self.write_byte(0xFF0F, int_flag);
     }
 
+    /// Brings the PPU up to date before one of its registers is written, so the cycles before
+    /// the write are run with the old value. The PPU hasn't reached its next mode or line yet,
+    /// so this can't finish a frame or request an interrupt.
+    fn sync_ppu(&mut self) {
+        let interrupts = self
+            .vram
+            .update(self.ppu_cycles, &mut Vec::<FrameData>::new());
+        debug_assert!(interrupts.is_empty());
+        self.ppu_cycles = 0;
+        // The write can change what's due next, so update again on the next step
+        self.ppu_deadline = 0;
+    }
+
+    /// Requests every interrupt in `ints` at once, as `request_interrupt` does for one
+    fn request_interrupts(&mut self, ints: Interrupts) {
+        let int_flag = self.read_byte(0xFF0F) | ints.bits();
+        self.write_byte(0xFF0F, int_flag);
+    }
+
     /// Debug function. Returns a simple Vec of the requested range of data. Only returns
     /// data visible to MMU, so any non-selected banks or block-internal data not memory-mapped
     /// will not be returned.
@@ -241,6 +492,16 @@ impl Mmu {
     /// It takes about 160 us for a full DMA, which is a little more than
     /// 1 us per cycle. Doing 1-to-1 cycles into a write of data for simplicity
     /// even though that will complete DMA a *bit* faster than hardware.
+    /// Reports a byte copied by DMA to the bus observer, if one is attached
+    #[allow(unused_variables)]
+    fn observe_dma(&mut self, src_addr: u16, oam_addr: u16, val: u8) {
+        #[cfg(feature = "bus-observer")]
+        if let Some(observer) = &mut self.observer {
+            observer.on_read(src_addr, val, BusSource::Dma);
+            observer.on_write(oam_addr, val, BusSource::Dma);
+        }
+    }
+
     fn run_dma(&mut self, cycles: u32) -> DmaState {
         match self.dma_state {
             DmaState::Starting(s) => {
@@ -252,9 +513,11 @@ impl Mmu {
                         0x8000..=0x9F9F => self.vram.read_byte(src_addr),
                         0xA000..=0xBF9F => self.cart.read_byte(src_addr),
                         0xC000..=0xF19F => self.wram.read_byte(src_addr),
-                        _ => panic!("Invalid DMA read location {:4X}", src_addr),
+                        // Higher pages read from work RAM, like the echo area does on the DMG
+                        _ => self.wram.read_byte(0xC000 | (src_addr & 0x1FFF)),
                     };
                     let oam_addr = 0xFE00 | (src_addr & 0xFF);
+                    self.observe_dma(src_addr, oam_addr, val);
                     self.vram.write_byte(oam_addr, val);
                 }
                 DmaState::Running(addr + cycles as u16)
@@ -273,9 +536,11 @@ impl Mmu {
                             0x8000..=0x9F9F => self.vram.read_byte(src_addr),
                             0xA000..=0xBF9F => self.cart.read_byte(src_addr),
                             0xC000..=0xF19F => self.wram.read_byte(src_addr),
-                            _ => panic!("Invalid DMA read location {:4X}", src_addr),
+                            // Higher pages read from work RAM, like the echo area does on the DMG
+                            _ => self.wram.read_byte(0xC000 | (src_addr & 0x1FFF)),
                         };
                         let oam_addr = 0xFE00 | (src_addr & 0xFF);
+                        self.observe_dma(src_addr, oam_addr, val);
                         self.vram.write_byte(oam_addr, val);
                     }
                 }
@@ -308,7 +573,11 @@ impl Memory for Mmu {
             0xFF
         } else {
             match addr {
-                0x0000..=0x7FFF => self.cart.read_byte(addr),
+                0x0000..=0x00FF if self.boot_rom_mapped => self
+                    .boot_rom
+                    .as_ref()
+                    .map_or(0xFF, |rom| rom[addr as usize]),
+                0x0000..=0x7FFF => self.read_rom(addr),
                 0x8000..=0x9FFF => self.vram.read_byte(addr),
                 0xA000..=0xBFFF => self.cart.read_byte(addr),
                 0xC000..=0xFDFF => self.wram.read_byte(addr),
@@ -317,7 +586,10 @@ impl Memory for Mmu {
                 0xFF01..=0xFF02 => self.serial.read_byte(addr),
                 0xFF04..=0xFF07 => self.timer.read_byte(addr),
                 0xFF0F => self.intf,
+                #[cfg(feature = "apu")]
                 0xFF10..=0xFF3F => self.apu.read_byte(addr),
+                #[cfg(not(feature = "apu"))]
+                0xFF10..=0xFF3F => 0xFF,
                 0xFF46 => self.previous_dma,
                 0xFF40..=0xFF6F => self.vram.read_byte(addr),
                 0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize],
@@ -333,20 +605,32 @@ impl Memory for Mmu {
             match addr {
                 0x0000..=0x7FFF => self.cart.write_byte(addr, val),
                 0x8000..=0x9FFF => self.vram.write_byte(addr, val),
-                0xA000..=0xBFFF => self.cart.write_byte(addr, val),
+                0xA000..=0xBFFF => {
+                    if self.watch_sram_writes {
+                        self.sram_writes.push((addr, val));
+                    }
+                    self.cart.write_byte(addr, val);
+                }
                 0xC000..=0xFDFF => self.wram.write_byte(addr, val),
                 0xFE00..=0xFE9F => self.vram.write_byte(addr, val),
                 0xFF00 => self.joypad.write_byte(addr, val),
                 0xFF01..=0xFF02 => self.serial.write_byte(addr, val),
                 0xFF04..=0xFF07 => self.timer.write_byte(addr, val),
                 0xFF0F => self.intf = val,
+                #[cfg(feature = "apu")]
                 0xFF10..=0xFF3F => self.apu.write_byte(addr, val),
+                #[cfg(not(feature = "apu"))]
+                0xFF10..=0xFF3F => {}
                 0xFF46 => {
                     trace!("Beginning DMA Transfer at {:2X}00...", val);
                     self.dma_state = DmaState::Starting(val);
                     self.previous_dma = val;
                 }
-                0xFF40..=0xFF6F => self.vram.write_byte(addr, val),
+                0xFF50 if val & 0x01 != 0 => self.boot_rom_mapped = false,
+                0xFF40..=0xFF6F => {
+                    self.sync_ppu();
+                    self.vram.write_byte(addr, val);
+                }
                 0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize] = val,
                 0xFFFF => self.ie = val,
                 _ => self.unassigned_write(addr, val),
@@ -357,6 +641,311 @@ impl Memory for Mmu {
 
 #[cfg(test)]
 mod mmu_tests {
+    use super::*;
+    use crate::debug::NoProfiler;
+
     #[test]
     fn interrupt_requests() {}
+
+    #[test]
+    fn rejects_invalid_roms() {
+        let rom = vec![0; 0x100].into_boxed_slice();
+        assert_eq!(
+            Mmu::power_on(rom, None).err(),
+            Some(GabeError::InvalidRomLength(0x100))
+        );
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0xFD;
+        assert_eq!(
+            Mmu::power_on(rom.into_boxed_slice(), None).err(),
+            Some(GabeError::UnsupportedMapper(0xFD))
+        );
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x01;
+        rom[0x148] = 0x20;
+        assert_eq!(
+            Mmu::power_on(rom.into_boxed_slice(), None).err(),
+            Some(GabeError::UnsupportedRomSize(0x20))
+        );
+    }
+
+    #[test]
+    fn ppu_catch_up() {
+        let mut mmu = Mmu::power_on(vec![0; 0x8000].into_boxed_slice(), None).unwrap();
+        mmu.write_byte(0xFF40, 0x80);
+        mmu.write_byte(0xFF0F, 0x00);
+        let mut frames: Vec<FrameData> = Vec::new();
+        let mut step = |mmu: &mut Mmu, cycles| {
+            mmu.update(cycles, &mut frames, &mut NoProfiler);
+        };
+        // Updated when entering mode 2, then left behind until mode 3
+        step(&mut mmu, 4);
+        assert_eq!(mmu.read_byte(0xFF41) & 0b11, 2);
+        assert_eq!(mmu.ppu_deadline, 77);
+        step(&mut mmu, 76);
+        assert_eq!(mmu.ppu_cycles, 76);
+        step(&mut mmu, 4);
+        assert_eq!(mmu.read_byte(0xFF41) & 0b11, 3);
+        // Writing a register catches up first
+        step(&mut mmu, 8);
+        mmu.write_byte(0xFF45, 0x01);
+        assert_eq!(mmu.ppu_cycles, 0);
+        for _ in 0..(456 - 92) / 4 {
+            step(&mut mmu, 4);
+        }
+        assert_eq!(mmu.read_byte(0xFF44), 1);
+        assert_eq!(mmu.read_byte(0xFF41) & 0b0100, 0b0100);
+        for _ in 0..143 * 456 / 4 {
+            step(&mut mmu, 4);
+        }
+        assert_eq!(mmu.read_byte(0xFF44), 144);
+        assert_eq!(mmu.read_byte(0xFF0F) & InterruptKind::VBlank as u8, 1);
+        assert_eq!(frames.len(), 1);
+    }
+
+    #[test]
+    fn pads_short_roms() {
+        // Headers claiming more banks than the file holds are common in homebrew
+        let mut rom = vec![0; 0x4000];
+        rom[0x147] = 0x01;
+        rom[0x148] = 0x01;
+        let mmu = Mmu::power_on(rom.into_boxed_slice(), None).unwrap();
+        assert_eq!(mmu.read_byte(0x4000), 0xFF);
+    }
+
+    #[test]
+    fn mbc5_banks() {
+        let mut rom = vec![0; 0x80_0000];
+        rom[0x147] = 0x1B;
+        rom[0x148] = 0x08;
+        rom[0x149] = 0x03;
+        for bank in 0..0x200 {
+            rom[bank * 0x4000 + 0x1000] = bank as u8;
+            rom[bank * 0x4000 + 0x1001] = (bank >> 8) as u8;
+        }
+        let mut mmu = Mmu::power_on(rom.into_boxed_slice(), None).unwrap();
+        let bank = |mmu: &Mmu| u16::from_le_bytes([mmu.read_byte(0x5000), mmu.read_byte(0x5001)]);
+        assert_eq!(bank(&mmu), 1);
+        // Nine bits of bank, split over two registers
+        mmu.write_byte(0x2000, 0x23);
+        mmu.write_byte(0x3000, 0x01);
+        assert_eq!(bank(&mmu), 0x123);
+        // Bank 0 can be mapped too
+        mmu.write_byte(0x2000, 0x00);
+        mmu.write_byte(0x3000, 0x00);
+        assert_eq!(bank(&mmu), 0);
+
+        mmu.write_byte(0x0000, 0x0A);
+        mmu.write_byte(0x4000, 0x02);
+        mmu.write_byte(0xA000, 0x42);
+        mmu.write_byte(0x4000, 0x00);
+        assert_eq!(mmu.read_byte(0xA000), 0x00);
+        mmu.write_byte(0x4000, 0x02);
+        assert_eq!(mmu.read_byte(0xA000), 0x42);
+    }
+
+    #[test]
+    fn mbc30_banks() {
+        // An MBC3 with RTC, RAM and battery, but 4 MB of ROM and 64 KB of RAM
+        let mut rom = vec![0; 0x40_0000];
+        rom[0x147] = 0x10;
+        rom[0x148] = 0x07;
+        rom[0x149] = 0x05;
+        for bank in 0..0x100 {
+            rom[bank * 0x4000 + 0x1000] = bank as u8;
+        }
+        let mut mmu = Mmu::power_on(rom.into_boxed_slice(), None).unwrap();
+        // The 8th bit of the ROM bank is connected
+        mmu.write_byte(0x2000, 0xC3);
+        assert_eq!(mmu.read_byte(0x5000), 0xC3);
+        mmu.write_byte(0x2000, 0x80);
+        assert_eq!(mmu.read_byte(0x5000), 0x80);
+
+        // And so is the 3rd bit of the RAM bank
+        mmu.write_byte(0x0000, 0x0A);
+        mmu.write_byte(0x4000, 0x07);
+        mmu.write_byte(0xA000, 0x42);
+        mmu.write_byte(0x4000, 0x03);
+        assert_eq!(mmu.read_byte(0xA000), 0x00);
+        mmu.write_byte(0x4000, 0x07);
+        assert_eq!(mmu.read_byte(0xA000), 0x42);
+        // Followed by the RTC's state
+        assert_eq!(mmu.cart.write_save_data().unwrap().len(), 0x1_0030);
+    }
+
+    #[test]
+    fn mbc3_rtc() {
+        use crate::rtc::ManualClock;
+
+        // MBC3 w/ RTC + Battery
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x0F;
+        let mut mmu = Mmu::power_on(rom.into_boxed_slice(), None).unwrap();
+        let clock = ManualClock::new(1000);
+        mmu.cart.connect_rtc_clock(Box::new(clock.clone())).unwrap();
+        let read = |mmu: &mut Mmu| -> [u8; 5] {
+            mmu.write_byte(0x6000, 0x00);
+            mmu.write_byte(0x6000, 0x01);
+            core::array::from_fn(|i| {
+                mmu.write_byte(0x4000, 0x08 + i as u8);
+                mmu.read_byte(0xA000)
+            })
+        };
+        mmu.write_byte(0x0000, 0x0A);
+        assert_eq!(read(&mut mmu), [0, 0, 0, 0, 0]);
+
+        // 1 day, 2 hours, 3 minutes and 4 seconds
+        clock.advance(86400 + 2 * 3600 + 3 * 60 + 4);
+        assert_eq!(read(&mut mmu), [4, 3, 2, 1, 0]);
+        // The latched registers don't change until latched again
+        clock.advance(1);
+        mmu.write_byte(0x4000, 0x08);
+        assert_eq!(mmu.read_byte(0xA000), 4);
+
+        // Halting stops the clock, and the registers can be set
+        mmu.write_byte(0x4000, 0x0C);
+        mmu.write_byte(0xA000, 0x41);
+        mmu.write_byte(0x4000, 0x0B);
+        mmu.write_byte(0xA000, 0xFF);
+        clock.advance(100);
+        assert_eq!(read(&mut mmu), [5, 3, 2, 0xFF, 0x41]);
+
+        // Running on past day 511 sets the carry
+        mmu.write_byte(0x4000, 0x0C);
+        mmu.write_byte(0xA000, 0x01);
+        clock.advance(86400);
+        assert_eq!(read(&mut mmu), [5, 3, 2, 0x00, 0x80]);
+
+        // The RTC is saved, and counts the time passed since when loaded
+        let save = mmu.cart.write_save_data().unwrap();
+        assert_eq!(save.len(), 48);
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x0F;
+        let mut mmu = Mmu::power_on(rom.into_boxed_slice(), None).unwrap();
+        mmu.cart.connect_rtc_clock(Box::new(clock.clone())).unwrap();
+        mmu.load_save_data(save);
+        clock.advance(60);
+        mmu.write_byte(0x0000, 0x0A);
+        assert_eq!(read(&mut mmu), [5, 4, 2, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn mbc7_accelerometer() {
+        let mut rom = vec![0; 0x10_0000];
+        rom[0x147] = 0x22;
+        rom[0x148] = 0x05;
+        let mut mmu = Mmu::power_on(rom.into_boxed_slice(), None).unwrap();
+        let latch = |mmu: &mut Mmu| {
+            mmu.write_byte(0xA000, 0x55);
+            mmu.write_byte(0xA010, 0xAA);
+            let x = u16::from_le_bytes([mmu.read_byte(0xA020), mmu.read_byte(0xA030)]);
+            let y = u16::from_le_bytes([mmu.read_byte(0xA040), mmu.read_byte(0xA050)]);
+            (x, y)
+        };
+        // The registers need both enables
+        mmu.write_byte(0x0000, 0x0A);
+        assert_eq!(mmu.read_byte(0xA020), 0xFF);
+        mmu.write_byte(0x4000, 0x40);
+
+        assert_eq!(latch(&mut mmu), (0x81D0, 0x81D0));
+        mmu.cart.set_tilt(1.0, -0.5);
+        assert_eq!(latch(&mut mmu), (0x81D0 - 0x70, 0x81D0 + 0x38));
+        // Latching again without erasing keeps the old reading
+        mmu.cart.set_tilt(0.0, 0.0);
+        mmu.write_byte(0xA010, 0xAA);
+        assert_eq!(mmu.read_byte(0xA020), 0x60);
+    }
+
+    #[test]
+    fn mbc7_eeprom() {
+        let mut rom = vec![0; 0x10_0000];
+        rom[0x147] = 0x22;
+        rom[0x148] = 0x05;
+        let mut mmu = Mmu::power_on(rom.into_boxed_slice(), None).unwrap();
+        mmu.write_byte(0x0000, 0x0A);
+        mmu.write_byte(0x4000, 0x40);
+        // Clocks bits in with chip select held, then drops it
+        let send = |mmu: &mut Mmu, bits: &[u8]| {
+            let mut out = vec![];
+            for bit in bits {
+                mmu.write_byte(0xA080, 0x80 | bit << 1);
+                mmu.write_byte(0xA080, 0xC0 | bit << 1);
+                out.push(mmu.read_byte(0xA080) & 1);
+            }
+            mmu.write_byte(0xA080, 0x00);
+            out
+        };
+        let command = |start_op: [u8; 3], address: u8| {
+            let mut bits = start_op.to_vec();
+            bits.extend((0..8).rev().map(|i| address >> i & 1));
+            bits
+        };
+        let word = |word: u16| (0..16).rev().map(move |i| (word >> i & 1) as u8);
+
+        // Writes are ignored until enabled
+        let write = command([1, 0, 1], 0x05).into_iter().chain(word(0x1234));
+        send(&mut mmu, &write.clone().collect::<Vec<_>>());
+        send(&mut mmu, &command([1, 0, 0], 0xC0));
+        send(&mut mmu, &write.collect::<Vec<_>>());
+
+        let mut read = command([1, 1, 0], 0x05);
+        read.extend([0; 16]);
+        let out = send(&mut mmu, &read);
+        let value = out[11..]
+            .iter()
+            .fold(0, |word, bit| word << 1 | *bit as u16);
+        assert_eq!(value, 0x1234);
+        let save = mmu.cart.write_save_data().unwrap();
+        assert_eq!(save.len(), 0x100);
+        assert_eq!(save[0x0A..0x0C], [0x34, 0x12]);
+        assert_eq!(save[0x0C..0x0E], [0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn camera_capture() {
+        use crate::camera::{StillImage, SENSOR_HEIGHT, SENSOR_WIDTH};
+
+        let mut rom = vec![0; 0x10_0000];
+        rom[0x147] = 0xFC;
+        rom[0x148] = 0x05;
+        rom[0x149] = 0x04;
+        let mut mmu = Mmu::power_on(rom.into_boxed_slice(), None).unwrap();
+        // White on the left half, black on the right
+        let image: Vec<u8> = (0..SENSOR_WIDTH * SENSOR_HEIGHT)
+            .map(|i| if i % SENSOR_WIDTH < 64 { 0xFF } else { 0x00 })
+            .collect();
+        let sensor = StillImage::new(&image, SENSOR_WIDTH, SENSOR_HEIGHT);
+        mmu.cart.connect_camera(Box::new(sensor)).unwrap();
+
+        // Select the registers, with an exposure of 0x1000 and the thresholds at 0x40, 0x80
+        // and 0xC0 everywhere, then start a capture
+        mmu.write_byte(0x4000, 0x10);
+        mmu.write_byte(0xA002, 0x10);
+        mmu.write_byte(0xA003, 0x00);
+        for reg in (0xA006..0xA036).step_by(3) {
+            mmu.write_byte(reg, 0x40);
+            mmu.write_byte(reg + 1, 0x80);
+            mmu.write_byte(reg + 2, 0xC0);
+        }
+        mmu.write_byte(0xA000, 0x01);
+        let mut frames: Vec<FrameData> = Vec::new();
+        let mut cycles = 0;
+        while mmu.read_byte(0xA000) & 0x01 != 0 {
+            mmu.update(4, &mut frames, &mut NoProfiler);
+            cycles += 4;
+        }
+        assert_eq!(cycles, (32446 + 512 + 16 * 0x1000) * 4);
+        // Other registers read as 0
+        assert_eq!(mmu.read_byte(0xA001), 0x00);
+
+        // The picture is in RAM bank 0 as tiles, color 0 on the left and 3 on the right
+        mmu.write_byte(0x4000, 0x00);
+        assert_eq!(mmu.read_byte(0xA100), 0x00);
+        assert_eq!(mmu.read_byte(0xA101), 0x00);
+        let right_tile = 0xA100 + 15 * 16;
+        assert_eq!(mmu.read_byte(right_tile), 0xFF);
+        assert_eq!(mmu.read_byte(right_tile + 1), 0xFF);
+    }
 }
@@ -0,0 +1,419 @@
+//! What's known about each opcode without running it: its mnemonic, length and timing.
+//! Shared by the CPU, which times instructions with it, and by the disassembler and debugger.
+
+/// Static details of one opcode
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OpcodeInfo {
+    pub opcode: u8,
+    /// The mnemonic, with placeholders for its operands, e.g. "LD B,d8" or "JP NZ,a16". Illegal
+    /// opcodes are "NULL", and the CB prefix is "PREFIX CB".
+    pub mnemonic: &'static str,
+    /// Bytes taken by the opcode and its operands, 1 to 3
+    pub len: usize,
+    /// Cycles taken, when a conditional branch isn't taken
+    pub cycles: u32,
+    /// Extra cycles taken when a conditional branch is taken, 0 for other opcodes
+    pub branch_cycles: u32,
+}
+
+impl OpcodeInfo {
+    /// Whether this is an opcode the CPU locks up on
+    pub fn is_illegal(&self) -> bool {
+        self.mnemonic == "NULL"
+    }
+}
+
+/// Returns the details of `opcode`. For the CB prefix, the cycles taken are given by
+/// `cb_cycles` instead.
+pub fn info(opcode: u8) -> OpcodeInfo {
+    OpcodeInfo {
+        opcode,
+        mnemonic: MNEMONICS[opcode as usize],
+        len: LENGTHS[opcode as usize],
+        cycles: CYCLES[opcode as usize],
+        branch_cycles: BRANCH_CYCLES[opcode as usize],
+    }
+}
+
+/// Returns the cycles taken by the extended opcode following a CB prefix, including the prefix
+pub fn cb_cycles(opcode: u8) -> u32 {
+    CB_CYCLES[opcode as usize]
+}
+
+/// Mnemonics, with placeholders for the operands such as "LD B,d8". Illegal opcodes are "NULL".
+pub(crate) const MNEMONICS: [&str; 256] = [
+    "NOP",
+    "LD BC,d16",
+    "LD (BC),A",
+    "INC BC",
+    "INC B",
+    "DEC B",
+    "LD B,d8",
+    "RLCA",
+    "LD (a16),SP",
+    "ADD HL,BC",
+    "LD A,(BC)",
+    "DEC BC",
+    "INC C",
+    "DEC C",
+    "LD C,d8",
+    "RRCA",
+    "STOP 0",
+    "LD DE,d16",
+    "LD (DE),A",
+    "INC DE",
+    "INC D",
+    "DEC D",
+    "LD D,d8",
+    "RLA",
+    "JR r8",
+    "ADD HL,DE",
+    "LD A,(DE)",
+    "DEC DE",
+    "INC E",
+    "DEC E",
+    "LD E,d8",
+    "RRA",
+    "JR NZ,r8",
+    "LD HL,d16",
+    "LD (HL+),A",
+    "INC HL",
+    "INC H",
+    "DEC H",
+    "LD H,d8",
+    "DAA",
+    "JR Z,r8",
+    "ADD HL,HL",
+    "LD A,(HL+)",
+    "DEC HL",
+    "INC L",
+    "DEC L",
+    "LD L,d8",
+    "CPL",
+    "JR NC,r8",
+    "LD SP,d16",
+    "LD (HL-),A",
+    "INC SP",
+    "INC (HL)",
+    "DEC (HL)",
+    "LD (HL),d8",
+    "SCF",
+    "JR C,r8",
+    "ADD HL,SP",
+    "LD A,(HL-)",
+    "DEC SP",
+    "INC A",
+    "DEC A",
+    "LD A,d8",
+    "CCF",
+    "LD B,B",
+    "LD B,C",
+    "LD B,D",
+    "LD B,E",
+    "LD B,H",
+    "LD B,L",
+    "LD B,(HL)",
+    "LD B,A",
+    "LD C,B",
+    "LD C,C",
+    "LD C,D",
+    "LD C,E",
+    "LD C,H",
+    "LD C,L",
+    "LD C,(HL)",
+    "LD C,A",
+    "LD D,B",
+    "LD D,C",
+    "LD D,D",
+    "LD D,E",
+    "LD D,H",
+    "LD D,L",
+    "LD D,(HL)",
+    "LD D,A",
+    "LD E,B",
+    "LD E,C",
+    "LD E,D",
+    "LD E,E",
+    "LD E,H",
+    "LD E,L",
+    "LD E,(HL)",
+    "LD E,A",
+    "LD H,B",
+    "LD H,C",
+    "LD H,D",
+    "LD H,E",
+    "LD H,H",
+    "LD H,L",
+    "LD H,(HL)",
+    "LD H,A",
+    "LD L,B",
+    "LD L,C",
+    "LD L,D",
+    "LD L,E",
+    "LD L,H",
+    "LD L,L",
+    "LD L,(HL)",
+    "LD L,A",
+    "LD (HL),B",
+    "LD (HL),C",
+    "LD (HL),D",
+    "LD (HL),E",
+    "LD (HL),H",
+    "LD (HL),L",
+    "HALT",
+    "LD (HL),A",
+    "LD A,B",
+    "LD A,C",
+    "LD A,D",
+    "LD A,E",
+    "LD A,H",
+    "LD A,L",
+    "LD A,(HL)",
+    "LD A,A",
+    "ADD A,B",
+    "ADD A,C",
+    "ADD A,D",
+    "ADD A,E",
+    "ADD A,H",
+    "ADD A,L",
+    "ADD A,(HL)",
+    "ADD A,A",
+    "ADC A,B",
+    "ADC A,C",
+    "ADC A,D",
+    "ADC A,E",
+    "ADC A,H",
+    "ADC A,L",
+    "ADC A,(HL)",
+    "ADC A,A",
+    "SUB A,B",
+    "SUB A,C",
+    "SUB A,D",
+    "SUB A,E",
+    "SUB A,H",
+    "SUB A,L",
+    "SUB A,(HL)",
+    "SUB A,A",
+    "SBC A,B",
+    "SBC A,C",
+    "SBC A,D",
+    "SBC A,E",
+    "SBC A,H",
+    "SBC A,L",
+    "SBC A,(HL)",
+    "SBC A,A",
+    "AND B",
+    "AND C",
+    "AND D",
+    "AND E",
+    "AND H",
+    "AND L",
+    "AND (HL)",
+    "AND A",
+    "XOR B",
+    "XOR C",
+    "XOR D",
+    "XOR E",
+    "XOR H",
+    "XOR L",
+    "XOR (HL)",
+    "XOR A",
+    "OR B",
+    "OR C",
+    "OR D",
+    "OR E",
+    "OR H",
+    "OR L",
+    "OR (HL)",
+    "OR A",
+    "CP B",
+    "CP C",
+    "CP D",
+    "CP E",
+    "CP H",
+    "CP L",
+    "CP (HL)",
+    "CP A",
+    "RET NZ",
+    "POP BC",
+    "JP NZ,a16",
+    "JP a16",
+    "CALL NZ,a16",
+    "PUSH BC",
+    "ADD A,d8",
+    "RST $00",
+    "RET Z",
+    "RET",
+    "JP Z,a16",
+    "PREFIX CB",
+    "CALL Z,a16",
+    "CALL a16",
+    "ADC A,d8",
+    "RST $08",
+    "RET NC",
+    "POP DE",
+    "JP NC,a16",
+    "NULL",
+    "CALL NC,a16",
+    "PUSH DE",
+    "SUB d8",
+    "RST $10",
+    "RET C",
+    "RETI",
+    "JP C,a16",
+    "NULL",
+    "CALL C,a16",
+    "NULL",
+    "SBC A,d8",
+    "RST $18",
+    "LDH (a8),A",
+    "POP HL",
+    "LD (C),A",
+    "NULL",
+    "NULL",
+    "PUSH HL",
+    "AND d8",
+    "RST $20",
+    "ADD SP,r8",
+    "JP (HL)",
+    "LD (a16),A",
+    "NULL",
+    "NULL",
+    "NULL",
+    "XOR d8",
+    "RST $28",
+    "LDH A,(a8)",
+    "POP AF",
+    "LD A,(C)",
+    "DI",
+    "NULL",
+    "PUSH AF",
+    "OR d8",
+    "RST $30",
+    "LD HL,SP+r8",
+    "LD SP,HL",
+    "LD A,(a16)",
+    "EI",
+    "NULL",
+    "NULL",
+    "CP d8",
+    "RST $38",
+];
+
+/// Bytes taken by each opcode and its operands
+#[rustfmt::skip]
+pub(crate) const LENGTHS: [usize; 256] = [
+//  0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+    1, 3, 1, 1, 1, 1, 2, 1, 3, 1, 1, 1, 1, 1, 2, 1, // 0
+    1, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1, // 1
+    2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1, // 2
+    2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1, // 3
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 4
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 5
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 6
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 7
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 8
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 9
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // A
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // B
+    1, 1, 3, 3, 3, 1, 2, 1, 1, 1, 3, 1, 3, 3, 2, 1, // C
+    1, 1, 3, 1, 3, 1, 2, 1, 1, 1, 3, 1, 3, 1, 2, 1, // D
+    2, 1, 1, 1, 1, 1, 2, 1, 2, 1, 3, 1, 1, 1, 2, 1, // E
+    2, 1, 1, 1, 1, 1, 2, 1, 2, 1, 3, 1, 1, 1, 2, 1, // F
+];
+
+/// Cycles taken by each opcode, when a conditional branch isn't taken
+#[rustfmt::skip]
+pub(crate) const CYCLES: [u32; 256] = [
+//  0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+    4,12, 8, 8, 4, 4, 8, 4,20, 8, 8, 8, 4, 4, 8, 4, // 0
+    4,12, 8, 8, 4, 4, 8, 4,12, 8, 8, 8, 4, 4, 8, 4, // 1
+    8,12, 8, 8, 4, 4, 8, 4, 8, 8, 8, 8, 4, 4, 8, 4, // 2
+    8,12, 8, 8,12,12,12, 4, 8, 8, 8, 8, 4, 4, 8, 4, // 3
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 4
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 5
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 6
+    8, 8, 8, 8, 8, 8, 4, 8, 4, 4, 4, 4, 4, 4, 8, 4, // 7
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 8
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 9
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // A
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // B
+    8,12,12,16,12,16, 8,16, 8,16,12, 4,12,24, 8,16, // C
+    8,12,12, 0,12,16, 8,16, 8,16,12, 0,12, 0, 8,16, // D
+   12,12, 8, 0, 0,16, 8,16,16, 4,16, 0, 0, 0, 8,16, // E
+   12,12, 8, 4, 0,16, 8,16,12, 8,16, 4, 0, 0, 8,16, // F
+];
+
+/// Extra cycles taken by conditional JR, JP, CALL and RET when the branch is taken
+#[rustfmt::skip]
+pub(crate) const BRANCH_CYCLES: [u32; 256] = [
+//  0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // 0
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // 1
+    4, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, // 2
+    4, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, // 3
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // 4
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // 5
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // 6
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // 7
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // 8
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // 9
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // A
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // B
+   12, 0, 4, 0,12, 0, 0, 0,12, 0, 4, 0,12, 0, 0, 0, // C
+   12, 0, 4, 0,12, 0, 0, 0,12, 0, 4, 0,12, 0, 0, 0, // D
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // E
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // F
+];
+
+/// Cycles taken by each extended opcode, following a CB prefix
+#[rustfmt::skip]
+pub(crate) const CB_CYCLES: [u32; 256] = [
+//  0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 0
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 1
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 2
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 3
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 4
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 5
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 6
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 7
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 8
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // 9
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // A
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // B
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // C
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // D
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // E
+    8, 8, 8, 8, 8, 8,16, 8, 8, 8, 8, 8, 8, 8,16, 8, // F
+];
+
+#[cfg(test)]
+mod opcodes_tests {
+    use super::*;
+
+    #[test]
+    fn lengths_match_operands() {
+        for opcode in 0..=255 {
+            let info = info(opcode);
+            let operands = if info.mnemonic.contains("16") {
+                2
+            } else if info.mnemonic.contains("8") && !info.mnemonic.starts_with("RST") {
+                1
+            } else {
+                0
+            };
+            assert_eq!(info.len, 1 + operands, "{:02X} {}", opcode, info.mnemonic);
+        }
+    }
+
+    #[test]
+    fn branches() {
+        assert_eq!(info(0x20).mnemonic, "JR NZ,r8");
+        assert_eq!(info(0x20).cycles + info(0x20).branch_cycles, 12);
+        assert_eq!(info(0xC4).cycles + info(0xC4).branch_cycles, 24);
+        assert_eq!(info(0xCD).branch_cycles, 0);
+        assert!(info(0xD3).is_illegal());
+        assert_eq!(cb_cycles(0x46), 16);
+    }
+}
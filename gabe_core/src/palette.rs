@@ -0,0 +1,319 @@
+/// An RGB color, as written into video frames
+pub type Rgb = (u8, u8, u8);
+
+/// The colors used to display the four DMG shades, from lightest (color 0) to darkest (color 3).
+/// Applied by the PPU when rendering, so frames are produced in the selected colors.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Palette {
+    pub colors: [Rgb; 4],
+}
+
+impl Palette {
+    /// Evenly spaced gray shades
+    pub const GRAYSCALE: Palette = Palette {
+        colors: [(255, 255, 255), (170, 170, 170), (85, 85, 85), (0, 0, 0)],
+    };
+
+    /// The green tint of the original DMG screen
+    pub const DMG: Palette = Palette {
+        colors: [(155, 188, 15), (139, 172, 15), (48, 98, 48), (15, 56, 15)],
+    };
+
+    /// The olive gray shades of the Gameboy Pocket screen
+    pub const POCKET: Palette = Palette {
+        colors: [(196, 207, 161), (139, 149, 109), (77, 83, 60), (31, 31, 31)],
+    };
+
+    pub const fn new(colors: [Rgb; 4]) -> Self {
+        Palette { colors }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::GRAYSCALE
+    }
+}
+
+/// The palettes in the CGB boot ROM, each color in the CGB's 15-bit format with red in the low
+/// bits
+const BOOT_PALETTES: [[u16; 4]; 30] = [
+    [0x7FFF, 0x32BF, 0x00D0, 0x0000],
+    [0x639F, 0x4279, 0x15B0, 0x04CB],
+    [0x7FFF, 0x6E31, 0x454A, 0x0000],
+    [0x7FFF, 0x1BEF, 0x0200, 0x0000],
+    [0x7FFF, 0x421F, 0x1CF2, 0x0000],
+    [0x7FFF, 0x5294, 0x294A, 0x0000],
+    [0x7FFF, 0x03FF, 0x012F, 0x0000],
+    [0x7FFF, 0x03EF, 0x01D6, 0x0000],
+    [0x7FFF, 0x42B5, 0x3DC8, 0x0000],
+    [0x7E74, 0x03FF, 0x0180, 0x0000],
+    [0x67FF, 0x77AC, 0x1A13, 0x2D6B],
+    [0x7ED6, 0x4BFF, 0x2175, 0x0000],
+    [0x53FF, 0x4A5F, 0x7E52, 0x0000],
+    [0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0],
+    [0x03ED, 0x7FFF, 0x255F, 0x0000],
+    [0x036A, 0x021F, 0x03FF, 0x7FFF],
+    [0x7FFF, 0x01DF, 0x0112, 0x0000],
+    [0x231F, 0x035F, 0x00F2, 0x0009],
+    [0x7FFF, 0x03EA, 0x011F, 0x0000],
+    [0x299F, 0x001A, 0x000C, 0x0000],
+    [0x7FFF, 0x027F, 0x001F, 0x0000],
+    [0x7FFF, 0x03E0, 0x0206, 0x0120],
+    [0x7FFF, 0x7EEB, 0x001F, 0x7C00],
+    [0x7FFF, 0x3FFF, 0x7E00, 0x001F],
+    [0x7FFF, 0x03FF, 0x001F, 0x0000],
+    [0x03FF, 0x001F, 0x000C, 0x0000],
+    [0x7FFF, 0x033F, 0x0193, 0x0000],
+    [0x0000, 0x4200, 0x037F, 0x7FFF],
+    [0x7FFF, 0x7E8C, 0x7C00, 0x0000],
+    [0x7FFF, 0x1BEF, 0x6180, 0x0000],
+];
+
+/// The colors of each palette combination in the boot ROM, as where the sprite palettes OBJ0
+/// and OBJ1 and the background palette start among the colors of `BOOT_PALETTES` in a row.
+/// Most start at a palette, but a few start a color early, overlapping the palette before.
+const BOOT_COMBINATIONS: [[u8; 3]; 51] = [
+    [16, 16, 116],
+    [72, 72, 72],
+    [80, 80, 80],
+    [96, 96, 96],
+    [36, 36, 36],
+    [0, 0, 0],
+    [108, 108, 108],
+    [20, 20, 20],
+    [48, 48, 48],
+    [104, 104, 104],
+    [64, 32, 32],
+    [16, 112, 112],
+    [16, 8, 8],
+    [12, 16, 16],
+    [16, 116, 116],
+    [112, 16, 112],
+    [8, 68, 8],
+    [64, 64, 32],
+    [16, 16, 28],
+    [16, 16, 72],
+    [16, 16, 80],
+    [76, 76, 36],
+    [15, 15, 44],
+    [68, 68, 8],
+    [16, 16, 8],
+    [16, 16, 12],
+    [112, 112, 0],
+    [12, 12, 0],
+    [0, 0, 4],
+    [72, 88, 72],
+    [80, 88, 80],
+    [96, 88, 96],
+    [64, 88, 32],
+    [68, 16, 52],
+    [111, 0, 56],
+    [111, 16, 60],
+    [76, 88, 36],
+    [64, 112, 40],
+    [16, 92, 112],
+    [68, 88, 8],
+    [16, 0, 8],
+    [16, 112, 12],
+    [112, 12, 0],
+    [12, 112, 16],
+    [84, 112, 16],
+    [12, 112, 0],
+    [100, 12, 112],
+    [0, 112, 32],
+    [16, 12, 112],
+    [112, 12, 24],
+    [16, 112, 116],
+];
+
+/// Title checksums the boot ROM picks a palette combination for, in its order. From
+/// `FIRST_DUPLICATE` on, checksums shared by more than one title are told apart by the title's
+/// 4th letter.
+const TITLE_CHECKSUMS: [u8; 94] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+    0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+];
+
+/// Index of the first checksum in `TITLE_CHECKSUMS` that needs the 4th letter to match too
+const FIRST_DUPLICATE: usize = 65;
+
+/// The 4th letter of the title for each checksum from `FIRST_DUPLICATE` on
+const DUPLICATE_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+/// The palette combination for each checksum in `TITLE_CHECKSUMS`
+const TITLE_COMBINATIONS: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44, 21, 32, 31, 20, 5, 33, 13, 14, 5, 29,
+    5, 18, 9, 3, 2, 26, 25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34, 5, 42, 6,
+    5, 33, 25, 42, 42, 40, 2, 16, 25, 42, 42, 5, 0, 39, 36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39,
+    24, 31, 50, 17, 46, 6, 27, 0, 47, 41, 41, 0, 0, 19, 34, 23, 18, 29,
+];
+
+/// Returns a color from the boot ROM's palettes, scaled up from 5 bits a channel to 8
+const fn boot_color(index: usize) -> Rgb {
+    let color = BOOT_PALETTES[index / 4][index % 4];
+    (channel(color), channel(color >> 5), channel(color >> 10))
+}
+
+/// Scales the low 5 bits of `color` up to a full 8-bit channel
+const fn channel(color: u16) -> u8 {
+    (((color & 0x1F) as u32 * 255 + 15) / 31) as u8
+}
+
+/// Returns the four colors of the boot ROM's palettes starting at `first`
+const fn boot_palette(first: u8) -> Palette {
+    let first = first as usize;
+    Palette::new([
+        boot_color(first),
+        boot_color(first + 1),
+        boot_color(first + 2),
+        boot_color(first + 3),
+    ])
+}
+
+/// Returns a palette combination from the boot ROM
+const fn boot_combination(index: usize) -> Colorization {
+    let [obj0, obj1, bg] = BOOT_COMBINATIONS[index];
+    Colorization {
+        bg: boot_palette(bg),
+        obj0: boot_palette(obj0),
+        obj1: boot_palette(obj1),
+    }
+}
+
+/// The colors a DMG game is shown in, with a palette for the background and window and one
+/// for each of the two sprite palettes, as the CGB colorizes games made for the DMG.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Colorization {
+    pub bg: Palette,
+    pub obj0: Palette,
+    pub obj1: Palette,
+}
+
+impl Colorization {
+    /// Shows the background and sprites alike in one palette, as the DMG does
+    pub const fn uniform(palette: Palette) -> Self {
+        Colorization {
+            bg: palette,
+            obj0: palette,
+            obj1: palette,
+        }
+    }
+
+    /// The colors the CGB gives games it has no palette of its own for, also chosen by holding
+    /// Right and A as it boots
+    pub const DEFAULT: Colorization = boot_combination(0);
+
+    /// The palettes picked by holding a direction, and optionally A or B, while the CGB boots,
+    /// by the names of the buttons held
+    pub const BUTTON_COMBINATIONS: [(&'static str, Colorization); 12] = [
+        ("up", boot_combination(5)),
+        ("up+a", boot_combination(43)),
+        ("up+b", boot_combination(28)),
+        ("left", boot_combination(48)),
+        ("left+a", boot_combination(40)),
+        ("left+b", boot_combination(7)),
+        ("down", boot_combination(8)),
+        ("down+a", boot_combination(3)),
+        ("down+b", boot_combination(49)),
+        ("right", boot_combination(1)),
+        ("right+a", boot_combination(0)),
+        ("right+b", boot_combination(6)),
+    ];
+
+    /// Returns the palettes picked by holding the buttons named, such as "left+a", while the
+    /// CGB boots
+    pub fn for_buttons(name: &str) -> Option<Colorization> {
+        Self::BUTTON_COMBINATIONS
+            .iter()
+            .find(|(buttons, _)| buttons.eq_ignore_ascii_case(name))
+            .map(|(_, colorization)| *colorization)
+    }
+
+    /// Returns the palettes the CGB's boot ROM picks for a ROM. Games licensed by Nintendo are
+    /// looked up by the checksum of their title, and the rest get `DEFAULT`.
+    pub fn for_rom(rom: &[u8]) -> Colorization {
+        boot_combination(title_combination(rom).unwrap_or(0))
+    }
+
+    /// The colors of the background palette, then of each sprite palette
+    pub(crate) fn colors(&self) -> [Rgb; 12] {
+        let mut colors = [(0, 0, 0); 12];
+        for (chunk, palette) in colors
+            .chunks_exact_mut(4)
+            .zip([self.bg, self.obj0, self.obj1])
+        {
+            chunk.copy_from_slice(&palette.colors);
+        }
+        colors
+    }
+}
+
+/// Returns the boot ROM's palette combination for a Nintendo game's title, if it has one
+fn title_combination(rom: &[u8]) -> Option<usize> {
+    let header = rom.get(0x134..0x14C)?;
+    // The old licensee code, or 0x33 to use the new one at 0x144
+    let nintendo = match header[0x14B - 0x134] {
+        0x01 => true,
+        0x33 => &header[0x144 - 0x134..0x146 - 0x134] == b"01",
+        _ => false,
+    };
+    if !nintendo {
+        return None;
+    }
+    let checksum = header[..0x10]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    let fourth_letter = header[3];
+    TITLE_CHECKSUMS
+        .iter()
+        .enumerate()
+        .find(|(i, title)| {
+            **title == checksum
+                && (*i < FIRST_DUPLICATE || DUPLICATE_LETTERS[i - FIRST_DUPLICATE] == fourth_letter)
+        })
+        .map(|(i, _)| TITLE_COMBINATIONS[i] as usize)
+}
+
+#[cfg(test)]
+mod palette_tests {
+    use super::*;
+
+    fn rom(title: &[u8], licensee: u8) -> [u8; 0x150] {
+        let mut rom = [0; 0x150];
+        rom[0x134..0x134 + title.len()].copy_from_slice(title);
+        rom[0x14B] = licensee;
+        rom
+    }
+
+    #[test]
+    fn colorization_by_title() {
+        let red = Colorization::for_rom(&rom(b"POKEMON RED", 0x01));
+        assert_eq!(red, boot_combination(13));
+        assert_eq!(red.bg.colors[1], (255, 132, 132));
+        assert_eq!(red.obj0.colors[1], (123, 255, 49));
+
+        // Shares its checksum with other titles, told apart by the 4th letter
+        let mario = Colorization::for_rom(&rom(b"SUPER MARIOLAND", 0x01));
+        assert_eq!(mario, boot_combination(22));
+        assert_eq!(mario.bg.colors[0], (181, 181, 255));
+    }
+
+    #[test]
+    fn colorization_by_licensee() {
+        let mut new_licensee = rom(b"POKEMON RED", 0x33);
+        new_licensee[0x144..0x146].copy_from_slice(b"01");
+        assert_eq!(Colorization::for_rom(&new_licensee), boot_combination(13));
+        assert_eq!(
+            Colorization::for_rom(&rom(b"POKEMON RED", 0x08)),
+            Colorization::DEFAULT
+        );
+        assert_eq!(Colorization::for_rom(&[]), Colorization::DEFAULT);
+    }
+}
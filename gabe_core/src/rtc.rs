@@ -0,0 +1,85 @@
+//! The clocks that a cartridge's real-time clock keeps time by. The core never reads the time
+//! itself, so the MBC3's RTC asks an `RtcClock` instead.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Tells a cartridge's real-time clock what time it is. Clocks are Send, so a Gameboy can be
+/// moved to another thread with its clock.
+pub trait RtcClock: Send {
+    /// Returns the time in seconds since a fixed point, such as the Unix epoch. Only the
+    /// time passed between calls matters, and it should never go backwards.
+    fn now(&self) -> u64;
+}
+
+/// Follows the host's wall clock, counting from the Unix epoch, so the RTC keeps time while
+/// the emulator isn't running, as a cartridge's battery does. This is the clock used unless
+/// another is given to `GameboyBuilder::rtc_clock`. Needs the `std` feature, and isn't
+/// available on wasm32-unknown-unknown.
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SystemClock;
+
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+impl RtcClock for SystemClock {
+    fn now(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    }
+}
+
+/// A clock that only moves when told to, for tests and for runs that have to play back the
+/// same way every time, such as tool-assisted ones. Clones share the same time, so the
+/// frontend can keep one to move while the Gameboy has another.
+#[derive(Clone, Default, Debug)]
+pub struct ManualClock(Arc<AtomicU64>);
+
+impl ManualClock {
+    /// Creates a clock stopped at `seconds`
+    pub fn new(seconds: u64) -> Self {
+        ManualClock(Arc::new(AtomicU64::new(seconds)))
+    }
+
+    /// Sets the time, in seconds
+    pub fn set(&self, seconds: u64) {
+        self.0.store(seconds, Ordering::Relaxed);
+    }
+
+    /// Moves the time forward by `seconds`
+    pub fn advance(&self, seconds: u64) {
+        self.0.fetch_add(seconds, Ordering::Relaxed);
+    }
+}
+
+impl RtcClock for ManualClock {
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Returns the clock an RTC keeps time by unless given another: the wall clock where there is
+/// one
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+pub(crate) fn default_clock() -> Option<Box<dyn RtcClock>> {
+    Some(Box::new(SystemClock))
+}
+
+/// Without a wall clock there's no default, which leaves the RTC stopped
+#[cfg(not(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+)))]
+pub(crate) fn default_clock() -> Option<Box<dyn RtcClock>> {
+    None
+}
@@ -0,0 +1,263 @@
+//! Running many ROMs at once, one Gameboy per ROM spread across threads, for test farms and
+//! fuzzing corpora. Needs the `std` feature, and isn't available on wasm32-unknown-unknown.
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::error::GabeError;
+use super::gb::{Gameboy, Model, RamInit};
+use super::rtc::ManualClock;
+use super::serial::SerialDevice;
+use super::CLOCK_HZ;
+
+/// A ROM to run, with the name its report is given
+pub struct Job {
+    pub name: String,
+    pub rom: Box<[u8]>,
+}
+
+impl Job {
+    pub fn new(name: impl Into<String>, rom: Box<[u8]>) -> Self {
+        Job {
+            name: name.into(),
+            rom,
+        }
+    }
+}
+
+/// What a ROM left behind once it had run
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RunStats {
+    /// `Gameboy::frame_hash` after the last frame
+    pub frame_hash: Option<u64>,
+    /// `Gameboy::audio_hash` after the last frame
+    pub audio_hash: u64,
+    /// Every byte sent out of the link port, which is where test ROMs such as Blargg's print
+    /// their results
+    pub serial: Vec<u8>,
+    /// Frames run, which is less than asked for if the CPU locked up
+    pub frames: u64,
+    /// Cycles run
+    pub cycles: u64,
+    /// Why the CPU locked up, if it did
+    pub fault: Option<GabeError>,
+    /// Time taken to run, not counting loading the ROM
+    pub elapsed: Duration,
+}
+
+/// Why a ROM couldn't be run to the end
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RunError {
+    /// The Gameboy couldn't be built from the ROM
+    Load(GabeError),
+    /// The emulator panicked, with the panic's message
+    Panic(String),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::Load(e) => write!(f, "Couldn't load the ROM: {}", e),
+            RunError::Panic(message) => write!(f, "Emulator panicked: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+/// The outcome of running one `Job`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RunReport {
+    pub name: String,
+    pub result: Result<RunStats, RunError>,
+}
+
+/// Runs ROMs for a fixed number of frames each, on a pool of threads. Every ROM gets a fresh
+/// Gameboy, so the results only depend on the ROM and the settings here.
+pub struct Runner {
+    frames: u64,
+    threads: Option<usize>,
+    model: Model,
+    ram_init: RamInit,
+}
+
+impl Runner {
+    /// Creates a runner that runs each ROM for `frames` frames
+    pub fn new(frames: u64) -> Self {
+        Runner {
+            frames,
+            threads: None,
+            model: Model::default(),
+            ram_init: RamInit::default(),
+        }
+    }
+
+    /// Sets how many threads run ROMs. Defaults to the number of CPUs.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads.max(1));
+        self
+    }
+
+    /// Sets the model every Gameboy is built as
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Sets how every Gameboy's RAM is filled at power on. Use a seed rather than an entropy
+    /// source for results that can be compared between runs.
+    pub fn ram_init(mut self, ram_init: RamInit) -> Self {
+        self.ram_init = ram_init;
+        self
+    }
+
+    /// Runs every job, returning their reports in the same order. A ROM that fails to load or
+    /// panics the emulator is reported as such without stopping the others.
+    pub fn run(&self, jobs: impl IntoIterator<Item = Job>) -> Vec<RunReport> {
+        let jobs: Vec<Job> = jobs.into_iter().collect();
+        let count = jobs.len();
+        let threads = self
+            .threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+            .min(count);
+        let queue = Mutex::new(jobs.into_iter().enumerate());
+        let reports = Mutex::new(Vec::with_capacity(count));
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| loop {
+                    // Released before running, so the other threads can take jobs meanwhile
+                    let next = queue.lock().unwrap().next();
+                    let Some((index, job)) = next else {
+                        break;
+                    };
+                    let report = RunReport {
+                        result: self.run_one(job.rom),
+                        name: job.name,
+                    };
+                    reports.lock().unwrap().push((index, report));
+                });
+            }
+        });
+        let mut reports = reports.into_inner().unwrap();
+        reports.sort_by_key(|(index, _)| *index);
+        reports.into_iter().map(|(_, report)| report).collect()
+    }
+
+    fn run_one(&self, rom: Box<[u8]>) -> Result<RunStats, RunError> {
+        let serial = SerialCapture::default();
+        // The RTC follows the cycles run, so it doesn't depend on when or how fast the ROM ran
+        let rtc_clock = ManualClock::default();
+        let mut gb = Gameboy::builder()
+            .rom_bytes(rom)
+            .model(self.model)
+            .ram_init(self.ram_init)
+            .serial(Box::new(serial.clone()))
+            .rtc_clock(Box::new(rtc_clock.clone()))
+            .build()
+            .map_err(RunError::Load)?;
+        let start = Instant::now();
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            while gb.frames() < self.frames && gb.fault().is_none() {
+                gb.step_frame();
+                rtc_clock.set(gb.cycles_elapsed() / CLOCK_HZ as u64);
+            }
+        }))
+        .map_err(|e| RunError::Panic(panic_message(e)))?;
+        let elapsed = start.elapsed();
+        let serial = serial.0.lock().unwrap().clone();
+        Ok(RunStats {
+            frame_hash: gb.frame_hash(),
+            audio_hash: gb.audio_hash(),
+            serial,
+            frames: gb.frames(),
+            cycles: gb.cycles_elapsed(),
+            fault: gb.fault(),
+            elapsed,
+        })
+    }
+}
+
+/// Records the bytes sent over the link port, acting as nothing connected otherwise
+#[derive(Clone, Default)]
+struct SerialCapture(Arc<Mutex<Vec<u8>>>);
+
+impl SerialDevice for SerialCapture {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.0.lock().unwrap().push(byte);
+        0xFF
+    }
+
+    fn poll_external(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// Returns the message given to `panic!`, which is a String or a &str
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map_or_else(|| "unknown panic".into(), |message| message.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod runner_tests {
+    use super::*;
+
+    /// A ROM that sends `byte` out of the link port, then loops forever
+    fn serial_rom(byte: u8) -> Box<[u8]> {
+        let mut rom = vec![0; 0x8000];
+        // LD A,byte; LDH (01),A; LD A,81; LDH (02),A; JR -2
+        let code = [0x3E, byte, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x18, 0xFE];
+        rom[0x100..0x100 + code.len()].copy_from_slice(&code);
+        rom.into_boxed_slice()
+    }
+
+    #[test]
+    fn runs_every_job_in_order() {
+        let jobs = vec![
+            Job::new("a", serial_rom(b'a')),
+            Job::new("short", vec![0; 0x10].into_boxed_slice()),
+            Job::new("b", serial_rom(b'b')),
+            Job::new("a again", serial_rom(b'a')),
+        ];
+        let reports = Runner::new(3).threads(2).run(jobs);
+        let names: Vec<&str> = reports.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["a", "short", "b", "a again"]);
+
+        let a = reports[0].result.as_ref().unwrap();
+        assert_eq!(a.serial, b"a");
+        assert_eq!(a.frames, 3);
+        assert_eq!(a.fault, None);
+        assert_eq!(
+            reports[1].result,
+            Err(RunError::Load(GabeError::InvalidRomLength(0x10)))
+        );
+        assert_eq!(reports[2].result.as_ref().unwrap().serial, b"b");
+        // The same ROM runs the same way
+        let again = reports[3].result.as_ref().unwrap();
+        assert_eq!(again.frame_hash, a.frame_hash);
+        assert_eq!(again.cycles, a.cycles);
+    }
+
+    #[test]
+    fn stops_on_fault() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100] = 0xD3;
+        let reports = Runner::new(10).run([Job::new("illegal", rom.into_boxed_slice())]);
+        let stats = reports[0].result.as_ref().unwrap();
+        assert_eq!(
+            stats.fault,
+            Some(GabeError::IllegalOpcode {
+                opcode: 0xD3,
+                address: 0x100
+            })
+        );
+        assert!(stats.frames < 10);
+    }
+}
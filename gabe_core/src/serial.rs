@@ -1,6 +1,24 @@
-#![allow(dead_code)]
+//! The serial port, used by the link cable to connect two Gameboys
 
-use super::mmu::Memory;
+use alloc::{boxed::Box, sync::Arc};
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use super::mmu::{InterruptKind, Memory};
+
+/// Cycles taken to shift out a byte with the internal clock, at 8192 Hz per bit
+const TRANSFER_CYCLES: u32 = 8 * 512;
+
+/// Something connected to the link port, which bytes are exchanged with. Devices are Send, so
+/// a Gameboy can be moved to another thread with its device connected.
+pub trait SerialDevice: Send {
+    /// Exchanges a byte while this Gameboy drives the clock. `byte` is shifted out, and the
+    /// returned byte is shifted in.
+    fn exchange(&mut self, byte: u8) -> u8;
+
+    /// Called while this Gameboy waits for the other side to drive the clock, with the byte it
+    /// will shift out. Returns the byte shifted in once the other side has run the transfer.
+    fn poll_external(&mut self, byte: u8) -> Option<u8>;
+}
 
 pub struct Serial {
     /// Serial transfer data: 8 Bits of data to be read/written
@@ -9,15 +27,55 @@ pub struct Serial {
     /// Bit 1 - Clock Speed (0=Normal, 1=Fast) ** CGB Mode Only **
     /// Bit 0 - Shift Clock (0=External Clock, 1=Internal Clock)
     sc: u8,
+    /// Cycles left in the transfer being run with the internal clock
+    transfer_cycles: u32,
+    device: Option<Box<dyn SerialDevice>>,
 }
 
 impl Serial {
     pub fn power_on() -> Self {
-        Serial { sb: 0, sc: 0 }
+        Serial {
+            sb: 0,
+            sc: 0,
+            transfer_cycles: 0,
+            device: None,
+        }
     }
 
-    pub fn update(&mut self) {
-        // TODO
+    /// Cancels any transfer, keeping the device connected
+    pub fn reset(&mut self) {
+        self.sb = 0;
+        self.sc = 0;
+        self.transfer_cycles = 0;
+    }
+
+    /// Connects a device to the port, or disconnects it with None
+    pub fn connect(&mut self, device: Option<Box<dyn SerialDevice>>) {
+        self.device = device;
+    }
+
+    /// Runs any transfer in progress. Returns a Serial interrupt once a byte has been
+    /// exchanged. With nothing connected, transfers using the internal clock shift in 0xFF,
+    /// and transfers using the external clock never finish.
+    pub fn update(&mut self, cycles: u32) -> Option<InterruptKind> {
+        if self.sc & 0x80 == 0 {
+            return None;
+        }
+        let received = if self.sc & 0x01 != 0 {
+            self.transfer_cycles = self.transfer_cycles.saturating_sub(cycles);
+            if self.transfer_cycles > 0 {
+                return None;
+            }
+            match &mut self.device {
+                Some(device) => device.exchange(self.sb),
+                None => 0xFF,
+            }
+        } else {
+            self.device.as_mut()?.poll_external(self.sb)?
+        };
+        self.sb = received;
+        self.sc &= 0x7F;
+        Some(InterruptKind::Serial)
     }
 }
 
@@ -33,8 +91,142 @@ impl Memory for Serial {
     fn write_byte(&mut self, addr: u16, val: u8) {
         match addr {
             0xFF01 => self.sb = val,
-            0xFF02 => self.sc = val,
+            0xFF02 => {
+                self.sc = val;
+                self.transfer_cycles = TRANSFER_CYCLES;
+            }
             _ => unreachable!(),
         }
     }
 }
+
+/// Stored in a `LinkSlot` when it holds no byte. Bytes are stored as themselves.
+const EMPTY: u16 = 0x100;
+
+/// A byte that may or may not be present, shared between the two ends of a link cable
+struct LinkSlot(AtomicU16);
+
+impl LinkSlot {
+    fn new() -> Self {
+        LinkSlot(AtomicU16::new(EMPTY))
+    }
+
+    fn take(&self) -> Option<u8> {
+        let value = self.0.swap(EMPTY, Ordering::AcqRel);
+        (value != EMPTY).then_some(value as u8)
+    }
+
+    fn set(&self, byte: Option<u8>) {
+        self.0
+            .store(byte.map_or(EMPTY, u16::from), Ordering::Release);
+    }
+}
+
+/// The state of one end of a link cable
+struct LinkEnd {
+    /// The byte this end will shift out, while it waits for the other end to drive the clock
+    waiting: LinkSlot,
+    /// The byte shifted in by a transfer the other end drove, until this end picks it up
+    received: LinkSlot,
+}
+
+impl Default for LinkEnd {
+    fn default() -> Self {
+        LinkEnd {
+            waiting: LinkSlot::new(),
+            received: LinkSlot::new(),
+        }
+    }
+}
+
+/// One end of an in-memory link cable between two Gameboys in the same program, created by
+/// `link_cable`. The Gameboys need to be stepped in turn, so neither gets far ahead.
+pub struct LinkPort {
+    ends: Arc<[LinkEnd; 2]>,
+    /// Index of this end in `ends`
+    side: usize,
+}
+
+/// Creates a link cable, returning the port to connect to each Gameboy
+pub fn link_cable() -> (LinkPort, LinkPort) {
+    let ends = Arc::new([LinkEnd::default(), LinkEnd::default()]);
+    (
+        LinkPort {
+            ends: ends.clone(),
+            side: 0,
+        },
+        LinkPort { ends, side: 1 },
+    )
+}
+
+impl SerialDevice for LinkPort {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let other = &self.ends[1 - self.side];
+        match other.waiting.take() {
+            Some(theirs) => {
+                other.received.set(Some(byte));
+                theirs
+            }
+            // The other Gameboy isn't listening, so nothing drives the line
+            None => 0xFF,
+        }
+    }
+
+    fn poll_external(&mut self, byte: u8) -> Option<u8> {
+        let end = &self.ends[self.side];
+        match end.received.take() {
+            Some(received) => {
+                end.waiting.set(None);
+                Some(received)
+            }
+            None => {
+                end.waiting.set(Some(byte));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod serial_tests {
+    use crate::mmu::{InterruptKind, Memory};
+
+    use super::{link_cable, Serial, TRANSFER_CYCLES};
+
+    #[test]
+    fn unconnected_transfer() {
+        let mut serial = Serial::power_on();
+        serial.write_byte(0xFF01, 0x42);
+        serial.write_byte(0xFF02, 0x81);
+        assert!(serial.update(TRANSFER_CYCLES - 4).is_none());
+        assert!(matches!(serial.update(4), Some(InterruptKind::Serial)));
+        assert_eq!(serial.read_byte(0xFF01), 0xFF);
+        assert_eq!(serial.read_byte(0xFF02), 0x01);
+
+        // Nothing drives the clock for an external transfer
+        serial.write_byte(0xFF02, 0x80);
+        assert!(serial.update(TRANSFER_CYCLES * 4).is_none());
+        assert_eq!(serial.read_byte(0xFF02), 0x80);
+    }
+
+    #[test]
+    fn linked_transfer() {
+        let (a, b) = link_cable();
+        let mut master = Serial::power_on();
+        let mut slave = Serial::power_on();
+        master.connect(Some(alloc::boxed::Box::new(a)));
+        slave.connect(Some(alloc::boxed::Box::new(b)));
+
+        slave.write_byte(0xFF01, 0x12);
+        slave.write_byte(0xFF02, 0x80);
+        assert!(slave.update(4).is_none());
+        master.write_byte(0xFF01, 0x34);
+        master.write_byte(0xFF02, 0x81);
+        assert!(master.update(TRANSFER_CYCLES).is_some());
+        assert!(slave.update(4).is_some());
+
+        assert_eq!(master.read_byte(0xFF01), 0x12);
+        assert_eq!(slave.read_byte(0xFF01), 0x34);
+        assert_eq!(slave.read_byte(0xFF02), 0x00);
+    }
+}
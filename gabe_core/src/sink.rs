@@ -1,8 +1,39 @@
 use alloc::boxed::*;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use crate::palette::Rgb;
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 /// A trait that accepts input data for later processing
 pub trait Sink<T> {
     fn append(&mut self, value: T);
+
+    /// Appends a batch of values at once, which the core uses for audio. Sinks that can copy
+    /// a batch faster than appending each value in turn should override this.
+    fn append_slice(&mut self, values: &[T])
+    where
+        T: Clone,
+    {
+        for value in values {
+            self.append(value.clone());
+        }
+    }
+}
+
+impl<T> Sink<T> for Vec<T> {
+    fn append(&mut self, value: T) {
+        self.push(value);
+    }
+
+    fn append_slice(&mut self, values: &[T])
+    where
+        T: Clone,
+    {
+        self.extend_from_slice(values);
+    }
 }
 
 /// A Sink type for accepting value references
@@ -10,8 +41,302 @@ pub trait SinkRef<T: ?Sized> {
     fn append(&mut self, value: &T);
 }
 
-/// A frame of video data, consisting of pixel data in an RGB format
-pub type VideoFrame = Box<[u8]>;
+/// How the pixels of a `VideoFrame` are laid out, chosen with `Gameboy::set_pixel_format` so
+/// frontends can use frames as they come instead of converting every pixel
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PixelFormat {
+    /// 3 bytes: red, green, then blue
+    #[default]
+    Rgb24,
+    /// 4 bytes: red, green, blue, then alpha, which is always 255. Browsers' `ImageData` and
+    /// most textures take this.
+    Rgba32,
+    /// A u32 of 0x00RRGGBB in native byte order, so the bytes can be read as u32s, as
+    /// libretro's XRGB8888 and minifb take them
+    Xrgb32,
+}
+
+impl PixelFormat {
+    /// Bytes taken by each pixel
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Rgb24 => 3,
+            PixelFormat::Rgba32 | PixelFormat::Xrgb32 => 4,
+        }
+    }
+
+    /// Returns a color as a pixel in this format, in the first `bytes_per_pixel` bytes
+    pub fn encode(&self, (r, g, b): Rgb) -> [u8; 4] {
+        match self {
+            PixelFormat::Rgb24 => [r, g, b, 0],
+            PixelFormat::Rgba32 => [r, g, b, 0xFF],
+            PixelFormat::Xrgb32 => u32::from_be_bytes([0, r, g, b]).to_ne_bytes(),
+        }
+    }
+
+    /// Writes a row of pixels into `out`, each given by its index into `colors`, such as the
+    /// shades of a scanline. The colors are encoded once for the whole row, and the pixels are
+    /// then copied in fixed size chunks, which compiles to a tight loop instead of encoding
+    /// every pixel. Stops at the end of `indices` or `out`, whichever is first, and panics if
+    /// an index is past the last color.
+    pub fn encode_line<const N: usize>(&self, colors: &[Rgb; N], indices: &[u8], out: &mut [u8]) {
+        let table = colors.map(|color| self.encode(color));
+        match self {
+            PixelFormat::Rgb24 => {
+                for (pixel, &index) in out.chunks_exact_mut(3).zip(indices) {
+                    pixel.copy_from_slice(&table[index as usize][..3]);
+                }
+            }
+            PixelFormat::Rgba32 | PixelFormat::Xrgb32 => {
+                for (pixel, &index) in out.chunks_exact_mut(4).zip(indices) {
+                    pixel.copy_from_slice(&table[index as usize]);
+                }
+            }
+        }
+    }
+}
+
+/// A frame of video data, consisting of pixel data in an RGB format, with when it was drawn.
+/// Derefs to the pixel data.
+#[derive(Clone, Debug)]
+pub struct VideoFrame {
+    /// 160x144 pixels, row by row from the top left, each laid out as given by `format`.
+    /// Shared with the PPU rather than copied: it draws the next frame into a new buffer
+    /// while this one is still held, and reuses this one once it's dropped, so frontends
+    /// should drop frames they're done with.
+    pub pixels: Arc<[u8]>,
+    /// How each pixel is laid out, 3 bytes of RGB unless changed
+    pub format: PixelFormat,
+    /// Frames drawn since power on, starting from 0. Frames aren't drawn while the LCD is
+    /// off, which a jump in `cycles` shows.
+    pub frame_index: u64,
+    /// Cycles run since power on, at the end of the step the frame was finished in
+    pub cycles: u64,
+    /// Set if the frame wasn't drawn, being skipped as asked by `Gameboy::set_frame_skip`,
+    /// leaving `pixels` as they were in the last frame drawn
+    pub skipped: bool,
+}
+
+impl Deref for VideoFrame {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.pixels
+    }
+}
 
 /// A frame of audio data, consisting of (Left, Right) sample data of i16
 pub type AudioFrame = (f32, f32);
+
+/// Storage shared by the two ends of an `audio_ring`. The read and write positions count
+/// every frame ever read or written, wrapping around, and the slot for a position is found
+/// by masking it, so the number of slots is a power of two.
+#[cfg(target_has_atomic = "64")]
+struct AudioRing {
+    /// Frames packed into the bits of the two samples, left in the high half
+    slots: Box<[AtomicU64]>,
+    read: AtomicUsize,
+    write: AtomicUsize,
+}
+
+/// Creates a ring buffer holding at least `capacity` audio frames, for frontends that pull
+/// audio from their device's callback instead of having it pushed to them. The producer is
+/// given to the emulator as its audio sink, and the consumer can be moved to the audio
+/// thread. Neither end ever blocks or locks: frames that don't fit are dropped, and reads
+/// only return the frames available.
+#[cfg(target_has_atomic = "64")]
+pub fn audio_ring(capacity: usize) -> (AudioProducer, AudioConsumer) {
+    let ring = Arc::new(AudioRing {
+        slots: (0..capacity.max(1).next_power_of_two())
+            .map(|_| AtomicU64::new(0))
+            .collect(),
+        read: AtomicUsize::new(0),
+        write: AtomicUsize::new(0),
+    });
+    (
+        AudioProducer {
+            ring: ring.clone(),
+            dropped: 0,
+        },
+        AudioConsumer { ring },
+    )
+}
+
+/// The end of an `audio_ring` the emulator writes to
+#[cfg(target_has_atomic = "64")]
+pub struct AudioProducer {
+    ring: Arc<AudioRing>,
+    dropped: u64,
+}
+
+#[cfg(target_has_atomic = "64")]
+impl AudioProducer {
+    /// Returns the number of frames dropped because the ring was full, which happens when
+    /// emulation runs ahead of playback
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(target_has_atomic = "64")]
+impl Sink<AudioFrame> for AudioProducer {
+    fn append(&mut self, (left, right): AudioFrame) {
+        let ring = &*self.ring;
+        let write = ring.write.load(Ordering::Relaxed);
+        // Acquire, so the consumer has finished reading a slot before it's overwritten
+        let read = ring.read.load(Ordering::Acquire);
+        if write.wrapping_sub(read) == ring.slots.len() {
+            self.dropped += 1;
+            return;
+        }
+        let packed = (left.to_bits() as u64) << 32 | right.to_bits() as u64;
+        ring.slots[write & (ring.slots.len() - 1)].store(packed, Ordering::Relaxed);
+        ring.write.store(write.wrapping_add(1), Ordering::Release);
+    }
+
+    fn append_slice(&mut self, frames: &[AudioFrame]) {
+        let ring = &*self.ring;
+        let write = ring.write.load(Ordering::Relaxed);
+        let read = ring.read.load(Ordering::Acquire);
+        let free = ring.slots.len() - write.wrapping_sub(read);
+        let count = frames.len().min(free);
+        self.dropped += (frames.len() - count) as u64;
+        for (i, (left, right)) in frames[..count].iter().enumerate() {
+            let packed = (left.to_bits() as u64) << 32 | right.to_bits() as u64;
+            let slot = write.wrapping_add(i) & (ring.slots.len() - 1);
+            ring.slots[slot].store(packed, Ordering::Relaxed);
+        }
+        // Published all at once, so the consumer sees the whole batch or none of it
+        ring.write
+            .store(write.wrapping_add(count), Ordering::Release);
+    }
+}
+
+/// The end of an `audio_ring` the frontend reads from
+#[cfg(target_has_atomic = "64")]
+pub struct AudioConsumer {
+    ring: Arc<AudioRing>,
+}
+
+#[cfg(target_has_atomic = "64")]
+impl AudioConsumer {
+    /// Returns the number of frames waiting to be read
+    pub fn len(&self) -> usize {
+        let write = self.ring.write.load(Ordering::Acquire);
+        write.wrapping_sub(self.ring.read.load(Ordering::Relaxed))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Fills the start of `out` with the oldest frames waiting, returning how many were
+    /// read. Any frames left in `out` past that are untouched, so the caller can fill them
+    /// with silence.
+    pub fn fill(&mut self, out: &mut [AudioFrame]) -> usize {
+        let ring = &*self.ring;
+        let read = ring.read.load(Ordering::Relaxed);
+        // Acquire, so the slots written before the write position was moved are visible
+        let write = ring.write.load(Ordering::Acquire);
+        let count = write.wrapping_sub(read).min(out.len());
+        for (i, frame) in out[..count].iter_mut().enumerate() {
+            let slot = read.wrapping_add(i) & (ring.slots.len() - 1);
+            let packed = ring.slots[slot].load(Ordering::Relaxed);
+            *frame = (
+                f32::from_bits((packed >> 32) as u32),
+                f32::from_bits(packed as u32),
+            );
+        }
+        ring.read.store(read.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
+
+#[cfg(test)]
+mod sink_tests {
+    use super::*;
+
+    #[test]
+    fn encode_line() {
+        let colors = [(1, 2, 3), (4, 5, 6), (7, 8, 9), (10, 11, 12)];
+        let mut out = [0; 9];
+        PixelFormat::Rgb24.encode_line(&colors, &[3, 0, 1], &mut out);
+        assert_eq!(out, [10, 11, 12, 1, 2, 3, 4, 5, 6]);
+        let mut out = [0; 8];
+        PixelFormat::Rgba32.encode_line(&colors, &[2, 1], &mut out);
+        assert_eq!(out, [7, 8, 9, 0xFF, 4, 5, 6, 0xFF]);
+        let mut out = [0; 4];
+        PixelFormat::Xrgb32.encode_line(&colors, &[1], &mut out);
+        assert_eq!(u32::from_ne_bytes(out), 0x040506);
+    }
+
+    #[test]
+    fn audio_ring_fills_in_order() {
+        let (mut producer, mut consumer) = audio_ring(4);
+        for i in 0..3 {
+            producer.append((i as f32, -i as f32));
+        }
+        assert_eq!(consumer.len(), 3);
+        let mut out = [(9.0, 9.0); 2];
+        assert_eq!(consumer.fill(&mut out), 2);
+        assert_eq!(out, [(0.0, 0.0), (1.0, -1.0)]);
+
+        let mut out = [(9.0, 9.0); 2];
+        assert_eq!(consumer.fill(&mut out), 1);
+        assert_eq!(out, [(2.0, -2.0), (9.0, 9.0)]);
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn audio_ring_drops_when_full() {
+        let (mut producer, mut consumer) = audio_ring(3);
+        // Rounded up to 4 frames
+        for i in 0..6 {
+            producer.append((i as f32, 0.0));
+        }
+        assert_eq!(producer.dropped(), 2);
+        let mut out = [(0.0, 0.0); 8];
+        assert_eq!(consumer.fill(&mut out), 4);
+        assert_eq!(out[3], (3.0, 0.0));
+    }
+
+    #[test]
+    fn audio_ring_batches() {
+        let (mut producer, mut consumer) = audio_ring(4);
+        producer.append((0.0, 0.0));
+        let frames: Vec<AudioFrame> = (1..6).map(|i| (i as f32, 0.0)).collect();
+        producer.append_slice(&frames);
+        // Only the frames that fit are written
+        assert_eq!(producer.dropped(), 2);
+        let mut out = [(0.0, 0.0); 8];
+        assert_eq!(consumer.fill(&mut out), 4);
+        assert_eq!(out[..4], [(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)]);
+    }
+
+    #[test]
+    fn audio_ring_across_threads() {
+        let (mut producer, mut consumer) = audio_ring(64);
+        let writer = std::thread::spawn(move || {
+            let mut written = 0;
+            while written < 10_000 {
+                // Retried until it fits, so none go missing
+                let dropped = producer.dropped();
+                producer.append((written as f32, 0.0));
+                if producer.dropped() == dropped {
+                    written += 1;
+                }
+            }
+        });
+        let mut next = 0;
+        let mut out = [(0.0, 0.0); 16];
+        while next < 10_000 {
+            let count = consumer.fill(&mut out);
+            for frame in &out[..count] {
+                assert_eq!(frame.0, next as f32);
+                next += 1;
+            }
+        }
+        writer.join().unwrap();
+    }
+}
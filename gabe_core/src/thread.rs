@@ -0,0 +1,104 @@
+//! Running a Gameboy on a background thread, so a frontend's event loop never waits on
+//! emulation. Needs the `std` feature, and isn't available on wasm32-unknown-unknown.
+
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use super::gb::{Frame, Gameboy, GbKeys, JoypadState};
+
+/// Messages from the frontend to the emulation thread
+enum Command {
+    Key(GbKeys, bool),
+    Input(JoypadState),
+    Stop,
+}
+
+/// A Gameboy running on its own thread, which sends each frame it draws back to the
+/// frontend. Only a few frames are queued: once the frontend falls behind, the thread waits
+/// for it, so the frontend's pacing sets the speed.
+pub struct EmulatorThread {
+    commands: Sender<Command>,
+    frames: Receiver<Frame>,
+    handle: JoinHandle<Gameboy>,
+}
+
+impl EmulatorThread {
+    /// Moves the Gameboy to a new thread and starts running it, with up to `queued_frames`
+    /// frames produced ahead of the frontend
+    pub fn spawn(emu: Gameboy, queued_frames: usize) -> Self {
+        let (commands, command_receiver) = mpsc::channel();
+        let (frame_sender, frames) = mpsc::sync_channel(queued_frames);
+        let handle = thread::spawn(move || run(emu, command_receiver, frame_sender));
+        EmulatorThread {
+            commands,
+            frames,
+            handle,
+        }
+    }
+
+    /// Presses or releases a button, applied before the next frame is run
+    pub fn update_key_state(&self, key: GbKeys, pressed: bool) {
+        // Only fails once the thread has stopped, when the button no longer matters
+        let _ = self.commands.send(Command::Key(key, pressed));
+    }
+
+    /// Sets all eight buttons at once, applied before the next frame is run
+    pub fn set_input(&self, input: JoypadState) {
+        let _ = self.commands.send(Command::Input(input));
+    }
+
+    /// Returns the oldest frame queued, without waiting
+    pub fn try_recv_frame(&self) -> Option<Frame> {
+        self.frames.try_recv().ok()
+    }
+
+    /// Waits for the next frame to be run
+    pub fn recv_frame(&self) -> Option<Frame> {
+        self.frames.recv().ok()
+    }
+
+    /// Stops the thread, discarding any frames queued, and returns the Gameboy so its save
+    /// data can be written
+    pub fn stop(self) -> Gameboy {
+        let _ = self.commands.send(Command::Stop);
+        // Wakes the thread if it's waiting for room to queue a frame
+        drop(self.frames);
+        self.handle
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    }
+}
+
+fn run(mut emu: Gameboy, commands: Receiver<Command>, frames: SyncSender<Frame>) -> Gameboy {
+    loop {
+        loop {
+            match commands.try_recv() {
+                Ok(Command::Key(key, pressed)) => emu.update_key_state(key, pressed),
+                Ok(Command::Input(input)) => emu.set_input(input),
+                Ok(Command::Stop) | Err(TryRecvError::Disconnected) => return emu,
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        if frames.send(emu.step_frame()).is_err() {
+            return emu;
+        }
+    }
+}
+
+#[cfg(test)]
+mod thread_tests {
+    use super::*;
+
+    #[test]
+    fn runs_frames_until_stopped() {
+        let rom = vec![0; 0x8000].into_boxed_slice();
+        let thread = EmulatorThread::spawn(Gameboy::power_on(rom, None).unwrap(), 2);
+        let first = thread.recv_frame().unwrap().video.unwrap();
+        let second = thread.recv_frame().unwrap().video.unwrap();
+        assert_eq!(second.frame_index, first.frame_index + 1);
+        thread.update_key_state(GbKeys::A, true);
+        // Handed back still running, without the frames that were queued
+        let mut emu = thread.stop();
+        assert!(emu.step_frame().video.unwrap().frame_index > second.frame_index);
+    }
+}
@@ -0,0 +1,46 @@
+/// 64-bit FNV-1a, a fast non-cryptographic hash for comparing emulator output
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Fnv64(u64);
+
+const OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const PRIME: u64 = 0x0000_0100_0000_01B3;
+
+impl Fnv64 {
+    pub fn new() -> Self {
+        Fnv64(OFFSET_BASIS)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(PRIME);
+        }
+    }
+
+    /// Mixes in a whole word at once, which is quicker than its bytes one by one
+    pub fn write_u32(&mut self, word: u32) {
+        self.0 = (self.0 ^ word as u64).wrapping_mul(PRIME);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Fnv64 {
+    fn default() -> Self {
+        Fnv64::new()
+    }
+}
+
+#[cfg(test)]
+mod hash_tests {
+    use super::Fnv64;
+
+    #[test]
+    fn known_values() {
+        assert_eq!(Fnv64::new().finish(), 0xCBF2_9CE4_8422_2325);
+        let mut hash = Fnv64::new();
+        hash.write(b"a");
+        assert_eq!(hash.finish(), 0xAF63_DC4C_8601_EC8C);
+    }
+}
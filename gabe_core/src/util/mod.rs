@@ -1 +1,3 @@
 pub mod bit;
+pub mod hash;
+pub mod rng;
@@ -1,7 +1,9 @@
-use super::mmu::{InterruptKind, Memory};
+use super::mmu::{InterruptKind, Interrupts, Memory};
+use super::palette::*;
 use super::sink::*;
 
 use alloc::boxed::*;
+use alloc::sync::Arc;
 use alloc::vec::*;
 
 struct Lcdc {
@@ -168,6 +170,21 @@ enum GrayShades {
     Black = 3,
 }
 
+impl GrayShades {
+    /// Every shade, indexed by its value in a palette register
+    const ALL: [GrayShades; 4] = [
+        GrayShades::White,
+        GrayShades::LightGray,
+        GrayShades::DarkGray,
+        GrayShades::Black,
+    ];
+}
+
+/// Where the shades of each sprite palette start among the colors a scanline is encoded with,
+/// after the background palette's four
+const OBP0_SHADES: u8 = 4;
+const OBP1_SHADES: u8 = 8;
+
 struct PaletteData {
     color0: GrayShades,
     color1: GrayShades,
@@ -184,6 +201,17 @@ impl PaletteData {
             color3: GrayShades::White,
         }
     }
+
+    /// Returns the shade given to a color number
+    fn shade(&self, color_idx: u8) -> GrayShades {
+        match color_idx {
+            0 => self.color0,
+            1 => self.color1,
+            2 => self.color2,
+            3 => self.color3,
+            _ => unreachable!(),
+        }
+    }
 }
 
 impl Memory for PaletteData {
@@ -198,22 +226,58 @@ impl Memory for PaletteData {
     }
     fn write_byte(&mut self, addr: u16, val: u8) {
         assert!(addr == 0xFF47 || addr == 0xFF48 || addr == 0xFF49);
-        let mut colors: Vec<GrayShades> = vec![];
-        for i in 0..4 {
-            let v = (val >> (i * 2)) & 0b11;
-            colors.push(match v {
-                0 => GrayShades::White,
-                1 => GrayShades::LightGray,
-                2 => GrayShades::DarkGray,
-                3 => GrayShades::Black,
-                _ => panic!("Bad logic"),
-            });
+        // Each pair of bits, from the low bits up, gives the shade of colors 0 to 3
+        let shade = |i: u8| GrayShades::ALL[((val >> (i * 2)) & 0b11) as usize];
+        self.color0 = shade(0);
+        self.color1 = shade(1);
+        self.color2 = shade(2);
+        self.color3 = shade(3);
+    }
+}
+
+/// Number of tiles in the tile data, 0x8000-0x97FF
+const TILE_COUNT: usize = 384;
+
+/// The tiles in VRAM, decoded from 2bpp into a color number for each pixel, so scanlines are
+/// drawn without decoding the same rows of the same tiles over and over. A tile is decoded
+/// again when it's next drawn after being written.
+struct TileCache {
+    /// Color numbers of each tile's pixels, a row of 8 at a time from the top left
+    pixels: Box<[[u8; 64]]>,
+    /// Set for each tile written since it was last decoded
+    dirty: [bool; TILE_COUNT],
+}
+
+impl TileCache {
+    fn new() -> Self {
+        TileCache {
+            pixels: vec![[0; 64]; TILE_COUNT].into_boxed_slice(),
+            dirty: [true; TILE_COUNT],
+        }
+    }
+
+    /// Marks the tile holding the byte at `offset` into VRAM to be decoded again
+    fn invalidate(&mut self, offset: usize) {
+        if let Some(dirty) = self.dirty.get_mut(offset / 16) {
+            *dirty = true;
         }
-        assert!(colors.len() == 4);
-        self.color0 = colors[0];
-        self.color1 = colors[1];
-        self.color2 = colors[2];
-        self.color3 = colors[3];
+    }
+
+    /// Returns the color number of the pixel `x` from the left and `y` from the top of
+    /// `tile`, decoding the tile from `memory` first if it's been written
+    fn pixel(&mut self, memory: &[u8], tile: usize, x: u8, y: u8) -> u8 {
+        if self.dirty[tile] {
+            // Each row is 2 bytes, the low bits of its color numbers and then the high bits,
+            // with the leftmost pixel in bit 7
+            let data = &memory[tile * 16..tile * 16 + 16];
+            for (i, pixel) in self.pixels[tile].iter_mut().enumerate() {
+                let (lsb, msb) = (data[i / 8 * 2], data[i / 8 * 2 + 1]);
+                let shift = 7 - i % 8;
+                *pixel = (((msb >> shift) & 0x1) << 1) | ((lsb >> shift) & 0x1);
+            }
+            self.dirty[tile] = false;
+        }
+        self.pixels[tile][y as usize * 8 + x as usize]
     }
 }
 
@@ -225,12 +289,18 @@ struct PixelInfo {
     bg_prio: bool,
 }
 
-/// Type alias for the rendered screen data
-pub type FrameData = Box<[u8]>;
+/// Type alias for the rendered screen data. Frames handed out share the buffer the PPU drew
+/// them in, rather than being copied out of it.
+pub type FrameData = Arc<[u8]>;
 
 const SCREEN_WIDTH: usize = 160;
 const SCREEN_HEIGHT: usize = 144;
 
+/// Allocates a black screen for pixels in `format`
+fn new_screen(format: PixelFormat) -> FrameData {
+    vec![0; format.bytes_per_pixel() * SCREEN_WIDTH * SCREEN_HEIGHT].into()
+}
+
 pub struct Vram {
     /// 0xFF40: LCD Control
     lcdc: Lcdc,
@@ -293,13 +363,34 @@ pub struct Vram {
     /// Data containing the rendered scanlines. Presented as row-major, meaning that
     /// the first (top-left) pixel is represented by the first 3 values, the next pixel to the right is
     /// represented by the next 3 values, and the next row doesn't begin until the SCREEN_WIDTH * 3 value.
+    /// Pixels take 4 values instead in the 4 byte pixel formats.
     screen_data: FrameData,
 
+    /// The buffer drawn in before `screen_data`, which is drawn in again once the frame in it
+    /// is dropped, so a frontend holding on to the last frame doesn't cost an allocation per
+    /// frame. Empty until a second buffer is needed.
+    spare_screen: FrameData,
+
+    /// How pixels are written into `screen_data`
+    format: PixelFormat,
+
     /// VRAM data
     memory: Box<[u8]>,
 
     /// OAM Data
     oam: Box<[u8]>,
+
+    /// The tile data in `memory`, decoded for drawing
+    tiles: TileCache,
+
+    /// Colors used to render each gray shade, for the background and each sprite palette
+    colorization: Colorization,
+
+    /// Frames left undrawn after each one drawn, see `set_frame_skip`
+    frame_skip: u32,
+
+    /// Frames skipped since the last one drawn. The current frame is drawn when 0.
+    frames_skipped: u32,
 }
 
 impl Vram {
@@ -316,9 +407,15 @@ impl Vram {
             window_coords: (0x0, 0x0),
             scanline_cycles: 0,
             obj_list: Vec::with_capacity(40),
-            screen_data: vec![0x0; 3 * SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice(),
+            screen_data: new_screen(PixelFormat::Rgb24),
+            spare_screen: FrameData::default(),
+            format: PixelFormat::Rgb24,
             memory: vec![0; 0x2000].into_boxed_slice(),
             oam: vec![0; 0xA0].into_boxed_slice(),
+            tiles: TileCache::new(),
+            colorization: Colorization::uniform(Palette::default()),
+            frame_skip: 0,
+            frames_skipped: 0,
         };
 
         ret.bgp.write_byte(0xFF47, 0xFC);
@@ -326,16 +423,12 @@ impl Vram {
         ret
     }
 
-    pub fn update(
-        &mut self,
-        cycles: u32,
-        video_sink: &mut dyn Sink<VideoFrame>,
-    ) -> Option<Vec<InterruptKind>> {
-        let mut interrupts: Vec<InterruptKind> = vec![];
+    pub fn update(&mut self, cycles: u32, video_sink: &mut dyn Sink<FrameData>) -> Interrupts {
+        let mut interrupts = Interrupts::default();
 
         // If LCD is disabled, nothing is done, blank display
         if !self.lcdc.lcd_enable || cycles == 0 {
-            return None;
+            return interrupts;
         }
 
         // Each scanline is 456 dots (114 CPU cycles) long and consists of
@@ -357,11 +450,8 @@ impl Vram {
             self.ly = (self.ly + 1) % 154;
             self.stat.lyc_ly_flag = self.ly == self.lyc;
 
-            if self.stat.lyc_ly_flag
-                && self.stat.lyc_ly_interrupt
-                && !interrupts.contains(&InterruptKind::LcdStat)
-            {
-                interrupts.push(InterruptKind::LcdStat);
+            if self.stat.lyc_ly_flag && self.stat.lyc_ly_interrupt {
+                interrupts.insert(InterruptKind::LcdStat);
             }
         }
 
@@ -372,9 +462,14 @@ impl Vram {
                 self.stat.mode_flag = LCDMode::Mode1;
                 // New frame ready to be rendered
                 video_sink.append(self.screen_data.clone());
-                interrupts.push(InterruptKind::VBlank);
-                if self.stat.vblank_interrupt && !interrupts.contains(&InterruptKind::LcdStat) {
-                    interrupts.push(InterruptKind::LcdStat);
+                self.frames_skipped = if self.frames_skipped < self.frame_skip {
+                    self.frames_skipped + 1
+                } else {
+                    0
+                };
+                interrupts.insert(InterruptKind::VBlank);
+                if self.stat.vblank_interrupt {
+                    interrupts.insert(InterruptKind::LcdStat);
                 }
             }
         } else if self.scanline_cycles <= 80 {
@@ -384,8 +479,8 @@ impl Vram {
                 self.stat.mode_flag = LCDMode::Mode2;
                 // Perform the OAM Scan to collect the OBJs on this line
                 self.oam_search();
-                if self.stat.oam_interrupt && !interrupts.contains(&InterruptKind::LcdStat) {
-                    interrupts.push(InterruptKind::LcdStat);
+                if self.stat.oam_interrupt {
+                    interrupts.insert(InterruptKind::LcdStat);
                 }
             }
         } else if self.scanline_cycles <= (80 + 172) {
@@ -399,18 +494,37 @@ impl Vram {
             // Spend the rest of the scanline in Mode 0: H-Blank
             if self.stat.mode_flag != LCDMode::Mode0 {
                 self.stat.mode_flag = LCDMode::Mode0;
-                if self.stat.hblank_interrupt && !interrupts.contains(&InterruptKind::LcdStat) {
-                    interrupts.push(InterruptKind::LcdStat);
+                if self.stat.hblank_interrupt {
+                    interrupts.insert(InterruptKind::LcdStat);
+                }
+                if !self.skipping_frame() {
+                    self.draw_scanline();
                 }
-                self.draw_scanline();
             }
         }
 
-        if !interrupts.is_empty() {
-            Some(interrupts)
-        } else {
-            None
+        interrupts
+    }
+
+    /// Returns how many more cycles can pass before `update` has anything to do besides count
+    /// them, which is when the next mode or line starts. Updates until then can be put off
+    /// and made all at once.
+    pub fn cycles_to_next_event(&self) -> u32 {
+        if !self.lcdc.lcd_enable {
+            return u32::MAX;
         }
+        // The mode `update` puts the PPU in for the current cycle, and when it ends
+        let (mode, end) = match (self.ly, self.scanline_cycles) {
+            (144.., _) => (LCDMode::Mode1, 456),
+            (_, 0..=80) => (LCDMode::Mode2, 81),
+            (_, 81..=252) => (LCDMode::Mode3, 253),
+            _ => (LCDMode::Mode0, 456),
+        };
+        if self.stat.mode_flag != mode {
+            // Not yet entered, such as just after the LCD is turned on
+            return 0;
+        }
+        end - self.scanline_cycles
     }
 
     /// Scan the current contents of OAM to find all OBJs that are on the same scanline.
@@ -436,7 +550,9 @@ impl Vram {
 
     /// Compute and "render" the scanline into the internal LCD data state
     fn draw_scanline(&mut self) {
-        for p in 0..SCREEN_WIDTH {
+        // The shade of each pixel, converted to the pixel format together at the end
+        let mut shades = [0; SCREEN_WIDTH];
+        for (p, shade) in shades.iter_mut().enumerate() {
             let bg_pixel = if self.lcdc.background_enable {
                 Some(self.get_background_pixel(p as u8))
             } else {
@@ -449,80 +565,22 @@ impl Vram {
                 None
             };
 
-            let pixel_shade = if let (Some(b), Some(p)) = (&bg_pixel, &sprite_pixel) {
-                if p.color_idx > 0 {
-                    if b.color_idx == 0 || !p.bg_prio {
-                        match p.palette {
-                            0 => match p.color_idx {
-                                0 => self.obp0.color0,
-                                1 => self.obp0.color1,
-                                2 => self.obp0.color2,
-                                3 => self.obp0.color3,
-                                _ => unreachable!(),
-                            },
-                            1 => match p.color_idx {
-                                0 => self.obp1.color0,
-                                1 => self.obp1.color1,
-                                2 => self.obp1.color2,
-                                3 => self.obp1.color3,
-                                _ => unreachable!(),
-                            },
-                            _ => unreachable!(),
-                        }
-                    } else {
-                        match b.color_idx {
-                            0 => self.bgp.color0,
-                            1 => self.bgp.color1,
-                            2 => self.bgp.color2,
-                            3 => self.bgp.color3,
-                            _ => unreachable!(),
-                        }
-                    }
-                } else {
-                    match b.color_idx {
-                        0 => self.bgp.color0,
-                        1 => self.bgp.color1,
-                        2 => self.bgp.color2,
-                        3 => self.bgp.color3,
-                        _ => unreachable!(),
-                    }
-                }
-            } else if let (Some(b), None) = (&bg_pixel, &sprite_pixel) {
-                match b.color_idx {
-                    0 => self.bgp.color0,
-                    1 => self.bgp.color1,
-                    2 => self.bgp.color2,
-                    3 => self.bgp.color3,
-                    _ => unreachable!(),
+            *shade = match (&bg_pixel, &sprite_pixel) {
+                (Some(b), Some(p)) if p.color_idx > 0 && (b.color_idx == 0 || !p.bg_prio) => {
+                    self.sprite_shade(p)
                 }
-            } else if let (None, Some(p)) = (&bg_pixel, &sprite_pixel) {
-                match p.palette {
-                    0 => match p.color_idx {
-                        0 => self.obp0.color0,
-                        1 => self.obp0.color1,
-                        2 => self.obp0.color2,
-                        3 => self.obp0.color3,
-                        _ => unreachable!(),
-                    },
-                    1 => match p.color_idx {
-                        0 => self.obp1.color0,
-                        1 => self.obp1.color1,
-                        2 => self.obp1.color2,
-                        3 => self.obp1.color3,
-                        _ => unreachable!(),
-                    },
-                    _ => unreachable!(),
-                }
-            } else {
+                (Some(b), _) => self.bgp.shade(b.color_idx) as u8,
+                (None, Some(p)) => self.sprite_shade(p),
                 // Neither are present, return a White/Color 1
-                GrayShades::White
+                (None, None) => GrayShades::White as u8,
             };
-            let pixel_rgb = Self::shade_to_rgb_u8(&pixel_shade);
-
-            self.screen_data[(self.ly as usize * (SCREEN_WIDTH * 3)) + (p * 3)] = pixel_rgb.0;
-            self.screen_data[(self.ly as usize * (SCREEN_WIDTH * 3)) + (p * 3) + 1] = pixel_rgb.1;
-            self.screen_data[(self.ly as usize * (SCREEN_WIDTH * 3)) + (p * 3) + 2] = pixel_rgb.2;
         }
+        let (format, colors) = (self.format, self.colorization.colors());
+        let line_len = SCREEN_WIDTH * format.bytes_per_pixel();
+        let start = self.ly as usize * line_len;
+        // The lines above belong to this frame, and are kept if the screen has to be moved
+        let screen = self.screen_mut(self.ly != 0);
+        format.encode_line(&colors, &shades, &mut screen[start..start + line_len]);
     }
 
     /// Check internal state to determine what horizontal scanline background
@@ -602,17 +660,12 @@ impl Vram {
             tile_data_base *= 16;
         }
 
-        // Each set of 2 bytes represets the least and most signficant bits in the tile's color number, respectively,
-        // for each line of 8 pixels in the tile.
-        // Byte 0-1 is first line, Byte 2-3 is second line, etc.
-        // Offset the line we're looking for by applying the tile pixel y-offset, and grab both color bytes
-        let tile_colors_lsb = self.memory[(tile_data_base + (tile_pixel_y as u16 * 2)) as usize];
-        let tile_colors_msb =
-            self.memory[(tile_data_base + (tile_pixel_y as u16 * 2) + 1) as usize];
-
-        let pixel_shift = tile_pixel_x ^ 0x7;
-        let tile_color_number = (((tile_colors_msb >> pixel_shift) & 0x1) << 1)
-            | ((tile_colors_lsb >> pixel_shift) & 0x1);
+        let tile_color_number = self.tiles.pixel(
+            &self.memory,
+            tile_data_base as usize / 16,
+            tile_pixel_x,
+            tile_pixel_y,
+        );
 
         PixelInfo {
             color_idx: tile_color_number,
@@ -638,7 +691,7 @@ impl Vram {
 
             // Check x-pos for this OBJ
             if x_pos > pixel && x_pos <= pixel + 8 {
-                let tile_pixel_x = pixel + 8 - x_pos;
+                let mut tile_pixel_x = pixel + 8 - x_pos;
                 let mut tile_pixel_y = (self.ly + 16).wrapping_sub(y_pos);
 
                 // Parse attributes
@@ -670,24 +723,17 @@ impl Vram {
                     tile_pixel_y &= 0x7
                 }
 
-                // Each set of 2 bytes represets the least and most signficant bits in the tile's color number, respectively,
-                // for each line of 8 pixels in the tile.
-                // Byte 0-1 is first line, Byte 2-3 is second line, etc.
-                // Offset the line we're looking for by applying the tile pixel y-offset, and grab both color bytes
-                let tile_colors_lsb =
-                    self.memory[(tile_data_base + (tile_pixel_y as u16 * 2)) as usize];
-                let tile_colors_msb =
-                    self.memory[(tile_data_base + (tile_pixel_y as u16 * 2) + 1) as usize];
-
-                // Which pixel in the line we shift over changes on the status of x_flip
-                let pixel_shift = if x_flip {
-                    tile_pixel_x
-                } else {
-                    !tile_pixel_x & 0x7
-                };
+                // Which pixel in the line is drawn changes on the status of x_flip
+                if x_flip {
+                    tile_pixel_x = !tile_pixel_x & 0x7;
+                }
 
-                let tile_color_number = (((tile_colors_msb >> pixel_shift) & 0x1) << 1)
-                    | ((tile_colors_lsb >> pixel_shift) & 0x1);
+                let tile_color_number = self.tiles.pixel(
+                    &self.memory,
+                    tile_data_base as usize / 16,
+                    tile_pixel_x,
+                    tile_pixel_y,
+                );
 
                 if tile_color_number != 0 {
                     ret = PixelInfo {
@@ -703,15 +749,101 @@ impl Vram {
     }
 
     /// Converts the given GrayShade enum value into a tuple of
-    /// u8 values representing the RGB of the shade
-    fn shade_to_rgb_u8(shade: &GrayShades) -> (u8, u8, u8) {
-        match shade {
-            GrayShades::Black => (0, 0, 0),
-            GrayShades::DarkGray => (85, 85, 85),
-            GrayShades::LightGray => (170, 170, 170),
-            GrayShades::White => (255, 255, 255),
+    /// u8 values representing the RGB of the shade in the current background palette
+    fn shade_to_rgb_u8(&self, shade: GrayShades) -> Rgb {
+        self.colorization.bg.colors[shade as usize]
+    }
+
+    /// Returns the shade of a sprite's pixel, offset past the background's shades by the
+    /// sprite palette it's from so each can be colored differently
+    fn sprite_shade(&self, pixel: &PixelInfo) -> u8 {
+        match pixel.palette {
+            0 => OBP0_SHADES + self.obp0.shade(pixel.color_idx) as u8,
+            1 => OBP1_SHADES + self.obp1.shade(pixel.color_idx) as u8,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clears all screen data to white
+    fn clear_screen(&mut self) {
+        let bpp = self.format.bytes_per_pixel();
+        let white = self.format.encode(self.shade_to_rgb_u8(GrayShades::White));
+        for pixel in self.screen_mut(false).chunks_exact_mut(bpp) {
+            pixel.copy_from_slice(&white[..bpp]);
         }
     }
+
+    /// Returns the screen to draw into. While a frame handed out still shares the buffer, the
+    /// screen is moved to the spare buffer if that's free, or a new one, leaving the frame as
+    /// it was. The screen is only copied over if `keep` is set, as a frame starting from the
+    /// top draws every line.
+    fn screen_mut(&mut self, keep: bool) -> &mut [u8] {
+        if Arc::get_mut(&mut self.screen_data).is_none() {
+            let spare_free = Arc::get_mut(&mut self.spare_screen).is_some()
+                && self.spare_screen.len() == self.screen_data.len();
+            if !spare_free {
+                self.spare_screen = new_screen(self.format);
+            }
+            core::mem::swap(&mut self.screen_data, &mut self.spare_screen);
+            if keep {
+                let screen = Arc::get_mut(&mut self.screen_data).unwrap();
+                screen.copy_from_slice(&self.spare_screen);
+            }
+        }
+        Arc::get_mut(&mut self.screen_data).unwrap()
+    }
+
+    /// Returns the PPU and video memory to their power-on state, keeping the palette, pixel
+    /// format and frame skip the frontend selected
+    pub fn reset(&mut self) {
+        let format = self.format;
+        *self = Vram {
+            colorization: self.colorization,
+            frame_skip: self.frame_skip,
+            ..Vram::power_on()
+        };
+        self.set_pixel_format(format);
+    }
+
+    /// Sets how pixels are laid out in the frames produced. The frame being drawn is
+    /// cleared, so the next frame is the first drawn in the new format.
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        if format != self.format {
+            self.format = format;
+            self.screen_data = new_screen(format);
+            self.clear_screen();
+        }
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Leaves `frames` frames undrawn after each one drawn. Timing and interrupts carry on as
+    /// normal, only the scanlines aren't drawn, so skipped frames keep the last frame's pixels.
+    pub fn set_frame_skip(&mut self, frames: u32) {
+        self.frame_skip = frames;
+    }
+
+    pub fn frame_skip(&self) -> u32 {
+        self.frame_skip
+    }
+
+    /// Whether the frame being drawn is one being skipped
+    pub fn skipping_frame(&self) -> bool {
+        self.frames_skipped != 0
+    }
+
+    /// Sets the colors used to render each gray shade, starting with the next scanline drawn
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.colorization = Colorization::uniform(palette);
+    }
+
+    /// Sets the colors used to render the background's and each sprite palette's shades,
+    /// starting with the next scanline drawn
+    pub fn set_colorization(&mut self, colorization: Colorization) {
+        self.colorization = colorization;
+    }
 }
 
 impl Memory for Vram {
@@ -740,7 +872,10 @@ impl Memory for Vram {
     fn write_byte(&mut self, addr: u16, val: u8) {
         // TODO: Limit writes depending on Mode
         match addr {
-            0x8000..=0x9FFF => self.memory[(addr - 0x8000) as usize] = val,
+            0x8000..=0x9FFF => {
+                self.memory[(addr - 0x8000) as usize] = val;
+                self.tiles.invalidate((addr - 0x8000) as usize);
+            }
             0xFE00..=0xFE9F => self.oam[(addr - 0xFE00) as usize] = val,
             0xFF40 => {
                 self.lcdc.write_byte(addr, val);
@@ -749,10 +884,7 @@ impl Memory for Vram {
                     self.ly = 0;
                     self.scanline_cycles = 0;
                     self.stat.mode_flag = LCDMode::Mode0;
-                    for i in 0..self.screen_data.len() {
-                        // Clear all screen data to white
-                        self.screen_data[i] = 255;
-                    }
+                    self.clear_screen();
                 }
             }
             0xFF41 => self.stat.write_byte(addr, val),
@@ -833,4 +965,152 @@ mod vram_tests {
         assert_eq!(GrayShades::Black, p.color3);
         assert_eq!(0b1101_1000, p.read_byte(0xFF47));
     }
+
+    #[test]
+    fn update_interrupts() {
+        let mut vram = Vram::power_on();
+        vram.write_byte(0xFF40, 0x80);
+        // Only the STAT interrupt for entering VBlank
+        vram.write_byte(0xFF41, 0b0001_0000);
+        let mut frames = Vec::new();
+        for _ in 0..143 {
+            assert!(vram.update(456, &mut frames).is_empty());
+        }
+        let interrupts = vram.update(456, &mut frames);
+        assert_eq!(
+            interrupts.bits(),
+            InterruptKind::VBlank as u8 | InterruptKind::LcdStat as u8
+        );
+        assert_eq!(frames.len(), 1);
+        assert!(vram.update(4, &mut frames).is_empty());
+    }
+
+    #[test]
+    fn tile_cache() {
+        let mut vram = Vram::power_on();
+        // Row 1 of tile 2: color numbers 3, 1, 2, then 0
+        vram.write_byte(0x8022, 0b1100_0000);
+        vram.write_byte(0x8023, 0b1010_0000);
+        let row: Vec<u8> = (0..4)
+            .map(|x| vram.tiles.pixel(&vram.memory, 2, x, 1))
+            .collect();
+        assert_eq!(row, [3, 1, 2, 0]);
+        // Rewriting the tile decodes it again
+        vram.write_byte(0x8023, 0x00);
+        assert_eq!(vram.tiles.pixel(&vram.memory, 2, 0, 1), 1);
+        assert_eq!(vram.tiles.pixel(&vram.memory, 2, 2, 1), 0);
+        // Tile maps aren't tile data
+        vram.write_byte(0x9800, 0x01);
+        assert!(!vram.tiles.dirty[2]);
+    }
+
+    #[test]
+    fn pixel_formats() {
+        let mut vram = Vram::power_on();
+        vram.set_palette(Palette::new([(1, 2, 3); 4]));
+        vram.write_byte(0xFF40, 0x91);
+        let mut frames: Vec<FrameData> = Vec::new();
+        for format in [PixelFormat::Rgba32, PixelFormat::Xrgb32] {
+            vram.set_pixel_format(format);
+            // A frame of white, drawn through each scanline
+            while vram.update(4, &mut frames).is_empty() {}
+            let frame = frames.pop().unwrap();
+            assert_eq!(frame.len(), 4 * SCREEN_WIDTH * SCREEN_HEIGHT);
+            match format {
+                PixelFormat::Rgba32 => assert_eq!(frame[..4], [1, 2, 3, 0xFF]),
+                _ => assert_eq!(u32::from_ne_bytes(frame[..4].try_into().unwrap()), 0x010203),
+            }
+        }
+        vram.reset();
+        assert_eq!(vram.pixel_format(), PixelFormat::Xrgb32);
+        assert_eq!(vram.screen_data.len(), 4 * SCREEN_WIDTH * SCREEN_HEIGHT);
+    }
+
+    #[test]
+    fn frames_share_screen() {
+        let mut vram = Vram::power_on();
+        vram.write_byte(0xFF40, 0x91);
+        let mut frames: Vec<FrameData> = Vec::new();
+        while vram.update(4, &mut frames).is_empty() {}
+        let first = frames.pop().unwrap();
+        assert!(Arc::ptr_eq(&first, &vram.screen_data));
+        // Held on to while the next frame is drawn, so that one gets a new buffer
+        vram.set_palette(Palette::new([(1, 2, 3); 4]));
+        while vram.update(4, &mut frames).is_empty() {}
+        let second = frames.pop().unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert_eq!(first[..3], [0xFF, 0xFF, 0xFF]);
+        assert_eq!(second[..3], [1, 2, 3]);
+        // Holding on to only the last frame, the two buffers take turns
+        let first_buffer = Arc::as_ptr(&first);
+        drop(first);
+        while vram.update(4, &mut frames).is_empty() {}
+        let third = frames.pop().unwrap();
+        assert_eq!(Arc::as_ptr(&third), first_buffer);
+        // With every frame dropped, the same buffer is drawn over again
+        drop((second, third));
+        while vram.update(4, &mut frames).is_empty() {}
+        assert_eq!(Arc::as_ptr(&frames.pop().unwrap()), first_buffer);
+    }
+
+    #[test]
+    fn colorized_sprites() {
+        let mut vram = Vram::power_on();
+        // Tile 1 is solid color 1, drawn as a sprite in the top left corner with OBP1
+        for row in 0..8 {
+            vram.write_byte(0x8010 + row * 2, 0xFF);
+        }
+        vram.write_byte(0xFE00, 16);
+        vram.write_byte(0xFE01, 8);
+        vram.write_byte(0xFE02, 1);
+        vram.write_byte(0xFE03, 0x10);
+        vram.write_byte(0xFF47, 0xE4);
+        vram.write_byte(0xFF49, 0xE4);
+        vram.write_byte(0xFF40, 0x93);
+        let colorization = Colorization::for_buttons("Left+A").unwrap();
+        vram.set_colorization(colorization);
+        let mut frames: Vec<FrameData> = Vec::new();
+        while vram.update(4, &mut frames).is_empty() {}
+        let frame = frames.pop().unwrap();
+        let (r, g, b) = colorization.obj1.colors[1];
+        assert_eq!(frame[..3], [r, g, b]);
+        // Past the sprite is the background, in its own palette
+        let (r, g, b) = colorization.bg.colors[0];
+        assert_eq!(frame[8 * 3..9 * 3], [r, g, b]);
+    }
+
+    #[test]
+    fn frame_skip() {
+        let mut vram = Vram::power_on();
+        vram.write_byte(0xFF40, 0x91);
+        vram.set_frame_skip(1);
+        let mut frames: Vec<FrameData> = Vec::new();
+        let mut drawn = Vec::new();
+        for shade in 1..=4 {
+            vram.set_palette(Palette::new([(shade, 0, 0); 4]));
+            // VBlank is still raised for the frames skipped
+            while vram.update(4, &mut frames).is_empty() {}
+            drawn.push(frames.pop().unwrap()[0]);
+        }
+        // Skipped frames keep the last frame drawn
+        assert_eq!(drawn, [1, 1, 3, 3]);
+        vram.reset();
+        assert_eq!(vram.frame_skip(), 1);
+    }
+
+    #[test]
+    fn palette_colors() {
+        let mut vram = Vram::power_on();
+        assert_eq!((255, 255, 255), vram.shade_to_rgb_u8(GrayShades::White));
+        assert_eq!((0, 0, 0), vram.shade_to_rgb_u8(GrayShades::Black));
+        vram.set_palette(Palette::DMG);
+        assert_eq!(
+            Palette::DMG.colors[0],
+            vram.shade_to_rgb_u8(GrayShades::White)
+        );
+        assert_eq!(
+            Palette::DMG.colors[2],
+            vram.shade_to_rgb_u8(GrayShades::DarkGray)
+        );
+    }
 }
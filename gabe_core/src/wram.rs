@@ -11,6 +11,11 @@ impl Wram {
             memory: vec![0; 0x2000],
         }
     }
+
+    /// Fills the memory with bytes from `bytes`, as left in the RAM chip at power on
+    pub fn fill(&mut self, bytes: impl FnMut() -> u8) {
+        self.memory.fill_with(bytes);
+    }
 }
 
 impl Memory for Wram {
@@ -9,7 +9,7 @@ fn blargg_cpu_instrs() {
     let mut video_sink = common::NullSink;
     let mut audio_sink = common::NullSink;
     let rom_data = common::get_rom_data("tests/roms/cpu_instrs/cpu_instrs.gb").unwrap();
-    let mut gb = gb::Gameboy::power_on(rom_data, None);
+    let mut gb = gb::Gameboy::power_on(rom_data, None).unwrap();
     let mut result = std::string::String::new();
     loop {
         gb.step(&mut video_sink, &mut audio_sink);
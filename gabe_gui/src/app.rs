@@ -1,170 +1,1157 @@
 use std::{
-    collections::VecDeque,
     fs::{File, OpenOptions},
     io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
-use egui::{load::SizedTexture, ColorImage, Image, Key, TextureHandle, TextureOptions, Vec2};
-use gabe_core::gb::{Gameboy, GbKeys};
-use gabe_core::sink::{AudioFrame, Sink};
+use egui::{
+    load::SizedTexture, Align2, Color32, ColorImage, FontId, Frame, Image, Key, Sense,
+    TextureHandle, TextureOptions, Vec2, ViewportCommand,
+};
+use gabe_core::{
+    camera::StillImage,
+    cheats::Cheat,
+    palette::Colorization,
+    sink::{AudioFrame, VideoFrame},
+};
+
+use crate::{
+    archive,
+    audio_driver::{AudioDriver, SpeedResampler},
+    cheats,
+    cli::Args,
+    config::{Config, FramePacing, SaveConfig},
+    debugger::Debugger,
+    disassembly,
+    filters::Filter,
+    headless::CYCLES_PER_FRAME,
+    input::{self, KeyMap},
+    link::LinkedGameboy,
+    netplay::Netplay,
+    osd::Osd,
+    pacing::{self, VsyncPacer},
+    palettes::PaletteList,
+    recent::RecentRoms,
+    recorder::Recorder,
+    rumble::Rumble,
+    screenshot,
+    stats::PerfStats,
+    tilt::Tilt,
+    video_sinks::{BlendMode, LcdVideoSink},
+};
 
-use crate::{audio_driver::AudioDriver, video_sinks};
+const CYCLE_TIME_NS: f32 = gabe_core::CYCLE_TIME_NS as f32;
 
-const CYCLE_TIME_NS: f32 = 238.41858;
+pub const WINDOW_TITLE: &str = "Gabe Emulator";
 
-struct SimpleAudioSink {
-    inner: VecDeque<AudioFrame>,
+/// Size of the Gameboy screen in pixels
+const SCREEN_SIZE: Vec2 = Vec2::new(160.0, 144.0);
+/// Names of the sound channels shown when they're muted with the number keys
+const CHANNEL_NAMES: [&str; 4] = ["Square 1", "Square 2", "Wave", "Noise"];
+/// Largest window scale selectable with the scale hotkeys
+pub const MAX_SCALE: u32 = 10;
+/// Slowest speed multiplier allowed
+pub const MIN_SPEED: f32 = 0.25;
+/// Fastest speed multiplier allowed
+pub const MAX_SPEED: f32 = 4.0;
+/// Change in volume percentage for each press of the volume keys
+const VOLUME_STEP: u32 = 10;
+/// Host time spent emulating per update while fast-forwarding, leaving time to draw the UI
+const FAST_FORWARD_BUDGET: Duration = Duration::from_millis(12);
+/// Approximate space used by the menu bar and panel margins, for the initial window size
+const WINDOW_CHROME: Vec2 = Vec2::new(16.0, 40.0);
+
+/// Returns the window size needed to show the screen at the given scale
+pub fn window_size(scale: u32) -> Vec2 {
+    SCREEN_SIZE * scale.clamp(1, MAX_SCALE) as f32 + WINDOW_CHROME
 }
 
-impl Sink<AudioFrame> for SimpleAudioSink {
-    fn append(&mut self, value: AudioFrame) {
-        self.inner.push_back(value);
+/// Returns the size to draw the frame at within the available space, keeping the aspect ratio.
+/// With integer scaling, only whole multiples of the screen size are used.
+fn display_size(available: Vec2, integer_scaling: bool) -> Vec2 {
+    let scale = (available.x / SCREEN_SIZE.x).min(available.y / SCREEN_SIZE.y);
+    if integer_scaling {
+        SCREEN_SIZE * scale.floor().max(1.0)
+    } else {
+        SCREEN_SIZE * scale
     }
 }
 
+/// Shows the native file dialog for choosing a ROM
+fn pick_rom() -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .add_filter("Game Boy ROMs", &["gb", "gbc", "zip", "gz"])
+        .add_filter("All files", &["*"])
+        .pick_file()
+}
+
+/// Shows a button for each recently opened ROM, labeled with its file name, and returns the
+/// path of the one clicked
+fn recent_rom_buttons(ui: &mut egui::Ui, recent: &RecentRoms) -> Option<PathBuf> {
+    let mut clicked = None;
+    for path in recent.paths() {
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |n| n.to_string_lossy().into_owned(),
+        );
+        if ui
+            .button(name)
+            .on_hover_text(path.display().to_string())
+            .clicked()
+        {
+            clicked = Some(path.clone());
+        }
+    }
+    clicked
+}
+
 pub struct GabeApp {
     emu: Option<gabe_core::gb::Gameboy>,
     emulated_cycles: u64,
     start_time: u64,
+    /// Paused with the pause key, separately from the debugger
+    paused: bool,
+    /// Speed to run at, as a multiple of real time
+    speed: f32,
+    /// Fits the audio from running at `speed` back to real time
+    speed_resampler: SpeedResampler,
+    frame_pacing: FramePacing,
+    vsync_pacer: VsyncPacer,
+    /// With vsync pacing, the cycles to have run by the end of the update
+    vsync_target_cycles: u64,
     save_file: Option<File>,
     audio_driver: AudioDriver,
     framebuffer: TextureHandle,
+    debugger: Debugger,
+    key_map: KeyMap,
+    fullscreen: bool,
+    /// Window scale, changed with the scale hotkeys
+    scale: u32,
+    integer_scaling: bool,
+    /// Scale the frame with bilinear filtering rather than nearest-neighbor
+    smooth: bool,
+    /// Palettes to display the screen in, cycled with F2
+    palettes: PaletteList,
+    /// Upscaling filter, cycled with F3
+    filter: Filter,
+    /// Blends frames as its mode asks, cycled with F4
+    video_sink: LcdVideoSink,
+    /// The most recent frame from the core, after blending but before filtering, used for
+    /// screenshots
+    last_frame: Option<VideoFrame>,
+    /// The most recent frame after filtering, which OSD messages are drawn over
+    filtered_frame: Option<(Vec<u8>, [usize; 2])>,
+    osd: Osd,
+    /// Whether fast-forward was held during the last update
+    fast_forwarding: bool,
+    /// Title of the running game from its header, shown in the window title
+    game_title: Option<String>,
+    /// Window title last sent to the window
+    window_title: String,
+    /// Directory screenshots and recordings started with the hotkey are saved to
+    screenshot_dir: PathBuf,
+    recorder: Option<Recorder>,
+    /// File to start recording to when the next ROM is loaded
+    record_on_load: Option<PathBuf>,
+    /// ROM given on the command line, loaded on the first update
+    rom_on_start: Option<PathBuf>,
+    /// Save file given on the command line, used by the first ROM loaded
+    save_file_on_start: Option<PathBuf>,
+    /// Address from `--run-until` and its timeout in frames, for the first ROM loaded
+    run_until_on_start: Option<(u16, Option<u64>)>,
+    /// Coverage file given on the command line, recorded into by the first ROM loaded
+    coverage_on_start: Option<PathBuf>,
+    /// Where the running game's coverage is written when it stops, if it's being recorded
+    coverage_file: Option<PathBuf>,
+    saves: SaveConfig,
+    /// Cheats from the cheat file, added to each game loaded
+    cheats: Vec<Cheat>,
+    /// Grayscale picture from `--camera-image` and its size, shown to the Game Boy Camera
+    camera_image: Option<(Vec<u8>, usize, usize)>,
+    recent_roms: RecentRoms,
+    /// Frames not drawn for each one drawn while fast-forwarding
+    fast_forward_frame_skip: u32,
+    show_stats: bool,
+    stats: PerfStats,
+    /// Master volume percentage
+    volume: u32,
+    muted: bool,
+    pause_on_focus_loss: bool,
+    /// Gameboy model the games are started on
+    model: gabe_core::gb::Model,
+    /// Whether games flagged as supporting the CGB are told they're in CGB mode
+    cgb_mode: bool,
+    /// Colors games made only for the DMG are shown in on the CGB, the CGB's default if None
+    colorization: Option<Colorization>,
+    /// Set while the game loaded is colorized, which the palette would replace
+    colorized: bool,
+    /// Set while emulation is paused because the window lost focus
+    focus_paused: bool,
+    /// Sound channels muted with the number keys, kept for each game loaded
+    muted_channels: [bool; 4],
+    /// Space available for the frame during the last update, used to resize the window
+    frame_area: Vec2,
+    netplay: Option<Netplay>,
+    /// Cycles run in the current frame during netplay
+    netplay_frame_cycles: u64,
+    /// Whether the current frame's netplay input has been applied
+    netplay_frame_started: bool,
+    /// In link cable mode, the ROM for the second Gameboy, or None to run the main ROM in both
+    link_mode: Option<Option<PathBuf>>,
+    link: Option<LinkedGameboy>,
+    rumble: Rumble,
+    tilt: Tilt,
 }
 
 impl GabeApp {
     /// Called once before the first frame.
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        config: Config,
+        args: Args,
+        netplay: Option<Netplay>,
+    ) -> Self {
         // This is also where you can customize the look and feel of egui using
         // `cc.egui_ctx.set_visuals` and `cc.egui_ctx.set_fonts`.
-        Self {
+        let cheats = cheats::from_config(&config).unwrap_or_else(|e| {
+            log::warn!("{}, not using cheats", e);
+            vec![]
+        });
+        let camera_image = args.camera_image.as_deref().and_then(|path| {
+            screenshot::read_grayscale_png(path)
+                .map_err(|e| log::warn!("{}, not using the camera image", e))
+                .ok()
+        });
+        let mut app = Self {
             emu: None,
             emulated_cycles: 0,
             start_time: 0,
+            paused: false,
+            speed: config.emulation.speed,
+            speed_resampler: SpeedResampler::new(config.emulation.speed),
+            frame_pacing: config.video.frame_pacing,
+            vsync_pacer: VsyncPacer::new(),
+            vsync_target_cycles: 0,
             save_file: None,
-            audio_driver: AudioDriver::new(gabe_core::SAMPLE_RATE, 100),
+            audio_driver: AudioDriver::new(gabe_core::SAMPLE_RATE, config.audio.latency_ms),
             framebuffer: cc.egui_ctx.load_texture(
                 "framebuffer",
                 ColorImage::default(),
                 Default::default(),
             ),
+            debugger: Debugger::new(),
+            key_map: KeyMap::from_config(&config.keys).unwrap_or_else(|e| {
+                log::warn!("{}, using the default key bindings", e);
+                KeyMap::default()
+            }),
+            fullscreen: config.video.fullscreen,
+            scale: config.video.scale.clamp(1, MAX_SCALE),
+            integer_scaling: config.video.integer_scaling,
+            smooth: config.video.smooth,
+            palettes: PaletteList::from_config(&config).unwrap_or_else(|e| {
+                log::warn!("{}, using the default palette", e);
+                PaletteList::default()
+            }),
+            filter: config.video.filter,
+            video_sink: LcdVideoSink::new(config.video.blend),
+            last_frame: None,
+            filtered_frame: None,
+            osd: Osd::new(),
+            fast_forwarding: false,
+            game_title: None,
+            window_title: WINDOW_TITLE.to_string(),
+            screenshot_dir: config
+                .video
+                .screenshot_dir
+                .unwrap_or_else(|| PathBuf::from(".")),
+            recorder: None,
+            record_on_load: args.record,
+            rom_on_start: args.rom,
+            save_file_on_start: args.save_file,
+            run_until_on_start: args.run_until.map(|addr| (addr, args.timeout_frames)),
+            coverage_on_start: args.coverage,
+            coverage_file: None,
+            saves: config.saves,
+            cheats,
+            camera_image,
+            link_mode: args.link,
+            recent_roms: RecentRoms::load(),
+            fast_forward_frame_skip: config.video.fast_forward_frame_skip,
+            show_stats: config.video.show_stats,
+            stats: PerfStats::new(),
+            volume: config.audio.volume.min(100),
+            muted: config.audio.muted,
+            muted_channels: [false; 4],
+            pause_on_focus_loss: config.emulation.pause_on_focus_loss,
+            model: config.emulation.model.into(),
+            cgb_mode: config.emulation.cgb_mode,
+            colorization: config.emulation.colorization().unwrap_or_else(|e| {
+                log::warn!("{}, using the default colorization", e);
+                None
+            }),
+            colorized: false,
+            focus_paused: false,
+            frame_area: Vec2::ZERO,
+            netplay,
+            netplay_frame_cycles: 0,
+            netplay_frame_started: false,
+            link: None,
+            rumble: Rumble::new(config.emulation.rumble),
+            tilt: Tilt::new(),
+        };
+        app.apply_volume();
+        app
+    }
+
+    /// Handles the window hotkeys: F11 toggles fullscreen, Escape leaves it,
+    /// +/- change the window scale, F2 cycles the palette, F3 cycles the filter, F4 cycles the
+    /// blend mode, F9 starts and stops recording, F10 toggles the stats overlay, F12 saves a screenshot
+    /// (at the window scale with Shift held), and 1-4 mute and unmute the sound channels
+    fn handle_hotkeys(&mut self, ctx: &egui::Context) {
+        // Follow the actual window state, in case the OS changed it
+        if let Some(fullscreen) = ctx.input(|i| i.viewport().fullscreen) {
+            self.fullscreen = fullscreen;
+        }
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let pressed = |key| ctx.input(|i| i.key_pressed(key));
+        if pressed(Key::F11) || (pressed(Key::Escape) && self.fullscreen) {
+            self.set_fullscreen(ctx, !self.fullscreen);
+        }
+        if pressed(Key::PlusEquals) && self.scale < MAX_SCALE {
+            self.set_scale(ctx, self.scale + 1);
+            self.osd.show(format!("Scale {}x", self.scale));
+        } else if pressed(Key::Minus) && self.scale > 1 {
+            self.set_scale(ctx, self.scale - 1);
+            self.osd.show(format!("Scale {}x", self.scale));
+        }
+        if self.key_map.pause_pressed(ctx) && self.emu.is_some() {
+            self.set_paused(ctx, !self.paused);
+        }
+        match self.key_map.volume_change(ctx) {
+            1 => self.set_volume((self.volume + VOLUME_STEP).min(100)),
+            -1 => self.set_volume(self.volume.saturating_sub(VOLUME_STEP)),
+            _ => {}
+        }
+        if self.key_map.tilt_center_pressed(ctx) && self.emu.is_some() {
+            self.tilt.recenter();
+            self.osd.show("Tilt recentered");
+        }
+        if self.key_map.mute_pressed(ctx) {
+            self.muted = !self.muted;
+            self.apply_volume();
+            self.osd.show(if self.muted { "Muted" } else { "Sound on" });
+        }
+        if pressed(Key::F2) {
+            if self.colorized {
+                self.osd.show("Palette: colorized by the CGB");
+            } else {
+                self.palettes.next();
+                self.apply_palette();
+                self.osd
+                    .show(format!("Palette: {}", self.palettes.current().0));
+            }
+        }
+        if pressed(Key::F3) {
+            self.filter = self.filter.next();
+            self.osd.show(format!("Filter: {}", self.filter.name()));
+        }
+        if pressed(Key::F4) {
+            self.video_sink.set_mode(self.video_sink.mode().next());
+            self.osd
+                .show(format!("Blend: {}", self.video_sink.mode().name()));
+        }
+        if pressed(Key::F10) {
+            self.show_stats = !self.show_stats;
+        }
+        if pressed(Key::F9) {
+            if self.recorder.is_some() {
+                self.stop_recording();
+            } else if self.emu.is_some() {
+                let path = self
+                    .screenshot_dir
+                    .join(format!("gabe-{}.mkv", screenshot::timestamp()));
+                self.start_recording(&path);
+            }
+        }
+        if pressed(Key::F12) {
+            let shift = ctx.input(|i| i.modifiers.shift);
+            self.save_screenshot(if shift { self.scale } else { 1 });
+        }
+        let channel_keys = [Key::Num1, Key::Num2, Key::Num3, Key::Num4];
+        for (i, key) in channel_keys.into_iter().enumerate() {
+            // Keys bound to a button are left for the game
+            if pressed(key) && self.key_map.button(key).is_none() {
+                self.muted_channels[i] = !self.muted_channels[i];
+                self.apply_muted_channels();
+                let state = if self.muted_channels[i] {
+                    "muted"
+                } else {
+                    "on"
+                };
+                self.osd.show(format!("{} {}", CHANNEL_NAMES[i], state));
+            }
+        }
+    }
+
+    fn start_recording(&mut self, path: &Path) {
+        match Recorder::start(path, self.emulated_cycles) {
+            Ok(recorder) => {
+                log::info!("Recording to {}", path.display());
+                self.osd.show("Recording");
+                self.recorder = Some(recorder);
+            }
+            Err(e) => log::error!("Failed to start recording: {}", e),
+        }
+    }
+
+    /// Stops recording, returning the thread saving the recording
+    fn stop_recording(&mut self) -> Option<JoinHandle<()>> {
+        let recorder = self.recorder.take()?;
+        log::info!("Saving recording to {}", recorder.path().display());
+        self.osd.show("Recording saved");
+        Some(recorder.finish())
+    }
+
+    /// Saves the last frame from the core as a PNG, scaled up by `scale`
+    fn save_screenshot(&mut self, scale: u32) {
+        let Some(frame) = &self.last_frame else {
+            return;
+        };
+        match screenshot::save(&self.screenshot_dir, frame, scale) {
+            Ok(path) => {
+                log::info!("Saved screenshot to {}", path.display());
+                self.osd.show("Screenshot saved");
+            }
+            Err(e) => log::error!("Failed to save screenshot: {}", e),
+        }
+    }
+
+    /// With `pause_on_focus_loss`, pauses emulation while neither the main window nor the
+    /// linked Gameboy's window has focus, and resumes it when one gets focus back
+    fn check_focus(&mut self, ctx: &egui::Context) {
+        if !self.pause_on_focus_loss {
+            return;
+        }
+        let focused =
+            ctx.input(|i| i.raw.focused) || self.link.as_ref().map_or(false, |l| l.focused);
+        if focused != self.focus_paused {
+            return;
+        }
+        self.focus_paused = !focused;
+        if self.focus_paused {
+            self.audio_driver.silence();
+        } else {
+            // The window may not have been redrawn while in the background, so make sure
+            // emulation doesn't race to catch up with the time spent there
+            self.sync_clock();
+        }
+        self.update_title(ctx);
+    }
+
+    /// Moves the time baseline to the emulated time, so emulation carries on from now rather
+    /// than catching up with or waiting for the clock
+    fn sync_clock(&mut self) {
+        let real_time_ns = (self.emulated_cycles as f32 * CYCLE_TIME_NS / self.speed) as u64;
+        self.start_time = self
+            .audio_driver
+            .time_source()
+            .time_ns()
+            .saturating_sub(real_time_ns);
+        self.vsync_target_cycles = self.emulated_cycles;
+        self.vsync_pacer.reset();
+    }
+
+    /// Pauses or resumes emulation, showing the state in the window title
+    fn set_paused(&mut self, ctx: &egui::Context, paused: bool) {
+        self.paused = paused;
+        if paused {
+            self.audio_driver.silence();
+        }
+        self.update_title(ctx);
+    }
+
+    /// Sets the window title to the running game's title followed by the emulator's state,
+    /// e.g. "TETRIS - Gabe Emulator [60 FPS, Recording]". The title is only sent to the window
+    /// when it changes.
+    fn update_title(&mut self, ctx: &egui::Context) {
+        let mut title = match &self.game_title {
+            Some(game) => format!("{} - {}", game, WINDOW_TITLE),
+            None => WINDOW_TITLE.to_string(),
+        };
+        if self.emu.is_some() {
+            let mut status = vec![];
+            if self.paused || self.debugger.paused || self.focus_paused {
+                status.push("Paused".to_string());
+            } else {
+                status.push(format!("{:.0} FPS", self.stats.fps));
+                if self.fast_forwarding {
+                    status.push("Fast-forward".to_string());
+                }
+            }
+            if self.recorder.is_some() {
+                status.push("Recording".to_string());
+            }
+            title = format!("{} [{}]", title, status.join(", "));
+        }
+        if title != self.window_title {
+            ctx.send_viewport_cmd(ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    fn set_volume(&mut self, volume: u32) {
+        self.volume = volume;
+        self.muted = false;
+        self.apply_volume();
+        self.osd.show(format!("Volume {}%", volume));
+    }
+
+    /// Sends the volume to the audio driver, or silences it if muted
+    fn apply_volume(&mut self) {
+        let volume = if self.muted { 0 } else { self.volume };
+        self.audio_driver.set_volume(volume as f32 / 100.0);
+    }
+
+    /// Sends the selected palette to the running emulator.
+    /// Frames already drawn keep their colors until the next frame is rendered.
+    fn apply_palette(&mut self) {
+        if let Some(emu) = &mut self.emu {
+            emu.set_palette(self.palettes.current().1);
+        }
+        if let Some(link) = &mut self.link {
+            link.set_palette(self.palettes.current().1);
+        }
+    }
+
+    /// Sends the muted sound channels to the running emulator
+    fn apply_muted_channels(&mut self) {
+        if let Some(emu) = &mut self.emu {
+            for (channel, muted) in (1..=4).zip(self.muted_channels) {
+                emu.set_channel_muted(channel, muted);
+            }
+        }
+    }
+
+    /// Resizes the window so the frame area fits the screen at the given scale.
+    /// The window isn't resized in fullscreen, but the scale is used when leaving it.
+    fn set_scale(&mut self, ctx: &egui::Context, scale: u32) {
+        self.scale = scale;
+        if self.fullscreen {
+            return;
+        }
+        let inner_size = ctx.input(|i| i.viewport().inner_rect.map(|r| r.size()));
+        if let Some(inner_size) = inner_size {
+            // Keep the space used by the menu bar and margins, and resize the frame area
+            let chrome = inner_size - self.frame_area;
+            let size = chrome + SCREEN_SIZE * scale as f32;
+            ctx.send_viewport_cmd(ViewportCommand::InnerSize(size));
+        }
+    }
+
+    /// Loads a ROM and its save file, which is created next to the ROM or in the save directory
+    /// if missing, and starts running it. The ROM may be in a zip or gzip archive. Any running game is stopped first.
+    /// The first ROM loaded uses the save file given on the command line instead.
+    fn open_rom(&mut self, ctx: &egui::Context, path: &Path) {
+        self.stop_emulation(ctx);
+        // A netplay guest runs with the host's save data, and doesn't write it over its own
+        let host_save = self.netplay.as_mut().and_then(|n| n.host_save.take());
+        let save_file_override = self.save_file_on_start.take();
+        let result = archive::read_rom(path).and_then(|rom| {
+            if let Some(save_data) = host_save {
+                return Ok((rom.data, save_data, None));
+            }
+            let save_path =
+                save_file_override.unwrap_or_else(|| archive::save_path(&rom, &self.saves));
+            archive::create_save_dir(&save_path)?;
+            let mut save_file = OpenOptions::new()
+                .write(true)
+                .read(true)
+                .create(true)
+                .truncate(false)
+                .open(&save_path)
+                .map_err(|e| format!("{}: {}", save_path.display(), e))?;
+            let mut save_data = vec![];
+            save_file
+                .read_to_end(&mut save_data)
+                .map_err(|e| format!("{}: {}", save_path.display(), e))?;
+            Ok((rom.data, save_data, Some(save_file)))
+        });
+        let (rom_data, save_data, save_file) = match result {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                log::error!("Failed to load ROM: {}", e);
+                return;
+            }
+        };
+        // Named after the file when the header has no title, as with some homebrew
+        let game_title = archive::header_title(&rom_data)
+            .or_else(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()));
+        // The CGB colorizes games made only for the DMG, in place of the palette
+        self.colorized = self.model == gabe_core::gb::Model::Cgb
+            && rom_data.get(0x143).map_or(false, |flag| flag & 0x80 == 0);
+        let mut builder = gabe_core::gb::Gameboy::builder()
+            .rom_bytes(rom_data.into_boxed_slice())
+            .model(self.model)
+            .cgb_mode(self.cgb_mode)
+            .save_data(save_data.into_boxed_slice())
+            .cheats(self.cheats.iter().cloned());
+        if let Some(colorization) = self.colorization {
+            builder = builder.colorization(colorization);
+        }
+        if let Some((pixels, width, height)) = &self.camera_image {
+            let sensor = StillImage::new(pixels, *width, *height);
+            builder = builder.camera(Box::new(sensor));
+        }
+        let result = builder.build();
+        let mut emu = match result {
+            Ok(emu) => emu,
+            Err(e) => {
+                log::error!("Failed to load ROM: {}", e);
+                return;
+            }
+        };
+        self.rumble.start(&mut emu);
+        self.tilt.recenter();
+        self.recent_roms.add(path);
+        self.game_title = game_title;
+        if let Some(link_rom) = &self.link_mode {
+            let link_rom = link_rom.as_deref().unwrap_or(path);
+            match LinkedGameboy::start(
+                ctx,
+                &mut emu,
+                link_rom,
+                &self.saves,
+                self.model,
+                self.cgb_mode,
+            ) {
+                Ok(link) => self.link = Some(link),
+                Err(e) => log::error!("Failed to load the linked ROM: {}", e),
+            }
+        }
+        self.emu = Some(emu);
+        self.save_file = save_file;
+        if !self.colorized {
+            self.apply_palette();
+        }
+        self.apply_muted_channels();
+        if let Some(path) = self.record_on_load.take() {
+            self.start_recording(&path);
+        }
+        self.debugger.reset();
+        if let Some((addr, timeout_frames)) = self.run_until_on_start.take() {
+            self.debugger.run_until(addr, timeout_frames);
+        }
+        if let Some(path) = self.coverage_on_start.take() {
+            match disassembly::load_coverage(&path) {
+                Ok(coverage) => {
+                    if let Some(emu) = &mut self.emu {
+                        emu.start_coverage(coverage);
+                    }
+                    self.coverage_file = Some(path);
+                }
+                Err(e) => log::error!("Not recording coverage, {}", e),
+            }
+        }
+        self.audio_driver.play();
+        self.sync_clock();
+    }
+
+    /// At the start of each frame during netplay, sends the held buttons to the other player
+    /// and applies both players' buttons for the frame. Returns false if the other player's
+    /// buttons haven't arrived yet, and the frame has to wait.
+    fn start_netplay_frame(&mut self, ctx: &egui::Context) -> bool {
+        let (Some(netplay), Some(emu)) = (&mut self.netplay, &mut self.emu) else {
+            return true;
+        };
+        if self.netplay_frame_started {
+            return true;
         }
+        let local_buttons = if ctx.wants_keyboard_input() {
+            0
+        } else {
+            self.key_map.held_buttons(ctx)
+        };
+        match netplay.next_frame_input(local_buttons) {
+            Ok(Some(buttons)) => {
+                input::set_buttons(emu, buttons);
+                self.netplay_frame_started = true;
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                log::error!("{}, continuing alone", e);
+                self.netplay = None;
+                true
+            }
+        }
+    }
+
+    /// Tracks the end of each frame during netplay, after a step ran `cycles` cycles. While
+    /// the LCD is off no frames are drawn, so a frame's worth of cycles counts as a frame.
+    fn end_netplay_step(&mut self, cycles: u32, frame_drawn: bool) {
+        let (Some(netplay), Some(emu)) = (&mut self.netplay, &self.emu) else {
+            return;
+        };
+        self.netplay_frame_cycles += cycles as u64;
+        if frame_drawn || self.netplay_frame_cycles >= CYCLES_PER_FRAME {
+            self.netplay_frame_cycles = 0;
+            self.netplay_frame_started = false;
+            if let Err(e) = netplay.end_frame(emu) {
+                log::error!("{}, continuing alone", e);
+                self.netplay = None;
+            }
+        }
+    }
+
+    /// Resets the running game, as the Gameboy's power switch would, without reloading it.
+    /// The save data in the cartridge is kept.
+    fn reset_emulation(&mut self) {
+        let Some(emu) = &mut self.emu else {
+            return;
+        };
+        emu.reset();
+        if let Some(link) = &mut self.link {
+            link.reset();
+        }
+        self.debugger.reset();
+        self.osd.show("Reset");
+    }
+
+    /// Stops the running game, writing its save data to the save file
+    fn stop_emulation(&mut self, ctx: &egui::Context) {
+        if let Some(emu) = &mut self.emu {
+            // Stop all emulation, reset state
+            self.audio_driver.stop();
+            // Save the data to the save file, if valid
+            if let (Some(data), Some(save_file)) = (emu.get_save_data(), &mut self.save_file) {
+                if let Err(e) = save_file.rewind() {
+                    println! {"{}: No save file written.", e};
+                }
+                if let Err(e) = save_file.write_all(&data) {
+                    println! {"{}: Corrupt save file written.", e};
+                } else {
+                    self.osd.show("SRAM written");
+                }
+            }
+            if let Some(link) = self.link.take() {
+                link.save();
+            }
+            self.save_coverage();
+            self.rumble.stop();
+            // Setting to None drops the Gameboy object
+            self.emu = None;
+            self.game_title = None;
+            self.set_paused(ctx, false);
+            self.emulated_cycles = 0;
+            self.last_frame = None;
+            self.filtered_frame = None;
+            self.video_sink.clear();
+            self.stop_recording();
+            self.debugger.reset();
+            // Clear framebuffer
+            self.framebuffer
+                .set(ColorImage::default(), Default::default());
+        }
+    }
+
+    /// Writes the coverage recorded by the running game to the `--coverage` file, if recording
+    fn save_coverage(&mut self) {
+        let (Some(emu), Some(path)) = (&self.emu, self.coverage_file.take()) else {
+            return;
+        };
+        if let Some(coverage) = emu.coverage() {
+            if let Err(e) = disassembly::save_coverage(&path, coverage) {
+                log::error!("Failed to write coverage: {}", e);
+            }
+        }
+    }
+
+    /// Uploads the last filtered frame to the texture, with the OSD messages drawn over it
+    fn update_framebuffer(&mut self) {
+        let Some((pixels, size)) = &self.filtered_frame else {
+            return;
+        };
+        let mut pixels = pixels.clone();
+        self.osd.draw(&mut pixels, *size);
+        // The texture is scaled to the window on the GPU
+        let options = if self.smooth {
+            TextureOptions::LINEAR
+        } else {
+            TextureOptions::NEAREST
+        };
+        self.framebuffer
+            .set(ColorImage::from_rgb(*size, &pixels), options);
+    }
+
+    fn set_fullscreen(&mut self, ctx: &egui::Context, fullscreen: bool) {
+        self.fullscreen = fullscreen;
+        ctx.send_viewport_cmd(ViewportCommand::Fullscreen(fullscreen));
     }
 }
 
 impl eframe::App for GabeApp {
+    /// Called when the app is closing. Writes any coverage being recorded, and waits for any
+    /// recording to finish saving.
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.save_coverage();
+        if let Some(saving) = self.stop_recording() {
+            let _ = saving.join();
+        }
+    }
+
     /// Called each time the UI needs repainting, which may be many times per second.
     /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Menu Bar UI
-        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+        if let Some(path) = self.rom_on_start.take() {
+            self.open_rom(ctx, &path);
+        }
+        // Load a ROM dropped onto the window. If several files are dropped, only the first one
+        // is used.
+        let dropped = ctx.input(|i| i.raw.dropped_files.iter().find_map(|f| f.path.clone()));
+        if let Some(path) = dropped {
+            self.open_rom(ctx, &path);
+        }
+        self.handle_hotkeys(ctx);
+        self.check_focus(ctx);
+
+        // Menu Bar UI, hidden in fullscreen
+        egui::TopBottomPanel::top("top_panel").show_animated(ctx, !self.fullscreen, |ui| {
             // The top panel is often a good place for a menu bar:
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
                     if ui.button("Open File...").clicked() {
-                        if let Some(mut path) = rfd::FileDialog::new().pick_file() {
-                            let mut rom_file = std::fs::File::open(&path).unwrap();
-                            path.set_extension("sav");
-                            let mut save_file = OpenOptions::new()
-                                .write(true)
-                                .read(true)
-                                .create(true)
-                                .open(path)
-                                .unwrap();
-                            let mut rom_data = vec![];
-                            rom_file.read_to_end(&mut rom_data).unwrap();
-                            let mut save_data = vec![];
-                            save_file.read_to_end(&mut save_data).unwrap();
-                            self.emu = Some(gabe_core::gb::Gameboy::power_on(
-                                rom_data.into_boxed_slice(),
-                                Some(save_data.into_boxed_slice()),
-                            ));
-                            self.save_file = Some(save_file);
-                            self.audio_driver.play();
-                            self.start_time = self.audio_driver.time_source().time_ns();
+                        if let Some(path) = pick_rom() {
+                            self.open_rom(ctx, &path);
                         }
                         ui.close_menu();
                     }
+                    ui.add_enabled_ui(!self.recent_roms.paths().is_empty(), |ui| {
+                        ui.menu_button("Open Recent", |ui| {
+                            if let Some(path) = recent_rom_buttons(ui, &self.recent_roms) {
+                                self.open_rom(ctx, &path);
+                                ui.close_menu();
+                            }
+                        });
+                    });
                 });
                 ui.menu_button("Emulation", |ui| {
                     ui.add_enabled_ui(self.emu.is_some(), |ui| {
+                        let mut paused = self.paused;
+                        if ui.checkbox(&mut paused, "Pause (P)").clicked() {
+                            self.set_paused(ctx, paused);
+                            ui.close_menu();
+                        }
+                        // Only one side would be reset, desyncing netplay
+                        let reset = egui::Button::new("Reset");
+                        if ui.add_enabled(self.netplay.is_none(), reset).clicked() {
+                            self.reset_emulation();
+                            ui.close_menu();
+                        }
                         if ui.button("Stop").clicked() {
-                            if let Some(emu) = &mut self.emu {
-                                // Stop all emulation, reset state
-                                self.audio_driver.stop();
-                                // Save the data to the save file, if valid
-                                if let (Some(data), Some(save_file)) =
-                                    (emu.get_save_data(), &mut self.save_file)
-                                {
-                                    if let Err(e) = save_file.rewind() {
-                                        println! {"{}: No save file written.", e};
-                                    }
-                                    if let Err(e) = save_file.write_all(&data) {
-                                        println! {"{}: Corrupt save file written.", e};
-                                    }
-                                }
-                                // Setting to None drops the Gameboy object
-                                self.emu = None;
-                                self.emulated_cycles = 0;
-                                // Clear framebuffer
-                                self.framebuffer
-                                    .set(ColorImage::default(), Default::default());
-                            }
+                            self.stop_emulation(ctx);
                             ui.close_menu();
                         }
                     })
                 });
+                ui.menu_button("View", |ui| {
+                    if ui.button("Fullscreen (F11)").clicked() {
+                        self.set_fullscreen(ctx, true);
+                        ui.close_menu();
+                    }
+                    ui.menu_button("Scale (+/-)", |ui| {
+                        for scale in 1..=MAX_SCALE {
+                            let label = format!("{}x", scale);
+                            if ui.radio(self.scale == scale, label).clicked() {
+                                self.set_scale(ctx, scale);
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                    ui.checkbox(&mut self.integer_scaling, "Integer Scaling");
+                    ui.checkbox(&mut self.smooth, "Smooth Scaling");
+                    ui.add_enabled_ui(!self.colorized, |ui| {
+                        ui.menu_button("Palette (F2)", |ui| {
+                            let current = self.palettes.current().0.to_string();
+                            let mut selected = None;
+                            for (i, name) in self.palettes.names().enumerate() {
+                                if ui.radio(name == current, name).clicked() {
+                                    selected = Some(i);
+                                }
+                            }
+                            if let Some(i) = selected {
+                                self.palettes.select(i);
+                                self.apply_palette();
+                                ui.close_menu();
+                            }
+                        });
+                    });
+                    if ui
+                        .add_enabled(
+                            self.last_frame.is_some(),
+                            egui::Button::new("Screenshot (F12)"),
+                        )
+                        .clicked()
+                    {
+                        self.save_screenshot(1);
+                        ui.close_menu();
+                    }
+                    ui.menu_button("Filter (F3)", |ui| {
+                        for filter in Filter::ALL {
+                            if ui.radio(self.filter == filter, filter.name()).clicked() {
+                                self.filter = filter;
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                    ui.menu_button("Blend (F4)", |ui| {
+                        let current = self.video_sink.mode();
+                        for mode in BlendMode::ALL {
+                            if ui.radio(current == mode, mode.name()).clicked() {
+                                self.video_sink.set_mode(mode);
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                });
+                ui.menu_button("Audio", |ui| {
+                    let volume =
+                        ui.add(egui::Slider::new(&mut self.volume, 0..=100).text("Volume"));
+                    let mute = ui.checkbox(&mut self.muted, "Mute (M)");
+                    if volume.changed() || mute.changed() {
+                        self.apply_volume();
+                    }
+                    ui.label(format!("Latency: {:.0} ms", self.audio_driver.latency_ms()));
+                });
+                ui.menu_button("Debug", |ui| {
+                    ui.checkbox(&mut self.debugger.open, "Debugger");
+                });
             });
         });
 
+        self.debugger.poll_remote(&mut self.emu);
+
         // Main Render Panel
-        egui::CentralPanel::default().show(ctx, |ui| {
-            if let Some(emu) = &mut self.emu {
-                // Currently running a game
-                let mut video_sink = video_sinks::BlendVideoSink::new();
-                let mut audio_sink = SimpleAudioSink {
-                    inner: VecDeque::new(),
-                };
-                let time_source = self.audio_driver.time_source();
-                let mut audio_buffer_sink = self.audio_driver.sink();
-
-                let target_emu_time_ns = time_source.time_ns() - self.start_time;
-                let target_emu_cycles = (target_emu_time_ns as f32 / CYCLE_TIME_NS).floor() as u64;
-                while self.emulated_cycles < target_emu_cycles {
-                    self.emulated_cycles += emu.step(&mut video_sink, &mut audio_sink) as u64;
-
-                    if let Some(frame) = video_sink.get_frame() {
-                        self.framebuffer.set(
-                            ColorImage::from_rgb([160, 144], &frame),
-                            TextureOptions {
-                                magnification: egui::TextureFilter::Nearest,
-                                minification: egui::TextureFilter::Nearest,
-                            },
-                        );
+        // In fullscreen, letterbox the frame on black instead of the window background
+        let mut central_frame = Frame::central_panel(&ctx.style());
+        if self.fullscreen {
+            central_frame = central_frame.fill(Color32::BLACK);
+        }
+        egui::CentralPanel::default()
+            .frame(central_frame)
+            .show(ctx, |ui| {
+                self.frame_area = ui.available_size();
+                if self.emu.is_some() {
+                    // Currently running a game
+                    let mut audio_sink: Vec<AudioFrame> = Vec::new();
+                    let time_source = self.audio_driver.time_source();
+                    let mut audio_buffer_sink = self.audio_driver.sink();
+
+                    // While fast-forwarding, run as fast as possible instead of following the clock
+                    let fast_forward =
+                        !ctx.wants_keyboard_input() && self.key_map.fast_forward_held(ctx);
+                    if fast_forward && !self.fast_forwarding {
+                        self.osd.show("Fast-forward");
+                    }
+                    self.fast_forwarding = fast_forward;
+                    // The core skips drawing the frames that won't be shown, except while
+                    // recording, which needs every frame
+                    if let Some(emu) = self.emu.as_mut() {
+                        let frame_skip = if fast_forward && self.recorder.is_none() {
+                            self.fast_forward_frame_skip
+                        } else {
+                            0
+                        };
+                        emu.set_frame_skip(frame_skip);
+                    }
+                    let paused = self.paused || self.debugger.paused || self.focus_paused;
+                    if let Some(emu) = self.emu.as_mut() {
+                        let keys = if ctx.wants_keyboard_input() {
+                            (0.0, 0.0)
+                        } else {
+                            self.key_map.tilt_held(ctx)
+                        };
+                        self.tilt.update(keys, ctx.input(|i| i.stable_dt), emu);
+                    }
+                    let target_emu_cycles = match self.frame_pacing {
+                        FramePacing::Audio => {
+                            let target_emu_time_ns =
+                                (time_source.time_ns().saturating_sub(self.start_time) as f32
+                                    * self.speed) as u64;
+                            (target_emu_time_ns as f32 / CYCLE_TIME_NS).floor() as u64
+                        }
+                        FramePacing::Vsync => {
+                            if !paused {
+                                let dt = ctx.input(|i| i.stable_dt);
+                                let frames = self.vsync_pacer.frames_to_run(dt, self.speed);
+                                self.vsync_target_cycles += frames as u64 * CYCLES_PER_FRAME;
+                            }
+                            self.vsync_target_cycles
+                        }
+                    };
+                    let deadline = Instant::now() + FAST_FORWARD_BUDGET;
+                    let start_cycles = self.emulated_cycles;
+                    // Set when waiting for the other player's netplay input
+                    let mut stalled = false;
+                    // Set when a frame is ready to display
+                    let mut new_frame = false;
+                    while !paused
+                        && if fast_forward {
+                            Instant::now() < deadline
+                        } else {
+                            self.emulated_cycles < target_emu_cycles
+                        }
+                    {
+                        if !self.start_netplay_frame(ctx) {
+                            stalled = true;
+                            break;
+                        }
+                        // Re-borrowed every step, as debugger scripts temporarily take the emulator
+                        let Some(emu) = self.emu.as_mut() else {
+                            break;
+                        };
+                        let cycles = emu.step(&mut self.video_sink, &mut audio_sink);
+                        self.emulated_cycles += cycles as u64;
+                        if let Some(link) = &mut self.link {
+                            link.run_to(self.emulated_cycles);
+                        }
+                        // During netplay, buttons are only applied at the start of each frame
+                        if self.netplay.is_none() && !ctx.wants_keyboard_input() {
+                            self.key_map.update_key_states(ctx, emu);
+                        }
+
+                        let frame = self.video_sink.get_frame();
+                        self.end_netplay_step(cycles, frame.is_some());
+                        if let Some(frame) = frame {
+                            if let Some(recorder) = &mut self.recorder {
+                                if let Err(e) = recorder.append_frame(&frame) {
+                                    log::error!("Recording stopped: {}", e);
+                                    self.stop_recording();
+                                }
+                            }
+                            if !frame.skipped {
+                                self.filtered_frame = Some(self.filter.apply(&frame));
+                                new_frame = true;
+                            }
+                            self.last_frame = Some(frame);
+                            self.debugger.on_frame(&mut self.emu);
+                        }
+                        if self.debugger.should_break(&mut self.emu) {
+                            self.debugger.open = true;
+                            break;
+                        }
+                    }
+                    if let Some(emu) = self.emu.as_mut() {
+                        self.rumble.update(emu);
+                    }
+                    // Messages appearing or expiring also need the texture to be updated
+                    if self.osd.update() || new_frame {
+                        self.update_framebuffer();
+                    }
+                    if paused || fast_forward || stalled {
+                        // Resuming, releasing fast-forward or a netplay stall shouldn't try to
+                        // catch up or wait for the clock
+                        self.sync_clock();
+                    }
+                    // Audio is muted while fast-forwarding, as it's produced faster than it plays
+                    if !fast_forward {
+                        let samples = &audio_sink[..];
+                        let speed = match self.frame_pacing {
+                            FramePacing::Audio => self.speed,
+                            // Follow the buffer, as emulation only roughly keeps to real time
+                            FramePacing::Vsync => {
+                                self.speed * pacing::audio_rate(self.audio_driver.buffer_fill())
+                            }
+                        };
+                        if speed == 1.0 {
+                            audio_buffer_sink.append(samples);
+                        } else {
+                            self.speed_resampler.set_speed(speed);
+                            audio_buffer_sink.append(&self.speed_resampler.resample(samples));
+                        }
+                    }
+                    if let Some(recorder) = &mut self.recorder {
+                        if let Err(e) = recorder.append_audio(audio_sink.iter()) {
+                            log::error!("Recording stopped: {}", e);
+                            self.stop_recording();
+                        }
+                    }
+                    self.stats.record_frame(
+                        self.emulated_cycles - start_cycles,
+                        self.audio_driver.buffer_fill(),
+                    );
+                    self.update_title(ctx);
+                    // Scale to the largest size that keeps the aspect ratio, centered in the window
+                    let size = display_size(self.frame_area, self.integer_scaling);
+                    ui.centered_and_justified(|ui| {
+                        let (rect, image) = ui.allocate_exact_size(size, Sense::hover());
+                        Image::new(SizedTexture::from_handle(&self.framebuffer))
+                            .paint_at(ui, rect.translate(self.rumble.shake_offset()));
+                        if self.show_stats {
+                            ui.painter().text(
+                                image.rect.right_top() + Vec2::new(-8.0, 8.0),
+                                Align2::RIGHT_TOP,
+                                self.stats.text(),
+                                FontId::monospace(14.0),
+                                Color32::WHITE,
+                            );
+                        }
+                        if self.paused {
+                            ui.painter().text(
+                                image.rect.left_top() + Vec2::splat(8.0),
+                                Align2::LEFT_TOP,
+                                "Paused",
+                                FontId::proportional(20.0),
+                                Color32::WHITE,
+                            );
+                        }
+                        if self.netplay.as_ref().map_or(false, |n| n.desynced) {
+                            ui.painter().text(
+                                image.rect.left_bottom() + Vec2::new(8.0, -8.0),
+                                Align2::LEFT_BOTTOM,
+                                "Netplay desynced",
+                                FontId::proportional(20.0),
+                                Color32::RED,
+                            );
+                        }
+                    });
+                    ctx.request_repaint();
+                } else {
+                    // No game running, so offer a ROM to open
+                    let chosen = ui.vertical_centered(|ui| {
+                        ui.add_space(16.0);
+                        let mut chosen = None;
+                        if ui.button("Open ROM...").clicked() {
+                            chosen = pick_rom();
+                        }
+                        if !self.recent_roms.paths().is_empty() {
+                            ui.add_space(16.0);
+                            ui.label("Recent ROMs");
+                            chosen = chosen.or(recent_rom_buttons(ui, &self.recent_roms));
+                        }
+                        chosen
+                    });
+                    if let Some(path) = chosen.inner {
+                        self.open_rom(ctx, &path);
                     }
-                    update_key_states(ctx, emu);
                 }
-                audio_buffer_sink.append(audio_sink.inner.as_slices().0);
-                ui.add(
-                    Image::new(SizedTexture::from_handle(&self.framebuffer))
-                        .fit_to_fraction(Vec2::new(1.0, 1.0)),
-                );
-                ctx.request_repaint();
-            } else {
-                ui.heading("Use File->Open File to select and run a valid ROM file.");
+            });
+
+        // Closing the second Gameboy's window leaves the main one running alone
+        if let Some(link) = &mut self.link {
+            if !link.show(ctx, &self.key_map, self.smooth) {
+                link.save();
+                self.link = None;
+                self.link_mode = None;
+                if let Some(emu) = &mut self.emu {
+                    emu.disconnect_serial();
+                }
             }
-        });
-    }
-}
+        }
 
-fn update_key_states(ctx: &egui::Context, gb: &mut Gameboy) {
-    ctx.input(|i| {
-        gb.update_key_state(GbKeys::A, i.key_down(Key::X));
-        gb.update_key_state(GbKeys::B, i.key_down(Key::Z));
-        gb.update_key_state(GbKeys::Start, i.key_down(Key::Enter));
-        gb.update_key_state(GbKeys::Select, i.key_down(Key::Backspace));
-        gb.update_key_state(GbKeys::Up, i.key_down(Key::ArrowUp));
-        gb.update_key_state(GbKeys::Down, i.key_down(Key::ArrowDown));
-        gb.update_key_state(GbKeys::Left, i.key_down(Key::ArrowLeft));
-        gb.update_key_state(GbKeys::Right, i.key_down(Key::ArrowRight));
-    });
+        self.debugger.show(ctx, &mut self.emu);
+    }
 }
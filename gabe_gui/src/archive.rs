@@ -0,0 +1,201 @@
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+
+use crate::config::{SaveConfig, SaveNaming};
+
+/// File extensions of ROMs looked for inside archives
+const ROM_EXTENSIONS: &[&str] = &["gb", "gbc"];
+
+/// A ROM read from disk, decompressed if it was in an archive
+pub struct RomFile {
+    pub data: Vec<u8>,
+    /// Where the game's save data is kept: next to the ROM, named after the ROM itself rather
+    /// than its archive
+    pub save_path: PathBuf,
+}
+
+/// Reads a ROM file. `.zip` files are searched for the first `.gb` or `.gbc` entry, and `.gz`
+/// files are decompressed. Any other file is read as a ROM.
+pub fn read_rom(path: &Path) -> Result<RomFile, String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+    let bytes = fs::read(path).map_err(|e| error(&e))?;
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    let (data, rom_name) = match extension.as_deref() {
+        Some("zip") => read_zip(&bytes).map_err(|e| error(&e))?,
+        Some("gz") => {
+            let mut decoder = GzDecoder::new(&bytes[..]);
+            let mut data = vec![];
+            decoder.read_to_end(&mut data).map_err(|e| error(&e))?;
+            // Use the original file name stored in the header, or drop the .gz extension
+            let name = decoder
+                .header()
+                .and_then(|h| h.filename())
+                .map(|n| String::from_utf8_lossy(n).into_owned())
+                .or_else(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()));
+            (data, name)
+        }
+        _ => (bytes, None),
+    };
+    let save_path = match rom_name {
+        // Only the file name is used, in case the archive stores a directory or absolute path
+        Some(name) => {
+            let file_name = Path::new(&name)
+                .file_name()
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(name));
+            path.with_file_name(file_name).with_extension("sav")
+        }
+        None => path.with_extension("sav"),
+    };
+    Ok(RomFile { data, save_path })
+}
+
+/// Returns where a ROM's save file is kept: in the save directory if one is configured, and
+/// next to the ROM otherwise
+pub fn save_path(rom: &RomFile, saves: &SaveConfig) -> PathBuf {
+    let Some(dir) = &saves.dir else {
+        return rom.save_path.clone();
+    };
+    let name = match saves.naming {
+        SaveNaming::Title => header_name(&rom.data),
+        SaveNaming::FileName => None,
+    };
+    match name {
+        Some(name) => dir.join(format!("{}.sav", name)),
+        None => dir.join(rom.save_path.file_name().unwrap_or_default()),
+    }
+}
+
+/// Returns the title in a ROM's header, with anything after the first unprintable character
+/// left out, or None if it has no title
+pub fn header_title(data: &[u8]) -> Option<String> {
+    let title: String = data
+        .get(0x134..0x144)?
+        .iter()
+        .take_while(|&&b| b == b' ' || b.is_ascii_graphic())
+        .map(|&b| b as char)
+        .collect();
+    let title = title.trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+/// Returns a file name for a ROM made from the title and global checksum in its header, such
+/// as `TETRIS-3B8A`. Characters that can't be used in file names are replaced with `_`.
+/// Returns None if the header has no title.
+fn header_name(data: &[u8]) -> Option<String> {
+    let title = data.get(0x134..0x144)?;
+    let checksum = read_u16(data, 0x14E)?.swap_bytes();
+    let title: String = title
+        .iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| {
+            if b.is_ascii_alphanumeric() || b == b' ' || b == b'-' {
+                b as char
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let title = title.trim();
+    (!title.is_empty()).then(|| format!("{}-{:04X}", title, checksum))
+}
+
+/// Creates the directory a save file goes in, if it doesn't exist yet
+pub fn create_save_dir(save_path: &Path) -> Result<(), String> {
+    match save_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Extracts the first ROM in a zip archive, returning its data and name.
+/// Only stored and deflated entries are supported, which covers the archives ROMs come in.
+fn read_zip(bytes: &[u8]) -> Result<(Vec<u8>, Option<String>), String> {
+    const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+    const DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+    const LOCAL_HEADER: u32 = 0x0403_4b50;
+    let invalid = || "Invalid zip file".to_string();
+
+    // The end of central directory record is at the end of the file, before an optional
+    // comment of up to 64KB
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .take(0x10000 + 22)
+        .find(|i| read_u32(bytes, *i) == Some(END_OF_DIRECTORY))
+        .ok_or_else(invalid)?;
+    let entry_count = read_u16(bytes, end + 10).ok_or_else(invalid)?;
+    let mut entry = read_u32(bytes, end + 16).ok_or_else(invalid)? as usize;
+
+    for _ in 0..entry_count {
+        if read_u32(bytes, entry) != Some(DIRECTORY_ENTRY) {
+            return Err(invalid());
+        }
+        let field = |offset| read_u16(bytes, entry + offset).ok_or_else(invalid);
+        let method = field(10)?;
+        let compressed_size = read_u32(bytes, entry + 20).ok_or_else(invalid)? as usize;
+        let name_len = field(28)? as usize;
+        let extra_len = field(30)? as usize;
+        let comment_len = field(32)? as usize;
+        let header = read_u32(bytes, entry + 42).ok_or_else(invalid)? as usize;
+        let name = bytes
+            .get(entry + 46..entry + 46 + name_len)
+            .ok_or_else(invalid)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        entry += 46 + name_len + extra_len + comment_len;
+
+        let is_rom = Path::new(&name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map_or(false, |e| {
+                ROM_EXTENSIONS.contains(&e.to_lowercase().as_str())
+            });
+        if !is_rom {
+            continue;
+        }
+        // The local header has its own name and extra field lengths before the data
+        if read_u32(bytes, header) != Some(LOCAL_HEADER) {
+            return Err(invalid());
+        }
+        let local_name_len = read_u16(bytes, header + 26).ok_or_else(invalid)? as usize;
+        let local_extra_len = read_u16(bytes, header + 28).ok_or_else(invalid)? as usize;
+        let start = header + 30 + local_name_len + local_extra_len;
+        let compressed = bytes
+            .get(start..start + compressed_size)
+            .ok_or_else(invalid)?;
+        let data = match method {
+            0 => compressed.to_vec(),
+            8 => {
+                let mut data = vec![];
+                DeflateDecoder::new(compressed)
+                    .read_to_end(&mut data)
+                    .map_err(|e| e.to_string())?;
+                data
+            }
+            _ => return Err(format!("{} uses an unsupported compression method", name)),
+        };
+        return Ok((data, Some(name)));
+    }
+    Err("No .gb or .gbc file found in the zip file".to_string())
+}
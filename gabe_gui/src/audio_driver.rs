@@ -1,7 +1,7 @@
 use super::time_source::*;
 use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
-    Sample, SampleFormat,
+    BufferSize, Sample, SampleFormat, SupportedBufferSize,
 };
 use gabe_core::sink::*;
 use log::*;
@@ -17,6 +17,8 @@ struct SampleBuffer {
     count: usize,
     samples_read: u64,
     sample_rate: u32,
+    /// Master volume that samples are scaled by when output, from 0.0 to 1.0
+    volume: f32,
 }
 
 impl SampleBuffer {
@@ -92,12 +94,21 @@ impl SinkRef<[AudioFrame]> for AudioDriverSink {
     }
 }
 
+/// Smallest audio latency that can be requested, in milliseconds
+pub const MIN_LATENCY_MS: u32 = 10;
+/// Largest audio latency that can be requested, in milliseconds
+pub const MAX_LATENCY_MS: u32 = 1000;
+
 pub struct AudioDriver {
     buffer: Arc<Mutex<SampleBuffer>>,
     stream: cpal::Stream,
+    /// Latency of the sample buffer plus the device buffer, in milliseconds
+    latency_ms: f32,
 }
 
 impl AudioDriver {
+    /// Opens the default output device, buffering up to `latency_ms` of audio.
+    /// A quarter of the latency is requested as the device's buffer, within the range it supports.
     pub fn new(sample_rate: u32, latency_ms: u32) -> Self {
         // Set up audio device, use default device.
         let host = cpal::default_host();
@@ -130,11 +141,36 @@ impl AudioDriver {
         );
         info!("\t Device channels: {:?}", selected_config.channels());
 
-        let config = selected_config.config();
+        let mut config = selected_config.config();
+        let device_rate = config.sample_rate.0;
+        let requested_frames = device_rate * latency_ms / 4000;
+        let device_frames = match selected_config.buffer_size() {
+            SupportedBufferSize::Range { min, max } => {
+                let frames = requested_frames.clamp(*min, *max);
+                if frames != requested_frames {
+                    warn!(
+                        "Device buffer of {} frames is outside the supported range {}-{}, using {}",
+                        requested_frames, min, max, frames
+                    );
+                }
+                config.buffer_size = BufferSize::Fixed(frames);
+                Some(frames)
+            }
+            SupportedBufferSize::Unknown => None,
+        };
+        // If the device's buffer size is unknown, only the sample buffer is counted
+        let latency = latency_ms as f32
+            + device_frames.map_or(0.0, |f| f as f32 * 1000.0 / device_rate as f32);
+        match device_frames {
+            Some(frames) => info!("\t Device buffer: {} frames", frames),
+            None => info!("\t Device buffer: default"),
+        }
+        info!("\t Effective latency: {:.1} ms", latency);
         let audio_buffer = Arc::new(Mutex::new(SampleBuffer {
             inner: vec![0.0; buffer_samples].into_boxed_slice(),
             samples_read: 0,
             sample_rate,
+            volume: 1.0,
             count: 0,
             write_index: 0,
             read_index: 0,
@@ -149,9 +185,10 @@ impl AudioDriver {
                 &config,
                 move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
                     let mut buffer = read_audio_buffer.lock().unwrap();
+                    let volume = buffer.volume;
                     for frame in data.chunks_mut(2) {
                         for sample in frame.iter_mut() {
-                            *sample = resampler.next(&mut *buffer).to_sample();
+                            *sample = (resampler.next(&mut *buffer) * volume).to_sample();
                         }
                     }
                 },
@@ -162,9 +199,10 @@ impl AudioDriver {
                 &config,
                 move |data: &mut [i16], _: &cpal::OutputCallbackInfo| {
                     let mut buffer = read_audio_buffer.lock().unwrap();
+                    let volume = buffer.volume;
                     for frame in data.chunks_mut(2) {
                         for sample in frame.iter_mut() {
-                            *sample = resampler.next(&mut *buffer).to_sample();
+                            *sample = (resampler.next(&mut *buffer) * volume).to_sample();
                         }
                     }
                 },
@@ -175,9 +213,10 @@ impl AudioDriver {
                 &config,
                 move |data: &mut [u16], _: &cpal::OutputCallbackInfo| {
                     let mut buffer = read_audio_buffer.lock().unwrap();
+                    let volume = buffer.volume;
                     for frame in data.chunks_mut(2) {
                         for sample in frame.iter_mut() {
-                            *sample = resampler.next(&mut *buffer).to_sample();
+                            *sample = (resampler.next(&mut *buffer) * volume).to_sample();
                         }
                     }
                 },
@@ -191,6 +230,7 @@ impl AudioDriver {
         AudioDriver {
             buffer: audio_buffer,
             stream,
+            latency_ms: latency,
         }
     }
 
@@ -211,6 +251,31 @@ impl AudioDriver {
         self.stream.pause().unwrap();
     }
 
+    /// Returns the effective output latency in milliseconds
+    pub fn latency_ms(&self) -> f32 {
+        self.latency_ms
+    }
+
+    /// Sets the master volume, from 0.0 (silent) to 1.0 (full scale)
+    pub fn set_volume(&mut self, volume: f32) {
+        self.buffer.lock().unwrap().volume = volume.clamp(0.0, 1.0);
+    }
+
+    /// Discards any queued samples, so playback goes silent immediately.
+    /// Unlike `stop`, the stream keeps running and the time source stays valid.
+    pub fn silence(&mut self) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let samples_read = buffer.samples_read;
+        buffer.clear();
+        buffer.samples_read = samples_read;
+    }
+
+    /// Returns the fraction of the sample buffer currently filled, from 0.0 to 1.0
+    pub fn buffer_fill(&self) -> f32 {
+        let buffer = self.buffer.lock().unwrap();
+        buffer.count as f32 / buffer.inner.len() as f32
+    }
+
     /// Returns an AudioSink that receives audio frames to be passed along to the device.
     pub fn sink(&self) -> Box<dyn SinkRef<[AudioFrame]>> {
         Box::new(AudioDriverSink {
@@ -228,6 +293,39 @@ impl AudioDriver {
     }
 }
 
+/// Stretches audio produced at a multiple of real time back to the real-time sample rate,
+/// by dropping or repeating frames. This shifts the pitch along with the speed.
+pub struct SpeedResampler {
+    speed: f32,
+    /// Position of the next output frame within the input, carried between calls
+    position: f32,
+}
+
+impl SpeedResampler {
+    pub fn new(speed: f32) -> Self {
+        SpeedResampler {
+            speed,
+            position: 0.0,
+        }
+    }
+
+    /// Changes the speed of the audio given to `resample`
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    /// Resamples `input`, produced at `speed` times real time, to play back in real time
+    pub fn resample(&mut self, input: &[AudioFrame]) -> Vec<AudioFrame> {
+        let mut output = Vec::with_capacity((input.len() as f32 / self.speed) as usize + 1);
+        while (self.position as usize) < input.len() {
+            output.push(input[self.position as usize]);
+            self.position += self.speed;
+        }
+        self.position -= input.len() as f32;
+        output
+    }
+}
+
 /// Performs linear interpolation on audio samples
 /// Can upsample or downsample, depending on the provided sample rates
 struct LinearResampler {
@@ -0,0 +1,63 @@
+use std::{fs, path::Path};
+
+use gabe_core::cheats::{self, Cheat, CheatCode};
+use serde::Deserialize;
+
+use crate::config::Config;
+
+/// A cheat file: a TOML list of `[[cheat]]` tables
+#[derive(Deserialize)]
+struct CheatFile {
+    #[serde(default)]
+    cheat: Vec<CheatEntry>,
+}
+
+#[derive(Deserialize)]
+struct CheatEntry {
+    /// A Game Genie or GameShark code
+    code: String,
+    #[serde(default)]
+    label: String,
+    #[serde(default = "enabled_by_default")]
+    enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// Loads the cheats from a file, which lists each one as a `[[cheat]]` table with its `code`,
+/// and optionally a `label` and whether it's `enabled`, which it is by default. Files with a
+/// `.cht` extension are read as RetroArch cheat files instead.
+pub fn load(path: &Path) -> Result<Vec<Cheat>, String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+    let text = fs::read_to_string(path).map_err(|e| error(&e))?;
+    if matches!(path.extension(), Some(ext) if ext.eq_ignore_ascii_case("cht")) {
+        return cheats::parse_cht(&text).map_err(|e| error(&e));
+    }
+    let file: CheatFile = toml::from_str(&text).map_err(|e| error(&e))?;
+    file.cheat
+        .into_iter()
+        .map(|entry| {
+            let code = CheatCode::parse(&entry.code).map_err(|e| error(&e))?;
+            let label = if entry.label.is_empty() {
+                entry.code
+            } else {
+                entry.label
+            };
+            Ok(Cheat {
+                code,
+                label,
+                enabled: entry.enabled,
+            })
+        })
+        .collect()
+}
+
+/// Loads the cheat file in the config, if there is one
+pub fn from_config(config: &Config) -> Result<Vec<Cheat>, String> {
+    match &config.emulation.cheats {
+        Some(path) => load(path),
+        None => Ok(vec![]),
+    }
+}
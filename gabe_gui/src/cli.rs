@@ -0,0 +1,387 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::app::{MAX_SCALE, MAX_SPEED, MIN_SPEED};
+use crate::audio_driver::{MAX_LATENCY_MS, MIN_LATENCY_MS};
+use crate::cheats;
+use crate::config::{Config, FramePacing, Model, SaveNaming};
+use crate::debugger::parse_addr;
+use crate::disassembly::DisassemblyFormat;
+use crate::filters::Filter;
+use crate::input::{apply_mapping, KeyMap};
+use crate::palettes::PaletteList;
+use crate::video_sinks::BlendMode;
+
+/// Command line options, which override the settings in the config file
+#[derive(Parser)]
+#[command(name = "gabe", about = "A Gameboy emulator")]
+pub struct Args {
+    /// ROM file to run on startup
+    #[arg(value_name = "ROM")]
+    pub rom: Option<PathBuf>,
+
+    /// Save file for the ROM, instead of the one named after it next to it. Its directory is
+    /// created if needed.
+    #[arg(long, value_name = "FILE", requires = "rom")]
+    pub save_file: Option<PathBuf>,
+
+    /// Load Game Genie and GameShark codes from FILE, a TOML file with a `[[cheat]]` table for
+    /// each one giving its `code`, and optionally a `label` and whether it's `enabled`, or a
+    /// RetroArch `.cht` cheat file
+    #[arg(long, value_name = "FILE")]
+    pub cheats: Option<PathBuf>,
+
+    /// Show the picture in FILE, a PNG, to the Game Boy Camera, scaled to cover its 128x112
+    /// sensor. Without it the camera sees a blank gray.
+    #[arg(long, value_name = "FILE")]
+    pub camera_image: Option<PathBuf>,
+
+    /// Directory to keep all save files in, instead of next to each ROM
+    #[arg(long, value_name = "DIR")]
+    pub save_dir: Option<PathBuf>,
+
+    /// How save files in the save directory are named: after the ROM's file name, or after
+    /// the title and checksum in its header
+    #[arg(long, value_name = "NAMING", value_enum)]
+    pub save_naming: Option<SaveNaming>,
+
+    /// Config file to load instead of the default location
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Bind a key to a button, e.g. `--map W=up`. May be repeated.
+    /// Buttons are a, b, start, select, up, down, left, right, and the emulator
+    /// controls fast_forward, pause, volume_down, volume_up, mute, tilt_up, tilt_down,
+    /// tilt_left, tilt_right and tilt_center. Buttons of players 2
+    /// to 4, for Super Gameboy games with multiplayer, are prefixed with p2:, p3: or p4:.
+    #[arg(long = "map", value_name = "KEY=BUTTON")]
+    pub mappings: Vec<String>,
+
+    /// Start in fullscreen mode. Toggle with F11 while running.
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Initial window size as a multiple of the 160x144 screen. Change with +/- while running.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..=MAX_SCALE as i64))]
+    pub scale: Option<u32>,
+
+    /// Only scale the frame by whole multiples, avoiding uneven pixel sizes
+    #[arg(long)]
+    pub integer_scaling: bool,
+
+    /// Smooth the scaled frame with bilinear filtering instead of keeping sharp pixels
+    #[arg(long)]
+    pub smooth: bool,
+
+    /// Sync presentation to the display's refresh rate
+    #[arg(long)]
+    pub vsync: bool,
+
+    /// Time emulation by the audio device, or run frames in step with the display's refresh
+    /// for smoother motion. Vsync pacing turns on --vsync.
+    #[arg(long, value_name = "MODE", value_enum)]
+    pub frame_pacing: Option<FramePacing>,
+
+    /// Palette used to display the screen: grayscale, dmg, pocket, or one defined in the
+    /// config file. Cycle through them with F2 while running.
+    #[arg(long, value_name = "NAME")]
+    pub palette: Option<String>,
+
+    /// Upscaling filter applied to the screen. Cycle through them with F3 while running.
+    #[arg(long, value_name = "FILTER", value_enum)]
+    pub filter: Option<Filter>,
+
+    /// How frames are combined with the ones before them: raw, blend (the average of the last
+    /// two), persistence (an LCD's slowly fading pixels) or dmg (persistence on the DMG's green
+    /// screen). Cycle through them with F4 while running.
+    #[arg(long, value_name = "MODE", value_enum)]
+    pub blend: Option<BlendMode>,
+
+    /// Directory to save screenshots to. F12 saves the screen at its native size, and
+    /// Shift+F12 at the current window scale.
+    #[arg(long, value_name = "DIR")]
+    pub screenshot_dir: Option<PathBuf>,
+
+    /// Record video and audio to FILE, starting when the ROM is loaded. F9 starts and stops
+    /// recording while running. Requires ffmpeg.
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    /// Speed to run at as a multiple of real time, from 0.25 (slow motion) to 4
+    #[arg(long, value_name = "SPEED")]
+    pub speed: Option<f32>,
+
+    /// Pause emulation and silence the sound while the window doesn't have focus
+    #[arg(long)]
+    pub pause_on_focus_loss: bool,
+
+    /// Gameboy model to emulate. Only the DMG's hardware is emulated, but games can read
+    /// which model they're on and behave differently.
+    #[arg(long, value_name = "MODEL", value_enum)]
+    pub model: Option<Model>,
+
+    /// With `--model cgb`, tell games flagged as supporting the CGB that they're in CGB mode
+    /// instead of running them as on the DMG. Its own features still aren't emulated, so
+    /// only games that work without them run properly.
+    #[arg(long)]
+    pub cgb_mode: bool,
+
+    /// Colors games made only for the DMG are shown in with `--model cgb`, picked by the
+    /// buttons held as the CGB boots: up, up+a, up+b, left, left+a, left+b, down, down+a,
+    /// down+b, right, right+a (the default) or right+b
+    #[arg(long, value_name = "BUTTONS")]
+    pub colorization: Option<String>,
+
+    /// Master volume as a percentage, from 0 to 100
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u32).range(0..=100))]
+    pub volume: Option<u32>,
+
+    /// Start with the sound muted. Toggle with M while running.
+    #[arg(long)]
+    pub mute: bool,
+
+    /// Audio buffered ahead of playback, in milliseconds. Raise it if the sound crackles,
+    /// or lower it to reduce lag.
+    #[arg(long, value_name = "MS")]
+    pub audio_latency: Option<u32>,
+
+    /// Host a netplay session on PORT, waiting for the other player to connect before
+    /// starting. Both players' buttons are combined, and the host's save data is used.
+    #[arg(long, value_name = "PORT", requires = "rom")]
+    pub netplay_host: Option<u16>,
+
+    /// Join the netplay session hosted at ADDRESS (HOST:PORT), with the same ROM as the host
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        requires = "rom",
+        conflicts_with = "netplay_host"
+    )]
+    pub netplay_connect: Option<String>,
+
+    /// Frames between a button being pressed and it taking effect during netplay, giving
+    /// time for inputs to reach the other player. Set by the host.
+    #[arg(
+        long,
+        value_name = "FRAMES",
+        default_value = "2",
+        value_parser = clap::value_parser!(u8).range(0..=30)
+    )]
+    pub netplay_delay: u8,
+
+    /// Run a second Gameboy in its own window, connected to the first by a link cable, for
+    /// trading or two-player games. It runs ROM, or the same ROM as the first if not given,
+    /// and uses NAME.2.sav for its save data. The keys control whichever window has focus.
+    #[arg(
+        long,
+        value_name = "ROM",
+        num_args = 0..=1,
+        requires = "rom",
+        conflicts_with_all = ["netplay_host", "netplay_connect", "headless", "terminal"]
+    )]
+    pub link: Option<Option<PathBuf>>,
+
+    /// Run the ROM without a window or sound, as fast as possible
+    #[arg(long, requires = "rom")]
+    pub headless: bool,
+
+    /// With --headless or --terminal, stop after running this many frames. With --test-rom, the number of
+    /// frames to wait for a result, 3600 by default. With --reference, the frame to compare,
+    /// 600 by default.
+    #[arg(long, value_name = "N")]
+    pub frames: Option<u64>,
+
+    /// With --headless or --reference, press and release buttons as listed in FILE, with one
+    /// `FRAME BUTTON down|up` event per line
+    #[arg(long, value_name = "FILE")]
+    pub input: Option<PathBuf>,
+
+    /// Run until the CPU is about to execute the instruction at ADDR (hexadecimal, e.g. 0150),
+    /// then stop. With --headless, the registers are printed and the run fails if ADDR isn't
+    /// reached. Otherwise emulation pauses and the debugger opens.
+    #[arg(long, value_name = "ADDR", requires = "rom", value_parser = parse_hex_addr)]
+    pub run_until: Option<u16>,
+
+    /// With --run-until, give up after running this many frames without reaching the address
+    #[arg(long, value_name = "N", requires = "run_until")]
+    pub timeout_frames: Option<u64>,
+
+    /// With --headless and --run-until, write the 64 KiB address space to FILE when stopping,
+    /// as read by the CPU
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "run_until",
+        requires = "headless"
+    )]
+    pub dump_memory: Option<PathBuf>,
+
+    /// Run the ROM in the terminal, drawing the screen with colored half-block characters and
+    /// reading keys from stdin. Press Ctrl+C to quit.
+    #[arg(long, requires = "rom", conflicts_with_all = ["headless", "run_until"])]
+    pub terminal: bool,
+
+    /// Run N frames of the ROM as fast as possible without a window, then print the frame
+    /// rate and the time spent in each part of the emulator
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "rom",
+        conflicts_with_all = ["headless", "terminal", "run_until"]
+    )]
+    pub bench: Option<u64>,
+
+    /// With --bench, skip passing frames through the display filter
+    #[arg(long, requires = "bench")]
+    pub no_render: bool,
+
+    /// Run a test ROM without a window until it reports passing or failing, print its output,
+    /// and exit with 0 if it passed, 1 if it failed, or 2 if it timed out
+    #[arg(
+        long,
+        requires = "rom",
+        conflicts_with_all = ["headless", "terminal", "bench", "run_until"]
+    )]
+    pub test_rom: bool,
+
+    /// Run the ROM without a window for --frames frames, and compare the last frame to the
+    /// PNG at FILE, exiting with 1 if they differ. The frame is saved as FILE if it doesn't
+    /// exist yet.
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "rom",
+        conflicts_with_all = ["headless", "terminal", "bench", "test_rom", "run_until"]
+    )]
+    pub reference: Option<PathBuf>,
+
+    /// Disassemble every bank of the ROM into FILE instead of running it, with each line
+    /// giving the bank and address of its instruction, e.g. `01:4000`
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "rom",
+        conflicts_with_all = ["headless", "terminal", "bench", "test_rom", "reference", "run_until"]
+    )]
+    pub disassemble: Option<PathBuf>,
+
+    /// With --disassemble, write a listing, source for rgbasm, or JSON
+    #[arg(long, value_name = "FORMAT", value_enum, requires = "disassemble")]
+    pub disassembly_format: Option<DisassemblyFormat>,
+
+    /// Record which ROM instructions run into FILE, adding to the coverage it already holds,
+    /// and write it when the game stops. With --disassemble, use the coverage in FILE to tell
+    /// code from data and find which bank banked code runs in.
+    #[arg(long, value_name = "FILE", requires = "rom")]
+    pub coverage: Option<PathBuf>,
+
+    /// With --disassemble, name labels as listed in FILE, a symbol file with a `bank:address
+    /// name` line for each one, e.g. `01:4000 Main`
+    #[arg(long, value_name = "FILE", requires = "disassemble")]
+    pub symbols: Option<PathBuf>,
+}
+
+impl Args {
+    /// Loads the config file and applies the command line overrides to it.
+    /// Fails if the config file can't be read or contains invalid settings.
+    pub fn load_config(&self) -> Result<Config, String> {
+        let mut config = Config::load(self.config.as_deref())?;
+        for mapping in &self.mappings {
+            apply_mapping(&mut config.keys, mapping)?;
+        }
+        if self.fullscreen {
+            config.video.fullscreen = true;
+        }
+        if let Some(scale) = self.scale {
+            config.video.scale = scale;
+        }
+        if self.integer_scaling {
+            config.video.integer_scaling = true;
+        }
+        if self.smooth {
+            config.video.smooth = true;
+        }
+        if self.vsync {
+            config.video.vsync = true;
+        }
+        if let Some(pacing) = self.frame_pacing {
+            config.video.frame_pacing = pacing;
+        }
+        if config.video.frame_pacing == FramePacing::Vsync {
+            config.video.vsync = true;
+        }
+        if let Some(palette) = &self.palette {
+            config.video.palette = palette.clone();
+        }
+        if let Some(filter) = self.filter {
+            config.video.filter = filter;
+        }
+        if let Some(blend) = self.blend {
+            config.video.blend = blend;
+        }
+        if let Some(dir) = &self.screenshot_dir {
+            config.video.screenshot_dir = Some(dir.clone());
+        }
+        if let Some(dir) = &self.save_dir {
+            config.saves.dir = Some(dir.clone());
+        }
+        if let Some(naming) = self.save_naming {
+            config.saves.naming = naming;
+        }
+        if self.pause_on_focus_loss {
+            config.emulation.pause_on_focus_loss = true;
+        }
+        if let Some(model) = self.model {
+            config.emulation.model = model;
+        }
+        if self.cgb_mode {
+            config.emulation.cgb_mode = true;
+        }
+        if let Some(colorization) = &self.colorization {
+            config.emulation.colorization = Some(colorization.clone());
+        }
+        if let Some(volume) = self.volume {
+            config.audio.volume = volume;
+        }
+        if self.mute {
+            config.audio.muted = true;
+        }
+        if config.audio.volume > 100 {
+            return Err(format!(
+                "Volume must be between 0 and 100, got {}",
+                config.audio.volume
+            ));
+        }
+        if let Some(latency) = self.audio_latency {
+            config.audio.latency_ms = latency;
+        }
+        if !(MIN_LATENCY_MS..=MAX_LATENCY_MS).contains(&config.audio.latency_ms) {
+            return Err(format!(
+                "Audio latency must be between {} and {} ms, got {}",
+                MIN_LATENCY_MS, MAX_LATENCY_MS, config.audio.latency_ms
+            ));
+        }
+        if let Some(path) = &self.cheats {
+            config.emulation.cheats = Some(path.clone());
+        }
+        if let Some(speed) = self.speed {
+            config.emulation.speed = speed;
+        }
+        if !(MIN_SPEED..=MAX_SPEED).contains(&config.emulation.speed) {
+            return Err(format!(
+                "Speed must be between {} and {}, got {}",
+                MIN_SPEED, MAX_SPEED, config.emulation.speed
+            ));
+        }
+        // Check the key and palette names from the config file are valid
+        KeyMap::from_config(&config.keys)?;
+        PaletteList::from_config(&config)?;
+        cheats::from_config(&config)?;
+        Ok(config)
+    }
+}
+
+fn parse_hex_addr(s: &str) -> Result<u16, String> {
+    parse_addr(s).ok_or_else(|| format!("Invalid address: {}", s))
+}
@@ -0,0 +1,341 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use gabe_core::palette::Colorization;
+use serde::{Deserialize, Serialize};
+
+use crate::filters::Filter;
+use crate::video_sinks::BlendMode;
+
+/// Name of the config file within the gabe config directory
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// User settings, loaded from a TOML file.
+/// Every field has a default, so the file only needs to contain the settings being changed.
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub keys: KeyConfig,
+    pub video: VideoConfig,
+    pub emulation: EmulationConfig,
+    pub audio: AudioConfig,
+    pub saves: SaveConfig,
+    /// User-defined palettes, keyed by name. Each is four `#RRGGBB` colors from lightest to
+    /// darkest.
+    pub palettes: BTreeMap<String, [String; 4]>,
+}
+
+/// Settings for how frames are displayed
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VideoConfig {
+    /// Start in fullscreen mode
+    pub fullscreen: bool,
+    /// Initial window size, as a multiple of the Gameboy's 160x144 screen
+    pub scale: u32,
+    /// Only scale the frame by whole multiples, so pixels are all the same size
+    pub integer_scaling: bool,
+    /// Smooth the frame with bilinear filtering when it's scaled on the GPU, instead of keeping
+    /// sharp pixels
+    pub smooth: bool,
+    /// Wait for the display's refresh before presenting each frame, to avoid tearing
+    pub vsync: bool,
+    /// What emulation is timed by. Vsync pacing turns on `vsync`.
+    pub frame_pacing: FramePacing,
+    /// Name of the palette used to display the gray shades
+    pub palette: String,
+    /// Upscaling filter applied to frames before they are displayed
+    pub filter: Filter,
+    /// How frames are combined with the ones before them, to smooth over flickering
+    pub blend: BlendMode,
+    /// Directory screenshots are saved to, the current directory by default
+    pub screenshot_dir: Option<PathBuf>,
+    /// Frames skipped for each frame drawn while fast-forwarding
+    pub fast_forward_frame_skip: u32,
+    /// Show the frame rate, emulation speed and audio buffer overlay
+    pub show_stats: bool,
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        VideoConfig {
+            fullscreen: false,
+            scale: 4,
+            integer_scaling: false,
+            smooth: false,
+            vsync: false,
+            frame_pacing: FramePacing::Audio,
+            palette: "grayscale".to_string(),
+            filter: Filter::None,
+            blend: BlendMode::Blend,
+            screenshot_dir: None,
+            fast_forward_frame_skip: 3,
+            show_stats: false,
+        }
+    }
+}
+
+/// What the emulator follows to run in real time
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum FramePacing {
+    /// Run as much as the audio device has played, which keeps the sound steady but may show
+    /// a frame twice now and then on a 60 Hz display
+    #[default]
+    Audio,
+    /// Run frames in step with the display's refresh, adjusting the audio's playback rate to
+    /// keep it in sync
+    Vsync,
+}
+
+/// The Gameboy hardware emulated, see `gabe_core::gb::Model`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Model {
+    /// The original Gameboy
+    #[default]
+    Dmg,
+    /// The Gameboy Pocket
+    Mgb,
+    /// The Super Gameboy
+    Sgb,
+    /// The Gameboy Color, running games in DMG compatibility mode
+    Cgb,
+}
+
+impl From<Model> for gabe_core::gb::Model {
+    fn from(model: Model) -> Self {
+        match model {
+            Model::Dmg => gabe_core::gb::Model::Dmg,
+            Model::Mgb => gabe_core::gb::Model::Mgb,
+            Model::Sgb => gabe_core::gb::Model::Sgb,
+            Model::Cgb => gabe_core::gb::Model::Cgb,
+        }
+    }
+}
+
+/// What the motor of rumble cartridges drives
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum RumbleMode {
+    /// Force feedback on the connected gamepads
+    #[default]
+    Gamepad,
+    /// Shaking the screen
+    Shake,
+    /// Nothing
+    Off,
+}
+
+/// Settings for how the emulator runs
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EmulationConfig {
+    /// Speed to run at, as a multiple of real time
+    pub speed: f32,
+    /// Cheat file applied to the games loaded
+    pub cheats: Option<PathBuf>,
+    /// Pause and silence emulation while the window doesn't have focus
+    pub pause_on_focus_loss: bool,
+    /// Gameboy model the games are told they're running on
+    pub model: Model,
+    /// Tell games flagged as supporting the CGB that they're in CGB mode with the CGB model,
+    /// rather than running them as on the DMG
+    pub cgb_mode: bool,
+    /// What the motor of rumble cartridges drives
+    pub rumble: RumbleMode,
+    /// Colors games made only for the DMG are shown in on the CGB model, named by the buttons
+    /// held to pick them as the CGB boots, such as "left+a". The CGB's default colors if unset.
+    pub colorization: Option<String>,
+}
+
+impl EmulationConfig {
+    /// Returns the colorization named, if one is
+    pub fn colorization(&self) -> Result<Option<Colorization>, String> {
+        self.colorization
+            .as_deref()
+            .map(|name| {
+                Colorization::for_buttons(name)
+                    .ok_or_else(|| format!("Unknown colorization: {}", name))
+            })
+            .transpose()
+    }
+}
+
+impl Default for EmulationConfig {
+    fn default() -> Self {
+        EmulationConfig {
+            speed: 1.0,
+            cheats: None,
+            pause_on_focus_loss: false,
+            model: Model::Dmg,
+            cgb_mode: false,
+            rumble: RumbleMode::Gamepad,
+            colorization: None,
+        }
+    }
+}
+
+/// Settings for sound output
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// Master volume, as a percentage from 0 to 100
+    pub volume: u32,
+    /// Start with the sound muted
+    pub muted: bool,
+    /// Audio buffered ahead of playback, in milliseconds. Lower values reduce lag, but may
+    /// crackle on slower machines.
+    pub latency_ms: u32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            volume: 100,
+            muted: false,
+            latency_ms: 100,
+        }
+    }
+}
+
+/// Settings for where save files are kept
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SaveConfig {
+    /// Directory all save files are kept in, instead of next to each ROM. Useful when ROMs are
+    /// in a read-only folder or on removable media.
+    pub dir: Option<PathBuf>,
+    /// How save files in `dir` are named
+    pub naming: SaveNaming,
+}
+
+/// How save files in the save directory are named
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SaveNaming {
+    /// After the ROM's file name, like saves kept next to the ROM
+    #[default]
+    FileName,
+    /// After the title and checksum in the cartridge header, so a game keeps its save when the
+    /// ROM is renamed
+    Title,
+}
+
+/// Names of the keyboard keys bound to each Gameboy button.
+/// Names are those shown by egui, e.g. "X", "Enter", "Up" or "Num1".
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KeyConfig {
+    pub a: String,
+    pub b: String,
+    pub start: String,
+    pub select: String,
+    pub up: String,
+    pub down: String,
+    pub left: String,
+    pub right: String,
+    /// Held to run the emulator as fast as possible
+    pub fast_forward: String,
+    /// Pauses and resumes emulation
+    pub pause: String,
+    pub volume_down: String,
+    pub volume_up: String,
+    /// Toggles muting the sound
+    pub mute: String,
+    /// Tilt the Gameboy for games with an accelerometer: up tilts the top away, and right
+    /// tilts the right side down
+    pub tilt_up: String,
+    pub tilt_down: String,
+    pub tilt_left: String,
+    pub tilt_right: String,
+    /// Levels the Gameboy, taking the gamepad stick's resting position as level
+    pub tilt_center: String,
+    /// Keys for players 2 to 4, in `[keys.player2]` and so on
+    pub player2: PlayerKeyConfig,
+    pub player3: PlayerKeyConfig,
+    pub player4: PlayerKeyConfig,
+}
+
+/// Names of the keyboard keys bound to the buttons of another player, read by Super Gameboy
+/// games with multiplayer support. Buttons without a key are never held.
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PlayerKeyConfig {
+    pub a: Option<String>,
+    pub b: Option<String>,
+    pub start: Option<String>,
+    pub select: Option<String>,
+    pub up: Option<String>,
+    pub down: Option<String>,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        KeyConfig {
+            a: "X".to_string(),
+            b: "Z".to_string(),
+            start: "Enter".to_string(),
+            select: "Backspace".to_string(),
+            up: "Up".to_string(),
+            down: "Down".to_string(),
+            left: "Left".to_string(),
+            right: "Right".to_string(),
+            fast_forward: "Tab".to_string(),
+            pause: "P".to_string(),
+            volume_down: "F7".to_string(),
+            volume_up: "F8".to_string(),
+            mute: "M".to_string(),
+            tilt_up: "I".to_string(),
+            tilt_down: "K".to_string(),
+            tilt_left: "J".to_string(),
+            tilt_right: "L".to_string(),
+            tilt_center: "C".to_string(),
+            player2: PlayerKeyConfig::default(),
+            player3: PlayerKeyConfig::default(),
+            player4: PlayerKeyConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config from `path`, or from the default location if no path is given.
+    /// A missing default config file is not an error, the defaults are used instead.
+    pub fn load(path: Option<&Path>) -> Result<Config, String> {
+        let (path, required) = match path {
+            Some(p) => (p.to_path_buf(), true),
+            None => match default_path() {
+                Some(p) => (p, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if required || e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("{}: {}", path.display(), e))
+            }
+            Err(_) => Ok(Config::default()),
+        }
+    }
+}
+
+/// Returns the default config file location: `$XDG_CONFIG_HOME/gabe/config.toml`,
+/// falling back to `~/.config/gabe/config.toml`, or `%APPDATA%\gabe\config.toml` on Windows.
+pub fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join(CONFIG_FILE_NAME))
+}
+
+/// Returns the gabe config directory: `$XDG_CONFIG_HOME/gabe`, falling back to
+/// `~/.config/gabe`, or `%APPDATA%\gabe` on Windows
+pub fn config_dir() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    Some(dir.join("gabe"))
+}
@@ -0,0 +1,116 @@
+use gabe_core::debug::Registers;
+use gabe_core::gb::Gameboy;
+
+/// Evaluates a debugger expression against the emulator state.
+///
+/// Expressions are a sum of terms separated by `+` or `-`, where a term is one of:
+/// - a register name (a, f, b, c, d, e, h, l, af, bc, de, hl, sp, pc)
+/// - a hexadecimal number, with an optional 0x or $ prefix. Numbers that are also register
+///   names, such as `a`, must use a prefix.
+/// - `[expr]` to read the byte in memory at the address expr evaluates to
+///
+/// Arithmetic wraps at 16 bits, e.g. `[hl+1]` or `[ff00+c]`.
+pub fn evaluate(expr: &str, emu: &Gameboy) -> Result<u16, String> {
+    let reg = emu.get_debug_state().cpu_data.reg;
+    let mut parser = Parser {
+        input: expr,
+        pos: 0,
+        reg: &reg,
+        emu,
+    };
+    let value = parser.sum()?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(value),
+        Some(c) => Err(format!("Unexpected '{}' in expression", c)),
+    }
+}
+
+/// Returns the value of the named register, or `None` if the name is not a register
+pub fn get_register(reg: &Registers, name: &str) -> Option<u16> {
+    let pair = |hi: u8, lo: u8| (hi as u16) << 8 | lo as u16;
+    Some(match name.to_lowercase().as_str() {
+        "a" => reg.a as u16,
+        "f" => reg.f as u16,
+        "b" => reg.b as u16,
+        "c" => reg.c as u16,
+        "d" => reg.d as u16,
+        "e" => reg.e as u16,
+        "h" => reg.h as u16,
+        "l" => reg.l as u16,
+        "af" => pair(reg.a, reg.f),
+        "bc" => pair(reg.b, reg.c),
+        "de" => pair(reg.d, reg.e),
+        "hl" => pair(reg.h, reg.l),
+        "sp" => reg.sp,
+        "pc" => reg.pc,
+        _ => return None,
+    })
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    reg: &'a Registers,
+    emu: &'a Gameboy,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    /// sum := term (('+' | '-') term)*
+    fn sum(&mut self) -> Result<u16, String> {
+        let mut value = self.term()?;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('+') => {
+                    self.pos += 1;
+                    value = value.wrapping_add(self.term()?);
+                }
+                Some('-') => {
+                    self.pos += 1;
+                    value = value.wrapping_sub(self.term()?);
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    /// term := '[' sum ']' | register | number
+    fn term(&mut self) -> Result<u16, String> {
+        self.skip_whitespace();
+        if self.peek() == Some('[') {
+            self.pos += 1;
+            let addr = self.sum()? as usize;
+            self.skip_whitespace();
+            if self.peek() != Some(']') {
+                return Err("Missing ']' in expression".to_string());
+            }
+            self.pos += 1;
+            return Ok(self.emu.get_memory_range(addr..addr + 1)[0] as u16);
+        }
+        let start = self.pos;
+        while self
+            .peek()
+            .map_or(false, |c| c.is_ascii_alphanumeric() || c == '$')
+        {
+            self.pos += 1;
+        }
+        let word = &self.input[start..self.pos];
+        if word.is_empty() {
+            return Err("Expected a register, number or '[' in expression".to_string());
+        }
+        get_register(self.reg, word)
+            .or_else(|| super::parse_addr(word))
+            .ok_or_else(|| format!("Unknown register or invalid number: {}", word))
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+/// Maximum number of commands kept in the history
+const MAX_HISTORY: usize = 500;
+
+/// Name of the file, in the user's home directory, that history is persisted to
+const HISTORY_FILE_NAME: &str = ".gabe_history";
+
+/// History of commands entered into the debugger console.
+/// Persisted to a file so previous commands are available across sessions, and
+/// navigable with the arrow keys similar to a shell.
+pub struct History {
+    entries: Vec<String>,
+    /// Index into `entries` while browsing, `None` when editing a new line
+    position: Option<usize>,
+    /// The line being edited before browsing began, restored when browsing past the newest entry
+    pending: String,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// Loads the history from the history file in the home directory, if it exists. Commands
+    /// are appended to the file as they're entered, so once it holds more than
+    /// `MAX_HISTORY` it's rewritten with the most recent ones.
+    pub fn load() -> Self {
+        let path = std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(HISTORY_FILE_NAME));
+        let mut entries: Vec<String> = vec![];
+        let mut truncated = false;
+        if let Some(file) = path.as_ref().and_then(|p| File::open(p).ok()) {
+            entries = BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter(|l| !l.trim().is_empty())
+                .collect();
+            let excess = entries.len().saturating_sub(MAX_HISTORY);
+            entries.drain(..excess);
+            truncated = excess > 0;
+        }
+        let history = History {
+            entries,
+            position: None,
+            pending: String::new(),
+            path,
+        };
+        if truncated {
+            history.save();
+        }
+        history
+    }
+
+    /// Writes every entry to the history file, replacing what it held
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = File::create(path).and_then(|mut f| {
+            self.entries
+                .iter()
+                .try_for_each(|entry| writeln!(f, "{}", entry))
+        });
+        if let Err(e) = result {
+            log::warn!("Unable to write debugger history to {:?}: {}", path, e);
+        }
+    }
+
+    /// Adds a command to the history and appends it to the history file.
+    /// Repeats of the most recent command are not added.
+    pub fn push(&mut self, line: &str) {
+        self.position = None;
+        let line = line.trim();
+        if line.is_empty() || self.entries.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.remove(0);
+        }
+        if let Some(path) = &self.path {
+            let result = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut f| writeln!(f, "{}", line));
+            if let Err(e) = result {
+                log::warn!("Unable to write debugger history to {:?}: {}", path, e);
+            }
+        }
+    }
+
+    /// Moves to the previous (older) entry, returning the line to show
+    pub fn previous(&mut self, current: &str) -> Option<&str> {
+        let index = match self.position {
+            Some(0) => return None,
+            Some(i) => i - 1,
+            None => {
+                self.pending = current.to_string();
+                self.entries.len().checked_sub(1)?
+            }
+        };
+        self.position = Some(index);
+        Some(&self.entries[index])
+    }
+
+    /// Moves to the next (newer) entry, returning the line to show.
+    /// Moving past the newest entry restores the line being edited before browsing.
+    pub fn next(&mut self) -> Option<&str> {
+        let index = self.position?;
+        if index + 1 < self.entries.len() {
+            self.position = Some(index + 1);
+            Some(&self.entries[index + 1])
+        } else {
+            self.position = None;
+            Some(&self.pending)
+        }
+    }
+}
@@ -0,0 +1,1172 @@
+mod expr;
+mod history;
+mod panes;
+mod remote;
+mod script;
+mod search;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use egui::text::{CCursor, CCursorRange};
+use egui::{Id, Key, Modifiers, ScrollArea, TextEdit, TextStyle};
+use gabe_core::cheats::{Cheat, CheatCode};
+use gabe_core::debug::InterruptKind;
+use gabe_core::disassemble::{
+    decode_memory, format_memory, BankedAddress, CodeMap, CrossReference, RefKind,
+};
+use gabe_core::gb::Gameboy;
+use serde_json::{json, Value};
+
+use history::History;
+use panes::MemoryView;
+use remote::RemoteServer;
+use script::{Hook, ScriptHost};
+use search::{Filter, RamSearch};
+
+use crate::disassembly::load_symbols;
+
+/// LD B,B, used by homebrew as a software breakpoint
+const OPCODE_LD_B_B: u8 = 0x40;
+/// LD D,D, used by homebrew to mark a debug message block
+const OPCODE_LD_D_D: u8 = 0x52;
+/// JR n, used to jump over the text of a debug message block
+const OPCODE_JR: u8 = 0x18;
+
+/// Maximum number of lines kept in the console output
+const MAX_OUTPUT_LINES: usize = 1000;
+
+/// Full names of all commands, used for tab completion
+const COMMANDS: &[&str] = &[
+    "help",
+    "continue",
+    "step",
+    "break",
+    "delete",
+    "regs",
+    "mem",
+    "ints",
+    "breakint",
+    "breakbank",
+    "display",
+    "undisplay",
+    "traps",
+    "cheat",
+    "remote",
+    "script",
+    "symbols",
+    "xref",
+    "search",
+];
+
+/// Commands taking an address, which can be given as a symbol name, completed with the
+/// names of the loaded symbols
+const ADDRESS_COMMANDS: &[&str] = &["break", "b", "delete", "d", "xref"];
+
+/// Subcommands of the `script` command, used for tab completion
+const SCRIPT_COMMANDS: &[&str] = &["load", "list", "clear"];
+
+/// Subcommands of the `symbols` command, used for tab completion
+const SYMBOL_COMMANDS: &[&str] = &["load", "clear"];
+
+/// Subcommands of the `cheat` command, used for tab completion
+const CHEAT_COMMANDS: &[&str] = &["add", "on", "off"];
+
+/// Subcommands of the `search` command, used for tab completion
+const SEARCH_COMMANDS: &[&str] = &[
+    "start",
+    "equal",
+    "changed",
+    "increased",
+    "decreased",
+    "value",
+    "list",
+];
+
+/// Most addresses listed after a search step, so the first steps don't flood the console
+const MAX_SEARCH_LISTED: usize = 20;
+
+/// Subcommands of the `remote` command, used for tab completion
+const REMOTE_COMMANDS: &[&str] = &["start", "stop"];
+
+/// Names accepted by `parse_interrupt`, used for tab completion
+const INTERRUPT_NAMES: &[&str] = &["vblank", "stat", "timer", "serial", "joypad"];
+
+const HELP_TEXT: &str = "\
+Commands:
+  help                 Show this message
+  c, continue          Resume emulation
+  s, step [n]          Execute n instructions (default 1)
+  b, break [bank:]<addr>
+                       Add a breakpoint at addr. Lists breakpoints if no addr
+                       is given. With a bank, only breaks when that bank is
+                       mapped, e.g. 'break 5:4123' for ROM bank 5. A loaded
+                       symbol's name can be given instead.
+  d, delete [bank:]<addr>
+                       Remove the breakpoint at addr. Without a bank, removes
+                       breakpoints at addr in all banks.
+  r, regs              Print the CPU registers
+  x, mem <addr> [len]  Print len bytes of memory starting at addr (default 16)
+  ints                 Print the recent interrupt requests and dispatches
+  breakint [kind]      Toggle breaking when an interrupt vector is entered.
+                       kind is a name (vblank, stat, timer, serial, joypad)
+                       or a vector address (40, 48, 50, 58, 60). Lists active
+                       interrupt breaks if no kind is given.
+  breakbank            Toggle breaking when the mapped ROM or RAM bank changes
+  display [expr]       Print expr every time emulation pauses. Lists the
+                       display expressions if no expr is given.
+  undisplay <n>        Remove display expression number n
+  traps                Toggle debug traps: pause before executing LD B,B and
+                       print the debug message of LD D,D message blocks
+  cheat                List the cheats, numbered, and whether they're on
+  cheat add <code> [label]
+                       Add a Game Genie or GameShark code, turned on
+  cheat on|off <n>     Turn cheat number n on or off
+  remote start [port]  Accept debugger commands as JSON over TCP on localhost
+                       (default port 8765)
+  remote stop          Stop the remote debugger server
+  remote               Show the remote debugger status
+  script load <path>   Load a Rhai script, replacing it if already loaded
+  script list          List the loaded scripts and the hooks they define
+  script clear         Unload all scripts
+  symbols load <path>  Load the names in a .sym file, replacing any loaded,
+                       to use for breakpoints and to show where the PC is
+  symbols clear        Unload all symbols
+  symbols              Show how many symbols are loaded
+  xref [bank:]<addr>   List the instructions in the ROM that call or jump to
+                       addr, or read or write it directly. A loaded symbol's
+                       name can be given instead.
+  search start         Start a cheat search, snapshotting every bank of the
+                       cartridge RAM, the work RAM and the high RAM
+  search equal|changed|increased|decreased
+                       Keep the addresses whose byte compares so with the
+                       last snapshot, then snapshot them again
+  search value <n>     Keep the addresses whose byte is now n, in decimal
+                       or in hex with 0x or $
+  search [list]        List the addresses left, with their values
+Addresses are hexadecimal, with an optional 0x or $ prefix.
+Expressions add or subtract registers, numbers and [expr] memory reads,
+e.g. 'display [hl+1]'. Numbers that are also register names need a prefix.
+Use the up/down arrows to browse command history, and tab to complete.";
+
+/// A command-based debugger, shown as a window next to the emulator with panes for the
+/// registers, disassembly around the PC and memory above the command console.
+/// Tracks breakpoints and pause state, and is checked by the run loop after
+/// every emulator step to determine if emulation should halt.
+pub struct Debugger {
+    /// Whether the debugger window is shown
+    pub open: bool,
+    /// Whether emulation is halted waiting on debugger commands
+    pub paused: bool,
+    /// Instructions left to execute before pausing again, when stepping
+    steps_remaining: u32,
+    breakpoints: Vec<Breakpoint>,
+    interrupt_breaks: Vec<InterruptKind>,
+    /// Total interrupt events seen when last checking for interrupt breaks
+    seen_interrupts: u64,
+    /// Whether to break when the mapped cartridge banks change
+    bank_break: bool,
+    /// Whether the LD B,B and LD D,D debug trap conventions are handled
+    traps: bool,
+    /// Expressions printed every time emulation pauses
+    displays: Vec<String>,
+    /// ROM and RAM banks mapped when last checking for bank breaks
+    last_banks: Option<(u16, u8)>,
+    /// Address to run until, from `--run-until`, with the frames left before giving up
+    run_until: Option<(u16, Option<u64>)>,
+    /// Set once the CPU has locked up and the error has been reported, so it only breaks once
+    fault_reported: bool,
+    output: Vec<String>,
+    input: String,
+    history: History,
+    scripts: ScriptHost,
+    memory_view: MemoryView,
+    remote: Option<RemoteServer>,
+    /// Paused state last reported to remote clients
+    remote_paused: bool,
+    /// Collects printed lines while running a remote command, to send in the response
+    capture: Option<Vec<String>>,
+    /// Names loaded from a symbol file
+    symbols: BTreeMap<BankedAddress, String>,
+    /// The cheat search in progress, if one's been started
+    search: Option<RamSearch>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            open: false,
+            paused: false,
+            steps_remaining: 0,
+            breakpoints: vec![],
+            interrupt_breaks: vec![],
+            seen_interrupts: 0,
+            bank_break: false,
+            displays: vec![],
+            traps: false,
+            last_banks: None,
+            run_until: None,
+            fault_reported: false,
+            output: vec!["Type 'help' for a list of commands.".to_string()],
+            input: String::new(),
+            history: History::load(),
+            scripts: ScriptHost::new(),
+            memory_view: MemoryView::new(),
+            remote: None,
+            remote_paused: false,
+            capture: None,
+            symbols: BTreeMap::new(),
+            search: None,
+        }
+    }
+
+    /// Clears state tied to a running emulator, keeping breakpoints and console output.
+    /// Called whenever a ROM is loaded or emulation is stopped.
+    pub fn reset(&mut self) {
+        self.paused = false;
+        self.steps_remaining = 0;
+        self.seen_interrupts = 0;
+        self.last_banks = None;
+        self.run_until = None;
+        self.fault_reported = false;
+        self.search = None;
+    }
+
+    /// Pauses and opens the debugger once the CPU reaches `addr`, or after `timeout_frames`
+    /// frames if it doesn't, for `--run-until`. Cleared when the emulator is reset.
+    pub fn run_until(&mut self, addr: u16, timeout_frames: Option<u64>) {
+        self.run_until = Some((addr, timeout_frames));
+    }
+
+    /// Checks the emulator state after a step and returns true if emulation should pause,
+    /// printing the reason for the break into the console.
+    /// Runs the instruction hook of loaded scripts, and the break hook when pausing.
+    pub fn should_break(&mut self, emu: &mut Option<Gameboy>) -> bool {
+        if self.run_hook(Hook::Instruction, emu) {
+            return true;
+        }
+        let Some(gb) = emu.as_ref() else {
+            return false;
+        };
+        if !self.check_breaks(gb) {
+            return false;
+        }
+        self.run_hook(Hook::Break, emu);
+        true
+    }
+
+    /// Runs the frame hook of loaded scripts. Called whenever the emulator completes a frame.
+    pub fn on_frame(&mut self, emu: &mut Option<Gameboy>) {
+        if let Some((_, Some(frames_left))) = &mut self.run_until {
+            *frames_left = frames_left.saturating_sub(1);
+        }
+        self.run_hook(Hook::Frame, emu);
+    }
+
+    /// Runs a script hook, printing its output to the console and pausing if a script
+    /// requested it. Returns true if emulation was paused.
+    fn run_hook(&mut self, hook: Hook, emu: &mut Option<Gameboy>) -> bool {
+        if !self.scripts.has_hook(hook) {
+            return false;
+        }
+        self.scripts.run_hook(hook, emu);
+        self.print_script_output();
+        if !self.scripts.take_pause_request() || self.paused {
+            return false;
+        }
+        match emu {
+            Some(gb) => {
+                self.print("Paused by script".to_string());
+                self.pause(gb)
+            }
+            None => false,
+        }
+    }
+
+    fn print_script_output(&mut self) {
+        for line in self.scripts.take_output() {
+            self.print(line);
+        }
+    }
+
+    /// Checks breakpoints, interrupt breaks and stepping, pausing if any were hit
+    fn check_breaks(&mut self, emu: &Gameboy) -> bool {
+        if let Some(fault) = emu.fault().filter(|_| !self.fault_reported) {
+            self.fault_reported = true;
+            log::error!("CPU stopped: {}", fault);
+            self.print(format!("CPU stopped: {}", fault));
+            return self.pause(emu);
+        }
+
+        if let Some((addr, frames_left)) = self.run_until {
+            if emu.get_pc() == addr {
+                self.run_until = None;
+                self.print(format!("Reached {:04X}", addr));
+                self.print(format_registers(emu));
+                return self.pause(emu);
+            }
+            if frames_left == Some(0) {
+                self.run_until = None;
+                self.print(format!("Timed out before reaching {:04X}", addr));
+                return self.pause(emu);
+            }
+        }
+
+        if !self.interrupt_breaks.is_empty() {
+            let log = emu.interrupt_log();
+            let hit = log
+                .since(self.seen_interrupts)
+                .find(|e| e.taken && self.interrupt_breaks.contains(&e.kind))
+                .copied();
+            self.seen_interrupts = log.total();
+            if let Some(event) = hit {
+                self.print(format!(
+                    "Interrupt {:?} serviced (from {:04X})",
+                    event.kind, event.pc
+                ));
+                return self.pause(emu);
+            }
+        }
+
+        if self.bank_break {
+            let banks = (emu.rom_bank(), emu.ram_bank());
+            let last = self.last_banks.replace(banks);
+            if let Some((rom, ram)) = last.filter(|l| *l != banks) {
+                if rom != banks.0 {
+                    self.print(format!(
+                        "ROM bank switched from {:02X} to {:02X}",
+                        rom, banks.0
+                    ));
+                }
+                if ram != banks.1 {
+                    self.print(format!(
+                        "RAM bank switched from {:02X} to {:02X}",
+                        ram, banks.1
+                    ));
+                }
+                return self.pause(emu);
+            }
+        }
+
+        if self.traps {
+            let pc = emu.get_pc();
+            match emu.get_memory_range(pc as usize..pc as usize + 1)[0] {
+                OPCODE_LD_B_B => {
+                    self.print(format!("Software breakpoint (LD B,B) at {:04X}", pc));
+                    return self.pause(emu);
+                }
+                OPCODE_LD_D_D => {
+                    let message =
+                        debug_message(emu, pc).unwrap_or_else(|| "(no debug message)".to_string());
+                    self.print(format!("Debug message at {:04X}: {}", pc, message));
+                }
+                _ => {}
+            }
+        }
+
+        if self.steps_remaining > 0 {
+            self.steps_remaining -= 1;
+            if self.steps_remaining == 0 {
+                return self.pause(emu);
+            }
+        } else if let Some(bp) = self.breakpoints.iter().find(|b| b.hit(emu)) {
+            self.print(format!("Breakpoint hit at {}", bp));
+            return self.pause(emu);
+        }
+        false
+    }
+
+    /// Shows the debugger window, if open. Commands entered are run against `emu`.
+    pub fn show(&mut self, ctx: &egui::Context, emu: &mut Option<Gameboy>) {
+        let mut open = self.open;
+        egui::Window::new("Debugger")
+            .open(&mut open)
+            .default_width(720.0)
+            .show(ctx, |ui| {
+                ui.label(match (&*emu, self.paused) {
+                    (None, _) => "No ROM loaded".to_string(),
+                    (Some(emu), true) => format!("Paused at {:04X}", emu.get_pc()),
+                    (Some(_), false) => "Running".to_string(),
+                });
+                if let Some(emu) = emu.as_ref() {
+                    // Panes are redrawn every frame, so they update live while running
+                    ui.separator();
+                    ui.horizontal_top(|ui| {
+                        ui.vertical(|ui| panes::registers(ui, emu));
+                        ui.separator();
+                        ui.vertical(|ui| panes::disassembly(ui, emu));
+                        ui.separator();
+                        ui.vertical(|ui| self.memory_view.show(ui, emu));
+                    });
+                }
+                ui.separator();
+                ScrollArea::vertical()
+                    .max_height(240.0)
+                    .stick_to_bottom(true)
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        for line in &self.output {
+                            ui.monospace(line);
+                        }
+                    });
+                ui.separator();
+                let input_id = Id::new("debugger_input");
+                let edited = ui.memory(|m| m.has_focus(input_id)) && self.handle_editing_keys(ui);
+                let output = TextEdit::singleline(&mut self.input)
+                    .id(input_id)
+                    .font(TextStyle::Monospace)
+                    .desired_width(f32::INFINITY)
+                    .lock_focus(true)
+                    .show(ui);
+                if edited {
+                    // Line was replaced, move the cursor to the end of the new text
+                    let mut state = output.state;
+                    let end = CCursor::new(self.input.chars().count());
+                    state.set_ccursor_range(Some(CCursorRange::one(end)));
+                    state.store(ui.ctx(), input_id);
+                }
+                let response = output.response;
+                if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                    let line = std::mem::take(&mut self.input);
+                    self.history.push(&line);
+                    self.print(format!("> {}", line));
+                    self.run_line(&line, emu);
+                    response.request_focus();
+                }
+            });
+        self.open = open;
+    }
+
+    /// Handles requests from remote debugger clients, and notifies them when emulation is
+    /// paused or resumed. Called once per frame.
+    pub fn poll_remote(&mut self, emu: &mut Option<Gameboy>) {
+        let Some(remote) = self.remote.as_mut() else {
+            return;
+        };
+        for (client, request) in remote.poll() {
+            let response = match parse_remote_request(&request) {
+                Ok((id, command)) => {
+                    self.print(format!("[remote] > {}", command));
+                    self.capture = Some(vec![]);
+                    self.run_line(&command, emu);
+                    json!({
+                        "id": id,
+                        "output": self.capture.take().unwrap_or_default(),
+                        "paused": self.paused,
+                        "pc": emu.as_ref().map(|e| e.get_pc()),
+                    })
+                }
+                Err(e) => json!({ "error": e }),
+            };
+            // The command may have stopped the server
+            if let Some(remote) = self.remote.as_mut() {
+                remote.send(client, &response);
+            }
+        }
+        if self.paused != self.remote_paused {
+            self.remote_paused = self.paused;
+            let event = match (self.paused, emu.as_ref()) {
+                (true, Some(emu)) => json!({ "event": "paused", "pc": emu.get_pc() }),
+                (true, None) => json!({ "event": "paused" }),
+                (false, _) => json!({ "event": "resumed" }),
+            };
+            if let Some(remote) = self.remote.as_mut() {
+                remote.broadcast(&event);
+            }
+        }
+    }
+
+    /// Runs a command line entered in the console or received from a remote client
+    fn run_line(&mut self, line: &str, emu: &mut Option<Gameboy>) {
+        match line.split_whitespace().next() {
+            // Scripts and the remote server can be managed without a ROM loaded
+            Some("script") => self.script_command(line, emu),
+            Some("remote") => self.remote_command(line),
+            Some("symbols") => self.symbols_command(line),
+            _ => match emu.as_mut() {
+                Some(emu) => self.execute(line, emu),
+                None => self.print("No ROM loaded.".to_string()),
+            },
+        }
+    }
+
+    /// Runs a `remote` subcommand
+    fn remote_command(&mut self, line: &str) {
+        let args: Vec<&str> = line.split_whitespace().skip(1).collect();
+        match args.as_slice() {
+            [] => match &self.remote {
+                Some(remote) => {
+                    let status = format!(
+                        "Remote debugger listening on {}, {} client(s) connected",
+                        remote
+                            .local_addr()
+                            .map_or_else(|e| e.to_string(), |a| a.to_string()),
+                        remote.client_count()
+                    );
+                    self.print(status);
+                }
+                None => self.print("Remote debugger not running.".to_string()),
+            },
+            ["stop"] => {
+                if self.remote.take().is_some() {
+                    self.print("Remote debugger stopped.".to_string());
+                } else {
+                    self.print("Remote debugger not running.".to_string());
+                }
+            }
+            ["start"] => self.start_remote(remote::DEFAULT_PORT),
+            ["start", port] => match port.parse::<u16>() {
+                Ok(port) => self.start_remote(port),
+                Err(_) => self.print(format!("Invalid port: {}", port)),
+            },
+            _ => self.print("Usage: remote [start [port] | stop]".to_string()),
+        }
+    }
+
+    /// Starts the remote debugger server on the given port, replacing any running server
+    fn start_remote(&mut self, port: u16) {
+        // Drop the old server first, in case it holds the requested port
+        self.remote = None;
+        match RemoteServer::bind(port) {
+            Ok(remote) => {
+                self.print(format!("Remote debugger listening on port {}", port));
+                self.remote_paused = self.paused;
+                self.remote = Some(remote);
+            }
+            Err(e) => self.print(format!("Unable to start remote debugger: {}", e)),
+        }
+    }
+
+    /// Handles history navigation and tab completion keys for the focused input line.
+    /// Consumes the keys so the text edit doesn't process them, and returns true if the
+    /// input line was changed.
+    fn handle_editing_keys(&mut self, ui: &mut egui::Ui) -> bool {
+        let (up, down, tab) = ui.input_mut(|i| {
+            (
+                i.consume_key(Modifiers::NONE, Key::ArrowUp),
+                i.consume_key(Modifiers::NONE, Key::ArrowDown),
+                i.consume_key(Modifiers::NONE, Key::Tab),
+            )
+        });
+        let line = if up {
+            self.history.previous(&self.input).map(str::to_string)
+        } else if down {
+            self.history.next().map(str::to_string)
+        } else if tab {
+            self.complete()
+        } else {
+            None
+        };
+        match line {
+            Some(line) => {
+                self.input = line;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Completes the word being typed against the command names, or against interrupt
+    /// names for the `breakint` argument. Returns the completed line, if it changed.
+    /// When multiple candidates match, completes their common prefix and lists them.
+    fn complete(&mut self) -> Option<String> {
+        let input = self.input.clone();
+        let words: Vec<&str> = input.split_whitespace().collect();
+        let new_word = words.is_empty() || input.ends_with(' ');
+        let symbol_names: Vec<String>;
+        let mut symbol_candidates: Vec<&str>;
+        let candidates: &[&str] = match (words.len(), new_word) {
+            (0, _) | (1, false) => COMMANDS,
+            (1, true) | (2, false) if words[0] == "breakint" => INTERRUPT_NAMES,
+            (1, true) | (2, false) if words[0] == "script" => SCRIPT_COMMANDS,
+            (1, true) | (2, false) if words[0] == "cheat" => CHEAT_COMMANDS,
+            (1, true) | (2, false) if words[0] == "remote" => REMOTE_COMMANDS,
+            (1, true) | (2, false) if words[0] == "symbols" => SYMBOL_COMMANDS,
+            (1, true) | (2, false) if words[0] == "search" => SEARCH_COMMANDS,
+            (1, true) | (2, false) if ADDRESS_COMMANDS.iter().any(|c| *c == words[0]) => {
+                // Copied, as the symbols are borrowed while listing the matches. A name can
+                // be given to more than one address, but is only listed once.
+                symbol_names = self.symbols.values().cloned().collect();
+                symbol_candidates = symbol_names.iter().map(String::as_str).collect();
+                symbol_candidates.sort_unstable();
+                symbol_candidates.dedup();
+                &symbol_candidates
+            }
+            _ => return None,
+        };
+        let prefix = if new_word { "" } else { words[words.len() - 1] };
+        let matches: Vec<&str> = candidates
+            .iter()
+            .filter(|c| c.starts_with(prefix))
+            .copied()
+            .collect();
+        let completed = match matches.as_slice() {
+            [] => return None,
+            [only] => format!("{} ", only),
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.len(), |len, m| {
+                    first
+                        .bytes()
+                        .zip(m.bytes())
+                        .take(len)
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                });
+                let list = matches.join("  ");
+                self.print(list);
+                first[..common].to_string()
+            }
+        };
+        let base = &input[..input.len() - prefix.len()];
+        let line = format!("{}{}", base, completed);
+        (line != input).then_some(line)
+    }
+
+    /// Parses and runs a single command line against the emulator
+    fn execute(&mut self, line: &str, emu: &mut Gameboy) {
+        let mut args = line.split_whitespace();
+        let Some(cmd) = args.next() else {
+            return;
+        };
+        let args: Vec<&str> = args.collect();
+        match cmd {
+            "help" | "h" => self.print(HELP_TEXT.to_string()),
+            "c" | "continue" => {
+                self.paused = false;
+                self.steps_remaining = 0;
+            }
+            "s" | "step" => {
+                let count = match args.first() {
+                    Some(a) => match a.parse::<u32>() {
+                        Ok(c) if c > 0 => c,
+                        _ => return self.print(format!("Invalid step count: {}", a)),
+                    },
+                    None => 1,
+                };
+                self.paused = false;
+                self.steps_remaining = count;
+            }
+            "b" | "break" => match args.first().map(|a| self.parse_breakpoint(a)) {
+                Some(Some(bp)) => {
+                    if !self.breakpoints.contains(&bp) {
+                        self.breakpoints.push(bp);
+                    }
+                    self.print(format!("Breakpoint set at {}", bp));
+                }
+                Some(None) => self.print("Invalid address.".to_string()),
+                None => {
+                    if self.breakpoints.is_empty() {
+                        self.print("No breakpoints set.".to_string());
+                    }
+                    for bp in self.breakpoints.clone() {
+                        self.print(format!("  {}", bp));
+                    }
+                }
+            },
+            "d" | "delete" => match args.first().and_then(|a| self.parse_breakpoint(a)) {
+                Some(bp) => {
+                    let count = self.breakpoints.len();
+                    // Without a bank, remove the address in every bank
+                    self.breakpoints
+                        .retain(|b| b.addr != bp.addr || (bp.bank.is_some() && b.bank != bp.bank));
+                    if self.breakpoints.len() == count {
+                        self.print(format!("No breakpoint at {}", bp));
+                    } else {
+                        self.print(format!("Breakpoint removed at {}", bp));
+                    }
+                }
+                None => self.print("Usage: delete [bank:]<addr>".to_string()),
+            },
+            "r" | "regs" => self.print(format_registers(emu)),
+            "x" | "mem" => {
+                let Some(addr) = args.first().and_then(|a| parse_addr(a)) else {
+                    return self.print("Usage: mem <addr> [len]".to_string());
+                };
+                let len = match args.get(1) {
+                    Some(a) => match a.parse::<usize>() {
+                        Ok(l) => l,
+                        Err(_) => return self.print(format!("Invalid length: {}", a)),
+                    },
+                    None => 16,
+                };
+                // Stops at the end of the address space
+                let start = addr as usize;
+                let len = len.min(0x10000 - start);
+                let data = emu.get_memory_range(start..start + len);
+                for (i, row) in data.chunks(16).enumerate() {
+                    let bytes: Vec<String> = row.iter().map(|b| format!("{:02X}", b)).collect();
+                    self.print(format!("{:04X}: {}", start + i * 16, bytes.join(" ")));
+                }
+            }
+            "ints" => {
+                let lines: Vec<String> = emu
+                    .interrupt_log()
+                    .iter()
+                    .map(|e| {
+                        format!(
+                            "{:>12}  {:<8} PC:{:04X}  {}",
+                            e.cycle,
+                            format!("{:?}", e.kind),
+                            e.pc,
+                            if e.taken { "taken" } else { "requested" }
+                        )
+                    })
+                    .collect();
+                if lines.is_empty() {
+                    self.print("No interrupts recorded.".to_string());
+                }
+                for line in lines {
+                    self.print(line);
+                }
+            }
+            "breakint" => match args.first() {
+                Some(a) => match parse_interrupt(a) {
+                    Some(kind) => {
+                        if self.interrupt_breaks.contains(&kind) {
+                            self.interrupt_breaks.retain(|k| *k != kind);
+                            self.print(format!("No longer breaking on {:?}", kind));
+                        } else {
+                            self.interrupt_breaks.push(kind);
+                            self.seen_interrupts = emu.interrupt_log().total();
+                            self.print(format!("Breaking on {:?}", kind));
+                        }
+                    }
+                    None => self.print(format!("Unknown interrupt: {}", a)),
+                },
+                None => {
+                    if self.interrupt_breaks.is_empty() {
+                        self.print("No interrupt breaks set.".to_string());
+                    }
+                    for kind in self.interrupt_breaks.clone() {
+                        self.print(format!("  {:?}", kind));
+                    }
+                }
+            },
+            "breakbank" => {
+                self.bank_break = !self.bank_break;
+                if self.bank_break {
+                    self.last_banks = Some((emu.rom_bank(), emu.ram_bank()));
+                    self.print(format!(
+                        "Breaking on bank switches (ROM bank {:02X}, RAM bank {:02X} mapped)",
+                        emu.rom_bank(),
+                        emu.ram_bank()
+                    ));
+                } else {
+                    self.print("No longer breaking on bank switches".to_string());
+                }
+            }
+            "traps" => {
+                self.traps = !self.traps;
+                self.print(format!(
+                    "Debug traps {}",
+                    if self.traps { "enabled" } else { "disabled" }
+                ));
+            }
+            "display" => {
+                if args.is_empty() {
+                    if self.displays.is_empty() {
+                        self.print("No display expressions.".to_string());
+                    }
+                    for (i, expr) in self.displays.clone().iter().enumerate() {
+                        self.print(format!("  {}: {}", i + 1, expr));
+                    }
+                    return;
+                }
+                let expr = args.join(" ");
+                match expr::evaluate(&expr, emu) {
+                    Ok(value) => {
+                        self.displays.push(expr.clone());
+                        self.print(format!("{}: {} = {:04X}", self.displays.len(), expr, value));
+                    }
+                    Err(e) => self.print(e),
+                }
+            }
+            "undisplay" => match args.first().and_then(|a| a.parse::<usize>().ok()) {
+                Some(n) if n >= 1 && n <= self.displays.len() => {
+                    let expr = self.displays.remove(n - 1);
+                    self.print(format!("Removed display {}: {}", n, expr));
+                }
+                _ => self.print("Usage: undisplay <n>".to_string()),
+            },
+            "cheat" => self.cheat_command(&args, emu),
+            "search" => self.search_command(&args, emu),
+            "xref" => match args.first().and_then(|a| self.parse_breakpoint(a)) {
+                Some(target) => self.cross_references(target, emu),
+                None => self.print("Usage: xref [bank:]<addr>".to_string()),
+            },
+            _ => self.print(format!("Unknown command: {}", cmd)),
+        }
+    }
+
+    /// Prints the instructions referring to `target`, each with the symbol it follows. The ROM
+    /// is traced each time, guided by the coverage recorded so far, so code that's only just
+    /// been run is included.
+    fn cross_references(&mut self, target: Breakpoint, emu: &Gameboy) {
+        let map = match emu.coverage() {
+            Some(coverage) => CodeMap::trace_with_coverage(emu.rom(), coverage.clone()),
+            None => CodeMap::trace(emu.rom()),
+        };
+        let refs = map.cross_references(emu.rom());
+        let found: Vec<CrossReference> = match target.bank {
+            Some(bank) => refs
+                .to_banked(BankedAddress {
+                    bank,
+                    address: target.addr,
+                })
+                .copied()
+                .collect(),
+            None => refs.to(target.addr).to_vec(),
+        };
+        if found.is_empty() {
+            self.print(format!("No references to {}", target));
+        }
+        for r in found {
+            let kind = match r.kind {
+                RefKind::Call => "call",
+                RefKind::Jump => "jump",
+                RefKind::Read => "read",
+                RefKind::Write => "write",
+            };
+            let symbol = match self.symbols.range(..=r.from).next_back() {
+                Some((addr, name)) if addr.bank == r.from.bank => {
+                    match r.from.address - addr.address {
+                        0 => format!(" {}", name),
+                        offset => format!(" {}+{:X}", name, offset),
+                    }
+                }
+                _ => String::new(),
+            };
+            self.print(format!("  {:<5} {}{}", kind, r.from, symbol));
+        }
+    }
+
+    /// Runs a `cheat` subcommand
+    fn cheat_command(&mut self, args: &[&str], emu: &mut Gameboy) {
+        match args {
+            [] => {
+                if emu.cheats().is_empty() {
+                    self.print("No cheats loaded.".to_string());
+                }
+                let lines: Vec<String> = emu
+                    .cheats()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, cheat)| {
+                        let state = if cheat.enabled { "on" } else { "off" };
+                        format!("  {}: [{}] {}", i + 1, state, cheat.label)
+                    })
+                    .collect();
+                for line in lines {
+                    self.print(line);
+                }
+            }
+            ["add", code, label @ ..] => match CheatCode::parse(code) {
+                Ok(parsed) => {
+                    let label = if label.is_empty() {
+                        code.to_string()
+                    } else {
+                        label.join(" ")
+                    };
+                    let index = emu.add_cheat(Cheat {
+                        code: parsed,
+                        label: label.clone(),
+                        enabled: true,
+                    });
+                    self.print(format!("Added cheat {}: {}", index + 1, label));
+                }
+                Err(e) => self.print(format!("{}", e)),
+            },
+            [state @ ("on" | "off"), n] => {
+                let enabled = *state == "on";
+                match n.parse::<usize>() {
+                    Ok(n) if n >= 1 && emu.set_cheat_enabled(n - 1, enabled) => {
+                        let label = &emu.cheats()[n - 1].label;
+                        self.print(format!("Cheat {} {}: {}", n, state, label));
+                    }
+                    _ => self.print(format!("No cheat number {}", n)),
+                }
+            }
+            _ => {
+                self.print("Usage: cheat | cheat add <code> [label] | cheat on|off <n>".to_string())
+            }
+        }
+    }
+
+    /// Runs a `search` subcommand, then lists the addresses left
+    fn search_command(&mut self, args: &[&str], emu: &Gameboy) {
+        match args {
+            ["start"] => self.search = Some(RamSearch::start(emu)),
+            [] | ["list"] => {}
+            _ => {
+                let Some(filter) = Filter::parse(args) else {
+                    return self.print(
+                        "Usage: search start | search equal|changed|increased|decreased | \
+                         search value <n> | search list"
+                            .to_string(),
+                    );
+                };
+                match &mut self.search {
+                    Some(search) => search.filter(filter, emu),
+                    None => return self.print("No search started.".to_string()),
+                }
+            }
+        }
+        let Some(search) = &self.search else {
+            return self.print("No search started.".to_string());
+        };
+        let candidates = search.candidates();
+        let mut lines = vec![format!("{} addresses left", candidates.len())];
+        lines.extend(
+            candidates
+                .iter()
+                .take(MAX_SEARCH_LISTED)
+                .map(|(addr, value)| format!("  {}: {:02X} ({})", addr, value, value)),
+        );
+        if candidates.len() > MAX_SEARCH_LISTED {
+            lines.push(format!(
+                "  ... and {} more",
+                candidates.len() - MAX_SEARCH_LISTED
+            ));
+        }
+        for line in lines {
+            self.print(line);
+        }
+    }
+
+    /// Runs a `script` subcommand
+    fn script_command(&mut self, line: &str, emu: &mut Option<Gameboy>) {
+        let args: Vec<&str> = line.split_whitespace().skip(1).collect();
+        match args.as_slice() {
+            ["load", path] => {
+                let result = self.scripts.load(Path::new(path), emu);
+                self.print_script_output();
+                match result {
+                    Ok(hooks) if hooks.is_empty() => {
+                        self.print(format!("Loaded {} (no hooks defined)", path))
+                    }
+                    Ok(hooks) => self.print(format!("Loaded {} with hooks {:?}", path, hooks)),
+                    Err(e) => self.print(format!("Unable to load {}: {}", path, e)),
+                }
+            }
+            ["list"] => {
+                let lines: Vec<String> = self
+                    .scripts
+                    .list()
+                    .map(|(path, hooks)| format!("  {}  {:?}", path.display(), hooks))
+                    .collect();
+                if lines.is_empty() {
+                    self.print("No scripts loaded.".to_string());
+                }
+                for line in lines {
+                    self.print(line);
+                }
+            }
+            ["clear"] => {
+                self.scripts.clear();
+                self.print("Unloaded all scripts.".to_string());
+            }
+            _ => self.print("Usage: script load <path> | script list | script clear".to_string()),
+        }
+    }
+
+    /// Runs a `symbols` subcommand
+    fn symbols_command(&mut self, line: &str) {
+        let args: Vec<&str> = line.split_whitespace().skip(1).collect();
+        match args.as_slice() {
+            [] => self.print(format!("{} symbols loaded.", self.symbols.len())),
+            ["load", path] => match load_symbols(Path::new(path)) {
+                Ok(symbols) => {
+                    self.symbols = symbols;
+                    self.print(format!(
+                        "Loaded {} symbols from {}",
+                        self.symbols.len(),
+                        path
+                    ));
+                }
+                Err(e) => self.print(format!("Unable to load {}", e)),
+            },
+            ["clear"] => {
+                self.symbols.clear();
+                self.print("Unloaded all symbols.".to_string());
+            }
+            _ => self.print("Usage: symbols [load <path> | clear]".to_string()),
+        }
+    }
+
+    /// Parses a breakpoint as `Breakpoint::parse` does, or from the name of a loaded symbol
+    fn parse_breakpoint(&self, s: &str) -> Option<Breakpoint> {
+        Breakpoint::parse(s).or_else(|| {
+            let (addr, _) = self.symbols.iter().find(|(_, name)| *name == s)?;
+            Some(Breakpoint::at_symbol(*addr))
+        })
+    }
+
+    /// Halts emulation and prints the display expressions and the instruction at the current PC
+    fn pause(&mut self, emu: &Gameboy) -> bool {
+        self.paused = true;
+        self.steps_remaining = 0;
+        for (i, expr) in self.displays.clone().iter().enumerate() {
+            match expr::evaluate(expr, emu) {
+                Ok(value) => self.print(format!("{}: {} = {:04X}", i + 1, expr, value)),
+                Err(e) => self.print(format!("{}: {} = <{}>", i + 1, expr, e)),
+            }
+        }
+        let pc = emu.get_pc();
+        // Only ROM bank symbols depend on the mapped bank
+        let bank = match pc {
+            0x4000..=0x7FFF => emu.rom_bank(),
+            _ => 0,
+        };
+        if let Some(name) = self.symbols.get(&BankedAddress { bank, address: pc }) {
+            self.print(format!("{}:", name));
+        }
+        if let Some(inst) = decode_memory(emu.memory(), pc) {
+            self.print(format!(
+                "{:04X}  {}",
+                pc,
+                format_memory(emu.memory(), &inst)
+            ));
+        }
+        true
+    }
+
+    /// Appends a line to the console output, dropping the oldest lines when full
+    fn print(&mut self, line: String) {
+        if let Some(capture) = &mut self.capture {
+            capture.push(line.clone());
+        }
+        self.output.push(line);
+        if self.output.len() > MAX_OUTPUT_LINES {
+            let excess = self.output.len() - MAX_OUTPUT_LINES;
+            self.output.drain(..excess);
+        }
+    }
+}
+
+/// A PC breakpoint, optionally restricted to when a specific cartridge bank is mapped
+#[derive(Clone, Copy, PartialEq)]
+struct Breakpoint {
+    /// The ROM bank for addresses in 0x0000-0x7FFF, or RAM bank for 0xA000-0xBFFF
+    bank: Option<u16>,
+    addr: u16,
+}
+
+impl Breakpoint {
+    /// Parses a breakpoint of the form `addr` or `bank:addr`, both hexadecimal.
+    /// A bank can only be given for addresses within cartridge ROM or RAM.
+    fn parse(s: &str) -> Option<Breakpoint> {
+        match s.split_once(':') {
+            Some((bank, addr)) => {
+                let addr = parse_addr(addr)?;
+                if !matches!(addr, 0x0000..=0x7FFF | 0xA000..=0xBFFF) {
+                    return None;
+                }
+                Some(Breakpoint {
+                    bank: Some(parse_addr(bank)?),
+                    addr,
+                })
+            }
+            None => Some(Breakpoint {
+                bank: None,
+                addr: parse_addr(s)?,
+            }),
+        }
+    }
+
+    /// Returns a breakpoint at a symbol's address, in its bank if it's in switchable ROM or
+    /// cartridge RAM
+    fn at_symbol(addr: BankedAddress) -> Breakpoint {
+        Breakpoint {
+            bank: matches!(addr.address, 0x4000..=0x7FFF | 0xA000..=0xBFFF).then_some(addr.bank),
+            addr: addr.address,
+        }
+    }
+
+    /// Returns true if the emulator is at the breakpoint
+    fn hit(&self, emu: &Gameboy) -> bool {
+        if emu.get_pc() != self.addr {
+            return false;
+        }
+        match (self.bank, self.addr) {
+            (None, _) => true,
+            // Bank 0 is always mapped into the lower half of ROM
+            (Some(bank), 0x0000..=0x3FFF) => bank == 0,
+            (Some(bank), 0x4000..=0x7FFF) => bank == emu.rom_bank(),
+            (Some(bank), _) => bank == emu.ram_bank() as u16,
+        }
+    }
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02X}:{:04X}", bank, self.addr),
+            None => write!(f, "{:04X}", self.addr),
+        }
+    }
+}
+
+/// Reads the message of a debug message block starting at the LD D,D at `addr`.
+/// Uses the convention from BGB and no$gmb, where LD D,D is followed by a jump over
+/// the signature 0x6464, 0x0000 and the message text:
+/// `ld d,d / jr .end / dw $6464 / dw $0000 / db "message" / .end:`
+fn debug_message(emu: &Gameboy, addr: u16) -> Option<String> {
+    let start = addr as usize;
+    let header = emu.get_memory_range(start..start + 7);
+    if header.len() < 7 || header[1] != OPCODE_JR || header[3..7] != [0x64, 0x64, 0x00, 0x00] {
+        return None;
+    }
+    // The jump skips the 4 signature bytes, and the message fills the rest
+    let len = (header[2] as usize).checked_sub(4)?;
+    let text = emu.get_memory_range(start + 7..start + 7 + len);
+    Some(String::from_utf8_lossy(&text).into_owned())
+}
+
+/// Parses a remote request line into its id and command
+fn parse_remote_request(request: &str) -> Result<(Value, String), String> {
+    let value: Value = serde_json::from_str(request).map_err(|e| e.to_string())?;
+    let command = value
+        .get("command")
+        .and_then(Value::as_str)
+        .ok_or("Request is missing a \"command\" string")?;
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    Ok((id, command.to_string()))
+}
+
+/// Formats the CPU registers and the interrupt and LCD registers, as shown by `regs`
+pub fn format_registers(emu: &Gameboy) -> String {
+    let state = emu.get_debug_state();
+    format!(
+        "{}IE: {:02X}  IF: {:02X}  LCDC: {:02X}  STAT: {:02X}  LY: {:02X}",
+        state.cpu_data,
+        state.ie_data,
+        state.if_data,
+        state.vram_lcdc,
+        state.vram_stat,
+        state.vram_ly
+    )
+}
+
+/// Parses a hexadecimal address, allowing an optional `0x` or `$` prefix
+pub fn parse_addr(s: &str) -> Option<u16> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix('$'))
+        .unwrap_or(s);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// Parses an interrupt name or vector address into its `InterruptKind`
+fn parse_interrupt(s: &str) -> Option<InterruptKind> {
+    if let Some(addr) = parse_addr(s) {
+        return InterruptKind::ALL.into_iter().find(|k| k.vector() == addr);
+    }
+    match s.to_lowercase().as_str() {
+        "vblank" => Some(InterruptKind::VBlank),
+        "stat" | "lcd" | "lcdstat" => Some(InterruptKind::LcdStat),
+        "timer" => Some(InterruptKind::Timer),
+        "serial" => Some(InterruptKind::Serial),
+        "joypad" => Some(InterruptKind::Joypad),
+        _ => None,
+    }
+}
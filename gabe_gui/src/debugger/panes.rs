@@ -0,0 +1,111 @@
+use egui::{Color32, RichText, TextEdit, TextStyle, Ui};
+use gabe_core::disassemble::{disassemble_memory, format_memory, Instruction};
+use gabe_core::gb::Gameboy;
+
+use super::parse_addr;
+
+/// Number of instructions shown before the PC in the disassembly pane
+const DISASSEMBLY_BEFORE: usize = 4;
+/// Number of instructions shown from the PC onward in the disassembly pane
+const DISASSEMBLY_AFTER: usize = 10;
+/// Number of 16 byte rows shown in the memory pane
+const MEMORY_ROWS: usize = 8;
+
+/// Shows the CPU registers, flags and interrupt registers
+pub fn registers(ui: &mut Ui, emu: &Gameboy) {
+    let state = emu.get_debug_state();
+    ui.monospace(format!("{}", state.cpu_data));
+    ui.monospace(format!(
+        "IE: {:02X}  IF: {:02X}\nLCDC: {:02X}  STAT: {:02X}\nLY: {:02X}",
+        state.ie_data, state.if_data, state.vram_lcdc, state.vram_stat, state.vram_ly
+    ));
+}
+
+/// Shows the instructions around the PC, highlighting the next instruction to execute
+pub fn disassembly(ui: &mut Ui, emu: &Gameboy) {
+    let pc = emu.get_pc();
+    for inst in disassemble_around(emu, pc) {
+        let text = format_memory(emu.memory(), &inst);
+        let line = format!("{:04X}  {}", inst.address, text.replace('\t', "  "));
+        if inst.address == pc {
+            ui.label(RichText::new(line).monospace().color(Color32::YELLOW));
+        } else {
+            ui.monospace(line);
+        }
+    }
+}
+
+/// Disassembles the instructions surrounding `pc`.
+/// Instructions are variable length, so the instructions before `pc` are found by trying
+/// start addresses before it until one decodes into an instruction starting exactly at `pc`.
+fn disassemble_around(emu: &Gameboy, pc: u16) -> Vec<Instruction> {
+    let memory = emu.memory();
+    // Instructions are at most 3 bytes long
+    let max_before = DISASSEMBLY_BEFORE * 3;
+    let mut lines = Vec::new();
+    for back in (1..=max_before.min(pc as usize)).rev() {
+        lines = disassemble_memory(memory, pc - back as u16)
+            .take_while(|inst| inst.address <= pc)
+            .collect();
+        if matches!(lines.last(), Some(inst) if inst.address == pc) {
+            lines.pop();
+            let first = lines.len().saturating_sub(DISASSEMBLY_BEFORE);
+            lines.drain(..first);
+            break;
+        }
+        lines.clear();
+    }
+    lines.extend(disassemble_memory(memory, pc).take(DISASSEMBLY_AFTER));
+    lines
+}
+
+/// A hex dump of memory, starting at an address entered by the user
+pub struct MemoryView {
+    addr: u16,
+    input: String,
+}
+
+impl MemoryView {
+    pub fn new() -> Self {
+        MemoryView {
+            addr: 0xC000,
+            input: "C000".to_string(),
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut Ui, emu: &Gameboy) {
+        ui.horizontal(|ui| {
+            ui.label("Address:");
+            let response = ui.add(
+                TextEdit::singleline(&mut self.input)
+                    .font(TextStyle::Monospace)
+                    .desired_width(48.0),
+            );
+            if response.changed() {
+                if let Some(addr) = parse_addr(self.input.trim()) {
+                    // Align to a row so the columns line up with the header
+                    self.addr = addr & 0xFFF0;
+                }
+            }
+        });
+        ui.monospace("      00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F");
+        let start = self.addr as usize;
+        let data = emu.get_memory_range(start..start + MEMORY_ROWS * 16);
+        for (i, row) in data.chunks(16).enumerate() {
+            let bytes: Vec<String> = row.iter().map(|b| format!("{:02X}", b)).collect();
+            let ascii: String = row
+                .iter()
+                .map(|b| match b {
+                    0x20..=0x7E => *b as char,
+                    _ => '.',
+                })
+                .collect();
+            ui.monospace(format!(
+                "{:04X}: {}  {}",
+                start + i * 16,
+                bytes.join(" "),
+                ascii
+            ));
+        }
+    }
+}
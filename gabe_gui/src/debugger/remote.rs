@@ -0,0 +1,162 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+};
+
+use serde_json::Value;
+
+/// Port the remote debugger listens on when none is given
+pub const DEFAULT_PORT: u16 = 8765;
+
+/// Maximum length of a single request line, clients sending longer lines are disconnected
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+struct Client {
+    id: u64,
+    stream: TcpStream,
+    /// Received bytes not yet terminated by a newline
+    incoming: Vec<u8>,
+    /// Bytes waiting to be written once the socket accepts them
+    outgoing: Vec<u8>,
+}
+
+/// A TCP server that lets external tools send debugger commands.
+///
+/// The protocol is line-delimited JSON. Each request is an object with a `command` field
+/// holding a debugger console command, and an optional `id` that is echoed in the response:
+/// `{"id": 1, "command": "break 0150"}`. Responses contain the console output of the
+/// command and the emulator state:
+/// `{"id": 1, "output": ["Breakpoint set at 0150"], "paused": false, "pc": 336}`.
+/// The server also sends `{"event": "paused", "pc": ...}` and `{"event": "resumed"}` to every
+/// client when emulation is paused or resumed.
+///
+/// Sockets are non-blocking and polled once per UI frame, so the emulator keeps running
+/// normally while clients are attached.
+pub struct RemoteServer {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    next_id: u64,
+}
+
+impl RemoteServer {
+    /// Listens for clients on the given port of the loopback interface
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(RemoteServer {
+            listener,
+            clients: vec![],
+            next_id: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Returns the number of connected clients
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Accepts new clients, flushes pending responses, and returns the complete request lines
+    /// received along with the id of the client that sent them. Disconnected clients are dropped.
+    pub fn poll(&mut self) -> Vec<(u64, String)> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        log::warn!("Unable to accept remote debugger client {}: {}", addr, e);
+                        continue;
+                    }
+                    log::info!("Remote debugger client connected from {}", addr);
+                    self.clients.push(Client {
+                        id: self.next_id,
+                        stream,
+                        incoming: vec![],
+                        outgoing: vec![],
+                    });
+                    self.next_id += 1;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("Remote debugger accept failed: {}", e);
+                    break;
+                }
+            }
+        }
+
+        let mut requests = vec![];
+        self.clients.retain_mut(|client| {
+            let connected = client.read(&mut requests) && client.flush();
+            if !connected {
+                log::info!("Remote debugger client disconnected");
+            }
+            connected
+        });
+        requests
+    }
+
+    /// Queues a message to the client with the given id, if it is still connected
+    pub fn send(&mut self, id: u64, message: &Value) {
+        if let Some(client) = self.clients.iter_mut().find(|c| c.id == id) {
+            client.queue(message);
+        }
+    }
+
+    /// Queues a message to every connected client
+    pub fn broadcast(&mut self, message: &Value) {
+        for client in &mut self.clients {
+            client.queue(message);
+        }
+    }
+}
+
+impl Client {
+    /// Reads all available data, appending complete lines to `requests`.
+    /// Returns false if the client disconnected or errored.
+    fn read(&mut self, requests: &mut Vec<(u64, String)>) -> bool {
+        let mut buf = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return false,
+                Ok(n) => self.incoming.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => return false,
+            }
+        }
+        while let Some(end) = self.incoming.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.incoming.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line).trim().to_string();
+            if !line.is_empty() {
+                requests.push((self.id, line));
+            }
+        }
+        self.incoming.len() <= MAX_LINE_LEN
+    }
+
+    fn queue(&mut self, message: &Value) {
+        self.outgoing
+            .extend_from_slice(message.to_string().as_bytes());
+        self.outgoing.push(b'\n');
+        self.flush();
+    }
+
+    /// Writes as much queued data as the socket accepts.
+    /// Returns false if the client disconnected or errored.
+    fn flush(&mut self) -> bool {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return false,
+                Ok(n) => {
+                    self.outgoing.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use gabe_core::debug::Registers;
+use gabe_core::gb::Gameboy;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+
+use super::expr::get_register;
+
+/// Points in emulation at which a script function is called, if the script defines it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Hook {
+    /// Called when emulation pauses on a breakpoint, interrupt break, or finished step
+    Break,
+    /// Called every time the PPU completes a frame
+    Frame,
+    /// Called after every instruction. Slows emulation considerably.
+    Instruction,
+}
+
+impl Hook {
+    const ALL: [Hook; 3] = [Hook::Break, Hook::Frame, Hook::Instruction];
+
+    /// Name of the script function called for this hook
+    fn function_name(&self) -> &'static str {
+        match self {
+            Hook::Break => "on_break",
+            Hook::Frame => "on_frame",
+            Hook::Instruction => "on_instruction",
+        }
+    }
+}
+
+/// State shared between the host and the functions registered with the script engine.
+/// The emulator is moved in here for the duration of a script call.
+#[derive(Default)]
+struct Shared {
+    emu: Option<Gameboy>,
+    output: Vec<String>,
+    pause_requested: bool,
+}
+
+struct Script {
+    path: PathBuf,
+    ast: AST,
+    /// Top level variables of the script, kept between hook calls
+    scope: Scope<'static>,
+    hooks: Vec<Hook>,
+}
+
+/// Loads and runs Rhai scripts for the debugger.
+///
+/// Scripts define any of the hook functions `on_break()`, `on_frame()` and `on_instruction()`,
+/// and can use the following functions to inspect and modify the emulator:
+/// - `read(addr)` / `write(addr, value)`: read or write a byte of memory as the CPU would
+/// - `reg(name)` / `set_reg(name, value)`: read or write a register (a, f, b, c, d, e, h, l,
+///   af, bc, de, hl, sp, pc)
+/// - `pause()`: pause emulation and open the debugger
+///
+/// Output from `print` is written to the debugger console.
+pub struct ScriptHost {
+    engine: Engine,
+    scripts: Vec<Script>,
+    shared: Rc<RefCell<Shared>>,
+}
+
+impl ScriptHost {
+    pub fn new() -> Self {
+        let shared = Rc::new(RefCell::new(Shared::default()));
+        let mut engine = Engine::new();
+
+        let s = shared.clone();
+        engine.on_print(move |text| s.borrow_mut().output.push(text.to_string()));
+        let s = shared.clone();
+        engine.on_debug(move |text, _, pos| {
+            s.borrow_mut().output.push(format!("[{}] {}", pos, text))
+        });
+
+        let s = shared.clone();
+        engine.register_fn(
+            "read",
+            move |addr: i64| -> Result<i64, Box<EvalAltResult>> {
+                let addr = to_addr(addr)? as usize;
+                let shared = s.borrow();
+                let emu = shared.emu.as_ref().ok_or("No ROM loaded")?;
+                Ok(emu.get_memory_range(addr..addr + 1)[0] as i64)
+            },
+        );
+        let s = shared.clone();
+        engine.register_fn(
+            "write",
+            move |addr: i64, val: i64| -> Result<(), Box<EvalAltResult>> {
+                let addr = to_addr(addr)?;
+                let mut shared = s.borrow_mut();
+                let emu = shared.emu.as_mut().ok_or("No ROM loaded")?;
+                emu.write_memory(addr, val as u8);
+                Ok(())
+            },
+        );
+        let s = shared.clone();
+        engine.register_fn(
+            "reg",
+            move |name: &str| -> Result<i64, Box<EvalAltResult>> {
+                let shared = s.borrow();
+                let emu = shared.emu.as_ref().ok_or("No ROM loaded")?;
+                let reg = emu.get_debug_state().cpu_data.reg;
+                get_register(&reg, name)
+                    .map(|v| v as i64)
+                    .ok_or_else(|| format!("Unknown register: {}", name).into())
+            },
+        );
+        let s = shared.clone();
+        engine.register_fn(
+            "set_reg",
+            move |name: &str, val: i64| -> Result<(), Box<EvalAltResult>> {
+                let mut shared = s.borrow_mut();
+                let emu = shared.emu.as_mut().ok_or("No ROM loaded")?;
+                let mut reg = emu.get_debug_state().cpu_data.reg;
+                if !set_register(&mut reg, name, val as u16) {
+                    return Err(format!("Unknown register: {}", name).into());
+                }
+                emu.set_registers(reg);
+                Ok(())
+            },
+        );
+        let s = shared.clone();
+        engine.register_fn("pause", move || s.borrow_mut().pause_requested = true);
+
+        ScriptHost {
+            engine,
+            scripts: vec![],
+            shared,
+        }
+    }
+
+    /// Compiles the script at `path` and runs its top level statements.
+    /// Returns the hooks the script defines.
+    pub fn load(&mut self, path: &Path, emu: &mut Option<Gameboy>) -> Result<Vec<Hook>, String> {
+        let ast = self
+            .engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| e.to_string())?;
+        let hooks: Vec<Hook> = Hook::ALL
+            .into_iter()
+            .filter(|h| {
+                ast.iter_functions()
+                    .any(|f| f.name == h.function_name() && f.params.is_empty())
+            })
+            .collect();
+        let mut scope = Scope::new();
+        let engine = &self.engine;
+        self.with_emu(emu, || engine.run_ast_with_scope(&mut scope, &ast))
+            .map_err(|e| e.to_string())?;
+        // Reloading a script replaces the previous copy
+        self.scripts.retain(|s| s.path != path);
+        self.scripts.push(Script {
+            path: path.to_path_buf(),
+            ast,
+            scope,
+            hooks: hooks.clone(),
+        });
+        Ok(hooks)
+    }
+
+    /// Unloads all scripts
+    pub fn clear(&mut self) {
+        self.scripts.clear();
+    }
+
+    /// Returns the paths and hooks of all loaded scripts
+    pub fn list(&self) -> impl Iterator<Item = (&Path, &[Hook])> {
+        self.scripts
+            .iter()
+            .map(|s| (s.path.as_path(), s.hooks.as_slice()))
+    }
+
+    /// Returns true if any loaded script defines the hook
+    pub fn has_hook(&self, hook: Hook) -> bool {
+        self.scripts.iter().any(|s| s.hooks.contains(&hook))
+    }
+
+    /// Calls the hook function of every script that defines it.
+    /// Scripts that fail with an error are unloaded, so a broken per-instruction hook doesn't
+    /// flood the console.
+    pub fn run_hook(&mut self, hook: Hook, emu: &mut Option<Gameboy>) {
+        if !self.has_hook(hook) {
+            return;
+        }
+        let engine = &self.engine;
+        let mut scripts = std::mem::take(&mut self.scripts);
+        let errors: Vec<String> = self.with_emu(emu, || {
+            let mut errors = vec![];
+            scripts.retain_mut(|script| {
+                if !script.hooks.contains(&hook) {
+                    return true;
+                }
+                // The AST was already evaluated on load, and calls should not leak variables
+                let options = CallFnOptions::new().eval_ast(false).rewind_scope(true);
+                let result = engine.call_fn_with_options::<Dynamic>(
+                    options,
+                    &mut script.scope,
+                    &script.ast,
+                    hook.function_name(),
+                    (),
+                );
+                match result {
+                    Ok(_) => true,
+                    Err(e) => {
+                        errors.push(format!("Script {} unloaded: {}", script.path.display(), e));
+                        false
+                    }
+                }
+            });
+            errors
+        });
+        self.scripts = scripts;
+        self.shared.borrow_mut().output.extend(errors);
+    }
+
+    /// Takes the output printed by scripts since the last call
+    pub fn take_output(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.shared.borrow_mut().output)
+    }
+
+    /// Returns true if a script called `pause()` since the last call
+    pub fn take_pause_request(&mut self) -> bool {
+        std::mem::take(&mut self.shared.borrow_mut().pause_requested)
+    }
+
+    /// Moves the emulator into the shared state so registered functions can access it while
+    /// `f` runs, then moves it back.
+    fn with_emu<T>(&self, emu: &mut Option<Gameboy>, f: impl FnOnce() -> T) -> T {
+        self.shared.borrow_mut().emu = emu.take();
+        let result = f();
+        *emu = self.shared.borrow_mut().emu.take();
+        result
+    }
+}
+
+/// Converts a script integer to an address, failing if it is out of range
+fn to_addr(addr: i64) -> Result<u16, Box<EvalAltResult>> {
+    u16::try_from(addr).map_err(|_| format!("Address out of range: {}", addr).into())
+}
+
+/// Sets the named register, truncating the value to the register's width.
+/// Returns false if the name is not a register.
+fn set_register(reg: &mut Registers, name: &str, val: u16) -> bool {
+    let [hi, lo] = val.to_be_bytes();
+    match name.to_lowercase().as_str() {
+        "a" => reg.a = lo,
+        "f" => reg.f = lo & 0xF0,
+        "b" => reg.b = lo,
+        "c" => reg.c = lo,
+        "d" => reg.d = lo,
+        "e" => reg.e = lo,
+        "h" => reg.h = lo,
+        "l" => reg.l = lo,
+        "af" => (reg.a, reg.f) = (hi, lo & 0xF0),
+        "bc" => (reg.b, reg.c) = (hi, lo),
+        "de" => (reg.d, reg.e) = (hi, lo),
+        "hl" => (reg.h, reg.l) = (hi, lo),
+        "sp" => reg.sp = val,
+        "pc" => reg.pc = val,
+        _ => return false,
+    }
+    true
+}
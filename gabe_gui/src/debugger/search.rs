@@ -0,0 +1,145 @@
+use std::ops::Range;
+
+use gabe_core::{disassemble::BankedAddress, gb::Gameboy};
+
+/// Memory searched besides the cartridge RAM: the work RAM, then the high RAM
+const SEARCHED: [Range<u16>; 2] = [0xC000..0xE000, 0xFF80..0xFFFF];
+
+/// Size of each bank of cartridge RAM mapped into 0xA000-0xBFFF
+const RAM_BANK_SIZE: usize = 0x2000;
+
+/// How a byte compares with its value in the last snapshot, or with a given value
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Filter {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    Value(u8),
+}
+
+impl Filter {
+    /// Parses a `search` subcommand and its value, if it takes one
+    pub fn parse(args: &[&str]) -> Option<Filter> {
+        match args {
+            ["equal" | "eq"] => Some(Filter::Equal),
+            ["changed" | "ne"] => Some(Filter::Changed),
+            ["increased" | "inc"] => Some(Filter::Increased),
+            ["decreased" | "dec"] => Some(Filter::Decreased),
+            ["value" | "val", value] => parse_value(value).map(Filter::Value),
+            _ => None,
+        }
+    }
+
+    fn keeps(self, old: u8, new: u8) -> bool {
+        match self {
+            Filter::Equal => new == old,
+            Filter::Changed => new != old,
+            Filter::Increased => new > old,
+            Filter::Decreased => new < old,
+            Filter::Value(v) => new == v,
+        }
+    }
+}
+
+/// Parses a byte value, in decimal as the values are listed, or in hexadecimal with a `0x`
+/// or `$` prefix
+fn parse_value(s: &str) -> Option<u8> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        Some(digits) => u8::from_str_radix(digits, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// A cheat search, narrowing down the RAM addresses that might hold a value, such as the
+/// player's health, by how the value changes as the game is played. Starts with every address
+/// in RAM, each filter keeping those whose byte compares as asked with the last snapshot,
+/// then taking a new one. Every bank of the cartridge RAM is searched, read from the cartridge
+/// so banks that aren't mapped, or RAM that's disabled, are searched too.
+pub struct RamSearch {
+    /// Addresses still in the running, with their values in the last snapshot
+    candidates: Vec<(BankedAddress, u8)>,
+}
+
+impl RamSearch {
+    /// Snapshots the RAM, starting a search with every address in it
+    pub fn start(emu: &Gameboy) -> Self {
+        let cartridge = emu.cartridge_ram().iter().enumerate().map(|(i, value)| {
+            let addr = BankedAddress {
+                bank: (i / RAM_BANK_SIZE) as u16,
+                address: 0xA000 + (i % RAM_BANK_SIZE) as u16,
+            };
+            (addr, *value)
+        });
+        let memory = emu.memory();
+        let mapped = SEARCHED.into_iter().flatten().map(|address| {
+            let addr = BankedAddress { bank: 0, address };
+            (addr, memory.read_byte(address))
+        });
+        RamSearch {
+            candidates: cartridge.chain(mapped).collect(),
+        }
+    }
+
+    /// Keeps the addresses that pass `filter`, taking their current values as the new
+    /// snapshot
+    pub fn filter(&mut self, filter: Filter, emu: &Gameboy) {
+        self.candidates.retain_mut(|(addr, value)| {
+            let new = read(emu, *addr);
+            let keep = filter.keeps(*value, new);
+            *value = new;
+            keep
+        });
+    }
+
+    /// The addresses left, with their values in the last snapshot
+    pub fn candidates(&self) -> &[(BankedAddress, u8)] {
+        &self.candidates
+    }
+}
+
+/// Reads a byte searched, from the cartridge for its RAM
+fn read(emu: &Gameboy, addr: BankedAddress) -> u8 {
+    match addr.address {
+        0xA000..=0xBFFF => {
+            let offset = addr.bank as usize * RAM_BANK_SIZE + (addr.address - 0xA000) as usize;
+            emu.cartridge_ram()[offset]
+        }
+        address => emu.memory().read_byte(address),
+    }
+}
+
+#[cfg(test)]
+mod search_tests {
+    use super::*;
+
+    #[test]
+    fn parse_filters() {
+        assert_eq!(Filter::parse(&["eq"]), Some(Filter::Equal));
+        assert_eq!(Filter::parse(&["changed"]), Some(Filter::Changed));
+        assert_eq!(Filter::parse(&["inc"]), Some(Filter::Increased));
+        assert_eq!(Filter::parse(&["decreased"]), Some(Filter::Decreased));
+        assert_eq!(Filter::parse(&["value", "0x3F"]), Some(Filter::Value(0x3F)));
+        assert_eq!(Filter::parse(&["value", "$ff"]), Some(Filter::Value(0xFF)));
+        assert_eq!(Filter::parse(&["val", "100"]), Some(Filter::Value(100)));
+        assert_eq!(Filter::parse(&["val", "256"]), None);
+        assert_eq!(Filter::parse(&["val", "0x100"]), None);
+        assert_eq!(Filter::parse(&["value"]), None);
+        assert_eq!(Filter::parse(&["eq", "1"]), None);
+        assert_eq!(Filter::parse(&["bigger"]), None);
+    }
+
+    #[test]
+    fn filters_keep() {
+        assert!(Filter::Equal.keeps(5, 5));
+        assert!(!Filter::Equal.keeps(5, 6));
+        assert!(Filter::Changed.keeps(5, 6));
+        assert!(!Filter::Changed.keeps(5, 5));
+        assert!(Filter::Increased.keeps(5, 6));
+        assert!(!Filter::Increased.keeps(5, 5));
+        assert!(Filter::Decreased.keeps(5, 4));
+        assert!(!Filter::Decreased.keeps(5, 6));
+        assert!(Filter::Value(9).keeps(5, 9));
+        assert!(!Filter::Value(9).keeps(9, 5));
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use gabe_core::debug::Coverage;
+use gabe_core::disassemble::{
+    disassemble_rom, disassemble_rom_lines, disassemble_rom_rgbds, parse_symbols, rom_symbols,
+    write_symbols, BankedAddress, CodeMap,
+};
+
+use crate::archive;
+use crate::cli::Args;
+
+/// How `--disassemble` writes the ROM's code
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+pub enum DisassemblyFormat {
+    /// A listing giving each instruction's bank, address and bytes
+    #[default]
+    Listing,
+    /// Source for rgbasm, with a section per bank, which assembles back into the same ROM. A
+    /// symbol file naming its labels is written next to it.
+    Rgbds,
+    /// A JSON array with an object for each instruction or line of data, giving its bank,
+    /// address, bytes, mnemonic, operands, and the target of jumps and calls
+    Json,
+}
+
+/// Disassembles the ROM into the file given by `--disassemble`, bank by bank
+pub fn run_disassemble(args: &Args) -> Result<(), String> {
+    let rom = args
+        .rom
+        .as_ref()
+        .ok_or("--disassemble requires a ROM file")?;
+    let path = args.disassemble.as_ref().ok_or("No output file given")?;
+    let rom = archive::read_rom(rom)?;
+    let names = match &args.symbols {
+        Some(path) => load_symbols(path)?,
+        None => BTreeMap::new(),
+    };
+    let map = match &args.coverage {
+        Some(path) => CodeMap::trace_with_coverage(&rom.data, load_coverage(path)?),
+        None => CodeMap::trace(&rom.data),
+    };
+    let mut output = match args.disassembly_format.unwrap_or_default() {
+        DisassemblyFormat::Listing => disassemble_rom(&rom.data, &map).join("\n"),
+        DisassemblyFormat::Rgbds => {
+            let sym_path = path.with_extension("sym");
+            let symbols = write_symbols(&rom_symbols(&rom.data, &map, &names));
+            fs::write(&sym_path, symbols).map_err(|e| format!("{}: {}", sym_path.display(), e))?;
+            disassemble_rom_rgbds(&rom.data, &map, &names).join("\n")
+        }
+        DisassemblyFormat::Json => {
+            serde_json::to_string_pretty(&disassemble_rom_lines(&rom.data, &map))
+                .map_err(|e| e.to_string())?
+        }
+    };
+    output.push('\n');
+    fs::write(path, output).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Reads the coverage recorded in a file by `--coverage`, or returns none if it doesn't exist
+/// yet
+pub fn load_coverage(path: &Path) -> Result<Coverage, String> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Coverage::from_bytes(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Coverage::new()),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+/// Writes recorded coverage to a file, to be added to or disassembled with later
+pub fn save_coverage(path: &Path, coverage: &Coverage) -> Result<(), String> {
+    fs::write(path, coverage.as_bytes()).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Reads the symbols in a symbol file, as written alongside rgbasm output
+pub fn load_symbols(path: &Path) -> Result<BTreeMap<BankedAddress, String>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(parse_symbols(&text))
+}
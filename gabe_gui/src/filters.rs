@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+/// Width of the Gameboy screen in pixels
+const WIDTH: usize = 160;
+/// Height of the Gameboy screen in pixels
+const HEIGHT: usize = 144;
+
+type Pixel = [u8; 3];
+
+/// Software upscaling filters applied to frames before they are displayed
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    /// Raw frames, scaled with nearest-neighbor
+    #[default]
+    None,
+    /// The Scale2x edge-smoothing filter
+    Scale2x,
+    /// The Scale3x edge-smoothing filter
+    Scale3x,
+    /// Dark scanlines between rows and an RGB phosphor mask
+    Crt,
+}
+
+impl Filter {
+    /// All filters, in the order cycled through by the filter hotkey
+    pub const ALL: [Filter; 4] = [Filter::None, Filter::Scale2x, Filter::Scale3x, Filter::Crt];
+
+    /// Returns the filter after this one, wrapping around to the first
+    pub fn next(self) -> Filter {
+        let i = Filter::ALL.iter().position(|f| *f == self).unwrap_or(0);
+        Filter::ALL[(i + 1) % Filter::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::None => "None",
+            Filter::Scale2x => "Scale2x",
+            Filter::Scale3x => "Scale3x",
+            Filter::Crt => "CRT",
+        }
+    }
+
+    /// Applies the filter to a 160x144 RGB frame, returning the filtered RGB data and its size
+    pub fn apply(self, frame: &[u8]) -> (Vec<u8>, [usize; 2]) {
+        let pixels: Vec<Pixel> = frame.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect();
+        let (factor, out) = match self {
+            Filter::None => return (frame.to_vec(), [WIDTH, HEIGHT]),
+            Filter::Scale2x => (2, scale2x(&pixels)),
+            Filter::Scale3x => (3, scale3x(&pixels)),
+            Filter::Crt => (3, crt(&pixels)),
+        };
+        (out.concat(), [WIDTH * factor, HEIGHT * factor])
+    }
+}
+
+/// Returns the pixel at (x, y), clamping coordinates to the edges of the screen
+fn pixel_at(pixels: &[Pixel], x: isize, y: isize) -> Pixel {
+    let x = x.clamp(0, WIDTH as isize - 1) as usize;
+    let y = y.clamp(0, HEIGHT as isize - 1) as usize;
+    pixels[y * WIDTH + x]
+}
+
+fn scale2x(pixels: &[Pixel]) -> Vec<Pixel> {
+    let out_width = WIDTH * 2;
+    let mut out = vec![[0; 3]; out_width * HEIGHT * 2];
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let (xi, yi) = (x as isize, y as isize);
+            let p = pixel_at(pixels, xi, yi);
+            let a = pixel_at(pixels, xi, yi - 1);
+            let b = pixel_at(pixels, xi + 1, yi);
+            let c = pixel_at(pixels, xi - 1, yi);
+            let d = pixel_at(pixels, xi, yi + 1);
+            let (mut e0, mut e1, mut e2, mut e3) = (p, p, p, p);
+            if c == a && c != d && a != b {
+                e0 = a;
+            }
+            if a == b && a != c && b != d {
+                e1 = b;
+            }
+            if d == c && d != b && c != a {
+                e2 = c;
+            }
+            if b == d && b != a && d != c {
+                e3 = d;
+            }
+            let top = y * 2 * out_width + x * 2;
+            out[top] = e0;
+            out[top + 1] = e1;
+            out[top + out_width] = e2;
+            out[top + out_width + 1] = e3;
+        }
+    }
+    out
+}
+
+fn scale3x(pixels: &[Pixel]) -> Vec<Pixel> {
+    let out_width = WIDTH * 3;
+    let mut out = vec![[0; 3]; out_width * HEIGHT * 3];
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let (xi, yi) = (x as isize, y as isize);
+            // Neighborhood, laid out as:
+            // a b c
+            // d e f
+            // g h i
+            let a = pixel_at(pixels, xi - 1, yi - 1);
+            let b = pixel_at(pixels, xi, yi - 1);
+            let c = pixel_at(pixels, xi + 1, yi - 1);
+            let d = pixel_at(pixels, xi - 1, yi);
+            let e = pixel_at(pixels, xi, yi);
+            let f = pixel_at(pixels, xi + 1, yi);
+            let g = pixel_at(pixels, xi - 1, yi + 1);
+            let h = pixel_at(pixels, xi, yi + 1);
+            let i = pixel_at(pixels, xi + 1, yi + 1);
+            let mut block = [e; 9];
+            if b != h && d != f {
+                if d == b {
+                    block[0] = d;
+                }
+                if (d == b && e != c) || (b == f && e != a) {
+                    block[1] = b;
+                }
+                if b == f {
+                    block[2] = f;
+                }
+                if (d == b && e != g) || (d == h && e != a) {
+                    block[3] = d;
+                }
+                if (b == f && e != i) || (h == f && e != c) {
+                    block[5] = f;
+                }
+                if d == h {
+                    block[6] = d;
+                }
+                if (d == h && e != i) || (h == f && e != g) {
+                    block[7] = h;
+                }
+                if h == f {
+                    block[8] = f;
+                }
+            }
+            for (n, color) in block.iter().enumerate() {
+                out[(y * 3 + n / 3) * out_width + x * 3 + n % 3] = *color;
+            }
+        }
+    }
+    out
+}
+
+fn crt(pixels: &[Pixel]) -> Vec<Pixel> {
+    let out_width = WIDTH * 3;
+    let mut out = vec![[0; 3]; out_width * HEIGHT * 3];
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let p = pixels[y * WIDTH + x];
+            for row in 0..3 {
+                for col in 0..3 {
+                    // Each column of the 3x3 block favors one of the red, green and blue
+                    // phosphors, and the last row is darkened as a scanline gap
+                    let mut color = p;
+                    for (channel, value) in color.iter_mut().enumerate() {
+                        let mut level = if channel == col { 100 } else { 70 };
+                        if row == 2 {
+                            level /= 2;
+                        }
+                        *value = (*value as u16 * level / 100) as u8;
+                    }
+                    out[(y * 3 + row) * out_width + x * 3 + col] = color;
+                }
+            }
+        }
+    }
+    out
+}
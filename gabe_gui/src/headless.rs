@@ -0,0 +1,489 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use gabe_core::{
+    debug::{NoProfiler, Profiler, Subsystem},
+    gb::{Gameboy, GbKeys},
+    rtc::ManualClock,
+    sink::{Sink, VideoFrame},
+    CLOCK_HZ,
+};
+
+use crate::{
+    archive, cheats,
+    cli::Args,
+    config::Config,
+    debugger::format_registers,
+    disassembly::{load_coverage, save_coverage},
+    input::parse_button,
+    screenshot,
+    video_sinks::{BlendMode, LcdVideoSink},
+};
+
+/// CPU cycles taken to draw one frame
+pub const CYCLES_PER_FRAME: u64 = gabe_core::CYCLES_PER_FRAME as u64;
+
+/// Discards everything appended to it
+pub struct NullSink;
+
+impl<T> Sink<T> for NullSink {
+    fn append(&mut self, _value: T) {}
+}
+
+/// Runs a Gameboy without a window or audio device, as fast as the host allows
+pub struct Headless {
+    pub emu: Gameboy,
+    save_path: PathBuf,
+    /// Frames run so far
+    pub frames: u64,
+    /// Cycles run so far
+    pub cycles: u64,
+    /// The most recent frame drawn by the core, if the LCD has been on
+    pub last_frame: Option<VideoFrame>,
+    /// The cartridge RTC's clock, which follows the cycles run rather than the wall clock, so
+    /// a run plays back the same way however fast it goes
+    rtc_clock: ManualClock,
+}
+
+impl Headless {
+    /// Loads a ROM, which may be in a zip or gzip archive, and its save file if one exists.
+    /// The save file is `save_file` if given, or found following the save settings.
+    pub fn load(
+        rom_path: &Path,
+        save_file: Option<&Path>,
+        config: &Config,
+    ) -> Result<Headless, String> {
+        let rom = archive::read_rom(rom_path)?;
+        let save_path = save_file.map_or_else(
+            || archive::save_path(&rom, &config.saves),
+            Path::to_path_buf,
+        );
+        let rtc_clock = ManualClock::default();
+        let mut builder = Gameboy::builder()
+            .rom_bytes(rom.data.into_boxed_slice())
+            .model(config.emulation.model.into())
+            .cgb_mode(config.emulation.cgb_mode)
+            .rtc_clock(Box::new(rtc_clock.clone()));
+        if let Some(colorization) = config.emulation.colorization()? {
+            builder = builder.colorization(colorization);
+        }
+        if let Ok(save_data) = fs::read(&save_path) {
+            builder = builder.save_data(save_data.into_boxed_slice());
+        }
+        let emu = builder.build().map_err(|e| e.to_string())?;
+        Ok(Headless {
+            emu,
+            save_path,
+            frames: 0,
+            cycles: 0,
+            last_frame: None,
+            rtc_clock,
+        })
+    }
+
+    /// Adds the cheats from the cheat file in the config, if there is one
+    pub fn add_cheats(&mut self, config: &Config) -> Result<(), String> {
+        for cheat in cheats::from_config(config)? {
+            self.emu.add_cheat(cheat);
+        }
+        Ok(())
+    }
+
+    /// Runs until the core finishes drawing a frame. While the LCD is off no frames are drawn,
+    /// so a frame's worth of cycles counts as a frame instead.
+    pub fn run_frame(&mut self) {
+        self.run_frame_profiled(&mut NoProfiler);
+    }
+
+    /// Same as `run_frame`, timing each subsystem with the profiler
+    pub fn run_frame_profiled<P: Profiler>(&mut self, profiler: &mut P) {
+        let mut video_sink = LcdVideoSink::new(BlendMode::Raw);
+        let start = self.cycles;
+        while !video_sink.has_frame() && self.cycles - start < CYCLES_PER_FRAME {
+            self.cycles +=
+                self.emu
+                    .step_profiled(&mut video_sink, &mut NullSink, profiler) as u64;
+        }
+        if let Some(frame) = video_sink.get_frame() {
+            self.last_frame = Some(frame);
+        }
+        self.rtc_clock.set(self.cycles / CLOCK_HZ as u64);
+        self.frames += 1;
+    }
+
+    /// Same as `run_frame`, but stops as soon as the CPU is about to execute the instruction
+    /// at `addr`, returning true if it did. The frame isn't counted if it was cut short.
+    pub fn run_frame_until(&mut self, addr: u16) -> bool {
+        let mut video_sink = LcdVideoSink::new(BlendMode::Raw);
+        let start = self.cycles;
+        while !video_sink.has_frame() && self.cycles - start < CYCLES_PER_FRAME {
+            if self.emu.get_pc() == addr {
+                return true;
+            }
+            self.cycles += self.emu.step(&mut video_sink, &mut NullSink) as u64;
+        }
+        if let Some(frame) = video_sink.get_frame() {
+            self.last_frame = Some(frame);
+        }
+        self.rtc_clock.set(self.cycles / CLOCK_HZ as u64);
+        self.frames += 1;
+        false
+    }
+
+    /// Writes the game's save data to the save file, if the cartridge has any
+    pub fn save(&self) -> Result<(), String> {
+        match self.emu.get_save_data() {
+            Some(data) => {
+                archive::create_save_dir(&self.save_path)?;
+                fs::write(&self.save_path, data)
+                    .map_err(|e| format!("{}: {}", self.save_path.display(), e))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Button presses and releases to apply at given frames, loaded from a text file with one
+/// `FRAME BUTTON down|up` event per line, e.g. `120 start down`. Lines starting with `#` are
+/// ignored.
+#[derive(Default)]
+pub struct InputScript {
+    /// Events sorted by frame
+    events: Vec<(u64, GbKeys, bool)>,
+    next: usize,
+}
+
+impl InputScript {
+    pub fn load(path: &Path) -> Result<InputScript, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut events = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |msg: &str| format!("{}:{}: {}", path.display(), number + 1, msg);
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let [frame, button, state] = parts[..] else {
+                return Err(error("Expected FRAME BUTTON down|up"));
+            };
+            let frame = frame.parse().map_err(|_| error("Invalid frame number"))?;
+            let button = parse_button(button).map_err(|e| error(&e))?;
+            let pressed = match state.to_lowercase().as_str() {
+                "down" => true,
+                "up" => false,
+                _ => return Err(error("Expected down or up")),
+            };
+            events.push((frame, button, pressed));
+        }
+        // Stable, so events on the same frame keep their order
+        events.sort_by_key(|(frame, _, _)| *frame);
+        Ok(InputScript { events, next: 0 })
+    }
+
+    /// Applies the events due by `frame` to the emulator
+    pub fn apply(&mut self, frame: u64, emu: &mut Gameboy) {
+        while let Some(&(event_frame, button, pressed)) = self.events.get(self.next) {
+            if event_frame > frame {
+                break;
+            }
+            emu.update_key_state(button, pressed);
+            self.next += 1;
+        }
+    }
+}
+
+/// Runs the ROM given on the command line without a window, for `--headless`.
+/// With `--run-until`, stops once the address is reached and prints the registers, failing
+/// if it isn't reached within `--timeout-frames`.
+pub fn run_headless(args: &Args, config: &Config) -> Result<(), String> {
+    let rom = args.rom.as_ref().ok_or("--headless requires a ROM file")?;
+    let mut runner = Headless::load(rom, args.save_file.as_deref(), config)?;
+    runner.add_cheats(config)?;
+    if let Some(path) = &args.coverage {
+        runner.emu.start_coverage(load_coverage(path)?);
+    }
+    let mut inputs = match &args.input {
+        Some(path) => InputScript::load(path)?,
+        None => InputScript::default(),
+    };
+    let start = Instant::now();
+    let frame_limit = args.frames.into_iter().chain(args.timeout_frames).min();
+    let mut reached = false;
+    while frame_limit.map_or(true, |frames| runner.frames < frames) {
+        inputs.apply(runner.frames, &mut runner.emu);
+        match args.run_until {
+            Some(addr) => {
+                if runner.run_frame_until(addr) {
+                    reached = true;
+                    break;
+                }
+            }
+            None => runner.run_frame(),
+        }
+        if runner.emu.fault().is_some() {
+            break;
+        }
+    }
+    runner.save()?;
+    if let (Some(path), Some(coverage)) = (&args.coverage, runner.emu.coverage()) {
+        save_coverage(path, coverage)?;
+    }
+    println!(
+        "Ran {} frames in {:.2}s",
+        runner.frames,
+        start.elapsed().as_secs_f32()
+    );
+    if let Some(fault) = runner.emu.fault() {
+        return Err(format!("CPU stopped: {}", fault));
+    }
+    let Some(addr) = args.run_until else {
+        return Ok(());
+    };
+    println!("{}", format_registers(&runner.emu));
+    if let Some(path) = &args.dump_memory {
+        fs::write(path, runner.emu.get_memory_range(0..0x10000))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    if reached {
+        println!("Reached {:04X}", addr);
+        Ok(())
+    } else {
+        Err(format!(
+            "Didn't reach {:04X} within {} frames",
+            addr, runner.frames
+        ))
+    }
+}
+
+/// Times each subsystem by the host time between the core's profiler marks
+struct TimingProfiler {
+    last_mark: Instant,
+    totals: [Duration; Subsystem::ALL.len()],
+}
+
+impl Profiler for TimingProfiler {
+    fn mark(&mut self, subsystem: Subsystem) {
+        let now = Instant::now();
+        self.totals[subsystem as usize] += now - self.last_mark;
+        self.last_mark = now;
+    }
+}
+
+/// Runs the number of frames given by `--bench` as fast as possible, and prints how fast
+/// they ran and where the time went. Frames are passed through the configured filter, as
+/// they would be for display, unless `--no-render` is given.
+pub fn run_bench(args: &Args, config: &Config) -> Result<(), String> {
+    let rom = args.rom.as_ref().ok_or("--bench requires a ROM file")?;
+    let frames = args.bench.unwrap_or(0);
+    let mut runner = Headless::load(rom, args.save_file.as_deref(), config)?;
+    let mut profiler = TimingProfiler {
+        last_mark: Instant::now(),
+        totals: Default::default(),
+    };
+    let mut render_time = Duration::ZERO;
+    let start = Instant::now();
+    while runner.frames < frames {
+        profiler.last_mark = Instant::now();
+        runner.run_frame_profiled(&mut profiler);
+        if !args.no_render {
+            let render_start = Instant::now();
+            if let Some(frame) = &runner.last_frame {
+                let _rendered = config.video.filter.apply(frame);
+            }
+            render_time += render_start.elapsed();
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let emulated = runner.cycles as f64 / gabe_core::CLOCK_RATE as f64;
+    println!("Ran {} frames in {:.3}s", runner.frames, elapsed);
+    println!("{:.1} frames/sec", runner.frames as f64 / elapsed);
+    println!("{:.2}x real time", emulated / elapsed);
+    let mut timings: Vec<(String, Duration)> = Subsystem::ALL
+        .iter()
+        .map(|s| (format!("{:?}", s), profiler.totals[*s as usize]))
+        .collect();
+    if !args.no_render {
+        timings.push(("Render".to_string(), render_time));
+    }
+    for (name, time) in timings {
+        println!(
+            "{:>8}: {:9.3}ms ({:4.1}%)",
+            name,
+            time.as_secs_f64() * 1000.0,
+            time.as_secs_f64() / elapsed * 100.0
+        );
+    }
+    Ok(())
+}
+
+/// Outcome of running a test ROM, as reported by `--test-rom`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TestResult {
+    Passed,
+    Failed,
+    /// The ROM didn't report a result before the frame limit
+    TimedOut,
+}
+
+impl TestResult {
+    /// Returns the process exit code for the result
+    pub fn exit_code(self) -> i32 {
+        match self {
+            TestResult::Passed => 0,
+            TestResult::Failed => 1,
+            TestResult::TimedOut => 2,
+        }
+    }
+}
+
+/// Frames a test ROM may run for before timing out, if `--frames` isn't given.
+/// About a minute of emulated time.
+const TEST_ROM_FRAMES: u64 = 3600;
+/// Opcode of `LD B,B`, which Mooneye test ROMs execute once they finish
+const LD_B_B: u8 = 0x40;
+/// Signature written to $A001-$A003 by Blargg test ROMs that report through memory
+const BLARGG_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+
+/// Runs a test ROM without a window until it reports a result, for `--test-rom`.
+/// Results are detected from the conventions used by common test ROMs:
+/// - "Passed" or "Failed" written to the serial port (Blargg)
+/// - A result code at $A000 with text from $A004, after the $A001 signature (Blargg)
+/// - An `LD B,B` with the Fibonacci numbers 3, 5, 8, 13, 21, 34 in B-L on success (Mooneye)
+pub fn run_test_rom(args: &Args, config: &Config) -> Result<TestResult, String> {
+    let rom = args.rom.as_ref().ok_or("--test-rom requires a ROM file")?;
+    let mut runner = Headless::load(rom, args.save_file.as_deref(), config)?;
+    let max_cycles = args.frames.unwrap_or(TEST_ROM_FRAMES) * CYCLES_PER_FRAME;
+    let mut serial = String::new();
+    let mut next_memory_check = 0;
+    let result = loop {
+        if runner.cycles >= max_cycles {
+            break TestResult::TimedOut;
+        }
+        let pc = runner.emu.get_pc();
+        if runner.emu.get_memory_range(pc as usize..pc as usize + 1)[0] == LD_B_B {
+            let reg = runner.emu.get_debug_state().cpu_data.reg;
+            let values = [reg.b, reg.c, reg.d, reg.e, reg.h, reg.l];
+            if values == [3, 5, 8, 13, 21, 34] {
+                break TestResult::Passed;
+            } else if values == [0x42; 6] {
+                break TestResult::Failed;
+            }
+        }
+        runner.cycles += runner.emu.step(&mut NullSink, &mut NullSink) as u64;
+        if let Some(byte) = runner.emu.poll_serial() {
+            serial.push(byte as char);
+            if serial.contains("Passed") {
+                break TestResult::Passed;
+            } else if serial.contains("Failed") {
+                break TestResult::Failed;
+            }
+        }
+        // The memory result is only checked once per frame, as it's slow to read
+        if runner.cycles >= next_memory_check {
+            next_memory_check += CYCLES_PER_FRAME;
+            let status = runner.emu.get_memory_range(0xA000..0xA004);
+            if status[1..] == BLARGG_SIGNATURE && status[0] != 0x80 {
+                let text = runner.emu.get_memory_range(0xA004..0xB000);
+                let text = text.iter().take_while(|c| **c != 0).map(|c| *c as char);
+                serial.extend(text);
+                break if status[0] == 0 {
+                    TestResult::Passed
+                } else {
+                    TestResult::Failed
+                };
+            }
+        }
+    };
+    if !serial.is_empty() {
+        println!("{}", serial.trim_end());
+    }
+    println!(
+        "{}: {:?} after {} frames",
+        rom.display(),
+        result,
+        runner.cycles / CYCLES_PER_FRAME
+    );
+    Ok(result)
+}
+
+/// Frames run before comparing against the reference image, if `--frames` isn't given
+const REFERENCE_FRAMES: u64 = 600;
+
+/// Returns the 64-bit FNV-1a hash of a frame, to identify it in reports
+pub fn frame_hash(frame: &[u8]) -> u64 {
+    frame.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Runs the ROM for a number of frames and compares the final frame to a reference image, for
+/// `--reference`. If the reference doesn't exist yet, the frame is saved as the reference.
+/// On a mismatch, an image marking the differing pixels in red is saved next to the
+/// reference. Returns true if the frame matched or a new reference was saved.
+pub fn run_regression(args: &Args, config: &Config) -> Result<bool, String> {
+    let rom = args.rom.as_ref().ok_or("--reference requires a ROM file")?;
+    let reference_path = args.reference.as_ref().ok_or("No reference image given")?;
+    let mut runner = Headless::load(rom, args.save_file.as_deref(), config)?;
+    runner.add_cheats(config)?;
+    let mut inputs = match &args.input {
+        Some(path) => InputScript::load(path)?,
+        None => InputScript::default(),
+    };
+    let frames = args.frames.unwrap_or(REFERENCE_FRAMES);
+    while runner.frames < frames {
+        inputs.apply(runner.frames, &mut runner.emu);
+        runner.run_frame();
+    }
+    let frame = runner
+        .last_frame
+        .as_ref()
+        .ok_or("The LCD was never turned on, so there is no frame to compare")?;
+    println!("Frame {} hash: {:016x}", runner.frames, frame_hash(frame));
+
+    if !reference_path.exists() {
+        screenshot::write_png(reference_path, frame, 1)?;
+        println!("Saved new reference {}", reference_path.display());
+        return Ok(true);
+    }
+    let reference = screenshot::read_png(reference_path)?;
+    let mut diff = Vec::with_capacity(frame.len());
+    let mut differing = 0;
+    let mut first_diff = None;
+    for (i, (actual, expected)) in frame
+        .chunks_exact(3)
+        .zip(reference.chunks_exact(3))
+        .enumerate()
+    {
+        if actual == expected {
+            // Dim matching pixels, so the differences stand out
+            diff.extend(actual.iter().map(|c| c / 3));
+        } else {
+            diff.extend_from_slice(&[0xFF, 0, 0]);
+            differing += 1;
+            first_diff.get_or_insert((i % 160, i / 160));
+        }
+    }
+    match first_diff {
+        None => {
+            println!("Matches {}", reference_path.display());
+            Ok(true)
+        }
+        Some((x, y)) => {
+            let diff_path = reference_path.with_extension("diff.png");
+            screenshot::write_png(&diff_path, &diff, 1)?;
+            println!(
+                "{} pixels differ from {}, first at ({}, {}). Differences saved to {}",
+                differing,
+                reference_path.display(),
+                x,
+                y,
+                diff_path.display()
+            );
+            Ok(false)
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use egui::Key;
+use gabe_core::gb::{Gameboy, GbKeys, JoypadState};
+
+use crate::config::{KeyConfig, PlayerKeyConfig};
+
+/// All keys that can be bound to a button, used to look keys up by name
+const BINDABLE_KEYS: &[Key] = &[
+    Key::ArrowDown,
+    Key::ArrowLeft,
+    Key::ArrowRight,
+    Key::ArrowUp,
+    Key::Escape,
+    Key::Tab,
+    Key::Backspace,
+    Key::Enter,
+    Key::Space,
+    Key::Insert,
+    Key::Delete,
+    Key::Home,
+    Key::End,
+    Key::PageUp,
+    Key::PageDown,
+    Key::Minus,
+    Key::PlusEquals,
+    Key::Num0,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+];
+
+/// The Gameboy buttons, in the order of the bits in a button mask
+const BUTTONS: [GbKeys; 8] = [
+    GbKeys::A,
+    GbKeys::B,
+    GbKeys::Start,
+    GbKeys::Select,
+    GbKeys::Up,
+    GbKeys::Down,
+    GbKeys::Left,
+    GbKeys::Right,
+];
+
+/// The keyboard key bound to each Gameboy button, and to the emulator controls
+pub struct KeyMap {
+    /// In the same order as `BUTTONS`
+    bindings: [(GbKeys, Key); 8],
+    /// The buttons bound for players 2 to 4
+    players: [Vec<(GbKeys, Key)>; 3],
+    fast_forward: Key,
+    pause: Key,
+    volume_down: Key,
+    volume_up: Key,
+    mute: Key,
+    /// Up, down, left and right
+    tilt: [Key; 4],
+    tilt_center: Key,
+}
+
+impl KeyMap {
+    /// Builds the key map from the key names in the config
+    pub fn from_config(config: &KeyConfig) -> Result<KeyMap, String> {
+        let keys = [
+            &config.a,
+            &config.b,
+            &config.start,
+            &config.select,
+            &config.up,
+            &config.down,
+            &config.left,
+            &config.right,
+        ];
+        let mut bindings = [(GbKeys::A, Key::X); 8];
+        for ((binding, button), key) in bindings.iter_mut().zip(BUTTONS).zip(keys) {
+            *binding = (button, parse_key(key)?);
+        }
+        Ok(KeyMap {
+            bindings,
+            players: [
+                player_bindings(&config.player2)?,
+                player_bindings(&config.player3)?,
+                player_bindings(&config.player4)?,
+            ],
+            fast_forward: parse_key(&config.fast_forward)?,
+            pause: parse_key(&config.pause)?,
+            volume_down: parse_key(&config.volume_down)?,
+            volume_up: parse_key(&config.volume_up)?,
+            mute: parse_key(&config.mute)?,
+            tilt: [
+                parse_key(&config.tilt_up)?,
+                parse_key(&config.tilt_down)?,
+                parse_key(&config.tilt_left)?,
+                parse_key(&config.tilt_right)?,
+            ],
+            tilt_center: parse_key(&config.tilt_center)?,
+        })
+    }
+
+    /// Returns true while the fast-forward key is held
+    pub fn fast_forward_held(&self, ctx: &egui::Context) -> bool {
+        ctx.input(|i| i.key_down(self.fast_forward))
+    }
+
+    /// Returns true if the pause key was pressed since the last frame
+    pub fn pause_pressed(&self, ctx: &egui::Context) -> bool {
+        ctx.input(|i| i.key_pressed(self.pause))
+    }
+
+    /// Returns the volume change requested by the volume keys since the last frame:
+    /// -1 for down, 1 for up, or 0
+    pub fn volume_change(&self, ctx: &egui::Context) -> i32 {
+        ctx.input(|i| i.key_pressed(self.volume_up) as i32 - i.key_pressed(self.volume_down) as i32)
+    }
+
+    /// Returns true if the mute key was pressed since the last frame
+    pub fn mute_pressed(&self, ctx: &egui::Context) -> bool {
+        ctx.input(|i| i.key_pressed(self.mute))
+    }
+
+    /// Returns the tilt asked for by the tilt keys held, from -1 to 1 along each axis, with
+    /// right and up positive
+    pub fn tilt_held(&self, ctx: &egui::Context) -> (f32, f32) {
+        let [up, down, left, right] = self.tilt;
+        ctx.input(|i| {
+            let axis =
+                |positive, negative| i.key_down(positive) as i32 - i.key_down(negative) as i32;
+            (axis(right, left) as f32, axis(up, down) as f32)
+        })
+    }
+
+    /// Returns true if the tilt center key was pressed since the last frame
+    pub fn tilt_center_pressed(&self, ctx: &egui::Context) -> bool {
+        ctx.input(|i| i.key_pressed(self.tilt_center))
+    }
+
+    /// Returns the Gameboy button bound to a key, for any player, if any
+    pub fn button(&self, key: Key) -> Option<GbKeys> {
+        self.bindings
+            .iter()
+            .chain(self.players.iter().flatten())
+            .find(|(_, k)| *k == key)
+            .map(|(button, _)| *button)
+    }
+
+    /// Updates the emulator's button states from the currently held keys, for every player
+    pub fn update_key_states(&self, ctx: &egui::Context, gb: &mut Gameboy) {
+        ctx.input(|i| {
+            for (button, key) in self.bindings {
+                gb.update_key_state(button, i.key_down(key));
+            }
+            for (player, bindings) in self.players.iter().enumerate() {
+                let input = bindings
+                    .iter()
+                    .filter(|(_, key)| i.key_down(*key))
+                    .fold(JoypadState::empty(), |input, (button, _)| {
+                        input | JoypadState::from(*button)
+                    });
+                gb.set_player_input(player + 1, input);
+            }
+        });
+    }
+
+    /// Returns the buttons whose keys are currently held, as a mask with a bit per button
+    pub fn held_buttons(&self, ctx: &egui::Context) -> u8 {
+        ctx.input(|i| {
+            self.bindings
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, key))| i.key_down(*key))
+                .fold(0, |mask, (bit, _)| mask | 1 << bit)
+        })
+    }
+}
+
+/// Sets the emulator's button states from a mask returned by `KeyMap::held_buttons`
+pub fn set_buttons(gb: &mut Gameboy, mask: u8) {
+    for (bit, button) in BUTTONS.into_iter().enumerate() {
+        gb.update_key_state(button, mask & (1 << bit) != 0);
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap::from_config(&KeyConfig::default()).expect("Default key names are valid")
+    }
+}
+
+/// Looks up the keys bound to another player's buttons, leaving out those without one
+fn player_bindings(config: &PlayerKeyConfig) -> Result<Vec<(GbKeys, Key)>, String> {
+    let keys = [
+        &config.a,
+        &config.b,
+        &config.start,
+        &config.select,
+        &config.up,
+        &config.down,
+        &config.left,
+        &config.right,
+    ];
+    BUTTONS
+        .into_iter()
+        .zip(keys)
+        .filter_map(|(button, key)| Some((button, key.as_deref()?)))
+        .map(|(button, key)| Ok((button, parse_key(key)?)))
+        .collect()
+}
+
+/// Finds a key by its egui name (e.g. "X", "Enter", "Up") or variant name (e.g. "ArrowUp"),
+/// ignoring case
+pub fn parse_key(name: &str) -> Result<Key, String> {
+    BINDABLE_KEYS
+        .iter()
+        .find(|k| {
+            k.name().eq_ignore_ascii_case(name) || format!("{:?}", k).eq_ignore_ascii_case(name)
+        })
+        .copied()
+        .ok_or_else(|| format!("Unknown key: {}", name))
+}
+
+/// Finds a Gameboy button by name: a, b, start, select, up, down, left or right
+pub fn parse_button(name: &str) -> Result<GbKeys, String> {
+    match name.to_lowercase().as_str() {
+        "a" => Ok(GbKeys::A),
+        "b" => Ok(GbKeys::B),
+        "start" => Ok(GbKeys::Start),
+        "select" => Ok(GbKeys::Select),
+        "up" => Ok(GbKeys::Up),
+        "down" => Ok(GbKeys::Down),
+        "left" => Ok(GbKeys::Left),
+        "right" => Ok(GbKeys::Right),
+        _ => Err(format!("Unknown button: {}", name)),
+    }
+}
+
+/// Sets the key bound to a button in the config from a `key=button` mapping,
+/// e.g. `W=up` or `J=a`. Buttons of players 2 to 4 are prefixed with the player,
+/// e.g. `G=p2:a`.
+pub fn apply_mapping(config: &mut KeyConfig, mapping: &str) -> Result<(), String> {
+    let (key, button) = mapping
+        .split_once('=')
+        .ok_or_else(|| format!("Expected key=button, got: {}", mapping))?;
+    let key = parse_key(key.trim())?;
+    if let Some((player, button)) = button.trim().split_once(':') {
+        let player = match player.to_lowercase().as_str() {
+            "p2" => &mut config.player2,
+            "p3" => &mut config.player3,
+            "p4" => &mut config.player4,
+            _ => return Err(format!("Unknown player: {}", player)),
+        };
+        let binding = match button.to_lowercase().as_str() {
+            "a" => &mut player.a,
+            "b" => &mut player.b,
+            "start" => &mut player.start,
+            "select" => &mut player.select,
+            "up" => &mut player.up,
+            "down" => &mut player.down,
+            "left" => &mut player.left,
+            "right" => &mut player.right,
+            _ => return Err(format!("Unknown button: {}", button)),
+        };
+        *binding = Some(key.name().to_string());
+        return Ok(());
+    }
+    let binding = match button.trim().to_lowercase().as_str() {
+        "a" => &mut config.a,
+        "b" => &mut config.b,
+        "start" => &mut config.start,
+        "select" => &mut config.select,
+        "up" => &mut config.up,
+        "down" => &mut config.down,
+        "left" => &mut config.left,
+        "right" => &mut config.right,
+        "fast_forward" => &mut config.fast_forward,
+        "pause" => &mut config.pause,
+        "volume_down" => &mut config.volume_down,
+        "volume_up" => &mut config.volume_up,
+        "mute" => &mut config.mute,
+        "tilt_up" => &mut config.tilt_up,
+        "tilt_down" => &mut config.tilt_down,
+        "tilt_left" => &mut config.tilt_left,
+        "tilt_right" => &mut config.tilt_right,
+        "tilt_center" => &mut config.tilt_center,
+        _ => return Err(format!("Unknown button: {}", button)),
+    };
+    *binding = key.name().to_string();
+    Ok(())
+}
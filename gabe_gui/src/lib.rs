@@ -1,7 +1,34 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
+mod archive;
 mod audio_driver;
+mod cheats;
+mod cli;
+mod config;
+mod debugger;
+mod disassembly;
+mod filters;
+mod headless;
+mod input;
+mod link;
+mod netplay;
+mod osd;
+mod pacing;
+mod palettes;
+mod recent;
+mod recorder;
+mod rumble;
+mod screenshot;
+mod stats;
+mod terminal;
+mod tilt;
 mod time_source;
 mod video_sinks;
-pub use app::GabeApp;
+pub use app::{window_size, GabeApp, WINDOW_TITLE};
+pub use cli::Args;
+pub use config::Config;
+pub use disassembly::run_disassemble;
+pub use headless::{run_bench, run_headless, run_regression, run_test_rom};
+pub use netplay::Netplay;
+pub use terminal::run_terminal;
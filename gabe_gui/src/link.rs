@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+
+use egui::{
+    load::SizedTexture, ColorImage, Image, TextureHandle, TextureOptions, ViewportBuilder,
+    ViewportId,
+};
+use gabe_core::{
+    gb::{Gameboy, Model},
+    palette::Palette,
+    serial,
+    sink::{AudioFrame, Sink},
+};
+
+use crate::{
+    app::window_size,
+    archive,
+    config::SaveConfig,
+    input::KeyMap,
+    video_sinks::{BlendMode, LcdVideoSink},
+};
+
+/// Discards the second Gameboy's sound, as only the main one is played
+struct NoAudio;
+
+impl Sink<AudioFrame> for NoAudio {
+    fn append(&mut self, _value: AudioFrame) {}
+}
+
+/// A second Gameboy connected to the main one by an emulated link cable, shown in its own
+/// window. It's stepped alongside the main Gameboy, so both run at the same speed.
+pub struct LinkedGameboy {
+    emu: Gameboy,
+    /// Cycles run, kept level with the main Gameboy
+    cycles: u64,
+    /// Where the save data is written when the game stops
+    save_path: PathBuf,
+    framebuffer: TextureHandle,
+    video_sink: LcdVideoSink,
+    /// Whether the window had focus when last shown
+    pub focused: bool,
+}
+
+impl LinkedGameboy {
+    /// Loads a ROM into a second Gameboy and connects it to `main` with a link cable. It has its
+    /// own save file alongside the main one, `NAME.2.sav`, so the two don't overwrite each
+    /// other's saves when running the same game.
+    pub fn start(
+        ctx: &egui::Context,
+        main: &mut Gameboy,
+        path: &Path,
+        saves: &SaveConfig,
+        model: Model,
+        cgb_mode: bool,
+    ) -> Result<Self, String> {
+        let rom = archive::read_rom(path)?;
+        let save_path = archive::save_path(&rom, saves).with_extension("2.sav");
+        let save_data = std::fs::read(&save_path).unwrap_or_default();
+        let mut emu = Gameboy::builder()
+            .rom_bytes(rom.data.into_boxed_slice())
+            .model(model)
+            .cgb_mode(cgb_mode)
+            .save_data(save_data.into_boxed_slice())
+            .build()
+            .map_err(|e| e.to_string())?;
+        let (main_port, linked_port) = serial::link_cable();
+        main.connect_serial(Box::new(main_port));
+        emu.connect_serial(Box::new(linked_port));
+        Ok(LinkedGameboy {
+            emu,
+            cycles: 0,
+            save_path,
+            framebuffer: ctx.load_texture(
+                "link_framebuffer",
+                ColorImage::default(),
+                Default::default(),
+            ),
+            video_sink: LcdVideoSink::new(BlendMode::default()),
+            focused: false,
+        })
+    }
+
+    /// Runs until the second Gameboy has caught up with `cycles` run by the main one
+    pub fn run_to(&mut self, cycles: u64) {
+        while self.cycles < cycles {
+            self.cycles += self.emu.step(&mut self.video_sink, &mut NoAudio) as u64;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.emu.reset();
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.emu.set_palette(palette);
+    }
+
+    /// Shows the second Gameboy's window, which takes the configured keys while it has focus.
+    /// Returns false once the window has been closed.
+    pub fn show(&mut self, ctx: &egui::Context, key_map: &KeyMap, smooth: bool) -> bool {
+        if let Some(frame) = self.video_sink.get_frame() {
+            let options = if smooth {
+                TextureOptions::LINEAR
+            } else {
+                TextureOptions::NEAREST
+            };
+            self.framebuffer
+                .set(ColorImage::from_rgb([160, 144], &frame), options);
+        }
+        let builder = ViewportBuilder::default()
+            .with_title(format!("{} (Player 2)", crate::app::WINDOW_TITLE))
+            .with_inner_size(window_size(2));
+        ctx.show_viewport_immediate(ViewportId::from_hash_of("link"), builder, |ctx, _| {
+            self.focused = ctx.input(|i| i.raw.focused);
+            if !ctx.wants_keyboard_input() {
+                key_map.update_key_states(ctx, &mut self.emu);
+            }
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.centered_and_justified(|ui| {
+                    ui.add(
+                        Image::new(SizedTexture::from_handle(&self.framebuffer))
+                            .maintain_aspect_ratio(true)
+                            .shrink_to_fit(),
+                    );
+                });
+            });
+            !ctx.input(|i| i.viewport().close_requested())
+        })
+    }
+
+    /// Writes the second Gameboy's save data, if the cartridge has any
+    pub fn save(&self) {
+        if let Some(data) = self.emu.get_save_data() {
+            let result = archive::create_save_dir(&self.save_path).and_then(|_| {
+                std::fs::write(&self.save_path, data)
+                    .map_err(|e| format!("{}: {}", self.save_path.display(), e))
+            });
+            if let Err(e) = result {
+                log::error!("{}", e);
+            }
+        }
+    }
+}
@@ -8,15 +8,84 @@ fn main() {
     // Log to stdout (if you run with `RUST_LOG=debug`).
     tracing_subscriber::fmt::init();
 
+    use clap::Parser;
+    let args = gabe_gui::Args::parse();
+    let config = match args.load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if args.test_rom {
+        match gabe_gui::run_test_rom(&args, &config) {
+            Ok(result) => std::process::exit(result.exit_code()),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(3);
+            }
+        }
+    }
+    if args.reference.is_some() {
+        match gabe_gui::run_regression(&args, &config) {
+            Ok(matched) => std::process::exit(if matched { 0 } else { 1 }),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(3);
+            }
+        }
+    }
+    if args.disassemble.is_some() {
+        if let Err(e) = gabe_gui::run_disassemble(&args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.bench.is_some() {
+        if let Err(e) = gabe_gui::run_bench(&args, &config) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.terminal {
+        if let Err(e) = gabe_gui::run_terminal(&args, &config) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.headless {
+        if let Err(e) = gabe_gui::run_headless(&args, &config) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let netplay = match gabe_gui::Netplay::start(&args, &config) {
+        Ok(netplay) => netplay,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     let native_options = eframe::NativeOptions {
-        vsync: false,
+        viewport: egui::ViewportBuilder::default()
+            .with_fullscreen(config.video.fullscreen)
+            .with_inner_size(gabe_gui::window_size(config.video.scale))
+            .with_drag_and_drop(true),
+        vsync: config.video.vsync,
         ..Default::default()
     };
 
     eframe::run_native(
-        "Gabe Emulator",
+        gabe_gui::WINDOW_TITLE,
         native_options,
-        Box::new(|cc| Box::new(gabe_gui::GabeApp::new(cc))),
+        Box::new(|cc| Box::new(gabe_gui::GabeApp::new(cc, config, args, netplay))),
     )
     .unwrap();
 }
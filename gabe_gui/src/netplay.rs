@@ -0,0 +1,279 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use gabe_core::gb::Gameboy;
+
+use crate::{archive, cli::Args, config::Config, headless::frame_hash};
+
+/// Sent at the start of the handshake, followed by the protocol version
+const MAGIC: &[u8; 4] = b"GABE";
+const PROTOCOL_VERSION: u8 = 1;
+/// Frames between state hashes being compared to detect desyncs
+const HASH_INTERVAL: u64 = 60;
+/// Message tags
+const INPUT: u8 = 1;
+const HASH: u8 = 2;
+
+/// A message from the other player
+enum Message {
+    /// The buttons held on a frame, as a bitmask
+    Input { frame: u64, buttons: u8 },
+    /// A hash of the emulator's state after a frame
+    Hash { frame: u64, hash: u64 },
+}
+
+/// Two players running the same ROM in lockstep. Each sends the buttons it holds on every
+/// frame, and both apply the combined buttons to their emulator on the same frame. Inputs are
+/// sent a few frames ahead, giving time for them to arrive before they're needed.
+pub struct Netplay {
+    stream: TcpStream,
+    messages: Receiver<Message>,
+    /// Frames between a button being read and it being applied
+    delay: u64,
+    /// The frame about to be run
+    frame: u64,
+    /// Local buttons for frames that haven't run yet
+    local_inputs: VecDeque<u8>,
+    /// Remote buttons for frames that haven't run yet, starting at `frame`
+    remote_inputs: VecDeque<u8>,
+    /// Hashes of recent frames that are waiting for the other side's hash
+    local_hashes: BTreeMap<u64, u64>,
+    remote_hashes: BTreeMap<u64, u64>,
+    /// On the guest, the save data received from the host, which is used instead of the
+    /// guest's own save file. Taken when the ROM is loaded.
+    pub host_save: Option<Vec<u8>>,
+    /// Set once the two emulators have been found to differ
+    pub desynced: bool,
+}
+
+impl Netplay {
+    /// Starts a netplay session if one was requested on the command line, waiting for the
+    /// other player to connect or connecting to them. The ROM's hash is checked against the
+    /// other player's, and the host's save data is sent to the guest so both start the same.
+    pub fn start(args: &Args, config: &Config) -> Result<Option<Netplay>, String> {
+        if args.netplay_host.is_none() && args.netplay_connect.is_none() {
+            return Ok(None);
+        }
+        let rom_path = args.rom.as_ref().ok_or("Netplay requires a ROM file")?;
+        let rom = archive::read_rom(rom_path)?;
+        let rom_hash = frame_hash(&rom.data);
+        let delay = args.netplay_delay as u64;
+        let error = |e: std::io::Error| format!("Netplay: {}", e);
+
+        let (stream, host_save, delay) = if let Some(port) = args.netplay_host {
+            let listener = TcpListener::bind(("0.0.0.0", port)).map_err(error)?;
+            log::info!("Waiting for the other player on port {}", port);
+            let (mut stream, address) = listener.accept().map_err(error)?;
+            log::info!("Player connected from {}", address);
+            let save_path = args
+                .save_file
+                .clone()
+                .unwrap_or_else(|| archive::save_path(&rom, &config.saves));
+            let save = std::fs::read(save_path).unwrap_or_default();
+            let mut hello = hello(rom_hash);
+            hello.push(delay as u8);
+            hello.extend_from_slice(&(save.len() as u32).to_le_bytes());
+            hello.extend_from_slice(&save);
+            stream.write_all(&hello).map_err(error)?;
+            check_hello(&mut stream, rom_hash)?;
+            (stream, None, delay)
+        } else {
+            let address = args.netplay_connect.as_deref().unwrap_or_default();
+            let mut stream = TcpStream::connect(address).map_err(error)?;
+            stream.write_all(&hello(rom_hash)).map_err(error)?;
+            check_hello(&mut stream, rom_hash)?;
+            let [delay] = read_array(&mut stream)?;
+            let save_len = u32::from_le_bytes(read_array(&mut stream)?) as usize;
+            let mut save = vec![0; save_len];
+            stream.read_exact(&mut save).map_err(error)?;
+            log::info!("Connected to {}", address);
+            // The host decides the delay, so both sides use the same one
+            (stream, Some(save), delay as u64)
+        };
+        stream.set_nodelay(true).map_err(error)?;
+
+        let (sender, messages) = mpsc::channel();
+        let reader = stream.try_clone().map_err(error)?;
+        thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            while let Ok(message) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Some(Netplay {
+            stream,
+            messages,
+            delay,
+            frame: 0,
+            // Nothing is pressed during the first frames, before any input could arrive
+            local_inputs: vec![0; delay as usize].into(),
+            remote_inputs: vec![0; delay as usize].into(),
+            local_hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+            host_save,
+            desynced: false,
+        }))
+    }
+
+    /// Returns the combined buttons to apply on the next frame, or None if the other player's
+    /// input for it hasn't arrived yet, in which case the emulator has to wait.
+    /// `local_buttons` are the buttons held now, which are sent to be applied `delay` frames
+    /// later. Fails if the connection was lost.
+    pub fn next_frame_input(&mut self, local_buttons: u8) -> Result<Option<u8>, String> {
+        self.receive()?;
+        let target = self.frame + self.delay;
+        // Only read the buttons once for each frame, even if the frame has to wait
+        if self.local_inputs.len() as u64 <= self.delay {
+            self.send(&input_message(target, local_buttons))?;
+            self.local_inputs.push_back(local_buttons);
+        }
+        match (self.local_inputs.front(), self.remote_inputs.front()) {
+            (Some(local), Some(remote)) => Ok(Some(local | remote)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Moves on to the next frame once it has been run, checking the emulator's state
+    /// against the other player's every few frames
+    pub fn end_frame(&mut self, emu: &Gameboy) -> Result<(), String> {
+        self.local_inputs.pop_front();
+        self.remote_inputs.pop_front();
+        if self.frame % HASH_INTERVAL == 0 {
+            let hash = state_hash(emu);
+            self.send(&hash_message(self.frame, hash))?;
+            self.local_hashes.insert(self.frame, hash);
+            self.compare_hashes();
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    /// Applies the messages received since the last call
+    fn receive(&mut self) -> Result<(), String> {
+        loop {
+            match self.messages.try_recv() {
+                Ok(Message::Input { frame, buttons }) => {
+                    // Inputs arrive in order, one per frame
+                    if frame == self.frame + self.remote_inputs.len() as u64 {
+                        self.remote_inputs.push_back(buttons);
+                    } else {
+                        log::warn!("Netplay: unexpected input for frame {}", frame);
+                    }
+                }
+                Ok(Message::Hash { frame, hash }) => {
+                    self.remote_hashes.insert(frame, hash);
+                    self.compare_hashes();
+                }
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) => {
+                    return Err("Netplay: the other player disconnected".to_string())
+                }
+            }
+        }
+    }
+
+    /// Compares the hashes both sides have for the same frames, then forgets them
+    fn compare_hashes(&mut self) {
+        let frames: Vec<u64> = self
+            .local_hashes
+            .keys()
+            .filter(|f| self.remote_hashes.contains_key(f))
+            .copied()
+            .collect();
+        for frame in frames {
+            let local = self.local_hashes.remove(&frame);
+            let remote = self.remote_hashes.remove(&frame);
+            if local != remote && !self.desynced {
+                log::error!("Netplay: desynced at frame {}", frame);
+                self.desynced = true;
+            }
+        }
+    }
+
+    fn send(&mut self, message: &[u8]) -> Result<(), String> {
+        self.stream
+            .write_all(message)
+            .map_err(|e| format!("Netplay: {}", e))
+    }
+}
+
+/// Hashes the parts of the emulator's state that differ quickly once emulators desync:
+/// the CPU registers, work RAM and high RAM
+fn state_hash(emu: &Gameboy) -> u64 {
+    let reg = emu.get_debug_state().cpu_data.reg;
+    let mut state = vec![
+        reg.a, reg.f, reg.b, reg.c, reg.d, reg.e, reg.h, reg.l, //
+    ];
+    state.extend_from_slice(&reg.sp.to_le_bytes());
+    state.extend_from_slice(&reg.pc.to_le_bytes());
+    state.extend_from_slice(&emu.get_memory_range(0xC000..0xE000));
+    state.extend_from_slice(&emu.get_memory_range(0xFF80..0xFFFF));
+    frame_hash(&state)
+}
+
+fn hello(rom_hash: u64) -> Vec<u8> {
+    let mut hello = MAGIC.to_vec();
+    hello.push(PROTOCOL_VERSION);
+    hello.extend_from_slice(&rom_hash.to_le_bytes());
+    hello
+}
+
+/// Reads the other player's hello, checking they speak the same protocol and loaded the same
+/// ROM
+fn check_hello(stream: &mut TcpStream, rom_hash: u64) -> Result<(), String> {
+    let magic: [u8; 4] = read_array(stream)?;
+    let [version] = read_array(stream)?;
+    if &magic != MAGIC || version != PROTOCOL_VERSION {
+        return Err("Netplay: the other player isn't running a compatible gabe".to_string());
+    }
+    if u64::from_le_bytes(read_array(stream)?) != rom_hash {
+        return Err("Netplay: the other player loaded a different ROM".to_string());
+    }
+    Ok(())
+}
+
+fn input_message(frame: u64, buttons: u8) -> Vec<u8> {
+    let mut message = vec![INPUT];
+    message.extend_from_slice(&frame.to_le_bytes());
+    message.push(buttons);
+    message
+}
+
+fn hash_message(frame: u64, hash: u64) -> Vec<u8> {
+    let mut message = vec![HASH];
+    message.extend_from_slice(&frame.to_le_bytes());
+    message.extend_from_slice(&hash.to_le_bytes());
+    message
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> Result<[u8; N], String> {
+    let mut bytes = [0; N];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(|e| format!("Netplay: {}", e))?;
+    Ok(bytes)
+}
+
+fn read_message(reader: &mut impl Read) -> Result<Message, String> {
+    let [tag] = read_array(reader)?;
+    let frame = u64::from_le_bytes(read_array(reader)?);
+    match tag {
+        INPUT => {
+            let [buttons] = read_array(reader)?;
+            Ok(Message::Input { frame, buttons })
+        }
+        HASH => Ok(Message::Hash {
+            frame,
+            hash: u64::from_le_bytes(read_array(reader)?),
+        }),
+        _ => Err(format!("Netplay: unknown message {}", tag)),
+    }
+}
@@ -0,0 +1,199 @@
+use std::time::{Duration, Instant};
+
+/// How long each message stays on screen
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+/// Most messages shown at once, dropping the oldest
+const MAX_MESSAGES: usize = 4;
+/// Width of the Gameboy screen, which the font is sized against
+const SCREEN_WIDTH: usize = 160;
+/// Space taken by each character, including the gap after it
+const CHAR_WIDTH: usize = 6;
+const LINE_HEIGHT: usize = 9;
+/// Distance of the messages from the bottom left corner of the screen
+const MARGIN: usize = 2;
+
+/// 5x7 glyphs for the printable ASCII characters, starting at space. Each byte is a column,
+/// from left to right, with the top row in the lowest bit.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // @
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // f
+    [0x0C, 0x52, 0x52, 0x52, 0x3E], // g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // j
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // l
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // t
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+/// Short messages shown over the bottom left of the screen for a couple of seconds, giving
+/// feedback for hotkeys and other actions. They're drawn into the displayed image with a
+/// bitmap font, so they scale with the screen and aren't included in screenshots.
+pub struct Osd {
+    /// Messages being shown, oldest first, with when each one disappears
+    messages: Vec<(String, Instant)>,
+    /// Set when the messages changed since the last `update`
+    changed: bool,
+}
+
+impl Osd {
+    pub fn new() -> Self {
+        Osd {
+            messages: vec![],
+            changed: false,
+        }
+    }
+
+    /// Shows a message, below any already being shown
+    pub fn show(&mut self, text: impl Into<String>) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+        self.messages
+            .push((text.into(), Instant::now() + MESSAGE_DURATION));
+        self.changed = true;
+    }
+
+    /// Removes the messages that have been shown long enough. Returns true if the messages
+    /// changed since the last call, so the image they're drawn on needs updating.
+    pub fn update(&mut self) -> bool {
+        let now = Instant::now();
+        let count = self.messages.len();
+        self.messages.retain(|(_, expires)| *expires > now);
+        let changed = self.changed || self.messages.len() != count;
+        self.changed = false;
+        changed
+    }
+
+    /// Draws the messages into an RGB image of the screen, which may have been scaled up by a
+    /// filter. The text is scaled by the same amount.
+    pub fn draw(&self, pixels: &mut [u8], size: [usize; 2]) {
+        let scale = (size[0] / SCREEN_WIDTH).max(1);
+        // Screen coordinates, before scaling
+        let (width, height) = (size[0] / scale, size[1] / scale);
+        let mut plot = |x: usize, y: usize, shade: fn(u8) -> u8| {
+            if x >= width || y >= height {
+                return;
+            }
+            for row in y * scale..(y + 1) * scale {
+                let start = (row * size[0] + x * scale) * 3;
+                for value in &mut pixels[start..start + scale * 3] {
+                    *value = shade(*value);
+                }
+            }
+        };
+        let lines = self.messages.len();
+        for (i, (text, _)) in self.messages.iter().enumerate() {
+            let Some(top) = height.checked_sub(MARGIN + (lines - i) * LINE_HEIGHT) else {
+                continue;
+            };
+            // Darken the area behind the text so it's readable on any background
+            let text_width = text.chars().count() * CHAR_WIDTH + 1;
+            for y in top..top + LINE_HEIGHT {
+                for x in MARGIN..MARGIN + text_width {
+                    plot(x, y, |v| v / 3);
+                }
+            }
+            for (c, ch) in text.chars().enumerate() {
+                let index = (ch as usize).wrapping_sub(' ' as usize);
+                let glyph = FONT
+                    .get(index)
+                    .unwrap_or(&FONT['?' as usize - ' ' as usize]);
+                let left = MARGIN + 1 + c * CHAR_WIDTH;
+                for (column, bits) in glyph.iter().enumerate() {
+                    for row in 0..7 {
+                        if bits & (1 << row) != 0 {
+                            plot(left + column, top + 1 + row, |_| 0xFF);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
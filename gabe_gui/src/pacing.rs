@@ -0,0 +1,59 @@
+/// Seconds the Gameboy takes to draw a frame, about 59.73 frames per second
+const FRAME_INTERVAL: f32 = (1.0 / gabe_core::FRAME_RATE) as f32;
+/// Largest change in audio playback rate used to keep the buffer level, and so the largest
+/// difference from the display's refresh rate that frames are locked to it
+const MAX_RATE_DELTA: f32 = 0.01;
+/// How quickly the measured refresh interval follows changes, from 0 to 1
+const SMOOTHING: f32 = 0.05;
+/// Most frames run in one update, so a long stall doesn't try to catch up all at once
+const MAX_FRAMES_DUE: f32 = 4.0;
+
+/// Paces emulation by the display's refresh rate rather than the audio clock, for use with
+/// vsync. When the refresh rate is close to a multiple of the Gameboy's, frames are run in
+/// step with it, so each one is shown for the same number of refreshes. The small difference
+/// in speed is taken up by playing the audio slightly faster or slower, following `audio_rate`.
+pub struct VsyncPacer {
+    /// Seconds between display refreshes, averaged over recent updates
+    refresh_interval: f32,
+    /// Frames owed, carried between updates
+    frames_due: f32,
+}
+
+impl VsyncPacer {
+    pub fn new() -> Self {
+        VsyncPacer {
+            refresh_interval: FRAME_INTERVAL,
+            frames_due: 0.0,
+        }
+    }
+
+    /// Returns the number of frames to run in this update, given the seconds since the last one
+    pub fn frames_to_run(&mut self, dt: f32, speed: f32) -> u32 {
+        let dt = dt.clamp(FRAME_INTERVAL / 8.0, FRAME_INTERVAL * MAX_FRAMES_DUE);
+        self.refresh_interval += (dt - self.refresh_interval) * SMOOTHING;
+        let refreshes_per_frame = (FRAME_INTERVAL / self.refresh_interval).round().max(1.0);
+        let rate_error = refreshes_per_frame * self.refresh_interval / FRAME_INTERVAL - 1.0;
+        self.frames_due += if rate_error.abs() <= MAX_RATE_DELTA {
+            speed / refreshes_per_frame
+        } else {
+            // Too far from the Gameboy's rate to lock to it, so follow the time instead
+            speed * dt / FRAME_INTERVAL
+        };
+        self.frames_due = self.frames_due.min(MAX_FRAMES_DUE);
+        let frames = self.frames_due.floor();
+        self.frames_due -= frames;
+        frames as u32
+    }
+
+    /// Drops frames owed, as when emulation is paused
+    pub fn reset(&mut self) {
+        self.frames_due = 0.0;
+    }
+}
+
+/// Returns the speed to play audio at, relative to the emulation speed, to keep the audio
+/// buffer half full as emulation runs slightly faster or slower than real time. `fill` is how
+/// full the buffer is, from 0.0 to 1.0.
+pub fn audio_rate(fill: f32) -> f32 {
+    1.0 + (fill.clamp(0.0, 1.0) * 2.0 - 1.0) * MAX_RATE_DELTA
+}
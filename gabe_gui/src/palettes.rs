@@ -0,0 +1,83 @@
+use gabe_core::palette::{Palette, Rgb};
+
+use crate::config::Config;
+
+/// Palettes built into the emulator, listed before any user-defined palettes
+const BUILTIN_PALETTES: &[(&str, Palette)] = &[
+    ("grayscale", Palette::GRAYSCALE),
+    ("dmg", Palette::DMG),
+    ("pocket", Palette::POCKET),
+];
+
+/// The palettes available to cycle through, and the one currently selected
+pub struct PaletteList {
+    palettes: Vec<(String, Palette)>,
+    current: usize,
+}
+
+impl PaletteList {
+    /// Builds the list from the built-in palettes and the user-defined palettes in the config,
+    /// selecting the palette named in the video config
+    pub fn from_config(config: &Config) -> Result<PaletteList, String> {
+        let mut palettes: Vec<(String, Palette)> = BUILTIN_PALETTES
+            .iter()
+            .map(|(name, palette)| (name.to_string(), *palette))
+            .collect();
+        for (name, colors) in &config.palettes {
+            let mut rgb = [(0, 0, 0); 4];
+            for (color, text) in rgb.iter_mut().zip(colors) {
+                *color = parse_color(text)
+                    .ok_or_else(|| format!("Invalid color in palette {}: {}", name, text))?;
+            }
+            match palettes.iter_mut().find(|(n, _)| n == name) {
+                // User palettes may replace the built-in palettes
+                Some((_, palette)) => *palette = Palette::new(rgb),
+                None => palettes.push((name.clone(), Palette::new(rgb))),
+            }
+        }
+        let current = palettes
+            .iter()
+            .position(|(n, _)| n.eq_ignore_ascii_case(&config.video.palette))
+            .ok_or_else(|| format!("Unknown palette: {}", config.video.palette))?;
+        Ok(PaletteList { palettes, current })
+    }
+
+    /// Returns the name and colors of the selected palette
+    pub fn current(&self) -> (&str, Palette) {
+        let (name, palette) = &self.palettes[self.current];
+        (name, *palette)
+    }
+
+    /// Selects the next palette, wrapping around to the first
+    pub fn next(&mut self) {
+        self.current = (self.current + 1) % self.palettes.len();
+    }
+
+    /// Selects the palette at `index` in the order given by `names`
+    pub fn select(&mut self, index: usize) {
+        if index < self.palettes.len() {
+            self.current = index;
+        }
+    }
+
+    /// Returns the names of all palettes, in cycling order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.palettes.iter().map(|(n, _)| n.as_str())
+    }
+}
+
+impl Default for PaletteList {
+    fn default() -> Self {
+        PaletteList::from_config(&Config::default()).expect("Default palette exists")
+    }
+}
+
+/// Parses a color written as `#RRGGBB` or `RRGGBB`
+fn parse_color(text: &str) -> Option<Rgb> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
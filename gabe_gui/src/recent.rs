@@ -0,0 +1,68 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::config;
+
+/// Name of the recent ROMs list within the gabe config directory
+const RECENT_FILE_NAME: &str = "recent.txt";
+/// Number of ROMs remembered
+const MAX_RECENT: usize = 10;
+
+/// The most recently opened ROMs, most recent first. Kept in the config directory as one path
+/// per line, separately from the config so that file is never rewritten.
+pub struct RecentRoms {
+    paths: Vec<PathBuf>,
+    file: Option<PathBuf>,
+}
+
+impl RecentRoms {
+    /// Loads the list from the config directory. A missing or unreadable list is empty.
+    pub fn load() -> RecentRoms {
+        let file = config::config_dir().map(|d| d.join(RECENT_FILE_NAME));
+        let paths = file
+            .as_ref()
+            .and_then(|f| fs::read_to_string(f).ok())
+            .map(|text| {
+                text.lines()
+                    .filter(|l| !l.trim().is_empty())
+                    .map(PathBuf::from)
+                    .take(MAX_RECENT)
+                    .collect()
+            })
+            .unwrap_or_default();
+        RecentRoms { paths, file }
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Moves a ROM to the top of the list, adding it if needed, and saves the list
+    pub fn add(&mut self, path: &Path) {
+        // Stored as absolute paths, so ROMs opened with relative paths can be found again
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.paths.retain(|p| *p != path);
+        self.paths.insert(0, path);
+        self.paths.truncate(MAX_RECENT);
+        if let Err(e) = self.save() {
+            log::warn!("Failed to save the recent ROMs list: {}", e);
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let text: String = self
+            .paths
+            .iter()
+            .map(|p| format!("{}\n", p.display()))
+            .collect();
+        fs::write(file, text).map_err(|e| format!("{}: {}", file.display(), e))
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, ExitStatus, Stdio},
+    thread::{self, JoinHandle},
+};
+
+use gabe_core::sink::{AudioFrame, VideoFrame};
+
+/// Width of the Gameboy screen in pixels
+const WIDTH: usize = 160;
+/// Height of the Gameboy screen in pixels
+const HEIGHT: usize = 144;
+/// CPU cycles taken to draw one frame
+const CYCLES_PER_FRAME: u64 = gabe_core::CYCLES_PER_FRAME as u64;
+
+/// Records frames and audio to a video file, using `ffmpeg`.
+///
+/// Video is piped to an ffmpeg process as it's recorded, and audio is written to a raw file
+/// next to the output. When recording stops, both are muxed into the output file.
+/// Frames are placed by the emulated cycle count, duplicating or dropping frames as needed, so
+/// the video stays in sync with the audio, which the core produces at a fixed rate.
+pub struct Recorder {
+    path: PathBuf,
+    video_path: PathBuf,
+    audio_path: PathBuf,
+    encoder: Child,
+    video: ChildStdin,
+    audio: BufWriter<File>,
+    start_cycles: u64,
+    frames_written: u64,
+}
+
+impl Recorder {
+    /// Starts recording to `path`, with emulated time counted from `start_cycles`
+    pub fn start(path: &Path, start_cycles: u64) -> Result<Recorder, String> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let video_path = path.with_extension("video.mkv");
+        let audio_path = path.with_extension("audio.raw");
+        let audio = File::create(&audio_path)
+            .map(BufWriter::new)
+            .map_err(|e| format!("{}: {}", audio_path.display(), e))?;
+        let frame_rate = format!("{}/{}", gabe_core::CLOCK_RATE, CYCLES_PER_FRAME);
+        let size = format!("{}x{}", WIDTH, HEIGHT);
+        let mut encoder = Command::new("ffmpeg")
+            .args([
+                "-y",
+                "-loglevel",
+                "error",
+                "-f",
+                "rawvideo",
+                "-pix_fmt",
+                "rgb24",
+            ])
+            .args([
+                "-video_size",
+                &size,
+                "-framerate",
+                &frame_rate,
+                "-i",
+                "pipe:0",
+            ])
+            // Lossless, as the Gameboy's flat colors compress well anyway
+            .args(["-c:v", "libx264rgb", "-qp", "0", "-preset", "veryfast"])
+            .arg(&video_path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+        let video = encoder.stdin.take().expect("ffmpeg stdin is piped");
+        Ok(Recorder {
+            path: path.to_path_buf(),
+            video_path,
+            audio_path,
+            encoder,
+            video,
+            audio,
+            start_cycles,
+            frames_written: 0,
+        })
+    }
+
+    /// Returns the file being recorded to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records a frame, repeating it to fill any time the LCD was off since the last one
+    pub fn append_frame(&mut self, frame: &VideoFrame) -> Result<(), String> {
+        let elapsed = frame.cycles.saturating_sub(self.start_cycles);
+        let target_frames = elapsed / CYCLES_PER_FRAME + 1;
+        while self.frames_written < target_frames {
+            self.video
+                .write_all(frame)
+                .map_err(|e| format!("Failed to write to ffmpeg: {}", e))?;
+            self.frames_written += 1;
+        }
+        Ok(())
+    }
+
+    pub fn append_audio<'a>(
+        &mut self,
+        samples: impl Iterator<Item = &'a AudioFrame>,
+    ) -> Result<(), String> {
+        for (left, right) in samples {
+            self.audio
+                .write_all(&left.to_le_bytes())
+                .and_then(|_| self.audio.write_all(&right.to_le_bytes()))
+                .map_err(|e| format!("{}: {}", self.audio_path.display(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Stops recording, and muxes the video and audio into the output file on a background
+    /// thread, so the emulator keeps running while the audio is encoded
+    pub fn finish(self) -> JoinHandle<()> {
+        let Recorder {
+            path,
+            video_path,
+            audio_path,
+            mut encoder,
+            video,
+            audio,
+            ..
+        } = self;
+        // Closing the pipe tells ffmpeg the video has ended
+        drop(video);
+        let flushed = audio.into_inner().map(drop).map_err(|e| e.to_string());
+        thread::spawn(move || {
+            let result = flushed
+                .and_then(|_| encoder.wait().map_err(|e| e.to_string()))
+                .and_then(|status| check_status(status, "encoding"))
+                .and_then(|_| mux(&path, &video_path, &audio_path));
+            let _ = fs::remove_file(&video_path);
+            let _ = fs::remove_file(&audio_path);
+            match result {
+                Ok(()) => log::info!("Saved recording to {}", path.display()),
+                Err(e) => log::error!("Failed to save recording {}: {}", path.display(), e),
+            }
+        })
+    }
+}
+
+/// Combines the recorded video and raw audio into `path`
+fn mux(path: &Path, video_path: &Path, audio_path: &Path) -> Result<(), String> {
+    let sample_rate = gabe_core::SAMPLE_RATE.to_string();
+    let status = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(video_path)
+        .args(["-f", "f32le", "-ar", &sample_rate, "-ac", "2", "-i"])
+        .arg(audio_path)
+        // Resample the audio to a rate players support
+        .args(["-c:v", "copy", "-ar", "48000", "-shortest"])
+        .arg(path)
+        .status()
+        .map_err(|e| e.to_string())?;
+    check_status(status, "muxing")
+}
+
+fn check_status(status: ExitStatus, step: &str) -> Result<(), String> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("ffmpeg {} failed with {}", step, status))
+    }
+}
@@ -0,0 +1,178 @@
+//! Passes the motor of rumble cartridges on to the player, through a gamepad's force feedback
+//! or by shaking the screen
+
+use egui::Vec2;
+use gabe_core::{
+    events::{Event, EventKind},
+    gb::Gameboy,
+};
+use gilrs::{
+    ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat, Replay, Ticks},
+    Gilrs,
+};
+
+use crate::config::RumbleMode;
+
+/// Distance the screen moves either way at full strength, in points
+const SHAKE_DISTANCE: f32 = 6.0;
+
+/// Steps the strength is rounded to, so the gamepad's effect is only remade when it changes
+/// noticeably
+const STRENGTH_STEPS: f32 = 4.0;
+
+pub struct Rumble {
+    mode: RumbleMode,
+    gamepads: Option<GamepadRumble>,
+    /// Whether the motor is on, as of the last event
+    motor_on: bool,
+    /// Cycle the motor was last switched on or off
+    switched_at: u64,
+    /// Cycle the last update ended at
+    updated_at: u64,
+    /// Share of the time the motor was on since the update before, from 0 to 1. Games set the
+    /// strength by switching the motor on and off many times a frame.
+    strength: f32,
+    /// Flips each update, moving the shaking screen one way then the other
+    shake_phase: bool,
+}
+
+impl Rumble {
+    pub fn new(mode: RumbleMode) -> Self {
+        let gamepads = match mode {
+            RumbleMode::Gamepad => GamepadRumble::new(),
+            _ => None,
+        };
+        Rumble {
+            mode,
+            gamepads,
+            motor_on: false,
+            switched_at: 0,
+            updated_at: 0,
+            strength: 0.0,
+            shake_phase: false,
+        }
+    }
+
+    /// Starts watching a newly loaded game's motor
+    pub fn start(&mut self, emu: &mut Gameboy) {
+        emu.watch_event(EventKind::Rumble, self.mode != RumbleMode::Off);
+        self.motor_on = emu.rumble();
+        self.switched_at = emu.cycles_elapsed();
+        self.updated_at = emu.cycles_elapsed();
+        self.set_strength(0.0);
+    }
+
+    /// Stops rumbling, as when the game is closed
+    pub fn stop(&mut self) {
+        self.motor_on = false;
+        self.set_strength(0.0);
+    }
+
+    /// Measures how much the motor ran since the last update and rumbles to match. While
+    /// paused no time passes, so the rumble stops.
+    pub fn update(&mut self, emu: &mut Gameboy) {
+        if self.mode == RumbleMode::Off {
+            return;
+        }
+        let now = emu.cycles_elapsed();
+        let mut on_cycles = 0;
+        for timed in emu.drain_events() {
+            if let Event::Rumble(on) = timed.event {
+                if self.motor_on {
+                    on_cycles += timed.cycle - self.switched_at.max(self.updated_at);
+                }
+                self.motor_on = on;
+                self.switched_at = timed.cycle;
+            }
+        }
+        if self.motor_on {
+            on_cycles += now - self.switched_at.max(self.updated_at);
+        }
+        let elapsed = now - self.updated_at;
+        self.updated_at = now;
+        let strength = if elapsed == 0 {
+            0.0
+        } else {
+            on_cycles as f32 / elapsed as f32
+        };
+        self.set_strength(strength);
+        self.shake_phase = !self.shake_phase;
+    }
+
+    /// How far to move the screen this update, for the shake
+    pub fn shake_offset(&self) -> Vec2 {
+        if self.mode != RumbleMode::Shake {
+            return Vec2::ZERO;
+        }
+        let direction = if self.shake_phase { 1.0 } else { -1.0 };
+        Vec2::new(direction * self.strength * SHAKE_DISTANCE, 0.0)
+    }
+
+    fn set_strength(&mut self, strength: f32) {
+        let strength = (strength * STRENGTH_STEPS).round() / STRENGTH_STEPS;
+        if strength != self.strength {
+            self.strength = strength;
+            if let Some(gamepads) = &mut self.gamepads {
+                gamepads.set_strength(strength);
+            }
+        }
+    }
+}
+
+/// Force feedback on every connected gamepad that supports it
+struct GamepadRumble {
+    gilrs: Gilrs,
+    /// The effect playing, stopped when dropped
+    effect: Option<Effect>,
+}
+
+impl GamepadRumble {
+    fn new() -> Option<Self> {
+        match Gilrs::new() {
+            Ok(gilrs) => Some(GamepadRumble {
+                gilrs,
+                effect: None,
+            }),
+            Err(e) => {
+                log::warn!("Gamepad rumble unavailable: {}", e);
+                None
+            }
+        }
+    }
+
+    fn set_strength(&mut self, strength: f32) {
+        // Handling the events keeps the list of connected gamepads up to date
+        while self.gilrs.next_event().is_some() {}
+        self.effect = None;
+        if strength == 0.0 {
+            return;
+        }
+        let ids: Vec<_> = self
+            .gilrs
+            .gamepads()
+            .filter(|(_, gamepad)| gamepad.is_ff_supported())
+            .map(|(id, _)| id)
+            .collect();
+        if ids.is_empty() {
+            return;
+        }
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong {
+                    magnitude: (strength * u16::MAX as f32) as u16,
+                },
+                scheduling: Replay {
+                    play_for: Ticks::from_ms(100),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .repeat(Repeat::Infinitely)
+            .gamepads(&ids)
+            .finish(&mut self.gilrs);
+        match effect.and_then(|effect| effect.play().map(|_| effect)) {
+            Ok(effect) => self.effect = Some(effect),
+            Err(e) => log::warn!("Gamepad rumble failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Width of the Gameboy screen in pixels
+const WIDTH: usize = 160;
+/// Height of the Gameboy screen in pixels
+const HEIGHT: usize = 144;
+
+/// Saves a 160x144 RGB frame as a PNG in `dir`, scaled up by `scale` with nearest-neighbor.
+/// The file is named after the current time, and its path is returned.
+pub fn save(dir: &Path, frame: &[u8], scale: u32) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let path = dir.join(format!("gabe-{}.png", timestamp()));
+    write_png(&path, frame, scale)?;
+    Ok(path)
+}
+
+/// Writes a 160x144 RGB frame to `path` as a PNG, scaled up by `scale` with nearest-neighbor
+pub fn write_png(path: &Path, frame: &[u8], scale: u32) -> Result<(), String> {
+    let scale = scale.max(1) as usize;
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        (WIDTH * scale) as u32,
+        (HEIGHT * scale) as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    writer
+        .write_image_data(&upscale(frame, scale))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Reads a 160x144 PNG as RGB data, in the same format as the core's frames
+pub fn read_png(path: &Path) -> Result<Vec<u8>, String> {
+    let (rgb, width, height) = read_any_png(path)?;
+    if (width, height) != (WIDTH, HEIGHT) {
+        return Err(format!(
+            "{}: Expected a {}x{} image, got {}x{}",
+            path.display(),
+            WIDTH,
+            HEIGHT,
+            width,
+            height
+        ));
+    }
+    Ok(rgb)
+}
+
+/// Reads a PNG of any size as grayscale, returning its pixels with its width and height
+pub fn read_grayscale_png(path: &Path) -> Result<(Vec<u8>, usize, usize), String> {
+    let (rgb, width, height) = read_any_png(path)?;
+    let gray = rgb
+        .chunks_exact(3)
+        .map(|p| ((p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114) / 1000) as u8)
+        .collect();
+    Ok((gray, width, height))
+}
+
+/// Reads a PNG as RGB data, returning it with the image's width and height
+fn read_any_png(path: &Path) -> Result<(Vec<u8>, usize, usize), String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+    let file = File::open(path).map_err(|e| error(&e))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    // Expand palettes and low bit depths to 8-bit channels
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| error(&e))?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(|e| error(&e))?;
+    let pixels = &data[..info.buffer_size()];
+    let rgb = match info.color_type {
+        png::ColorType::Rgb => pixels.to_vec(),
+        png::ColorType::Rgba => pixels
+            .chunks_exact(4)
+            .flat_map(|p| [p[0], p[1], p[2]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|g| [*g; 3]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks_exact(2).flat_map(|p| [p[0]; 3]).collect(),
+        other => return Err(error(&format!("Unsupported color type {:?}", other))),
+    };
+    Ok((rgb, info.width as usize, info.height as usize))
+}
+
+/// Repeats each pixel of the frame `scale` times in both directions
+fn upscale(frame: &[u8], scale: usize) -> Vec<u8> {
+    if scale == 1 {
+        return frame.to_vec();
+    }
+    let mut out = Vec::with_capacity(frame.len() * scale * scale);
+    for row in frame.chunks_exact(WIDTH * 3) {
+        let mut scaled_row = Vec::with_capacity(row.len() * scale);
+        for pixel in row.chunks_exact(3) {
+            for _ in 0..scale {
+                scaled_row.extend_from_slice(pixel);
+            }
+        }
+        for _ in 0..scale {
+            out.extend_from_slice(&scaled_row);
+        }
+    }
+    out
+}
+
+/// Returns the current UTC time as `YYYYMMDD-HHMMSS-mmm`, for naming saved files
+pub fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let time = secs % 86400;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        now.subsec_millis()
+    )
+}
+
+/// Converts days since the Unix epoch to a (year, month, day) date, using Howard Hinnant's
+/// civil-from-days algorithm
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
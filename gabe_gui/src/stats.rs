@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+/// How often the displayed numbers are recalculated
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Measures host frame rate and emulation speed over short intervals, for the stats overlay
+pub struct PerfStats {
+    interval_start: Instant,
+    frames: u32,
+    cycles: u64,
+    /// Host frames drawn per second over the last interval
+    pub fps: f32,
+    /// Emulated time as a percentage of real time over the last interval
+    pub speed_percent: f32,
+    /// Fraction of the audio buffer filled, from 0.0 (underrunning) to 1.0 (full)
+    pub audio_fill: f32,
+}
+
+impl PerfStats {
+    pub fn new() -> Self {
+        PerfStats {
+            interval_start: Instant::now(),
+            frames: 0,
+            cycles: 0,
+            fps: 0.0,
+            speed_percent: 0.0,
+            audio_fill: 0.0,
+        }
+    }
+
+    /// Records a host frame in which `cycles` cycles were emulated, with the audio buffer
+    /// `audio_fill` full at the end of it
+    pub fn record_frame(&mut self, cycles: u64, audio_fill: f32) {
+        self.frames += 1;
+        self.cycles += cycles;
+        let elapsed = self.interval_start.elapsed();
+        if elapsed >= UPDATE_INTERVAL {
+            let secs = elapsed.as_secs_f32();
+            self.fps = self.frames as f32 / secs;
+            self.speed_percent = self.cycles as f32 / secs / gabe_core::CLOCK_RATE as f32 * 100.0;
+            self.audio_fill = audio_fill;
+            self.interval_start = Instant::now();
+            self.frames = 0;
+            self.cycles = 0;
+        }
+    }
+
+    /// Returns the stats as text for the overlay
+    pub fn text(&self) -> String {
+        format!(
+            "{:.0} FPS\n{:.0}% speed\nAudio buffer {:.0}%",
+            self.fps,
+            self.speed_percent,
+            self.audio_fill * 100.0
+        )
+    }
+}
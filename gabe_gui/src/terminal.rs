@@ -0,0 +1,256 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    process::{Command, Stdio},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+use egui::Key;
+use gabe_core::gb::{GbKeys, Model};
+
+use crate::{
+    cli::Args,
+    config::Config,
+    headless::Headless,
+    input::{parse_key, KeyMap},
+    palettes::PaletteList,
+};
+
+/// Width of the Gameboy screen in pixels, and of the picture in columns
+const WIDTH: usize = 160;
+/// Height of the Gameboy screen in pixels. Each line of text shows two rows of pixels.
+const HEIGHT: usize = 144;
+/// Frames a button stays held after its key is pressed. Terminals only report presses, so
+/// holding a key relies on the terminal's key repeat to keep the button down.
+const HOLD_FRAMES: u32 = 8;
+/// Byte sent by Ctrl+C, which quits instead of interrupting as the terminal is in raw mode
+const CTRL_C: u8 = 0x03;
+
+/// Puts the terminal in raw mode and switches to the alternate screen while it's alive,
+/// restoring both when dropped
+struct RawTerminal {
+    /// Settings from `stty -g` to restore
+    saved: String,
+}
+
+impl RawTerminal {
+    fn enter() -> Result<RawTerminal, String> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        // Alternate screen, hide the cursor, clear
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        Ok(RawTerminal {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Runs `stty` on the terminal connected to stdin, returning its output
+fn stty(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Failed to run stty: {}", e))?;
+    if !output.status.success() {
+        return Err("stty failed, is stdin a terminal?".to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads stdin on a separate thread, so the emulator can poll for input without blocking
+fn spawn_input_reader() -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = [0; 64];
+        let mut stdin = io::stdin();
+        while let Ok(n) = stdin.read(&mut buf) {
+            if n == 0 || sender.send(buf[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Converts the bytes read from the terminal into the keys they stand for
+fn parse_input(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let key = match bytes[i] {
+            // Arrow keys are sent as ESC [ A-D
+            0x1b if bytes.get(i + 1) == Some(&b'[') && i + 2 < bytes.len() => {
+                i += 2;
+                match bytes[i] {
+                    b'A' => Some(Key::ArrowUp),
+                    b'B' => Some(Key::ArrowDown),
+                    b'C' => Some(Key::ArrowRight),
+                    b'D' => Some(Key::ArrowLeft),
+                    _ => None,
+                }
+            }
+            0x1b => Some(Key::Escape),
+            b'\r' | b'\n' => Some(Key::Enter),
+            0x7f | 0x08 => Some(Key::Backspace),
+            b'\t' => Some(Key::Tab),
+            b' ' => Some(Key::Space),
+            b'-' => Some(Key::Minus),
+            b'=' | b'+' => Some(Key::PlusEquals),
+            c if c.is_ascii_alphanumeric() => parse_key(&(c as char).to_string()).ok(),
+            _ => None,
+        };
+        keys.extend(key);
+        i += 1;
+    }
+    keys
+}
+
+/// How colors are written to the terminal
+#[derive(Clone, Copy)]
+enum ColorMode {
+    /// 24-bit RGB
+    TrueColor,
+    /// The xterm 256-color palette
+    Indexed,
+}
+
+impl ColorMode {
+    /// Uses 24-bit color if the terminal advertises it in `COLORTERM`
+    fn detect() -> ColorMode {
+        match std::env::var("COLORTERM").as_deref() {
+            Ok("truecolor") | Ok("24bit") => ColorMode::TrueColor,
+            _ => ColorMode::Indexed,
+        }
+    }
+
+    /// Appends the escape code setting the foreground (38) or background (48) color
+    fn write(self, out: &mut String, layer: u8, rgb: &[u8]) {
+        let _ = match self {
+            ColorMode::TrueColor => {
+                write!(out, "\x1b[{};2;{};{};{}m", layer, rgb[0], rgb[1], rgb[2])
+            }
+            ColorMode::Indexed => write!(out, "\x1b[{};5;{}m", layer, xterm_index(rgb)),
+        };
+    }
+}
+
+/// Returns the closest color in the xterm 256-color palette, from its 6x6x6 color cube or
+/// its 24 step gray ramp
+fn xterm_index(rgb: &[u8]) -> u8 {
+    let (r, g, b) = (rgb[0] as i32, rgb[1] as i32, rgb[2] as i32);
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            248..=255 => 231,
+            _ => 232 + ((r - 8) / 10).min(23) as u8,
+        };
+    }
+    let level = |c: i32| if c < 48 { 0 } else { ((c - 35) / 40).min(5) };
+    (16 + 36 * level(r) + 6 * level(g) + level(b)) as u8
+}
+
+/// Draws a frame using upper half block characters, with the top pixel of each pair as the
+/// foreground color and the bottom one as the background
+fn draw_frame(frame: &[u8], colors: ColorMode) -> String {
+    let mut out = String::with_capacity(WIDTH * HEIGHT * 10);
+    out.push_str("\x1b[H");
+    for y in (0..HEIGHT).step_by(2) {
+        let mut last: Option<(&[u8], &[u8])> = None;
+        for x in 0..WIDTH {
+            let top = &frame[(y * WIDTH + x) * 3..][..3];
+            let bottom = &frame[((y + 1) * WIDTH + x) * 3..][..3];
+            // Only change colors when they differ from the previous cell
+            if last.map_or(true, |(t, _)| t != top) {
+                colors.write(&mut out, 38, top);
+            }
+            if last.map_or(true, |(_, b)| b != bottom) {
+                colors.write(&mut out, 48, bottom);
+            }
+            last = Some((top, bottom));
+            out.push('▀');
+        }
+        out.push_str("\x1b[0m\r\n");
+    }
+    out
+}
+
+/// Runs the ROM given on the command line in the terminal, for `--terminal`. Keys are read
+/// from stdin using the configured bindings, and Ctrl+C quits.
+pub fn run_terminal(args: &Args, config: &Config) -> Result<(), String> {
+    let rom = args.rom.as_ref().ok_or("--terminal requires a ROM file")?;
+    let mut headless = Headless::load(rom, args.save_file.as_deref(), config)?;
+    headless.add_cheats(config)?;
+    let key_map = KeyMap::from_config(&config.keys)?;
+    // The CGB colorizes games made only for the DMG, in place of the palette
+    let emu = &mut headless.emu;
+    let colorized =
+        emu.model() == Model::Cgb && emu.rom().get(0x143).map_or(false, |f| f & 0x80 == 0);
+    if !colorized {
+        emu.set_palette(PaletteList::from_config(config)?.current().1);
+    }
+    let colors = ColorMode::detect();
+    let frame_time =
+        Duration::from_secs_f64(1.0 / gabe_core::FRAME_RATE / config.emulation.speed as f64);
+
+    let input = spawn_input_reader();
+    let terminal = RawTerminal::enter()?;
+    // Frames left before each held button is released
+    let mut held: Vec<(GbKeys, u32)> = vec![];
+    let mut next_frame = Instant::now();
+    'running: loop {
+        for bytes in input.try_iter() {
+            if bytes.contains(&CTRL_C) {
+                break 'running;
+            }
+            for key in parse_input(&bytes) {
+                if let Some(button) = key_map.button(key) {
+                    held.retain(|(b, _)| *b != button);
+                    held.push((button, HOLD_FRAMES));
+                    headless.emu.update_key_state(button, true);
+                }
+            }
+        }
+        held.retain_mut(|(button, frames)| {
+            *frames -= 1;
+            if *frames == 0 {
+                headless.emu.update_key_state(*button, false);
+            }
+            *frames > 0
+        });
+
+        headless.run_frame();
+        if let Some(frame) = &headless.last_frame {
+            let mut stdout = io::stdout().lock();
+            stdout
+                .write_all(draw_frame(frame, colors).as_bytes())
+                .and_then(|_| stdout.flush())
+                .map_err(|e| e.to_string())?;
+        }
+        if args.frames.map_or(false, |f| headless.frames >= f) {
+            break;
+        }
+
+        next_frame += frame_time;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            // Running behind, so don't try to catch up
+            next_frame = now;
+        }
+    }
+    drop(terminal);
+    headless.save()
+}
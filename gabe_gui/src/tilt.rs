@@ -0,0 +1,80 @@
+//! Tilts the Gameboy for games with an accelerometer, from the tilt keys or a gamepad's left
+//! stick
+
+use gabe_core::gb::Gameboy;
+use gilrs::{Axis, Gilrs};
+
+/// How fast the tilt follows the keys, in g per second. A tap nudges the Gameboy, holding a key
+/// tilts it fully in a quarter of a second, and it levels out as quickly once released.
+const KEY_TILT_RATE: f32 = 4.0;
+
+/// Distance from the stick's center that's ignored, as sticks rarely come to rest exactly on
+/// it
+const DEAD_ZONE: f32 = 0.15;
+
+pub struct Tilt {
+    gilrs: Option<Gilrs>,
+    /// Stick position taken as level, measured when a game starts or the tilt is recentered
+    stick_center: (f32, f32),
+    /// Tilt from the keys, moving towards the keys held
+    key_tilt: (f32, f32),
+}
+
+impl Tilt {
+    pub fn new() -> Self {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(e) => {
+                log::warn!("Gamepad tilt unavailable: {}", e);
+                None
+            }
+        };
+        Tilt {
+            gilrs,
+            stick_center: (0.0, 0.0),
+            key_tilt: (0.0, 0.0),
+        }
+    }
+
+    /// Levels the Gameboy, taking wherever the stick rests now as level
+    pub fn recenter(&mut self) {
+        self.key_tilt = (0.0, 0.0);
+        self.stick_center = self.stick().unwrap_or((0.0, 0.0));
+    }
+
+    /// Tilts the Gameboy by the stick and the keys held, `keys` being -1, 0 or 1 along each
+    /// axis, `dt` seconds after the last update
+    pub fn update(&mut self, keys: (f32, f32), dt: f32, emu: &mut Gameboy) {
+        let step = KEY_TILT_RATE * dt;
+        let approach = |tilt: f32, target: f32| tilt + (target - tilt).clamp(-step, step);
+        self.key_tilt = (
+            approach(self.key_tilt.0, keys.0),
+            approach(self.key_tilt.1, keys.1),
+        );
+
+        let (mut x, mut y) = self.key_tilt;
+        if let Some(stick) = self.stick() {
+            let (dx, dy) = (stick.0 - self.stick_center.0, stick.1 - self.stick_center.1);
+            let distance = dx.hypot(dy);
+            if distance > DEAD_ZONE {
+                // Rescaled so tilting starts from nothing at the edge of the dead zone
+                let scale = (distance - DEAD_ZONE) / (1.0 - DEAD_ZONE) / distance;
+                x += dx * scale;
+                y += dy * scale;
+            }
+        }
+        emu.set_tilt(x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0));
+    }
+
+    /// Returns the left stick's position on the first gamepad connected, if there is one
+    fn stick(&mut self) -> Option<(f32, f32)> {
+        let gilrs = self.gilrs.as_mut()?;
+        // Handling the events keeps the gamepads' state up to date
+        while gilrs.next_event().is_some() {}
+        let (_, gamepad) = gilrs.gamepads().next()?;
+        Some((
+            gamepad.value(Axis::LeftStickX),
+            gamepad.value(Axis::LeftStickY),
+        ))
+    }
+}
@@ -1,88 +1,167 @@
-#![allow(dead_code)]
+use std::sync::Arc;
 
 use gabe_core::sink::*;
+use serde::{Deserialize, Serialize};
 
-pub struct MostRecentSink {
-    inner: Option<VideoFrame>,
-}
+/// Share of the last frame shown that's left after each new frame, with LCD persistence
+const PERSISTENCE: f32 = 0.5;
 
-impl MostRecentSink {
-    pub fn new() -> Self {
-        MostRecentSink { inner: None }
-    }
+/// Darkest and lightest colors of the DMG's screen, which the DMG response shades between
+const DMG_DARKEST: [f32; 3] = [15.0, 56.0, 15.0];
+const DMG_LIGHTEST: [f32; 3] = [155.0, 188.0, 15.0];
+/// Power the DMG response raises brightness to. The screen darkens slowly at first, so light
+/// grays stay close to the lightest green while dark grays fall off quickly.
+const DMG_GAMMA: f32 = 1.6;
 
-    pub fn has_frame(&self) -> bool {
-        self.inner.is_some()
-    }
+/// How frames are combined with the ones before them before they're shown. Games flicker
+/// sprites on and off every other frame to show more of them or to fake transparency, which
+/// the slow LCD of a real Gameboy smoothed over.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    /// Each frame as the core drew it
+    Raw,
+    /// The average of each frame and the one before it
+    #[default]
+    Blend,
+    /// Each frame fading in over the ones before it, as an LCD's pixels slowly change
+    Persistence,
+    /// Persistence, with the shades mapped onto the DMG's green screen as its LCD responds to
+    /// them. Meant for the grayscale palette, as the shades are taken from each pixel's
+    /// brightness.
+    Dmg,
+}
 
-    pub fn get_frame(&mut self) -> Option<VideoFrame> {
-        if self.inner.is_some() {
-            let ret = self.inner.as_ref().unwrap().clone();
-            self.inner = None;
-            Some(ret)
-        } else {
-            None
-        }
-    }
+impl BlendMode {
+    /// All modes, in the order cycled through by the blend hotkey
+    pub const ALL: [BlendMode; 4] = [
+        BlendMode::Raw,
+        BlendMode::Blend,
+        BlendMode::Persistence,
+        BlendMode::Dmg,
+    ];
 
-    pub fn into_inner(self) -> Option<VideoFrame> {
-        self.inner
+    /// Returns the mode after this one, wrapping around to the first
+    pub fn next(self) -> BlendMode {
+        let i = BlendMode::ALL.iter().position(|m| *m == self).unwrap_or(0);
+        BlendMode::ALL[(i + 1) % BlendMode::ALL.len()]
     }
-}
 
-impl Sink<VideoFrame> for MostRecentSink {
-    fn append(&mut self, value: VideoFrame) {
-        self.inner = Some(value);
+    pub fn name(self) -> &'static str {
+        match self {
+            BlendMode::Raw => "Raw",
+            BlendMode::Blend => "2-frame blend",
+            BlendMode::Persistence => "LCD persistence",
+            BlendMode::Dmg => "DMG LCD",
+        }
     }
 }
 
-pub struct BlendVideoSink {
+/// Combines each RGB frame with the frames before it as its `BlendMode` asks. Kept between
+/// updates, as it remembers the frames it's shown.
+pub struct LcdVideoSink {
+    mode: BlendMode,
     inner: Option<VideoFrame>,
-    frames_blended: u16,
+    /// The last frame drawn, as the core drew it, for blending
+    previous: Option<Arc<[u8]>>,
+    /// Each byte of the last frame shown, unrounded so fading pixels don't stop short of
+    /// their new value, for persistence
+    shown: Vec<f32>,
 }
 
-impl BlendVideoSink {
-    pub fn new() -> Self {
-        BlendVideoSink {
+impl LcdVideoSink {
+    pub fn new(mode: BlendMode) -> Self {
+        LcdVideoSink {
+            mode,
             inner: None,
-            frames_blended: 0,
+            previous: None,
+            shown: vec![],
         }
     }
 
+    pub fn mode(&self) -> BlendMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: BlendMode) {
+        self.mode = mode;
+        self.clear();
+    }
+
+    /// Forgets the frames shown, so the next frame is shown as it is, as when a game is
+    /// loaded
+    pub fn clear(&mut self) {
+        self.inner = None;
+        self.previous = None;
+        self.shown.clear();
+    }
+
+    pub fn has_frame(&self) -> bool {
+        self.inner.is_some()
+    }
+
     pub fn get_frame(&mut self) -> Option<VideoFrame> {
-        if self.inner.is_some() {
-            let ret = self.inner.as_ref().unwrap().clone();
-            self.inner = None;
-            Some(ret)
-        } else {
-            None
-        }
+        self.inner.take()
     }
 
-    pub fn into_inner(self) -> Option<VideoFrame> {
-        self.inner
+    /// Fades the last frame shown towards `pixels`, returning the frame to show
+    fn persist(&mut self, pixels: &[u8]) -> Vec<u8> {
+        if self.shown.len() != pixels.len() {
+            self.shown = pixels.iter().map(|p| *p as f32).collect();
+        }
+        for (shown, new) in self.shown.iter_mut().zip(pixels) {
+            *shown = *new as f32 + (*shown - *new as f32) * PERSISTENCE;
+        }
+        self.shown.iter().map(|p| p.round() as u8).collect()
     }
 }
 
-impl Sink<VideoFrame> for BlendVideoSink {
+impl Sink<VideoFrame> for LcdVideoSink {
     fn append(&mut self, value: VideoFrame) {
-        if self.inner.is_some() {
-            let new_frame: VideoFrame = self
-                .inner
-                .as_mut()
-                .unwrap()
-                .iter()
-                .zip(value.iter())
-                .map(|(x1, x2)| {
-                    ((*x2 as u16 + (self.frames_blended * *x1 as u16)) / (self.frames_blended + 1))
-                        as u8
-                })
-                .collect();
-            self.inner = Some(new_frame);
-            self.frames_blended += 1;
-        } else {
+        // Skipped frames aren't shown, so they don't fade into the next
+        if value.skipped {
             self.inner = Some(value);
-            self.frames_blended += 1;
+            return;
         }
+        let pixels: Vec<u8> = match self.mode {
+            BlendMode::Raw => {
+                self.inner = Some(value);
+                return;
+            }
+            BlendMode::Blend => {
+                let previous = self.previous.replace(value.pixels.clone());
+                match previous {
+                    Some(previous) if previous.len() == value.len() => previous
+                        .iter()
+                        .zip(value.iter())
+                        .map(|(x1, x2)| ((*x1 as u16 + *x2 as u16) / 2) as u8)
+                        .collect(),
+                    _ => value.to_vec(),
+                }
+            }
+            BlendMode::Persistence => self.persist(&value),
+            BlendMode::Dmg => {
+                let mut pixels = self.persist(&value);
+                let bytes_per_pixel = value.format.bytes_per_pixel();
+                for pixel in pixels.chunks_exact_mut(bytes_per_pixel) {
+                    dmg_response(pixel);
+                }
+                pixels
+            }
+        };
+        self.inner = Some(VideoFrame {
+            pixels: pixels.into(),
+            ..value
+        });
+    }
+}
+
+/// Maps an RGB pixel onto the DMG's green screen by its brightness
+fn dmg_response(pixel: &mut [u8]) {
+    let brightness =
+        (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32) / 255.0;
+    let level = brightness.powf(DMG_GAMMA);
+    for (channel, (dark, light)) in pixel.iter_mut().zip(DMG_DARKEST.iter().zip(DMG_LIGHTEST)) {
+        *channel = (dark + (light - dark) * level).round() as u8;
     }
 }
@@ -0,0 +1,387 @@
+//! A libretro core wrapping gabe_core, so gabe can be loaded by RetroArch and other libretro
+//! frontends. Build it with `cargo build --release` and load the resulting shared library.
+
+use std::{
+    cell::RefCell,
+    os::raw::{c_char, c_uint, c_void},
+    sync::Arc,
+};
+
+use gabe_core::{
+    error::GabeError,
+    gb::{Gameboy, GbKeys, JoypadState},
+    sink::{AudioFrame, PixelFormat, Sink},
+};
+
+mod retro;
+
+/// Width of the Gameboy screen in pixels
+const WIDTH: usize = 160;
+/// Height of the Gameboy screen in pixels
+const HEIGHT: usize = 144;
+/// Core audio samples averaged into each output sample, giving a 32768 Hz output rate
+const AUDIO_DECIMATION: u32 = 8;
+/// CPU cycles per output audio sample
+const CYCLES_PER_SAMPLE: u64 =
+    (gabe_core::CLOCK_RATE / gabe_core::SAMPLE_RATE * AUDIO_DECIMATION) as u64;
+
+/// The libretro joypad button read for each Gameboy button
+const BUTTONS: [(GbKeys, c_uint); 8] = [
+    (GbKeys::A, retro::DEVICE_ID_JOYPAD_A),
+    (GbKeys::B, retro::DEVICE_ID_JOYPAD_B),
+    (GbKeys::Start, retro::DEVICE_ID_JOYPAD_START),
+    (GbKeys::Select, retro::DEVICE_ID_JOYPAD_SELECT),
+    (GbKeys::Up, retro::DEVICE_ID_JOYPAD_UP),
+    (GbKeys::Down, retro::DEVICE_ID_JOYPAD_DOWN),
+    (GbKeys::Left, retro::DEVICE_ID_JOYPAD_LEFT),
+    (GbKeys::Right, retro::DEVICE_ID_JOYPAD_RIGHT),
+];
+
+/// Functions the frontend calls back into, set before the game is loaded
+#[derive(Clone, Copy, Default)]
+struct Callbacks {
+    environment: Option<retro::EnvironmentFn>,
+    video_refresh: Option<retro::VideoRefreshFn>,
+    audio_sample_batch: Option<retro::AudioSampleBatchFn>,
+    input_poll: Option<retro::InputPollFn>,
+    input_state: Option<retro::InputStateFn>,
+}
+
+// libretro calls the core from a single thread, so its state is kept per thread rather than
+// requiring the emulator to be Send
+thread_local! {
+    static CALLBACKS: RefCell<Callbacks> = RefCell::new(Callbacks::default());
+    /// The loaded game
+    static CORE: RefCell<Option<Core>> = const { RefCell::new(None) };
+}
+
+fn with_callbacks<R>(f: impl FnOnce(&mut Callbacks) -> R) -> R {
+    CALLBACKS.with(|c| f(&mut c.borrow_mut()))
+}
+
+fn with_core<R>(f: impl FnOnce(&mut Option<Core>) -> R) -> R {
+    CORE.with(|c| f(&mut c.borrow_mut()))
+}
+
+/// Averages every `AUDIO_DECIMATION` samples into an interleaved 16-bit output sample
+struct AudioSink {
+    samples: Vec<i16>,
+    sum: (f32, f32),
+    count: u32,
+}
+
+impl Sink<AudioFrame> for AudioSink {
+    fn append(&mut self, (left, right): AudioFrame) {
+        self.sum.0 += left;
+        self.sum.1 += right;
+        self.count += 1;
+        if self.count == AUDIO_DECIMATION {
+            let convert = |s: f32| (s / AUDIO_DECIMATION as f32 * i16::MAX as f32) as i16;
+            self.samples.push(convert(self.sum.0));
+            self.samples.push(convert(self.sum.1));
+            self.sum = (0.0, 0.0);
+            self.count = 0;
+        }
+    }
+}
+
+struct Core {
+    emu: Gameboy,
+    rom: Box<[u8]>,
+    /// Battery-backed RAM shared with the frontend, which fills it from its save file after
+    /// the game is loaded and writes it back out when the game is closed
+    save_ram: Vec<u8>,
+    /// Whether the frontend's save RAM has been given to the emulator yet
+    save_loaded: bool,
+    /// The last frame, as XRGB8888
+    pixels: Arc<[u8]>,
+    audio: AudioSink,
+    /// Cycles run since the game was loaded
+    cycles: u64,
+    /// Audio samples passed to the frontend since the game was loaded
+    samples_output: u64,
+}
+
+impl Core {
+    fn new(rom: Box<[u8]>) -> Result<Core, GabeError> {
+        let mut emu = Gameboy::power_on(rom.clone(), None)?;
+        emu.set_pixel_format(PixelFormat::Xrgb32);
+        let save_ram = emu.get_save_data().map(Vec::from).unwrap_or_default();
+        Ok(Core {
+            emu,
+            rom,
+            save_ram,
+            save_loaded: false,
+            pixels: vec![0xFF; WIDTH * HEIGHT * 4].into(),
+            audio: AudioSink {
+                samples: vec![],
+                sum: (0.0, 0.0),
+                count: 0,
+            },
+            cycles: 0,
+            samples_output: 0,
+        })
+    }
+
+    /// Powers the Gameboy back on with the current save RAM
+    fn restart(&mut self) {
+        let save = (!self.save_ram.is_empty()).then(|| self.save_ram.clone().into_boxed_slice());
+        // The ROM has already been loaded once, so it can't fail now
+        if let Ok(emu) = Gameboy::power_on(self.rom.clone(), save) {
+            self.emu = emu;
+            self.emu.set_pixel_format(PixelFormat::Xrgb32);
+        }
+    }
+
+    /// Runs until a frame is drawn, or for a frame's worth of cycles while the LCD is off
+    fn run_frame(&mut self) {
+        if !self.save_loaded {
+            // The frontend only fills the save RAM after the game is loaded
+            self.restart();
+            self.save_loaded = true;
+        }
+        let frame = self.emu.step_frame();
+        for sample in frame.audio {
+            self.audio.append(sample);
+        }
+        // The APU produces no samples while sound is off, but the frontend paces itself by the
+        // audio it's given, so fill the gap with silence
+        self.cycles += frame.cycles as u64;
+        self.samples_output += self.audio.samples.len() as u64 / 2;
+        let expected = self.cycles / CYCLES_PER_SAMPLE;
+        if expected > self.samples_output {
+            let missing = (expected - self.samples_output) as usize;
+            self.audio
+                .samples
+                .resize(self.audio.samples.len() + missing * 2, 0);
+            self.samples_output = expected;
+        }
+        if let Some(video) = frame.video {
+            self.pixels = video.pixels;
+        }
+        if let Some(data) = self.emu.get_save_data() {
+            if data.len() == self.save_ram.len() {
+                self.save_ram.copy_from_slice(&data);
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    retro::API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: retro::EnvironmentFn) {
+    with_callbacks(|c| c.environment = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: retro::VideoRefreshFn) {
+    with_callbacks(|c| c.video_refresh = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: retro::AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: retro::AudioSampleBatchFn) {
+    with_callbacks(|c| c.audio_sample_batch = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: retro::InputPollFn) {
+    with_callbacks(|c| c.input_poll = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: retro::InputStateFn) {
+    with_callbacks(|c| c.input_state = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    with_core(|core| *core = None);
+}
+
+/// # Safety
+/// `info` must point to a `retro_system_info` struct
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut retro::SystemInfo) {
+    *info = retro::SystemInfo {
+        library_name: b"gabe\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"gb\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+/// `info` must point to a `retro_system_av_info` struct
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut retro::SystemAvInfo) {
+    *info = retro::SystemAvInfo {
+        geometry: retro::GameGeometry {
+            base_width: WIDTH as c_uint,
+            base_height: HEIGHT as c_uint,
+            max_width: WIDTH as c_uint,
+            max_height: HEIGHT as c_uint,
+            aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+        },
+        timing: retro::SystemTiming {
+            fps: gabe_core::FRAME_RATE,
+            sample_rate: (gabe_core::SAMPLE_RATE / AUDIO_DECIMATION) as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    with_core(|core| {
+        if let Some(core) = core {
+            core.emu.reset();
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = with_callbacks(|c| *c);
+    with_core(|core| {
+        if let Some(core) = core {
+            run(core, &callbacks);
+        }
+    });
+}
+
+/// Runs a frame, reading input before it and passing the video and audio produced to the
+/// frontend
+fn run(core: &mut Core, callbacks: &Callbacks) {
+    if let (Some(poll), Some(state)) = (callbacks.input_poll, callbacks.input_state) {
+        // SAFETY: the callbacks were given to us by the frontend for calling during retro_run
+        unsafe {
+            poll();
+            let mut input = JoypadState::empty();
+            for (button, id) in BUTTONS {
+                let pressed = state(0, retro::DEVICE_JOYPAD, 0, id) != 0;
+                input.set(button.into(), pressed);
+            }
+            core.emu.set_input(input);
+        }
+    }
+
+    core.run_frame();
+
+    if let Some(video_refresh) = callbacks.video_refresh {
+        unsafe {
+            video_refresh(
+                core.pixels.as_ptr() as *const c_void,
+                WIDTH as c_uint,
+                HEIGHT as c_uint,
+                WIDTH * 4,
+            );
+        }
+    }
+    if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+        let samples = &core.audio.samples;
+        unsafe {
+            audio_sample_batch(samples.as_ptr(), samples.len() / 2);
+        }
+    }
+    core.audio.samples.clear();
+}
+
+/// Save states aren't supported by the core yet
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize(_data: *mut c_void, _size: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unserialize(_data: *const c_void, _size: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// # Safety
+/// `game` must be null or point to a `retro_game_info` struct whose data is `size` bytes long
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const retro::GameInfo) -> bool {
+    let Some(game) = game.as_ref() else {
+        return false;
+    };
+    if game.data.is_null() {
+        return false;
+    }
+    if let Some(environment) = with_callbacks(|c| c.environment) {
+        let mut format = retro::PIXEL_FORMAT_XRGB8888;
+        if !environment(
+            retro::ENVIRONMENT_SET_PIXEL_FORMAT,
+            &mut format as *mut c_uint as *mut c_void,
+        ) {
+            return false;
+        }
+    }
+    let rom = std::slice::from_raw_parts(game.data as *const u8, game.size);
+    // The frontend reports the failure when the ROM isn't supported
+    let Ok(new_core) = Core::new(rom.into()) else {
+        return false;
+    };
+    with_core(|core| *core = Some(new_core));
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const retro::GameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    with_core(|core| *core = None);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    retro::REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    with_core(|core| match core {
+        Some(core) if id == retro::MEMORY_SAVE_RAM && !core.save_ram.is_empty() => {
+            core.save_ram.as_mut_ptr() as *mut c_void
+        }
+        _ => std::ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    with_core(|core| match core {
+        Some(core) if id == retro::MEMORY_SAVE_RAM => core.save_ram.len(),
+        _ => 0,
+    })
+}
@@ -0,0 +1,292 @@
+//! Browser frontend for gabe, built to WebAssembly with `wasm-pack build --target web`.
+//! `index.html` loads the module, reads a ROM chosen by the user and drives a `WebGameboy`
+//! from `requestAnimationFrame`.
+
+use std::sync::Arc;
+
+use gabe_core::{
+    gb::{Gameboy, GbKeys, StopOn},
+    sink::{AudioFrame, PixelFormat, Sink, VideoFrame},
+};
+use wasm_bindgen::{prelude::*, Clamped, JsCast};
+use web_sys::{AudioContext, CanvasRenderingContext2d, Gamepad, GamepadButton, ImageData};
+
+/// Width of the Gameboy screen in pixels
+const WIDTH: u32 = 160;
+/// Most emulated time run by one call, so a tab coming back from the background doesn't try
+/// to catch up on everything it missed
+const MAX_RUN_MS: f64 = 100.0;
+/// How far ahead of the audio clock sound is scheduled after an underrun, in seconds
+const AUDIO_LEAD: f64 = 0.05;
+/// Most sound scheduled ahead of the audio clock before samples are dropped, in seconds
+const MAX_AUDIO_AHEAD: f64 = 0.25;
+
+/// Buttons in the order they're stored in the input state arrays
+const BUTTONS: [GbKeys; 8] = [
+    GbKeys::A,
+    GbKeys::B,
+    GbKeys::Start,
+    GbKeys::Select,
+    GbKeys::Up,
+    GbKeys::Down,
+    GbKeys::Left,
+    GbKeys::Right,
+];
+
+/// Returns the index in `BUTTONS` of the button bound to a `KeyboardEvent.code`, using the same
+/// default keys as gabe_gui
+fn keyboard_button(code: &str) -> Option<usize> {
+    match code {
+        "KeyX" => Some(0),
+        "KeyZ" => Some(1),
+        "Enter" => Some(2),
+        "Backspace" => Some(3),
+        "ArrowUp" => Some(4),
+        "ArrowDown" => Some(5),
+        "ArrowLeft" => Some(6),
+        "ArrowRight" => Some(7),
+        _ => None,
+    }
+}
+
+/// Indices of the standard gamepad layout's buttons for each entry in `BUTTONS`. A and B use
+/// the right and bottom face buttons, matching their positions on a Gameboy.
+const GAMEPAD_BUTTONS: [u32; 8] = [1, 0, 9, 8, 12, 13, 14, 15];
+
+struct FrameSink {
+    frame: Option<VideoFrame>,
+}
+
+impl Sink<VideoFrame> for FrameSink {
+    fn append(&mut self, value: VideoFrame) {
+        self.frame = Some(value);
+    }
+}
+
+struct AudioSink {
+    samples: Vec<AudioFrame>,
+}
+
+impl Sink<AudioFrame> for AudioSink {
+    fn append(&mut self, value: AudioFrame) {
+        self.samples.push(value);
+    }
+
+    fn append_slice(&mut self, values: &[AudioFrame]) {
+        self.samples.extend_from_slice(values);
+    }
+}
+
+/// Converts the core's audio to the output sample rate, averaging the input samples that fall
+/// within each output sample
+struct Downsampler {
+    /// Input samples per output sample
+    ratio: f64,
+    /// Input samples accumulated towards the next output sample
+    position: f64,
+    sum: (f32, f32),
+    count: u32,
+}
+
+impl Downsampler {
+    fn new(output_rate: f32) -> Self {
+        Downsampler {
+            ratio: gabe_core::SAMPLE_RATE as f64 / output_rate as f64,
+            position: 0.0,
+            sum: (0.0, 0.0),
+            count: 0,
+        }
+    }
+
+    /// Returns the left and right channels of the output samples
+    fn run(&mut self, input: &[AudioFrame]) -> (Vec<f32>, Vec<f32>) {
+        let capacity = (input.len() as f64 / self.ratio) as usize + 1;
+        let mut left = Vec::with_capacity(capacity);
+        let mut right = Vec::with_capacity(capacity);
+        for (l, r) in input {
+            self.sum.0 += l;
+            self.sum.1 += r;
+            self.count += 1;
+            self.position += 1.0;
+            if self.position >= self.ratio {
+                self.position -= self.ratio;
+                left.push(self.sum.0 / self.count as f32);
+                right.push(self.sum.1 / self.count as f32);
+                self.sum = (0.0, 0.0);
+                self.count = 0;
+            }
+        }
+        (left, right)
+    }
+}
+
+/// Plays audio by scheduling a buffer for each batch of samples, back to back on the audio
+/// context's clock
+struct WebAudio {
+    ctx: AudioContext,
+    /// Audio context time at which the next buffer starts
+    next_time: f64,
+    downsampler: Downsampler,
+}
+
+impl WebAudio {
+    fn new() -> Result<Self, JsValue> {
+        let ctx = AudioContext::new()?;
+        let downsampler = Downsampler::new(ctx.sample_rate());
+        Ok(WebAudio {
+            ctx,
+            next_time: 0.0,
+            downsampler,
+        })
+    }
+
+    fn play(&mut self, samples: &[AudioFrame]) -> Result<(), JsValue> {
+        let (left, right) = self.downsampler.run(samples);
+        if left.is_empty() {
+            return Ok(());
+        }
+        let now = self.ctx.current_time();
+        if self.next_time < now {
+            // Underran, so start again a little ahead of the clock
+            self.next_time = now + AUDIO_LEAD;
+        } else if self.next_time > now + MAX_AUDIO_AHEAD {
+            // Emulation is running ahead of playback, so drop these samples to catch up
+            return Ok(());
+        }
+        let rate = self.ctx.sample_rate();
+        let buffer = self.ctx.create_buffer(2, left.len() as u32, rate)?;
+        buffer.copy_to_channel(&left, 0)?;
+        buffer.copy_to_channel(&right, 1)?;
+        let source = self.ctx.create_buffer_source()?;
+        source.set_buffer(Some(&buffer));
+        source.connect_with_audio_node(&self.ctx.destination())?;
+        source.start_with_when(self.next_time)?;
+        self.next_time += left.len() as f64 / rate as f64;
+        Ok(())
+    }
+}
+
+/// A Gameboy running in the browser, drawing to a canvas and playing sound through WebAudio
+#[wasm_bindgen]
+pub struct WebGameboy {
+    emu: Gameboy,
+    /// The last frame, as RGBA for `ImageData`
+    rgba: Arc<[u8]>,
+    /// Fractional cycles left over from the last run, carried into the next
+    cycle_debt: f64,
+    audio: Option<WebAudio>,
+    /// Buttons held on the keyboard, in `BUTTONS` order
+    keyboard: [bool; 8],
+    /// Buttons held on any gamepad, in `BUTTONS` order
+    gamepad: [bool; 8],
+}
+
+#[wasm_bindgen]
+impl WebGameboy {
+    /// Starts a ROM, with its save data if there is any. Throws if the ROM isn't supported.
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: Vec<u8>, save: Option<Vec<u8>>) -> Result<WebGameboy, JsValue> {
+        let mut emu = Gameboy::power_on(rom.into_boxed_slice(), save.map(Vec::into_boxed_slice))
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        emu.set_pixel_format(PixelFormat::Rgba32);
+        Ok(WebGameboy {
+            emu,
+            rgba: vec![0xFF; 160 * 144 * 4].into(),
+            cycle_debt: 0.0,
+            audio: None,
+            keyboard: [false; 8],
+            gamepad: [false; 8],
+        })
+    }
+
+    /// Creates the audio output. Browsers only allow this in response to user input, such as
+    /// the click that chose the ROM.
+    pub fn enable_audio(&mut self) -> Result<(), JsValue> {
+        if self.audio.is_none() {
+            self.audio = Some(WebAudio::new()?);
+        }
+        Ok(())
+    }
+
+    /// Updates a button from a keyboard event's `code`. Returns true if the key is bound, so the
+    /// page can stop the browser from also handling it.
+    pub fn set_key(&mut self, code: &str, pressed: bool) -> bool {
+        match keyboard_button(code) {
+            Some(i) => {
+                self.keyboard[i] = pressed;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs `elapsed_ms` milliseconds of emulated time, up to a limit. Returns true if a new
+    /// frame was drawn, which `draw` then shows.
+    pub fn run(&mut self, elapsed_ms: f64) -> Result<bool, JsValue> {
+        self.poll_gamepads();
+        for (i, button) in BUTTONS.into_iter().enumerate() {
+            self.emu
+                .update_key_state(button, self.keyboard[i] || self.gamepad[i]);
+        }
+
+        let mut video_sink = FrameSink { frame: None };
+        let mut audio_sink = AudioSink { samples: vec![] };
+        self.cycle_debt += elapsed_ms.min(MAX_RUN_MS) / 1000.0 * gabe_core::CLOCK_RATE as f64;
+        if self.cycle_debt > 0.0 {
+            let run = self.emu.run_cycles(
+                self.cycle_debt.ceil() as u32,
+                StopOn::default(),
+                &mut video_sink,
+                &mut audio_sink,
+            );
+            self.cycle_debt -= run.cycles as f64;
+        }
+        if let Some(audio) = &mut self.audio {
+            audio.play(&audio_sink.samples)?;
+        }
+
+        let Some(frame) = video_sink.frame else {
+            return Ok(false);
+        };
+        self.rgba = frame.pixels;
+        Ok(true)
+    }
+
+    /// Draws the last frame at the top left of the canvas, at its native 160x144 size. The page
+    /// scales the canvas up with CSS.
+    pub fn draw(&self, ctx: &CanvasRenderingContext2d) -> Result<(), JsValue> {
+        let image = ImageData::new_with_u8_clamped_array(Clamped(&self.rgba[..]), WIDTH)?;
+        ctx.put_image_data(&image, 0.0, 0.0)
+    }
+
+    /// Returns the game's battery-backed save data, if the cartridge has any
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        self.emu.get_save_data().map(|data| data.to_vec())
+    }
+
+    /// Reads the buttons held on all connected gamepads that use the standard layout
+    fn poll_gamepads(&mut self) {
+        self.gamepad = [false; 8];
+        let Some(gamepads) = web_sys::window().and_then(|w| w.navigator().get_gamepads().ok())
+        else {
+            return;
+        };
+        for gamepad in gamepads.iter() {
+            let Ok(gamepad) = gamepad.dyn_into::<Gamepad>() else {
+                // Empty slots are null
+                continue;
+            };
+            if !gamepad.connected() || gamepad.mapping() != web_sys::GamepadMappingType::Standard {
+                continue;
+            }
+            let buttons = gamepad.buttons();
+            for (i, index) in GAMEPAD_BUTTONS.into_iter().enumerate() {
+                let pressed = buttons
+                    .get(index)
+                    .dyn_into::<GamepadButton>()
+                    .map_or(false, |b| b.pressed());
+                self.gamepad[i] |= pressed;
+            }
+        }
+    }
+}